@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use tradingview_websocket::tradingview_api::{parse_message, split_messages};
+use tradingview_websocket::websocket::{decode_frame, encode_server_frame, encode_text_frame};
+
+// hand written frames shaped like what the server sends, not live captures
+const TIMESCALE_UPDATE: &str = include_str!("data/timescale_update.txt");
+const QUOTE_BATCH: &str = include_str!("data/quote_batch.txt");
+const PING: &str = include_str!("data/ping.txt");
+
+const FRAMES: [(&str, &str); 3] = [("timescale_update", TIMESCALE_UPDATE), ("quote_batch", QUOTE_BATCH), ("ping", PING)];
+
+fn bench_codec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec");
+    for (name, payload) in FRAMES {
+        group.throughput(Throughput::Bytes(payload.len() as u64));
+        group.bench_function(format!("encode_{}", name), |b| b.iter(|| encode_text_frame(black_box(payload))));
+        let frame = VecDeque::from(encode_server_frame(0x01, payload.as_bytes()));
+        group.bench_function(format!("decode_{}", name), |b| {
+            b.iter_batched_ref(|| frame.clone(), |buffer| decode_frame(black_box(buffer)), BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+fn bench_messages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("messages");
+    for (name, payload) in FRAMES {
+        group.throughput(Throughput::Bytes(payload.len() as u64));
+        group.bench_function(format!("split_{}", name), |b| b.iter(|| split_messages(black_box(payload)).count()));
+        let messages: Vec<&str> = split_messages(payload).collect();
+        group.bench_function(format!("classify_{}", name), |b| {
+            b.iter(|| messages.iter().filter(|message| parse_message(black_box(message)).is_ok()).count())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_codec, bench_messages);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tradingview_websocket::json;
+use tradingview_websocket::quote::{QuoteExtractor, QuoteField};
+
+// shaped like the timescale_update the server sends for a 5000 bar history request
+fn timescale_update(bars: usize) -> String {
+    let entries: Vec<String> = (0..bars)
+        .map(|index| {
+            let time = 1_700_000_000 + index as i64 * 60;
+            let open = 37_000.0 + (index % 97) as f64 * 1.25;
+            format!(
+                r#"{{"i":{},"v":[{}.0,{},{},{},{},{}]}}"#,
+                index, time, open, open + 12.5, open - 8.75, open + 3.5, 12.345 + (index % 13) as f64
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"m":"timescale_update","p":["cs_abc123",{{"series_id":{{"node":"sea1-2","s":[{}],"ns":{{"d":"","indexes":[]}},"t":"s1","lbs":{{"bar_close_time":1700300000}}}}}}]}}"#,
+        entries.join(",")
+    )
+}
+
+fn quote_update() -> String {
+    r#"{"m":"qsd","p":["qs_abc123",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"lp":37123.45,"lp_time":1700000123,"ch":123.4,"chp":0.33,"volume":12345.678,"bid":37123.4,"ask":37123.5,"bid_size":1.2,"ask_size":0.8}}]}"#.to_string()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for bars in [300, 5000] {
+        let message = timescale_update(bars);
+        group.throughput(Throughput::Bytes(message.len() as u64));
+        group.bench_function(format!("timescale_update_{}", bars), |b| b.iter(|| json::parse(black_box(&message))));
+    }
+    let message = quote_update();
+    group.throughput(Throughput::Bytes(message.len() as u64));
+    group.bench_function("qsd", |b| b.iter(|| json::parse(black_box(&message))));
+    let extractor = QuoteExtractor::new(&[QuoteField::LastPrice, QuoteField::Volume]);
+    group.bench_function("qsd_extract", |b| b.iter(|| extractor.extract(black_box(&message)).map(|update| update.f64(QuoteField::LastPrice))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+// generates the C header from src/ffi.rs into OUT_DIR when building with the ffi feature, and
+// over the checked in include/tradingview_websocket.h only with UPDATE_HEADER set, so a build
+// never writes into the source tree on its own
+fn main() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=UPDATE_HEADER");
+    #[cfg(feature = "ffi")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR");
+        let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).expect("cbindgen.toml");
+        let header = cbindgen::Builder::new()
+            // only this file, so the crate's other public constants stay out of the header
+            .with_src(format!("{}/src/ffi.rs", crate_dir))
+            .with_config(config)
+            .generate()
+            .expect("couldn't generate the C header");
+        header.write_to_file(format!("{}/tradingview_websocket.h", out_dir));
+        if std::env::var_os("UPDATE_HEADER").is_some() {
+            header.write_to_file(format!("{}/include/tradingview_websocket.h", crate_dir));
+        }
+    }
+}
@@ -0,0 +1,17 @@
+#![no_main]
+
+use std::collections::VecDeque;
+
+use libfuzzer_sys::fuzz_target;
+use tradingview_websocket::websocket::{decode_frame, Frame};
+
+// whatever the server sends, decoding stops with an error or waits for more and never panics;
+// invalid utf-8 is replaced, which at most triples a payload
+fuzz_target!(|data: &[u8]| {
+    let mut buffer = VecDeque::from(data.to_vec());
+    while let Ok(Some(frame)) = decode_frame(&mut buffer) {
+        if let Frame::Text(text) = frame {
+            assert!(text.len() <= data.len() * 3);
+        }
+    }
+});
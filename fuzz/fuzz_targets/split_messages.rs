@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tradingview_websocket::tradingview_api::{parse_message, split_messages};
+
+// frames as the websocket hands them to the api: split, then classified message by message
+fuzz_target!(|frame: &str| {
+    for message in split_messages(frame) {
+        let _ = parse_message(message);
+    }
+});
@@ -0,0 +1,93 @@
+#ifndef TRADINGVIEW_WEBSOCKET_H
+#define TRADINGVIEW_WEBSOCKET_H
+
+/* generated by cbindgen from src/ffi.rs when building with --features ffi, don't edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The call succeeded.
+ */
+#define TV_OK 0
+
+/**
+ * A pointer was null, a string wasn't UTF-8 or a symbol isn't valid.
+ */
+#define TV_INVALID_ARGUMENT -1
+
+/**
+ * The connection has ended; destroy the client and create a new one.
+ */
+#define TV_CLOSED -2
+
+/**
+ * Something went wrong inside the library.
+ */
+#define TV_INTERNAL_ERROR -3
+
+/**
+ * A connection streaming quotes for the subscribed symbols. Only use it from one thread at a time.
+ */
+typedef struct TvClient TvClient;
+
+/**
+ * Connects and starts an empty quote session. `quote_fields` is a comma separated list of
+ * field names like "lp,ch,chp,volume", or null for "lp,ch,chp". Returns null when a field
+ * isn't known. Free the client with `tv_client_destroy`.
+ *
+ * # Safety
+ * `quote_fields` is null or a nul terminated string.
+ */
+struct TvClient *tv_client_create(const char *quote_fields);
+
+/**
+ * Adds a symbol like "NASDAQ:AAPL" to the quote session. Returns the correlation id (1 or more)
+ * that the symbol's quote events carry as "correlation_id", the same id again when the symbol
+ * is already subscribed, or `TV_INVALID_ARGUMENT` or `TV_CLOSED`.
+ *
+ * # Safety
+ * `client` comes from `tv_client_create` and `symbol` is a nul terminated string.
+ */
+int64_t tv_client_subscribe(struct TvClient *client, const char *symbol);
+
+/**
+ * Removes a symbol from the quote session. Unsubscribing a symbol that isn't subscribed succeeds.
+ *
+ * # Safety
+ * `client` comes from `tv_client_create` and `symbol` is a nul terminated string.
+ */
+int32_t tv_client_unsubscribe(struct TvClient *client, const char *symbol);
+
+/**
+ * Waits up to `timeout_ms` for the next event and returns it as JSON in the crate's event schema,
+ * with quote and study values as objects keyed by name, e.g.
+ * {"type":"quote","symbol":"NASDAQ:AAPL","values":{"lp":189.5}}. Returns null when
+ * nothing came in time. When the connection ends it returns {"type":"closed","error":...} once,
+ * then only null. Free the string with `tv_string_free`.
+ *
+ * # Safety
+ * `client` comes from `tv_client_create`.
+ */
+char *tv_client_poll(struct TvClient *client, uint32_t timeout_ms);
+
+/**
+ * Frees a string returned by `tv_client_poll`; null is ignored.
+ *
+ * # Safety
+ * `value` comes from `tv_client_poll` and isn't used afterwards.
+ */
+void tv_string_free(char *value);
+
+/**
+ * Closes the connection and frees the client; null is ignored.
+ *
+ * # Safety
+ * `client` comes from `tv_client_create` and isn't used afterwards.
+ */
+void tv_client_destroy(struct TvClient *client);
+
+#endif /* TRADINGVIEW_WEBSOCKET_H */
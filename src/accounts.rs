@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+
+use crate::auth::{AuthError, AuthProvider};
+
+// an account name and the exchanges it serves, e.g. "futures=CME,CBOT"; without exchanges it
+// takes any symbol no other account claims
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountSpec {
+    pub name: String,
+    pub exchanges: Vec<String>
+}
+
+impl std::str::FromStr for AccountSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<AccountSpec, String> {
+        let (name, exchanges) = value.split_once('=').unwrap_or((value, ""));
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(value.to_string());
+        }
+        Ok(AccountSpec {
+            name: name.to_string(),
+            exchanges: exchanges.split(',').filter(|exchange| !exchange.is_empty()).map(str::to_uppercase).collect()
+        })
+    }
+}
+
+pub struct Account {
+    pub name: String,
+    pub auth: Arc<dyn AuthProvider>,
+    // empty for a catch-all account
+    pub exchanges: Vec<String>
+}
+
+// "CME_MINI:ES1!" is on CME_MINI; bare names and expressions have no exchange
+fn exchange_of(symbol: &str) -> Option<&str> {
+    let (exchange, _) = symbol.split_once(':')?;
+    exchange.chars().all(|c| c.is_ascii_alphanumeric() || c == '_').then_some(exchange)
+}
+
+// several logins, each serving some exchanges, e.g. real-time futures on one and crypto on another
+#[derive(Default)]
+pub struct Accounts {
+    accounts: Vec<Arc<Account>>
+}
+
+impl Accounts {
+    pub fn new(accounts: Vec<Account>) -> Accounts {
+        Accounts { accounts: accounts.into_iter().map(Arc::new).collect() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    // the accounts that claim the symbol's exchange, then the catch-all ones, in declaration order
+    pub fn candidates(&self, symbol: &str) -> Vec<Arc<Account>> {
+        let exchange = exchange_of(symbol).map(str::to_uppercase);
+        let claims = |account: &Account| exchange.as_ref().is_some_and(|exchange| account.exchanges.contains(exchange));
+        let claiming = self.accounts.iter().filter(|account| claims(account));
+        let catch_all = self.accounts.iter().filter(|account| account.exchanges.is_empty());
+        claiming.chain(catch_all).cloned().collect()
+    }
+
+    // None when no account serves the symbol, so the caller falls back to its own auth
+    pub fn auth_for(&self, symbol: &str) -> Option<Arc<dyn AuthProvider>> {
+        let candidates = self.candidates(symbol);
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Arc::new(Failover::new(candidates)))
+    }
+
+    // symbols grouped by the account that serves them first, in declaration order, so each
+    // account gets its own connections
+    pub fn assign(&self, symbols: &[String]) -> Vec<AccountGroup> {
+        let mut groups: Vec<AccountGroup> = vec![];
+        let mut index: HashMap<Option<String>, usize> = HashMap::new();
+        for symbol in symbols {
+            let candidates = self.candidates(symbol);
+            let account = candidates.first().map(|account| account.name.clone());
+            let group = *index.entry(account.clone()).or_insert_with(|| {
+                let auth = (!candidates.is_empty()).then(|| Arc::new(Failover::new(candidates.clone())) as Arc<dyn AuthProvider>);
+                groups.push(AccountGroup { account, auth, symbols: vec![] });
+                groups.len() - 1
+            });
+            groups[group].symbols.push(symbol.clone());
+        }
+        groups
+    }
+}
+
+pub struct AccountGroup {
+    // None and no auth for the symbols no account serves
+    pub account: Option<String>,
+    pub auth: Option<Arc<dyn AuthProvider>>,
+    pub symbols: Vec<String>
+}
+
+// hands out the first account's token; once it can't be refreshed any more, e.g. because the
+// subscription lapsed or the password changed, moves on to the next candidate for good
+pub struct Failover {
+    accounts: Vec<Arc<Account>>,
+    current: Mutex<usize>
+}
+
+impl Failover {
+    pub fn new(accounts: Vec<Arc<Account>>) -> Failover {
+        Failover { accounts, current: Mutex::new(0) }
+    }
+
+    // the account in use
+    pub fn current(&self) -> Option<String> {
+        let current = *self.current.lock().ok()?;
+        self.accounts.get(current).map(|account| account.name.clone())
+    }
+
+    // the current account's token, or else the first later account's; a refresh only applies to
+    // the current one, the others haven't been tried yet so their own token is as good as a fresh one
+    fn first_working(&self, refresh: bool) -> Result<String, Box<dyn Error + Send>> {
+        let mut current = self.current.lock().map_err(|_| AuthError::LoginFailed("account lock poisoned".to_string()))?;
+        let mut last_error: Box<dyn Error + Send> = AuthError::NoCredentials.into();
+        let mut refresh = refresh;
+        while let Some(account) = self.accounts.get(*current) {
+            let token = if refresh { account.auth.refresh() } else { account.auth.token() };
+            match token {
+                Ok(token) => return Ok(token),
+                Err(err) => {
+                    warn!("account {} has no working token, failing over: {}", account.name, err);
+                    last_error = err;
+                    *current += 1;
+                    refresh = false;
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+impl AuthProvider for Failover {
+    fn token(&self) -> Result<String, Box<dyn Error + Send>> {
+        self.first_working(false)
+    }
+
+    fn refresh(&self) -> Result<String, Box<dyn Error + Send>> {
+        self.first_working(true)
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// returned by Connection::command and carried by the events its messages lead to
+pub type CorrelationId = u64;
+
+// long enough for a big create_series on a slow connection
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CommandOutcome {
+    Acknowledged,
+    // the server answered with an error, e.g. symbol_error or study_error
+    Failed { reason: String },
+    // no answer within ApiOptions::command_timeout
+    TimedOut
+}
+
+// how one sent command ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandResult {
+    // the method sent, e.g. resolve_symbol
+    pub command: String,
+    pub session: String,
+    // what the command was about: a series, study or symbol id, or the quote symbol
+    pub target: String,
+    pub outcome: CommandOutcome,
+    // from sending to the answer, or to giving up
+    pub elapsed: Duration,
+    // the ApiCommand the message was sent for, None for the connection's own
+    pub correlation_id: Option<CorrelationId>
+}
+
+struct Pending {
+    command: &'static str,
+    session: String,
+    target: String,
+    sent: Instant,
+    correlation_id: Option<CorrelationId>
+}
+
+// the methods the server confirms
+const TRACKED: [&str; 6] = ["resolve_symbol", "create_series", "modify_series", "request_more_data", "create_study", "quote_add_symbols"];
+
+// the commands a reply confirms or fails
+fn answered_by(reply: &str) -> &'static [&'static str] {
+    match reply {
+        "symbol_resolved" | "symbol_error" => &["resolve_symbol"],
+        "series_completed" => &["create_series", "modify_series", "request_more_data"],
+        "study_completed" | "study_error" => &["create_study"],
+        "quote_completed" => &["quote_add_symbols"],
+        _ => &[],
+    }
+}
+
+fn text(message: &Value, index: usize) -> Option<&str> {
+    message.get("p")?.get(index)?.as_str()
+}
+
+// a symbol added as an "={...}" spec is answered under its plain name
+fn quote_symbol(param: &str) -> String {
+    param.strip_prefix('=')
+        .and_then(|spec| serde_json::from_str::<Value>(spec).ok())
+        .and_then(|spec| spec.get("symbol").and_then(Value::as_str).map(str::to_string))
+        .unwrap_or_else(|| param.to_string())
+}
+
+// commands sent but not yet answered, matched to their replies by session and target; a command
+// sent again before its answer restarts the wait instead of being tracked twice
+pub struct AckTracker {
+    timeout: Duration,
+    pending: Vec<Pending>
+}
+
+impl AckTracker {
+    pub fn new(timeout: Duration) -> AckTracker {
+        AckTracker { timeout, pending: vec![] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    // commands the server never answers are left alone
+    pub fn sent(&mut self, message: &Value, correlation_id: Option<CorrelationId>, now: Instant) {
+        let command = match message.get("m").and_then(Value::as_str).and_then(|method| TRACKED.iter().find(|tracked| **tracked == method)) {
+            Some(command) => *command,
+            None => return,
+        };
+        let session = text(message, 0).unwrap_or_default();
+        // quote_add_symbols answers with a quote_completed per symbol
+        let targets: Vec<String> = match command {
+            "quote_add_symbols" => message.get("p").and_then(Value::as_array).map_or(vec![], |params| params.iter().skip(1).filter_map(Value::as_str).map(quote_symbol).collect()),
+            _ => text(message, 1).into_iter().map(str::to_string).collect(),
+        };
+        for target in targets {
+            self.pending.retain(|pending| !(pending.command == command && pending.session == session && pending.target == target));
+            self.pending.push(Pending { command, session: session.to_string(), target, sent: now, correlation_id });
+        }
+    }
+
+    // the commands a server message answers; p[0] is the session and p[1] the target of every reply
+    pub fn received(&mut self, message: &Value, now: Instant) -> Vec<CommandResult> {
+        if self.pending.is_empty() {
+            return vec![];
+        }
+        let reply = message.get("m").and_then(Value::as_str).unwrap_or_default();
+        let commands = answered_by(reply);
+        let (session, target) = match (text(message, 0), text(message, 1)) {
+            (Some(session), Some(target)) if !commands.is_empty() => (session, target),
+            _ => return vec![],
+        };
+        let outcome = match reply {
+            "symbol_error" => CommandOutcome::Failed { reason: text(message, 2).unwrap_or("unknown error").to_string() },
+            "study_error" => CommandOutcome::Failed { reason: text(message, 3).unwrap_or("unknown error").to_string() },
+            _ => CommandOutcome::Acknowledged,
+        };
+        self.resolve(commands, session, target, outcome, now)
+    }
+
+    // a quote symbol the server rejected in its qsd instead of a reply of its own
+    pub fn quote_failed(&mut self, session: &str, symbol: &str, reason: &str, now: Instant) -> Vec<CommandResult> {
+        self.resolve(&["quote_add_symbols"], session, symbol, CommandOutcome::Failed { reason: reason.to_string() }, now)
+    }
+
+    fn resolve(&mut self, commands: &[&str], session: &str, target: &str, outcome: CommandOutcome, now: Instant) -> Vec<CommandResult> {
+        let mut results = vec![];
+        self.pending.retain(|pending| {
+            if !commands.contains(&pending.command) || pending.session != session || pending.target != target {
+                return true;
+            }
+            results.push(CommandResult {
+                command: pending.command.to_string(),
+                session: pending.session.clone(),
+                target: pending.target.clone(),
+                outcome: outcome.clone(),
+                elapsed: now.saturating_duration_since(pending.sent),
+                correlation_id: pending.correlation_id
+            });
+            false
+        });
+        results
+    }
+
+    // when the oldest command runs out of time
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|pending| pending.sent + self.timeout).min()
+    }
+
+    pub fn expire(&mut self, now: Instant) -> Vec<CommandResult> {
+        let timeout = self.timeout;
+        let mut results = vec![];
+        self.pending.retain(|pending| {
+            let elapsed = now.saturating_duration_since(pending.sent);
+            if elapsed < timeout {
+                return true;
+            }
+            results.push(CommandResult {
+                command: pending.command.to_string(),
+                session: pending.session.clone(),
+                target: pending.target.clone(),
+                outcome: CommandOutcome::TimedOut,
+                elapsed,
+                correlation_id: pending.correlation_id
+            });
+            false
+        });
+        results
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+#[cfg(feature = "futures")]
+use std::mem;
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(feature = "futures")]
+use std::sync::{mpsc, Condvar, Mutex};
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll, Waker};
+#[cfg(feature = "futures")]
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "barter")]
+use barter_data::event::{DataKind, MarketEvent as BarterMarketEvent};
+#[cfg(feature = "barter")]
+use barter_data::streams::consumer::MarketStreamEvent;
+#[cfg(feature = "barter")]
+use barter_data::subscription::{candle::Candle, trade::PublicTrade};
+#[cfg(feature = "barter")]
+use barter_instrument::{exchange::ExchangeId, Side};
+use chrono::{DateTime, TimeZone, Utc};
+#[cfg(feature = "futures")]
+use futures_core::Stream;
+
+use crate::bar::Bar;
+use crate::client::{ClientError, Connection};
+use crate::event::Event;
+#[cfg(feature = "barter")]
+use crate::quality;
+use crate::quote::QuoteField;
+use crate::trade::Trade;
+#[cfg(feature = "barter")]
+use crate::trade::TradeSide;
+use crate::tradingview_api::{ApiOptions, Mode};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketEventKind {
+    // closed is false while the bar can still change, true for BarClosed
+    Candle { interval: String, bar: Bar, closed: bool },
+    Trade(Trade),
+    LastPrice(f64)
+}
+
+// one normalized update per candle, trade or price, shaped like the market events of
+// barter-style trading frameworks so a feed can be mapped into them field by field
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketEvent {
+    pub time_exchange: DateTime<Utc>,
+    pub time_received: DateTime<Utc>,
+    // "NASDAQ" and "AAPL" for NASDAQ:AAPL; exchange is empty for symbols without a prefix
+    pub exchange: String,
+    pub instrument: String,
+    pub kind: MarketEventKind
+}
+
+fn split_symbol(symbol: &str) -> (String, String) {
+    match symbol.split_once(':') {
+        Some((exchange, instrument)) => (exchange.to_string(), instrument.to_string()),
+        None => (String::new(), symbol.to_string()),
+    }
+}
+
+fn seconds(time: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(time, 0).single()
+}
+
+// the market events in an event, none for the kinds frameworks don't consume
+pub fn market_events(event: &Event, received: DateTime<Utc>) -> Vec<MarketEvent> {
+    let (symbol, updates) = match *event {
+        Event::Bars { ref symbol, ref interval, ref bars, .. } | Event::GapFilled { ref symbol, ref interval, ref bars } => {
+            (symbol, bars.iter().map(|bar| (bar.time, MarketEventKind::Candle { interval: interval.clone(), bar: bar.clone(), closed: false })).collect())
+        }
+        Event::BarCorrected { ref symbol, ref interval, ref bar, .. } => {
+            (symbol, vec![(bar.time, MarketEventKind::Candle { interval: interval.clone(), bar: bar.clone(), closed: false })])
+        }
+        Event::BarClosed { ref symbol, ref interval, ref bar } => {
+            (symbol, vec![(bar.time, MarketEventKind::Candle { interval: interval.clone(), bar: bar.clone(), closed: true })])
+        }
+        Event::Trade { ref symbol, ref trade, .. } => (symbol, vec![(trade.time, MarketEventKind::Trade(trade.clone()))]),
+        Event::Quote { ref symbol, ref values, .. } => {
+            let value = |wanted: QuoteField| values.iter().find(|(field, _)| *field == wanted).and_then(|(_, value)| value.as_f64());
+            match value(QuoteField::LastPrice) {
+                Some(price) => {
+                    let time = value(QuoteField::LastPriceTime).map_or(received.timestamp(), |time| time as i64);
+                    (symbol, vec![(time, MarketEventKind::LastPrice(price))])
+                }
+                None => return vec![],
+            }
+        }
+        _ => return vec![],
+    };
+    let (exchange, instrument) = split_symbol(symbol);
+    updates.into_iter().map(|(time, kind)| MarketEvent {
+        time_exchange: seconds(time).unwrap_or(received),
+        time_received: received,
+        exchange: exchange.clone(),
+        instrument: instrument.clone(),
+        kind
+    }).collect()
+}
+
+#[cfg(feature = "barter")]
+impl MarketEvent {
+    // the symbol as TradingView spells it, e.g. NASDAQ:AAPL
+    fn symbol(&self) -> String {
+        match self.exchange.as_str() {
+            "" => self.instrument.clone(),
+            exchange => format!("{}:{}", exchange, self.instrument),
+        }
+    }
+
+    // barter-data's event for this one, keyed by the symbol under ExchangeId::Other since barter
+    // has no id for TradingView; None for what barter has no kind for, which are candles still
+    // forming, last prices and the trades before the tick rule could tell their side
+    pub fn to_barter(&self) -> Option<BarterMarketEvent<String, DataKind>> {
+        let kind = match self.kind {
+            MarketEventKind::Candle { ref interval, ref bar, closed: true } => DataKind::Candle(Candle {
+                close_time: seconds(bar.time + quality::interval_seconds(interval).unwrap_or(0)).unwrap_or(self.time_exchange),
+                open: bar.open,
+                high: bar.high,
+                low: bar.low,
+                close: bar.close,
+                volume: bar.volume,
+                // the feed doesn't count them
+                trade_count: 0
+            }),
+            MarketEventKind::Trade(ref trade) => DataKind::Trade(PublicTrade {
+                id: trade.time.to_string(),
+                price: trade.price,
+                amount: trade.size,
+                side: match trade.side {
+                    TradeSide::Buy => Side::Buy,
+                    TradeSide::Sell => Side::Sell,
+                    TradeSide::Unknown => return None,
+                }
+            }),
+            _ => return None,
+        };
+        Some(BarterMarketEvent {
+            time_exchange: self.time_exchange,
+            time_received: self.time_received,
+            exchange: ExchangeId::Other,
+            instrument: self.symbol(),
+            kind
+        })
+    }
+}
+
+// a connection as an iterator of market events, for frameworks that pull their data; next()
+// blocks until there is one and ends with the connection
+pub struct MarketStream {
+    connection: Connection,
+    pending: VecDeque<MarketEvent>,
+    // how much the async forwarder queues
+    #[cfg_attr(not(feature = "futures"), allow(dead_code))]
+    backlog: usize
+}
+
+impl MarketStream {
+    pub fn init(mode: Mode, options: ApiOptions) -> MarketStream {
+        let backlog = options.limits.max_event_backlog.max(1);
+        MarketStream { connection: Connection::open(mode, options), pending: VecDeque::new(), backlog }
+    }
+
+    // None when nothing arrived within the timeout, or the connection has ended
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<MarketEvent> {
+        while self.pending.is_empty() {
+            let event = self.connection.events_rx.recv_timeout(timeout).ok()?;
+            self.queue(&event);
+        }
+        self.pending.pop_front()
+    }
+
+    fn queue(&mut self, event: &Event) {
+        self.pending.extend(market_events(event, Utc::now()));
+    }
+
+    // ends the connection, with the error that ended it if it ended on its own
+    pub fn close(mut self) -> Result<(), ClientError> {
+        self.connection.close()
+    }
+
+    // the same events as a futures Stream, for async frameworks
+    #[cfg(feature = "futures")]
+    pub fn into_async(self) -> AsyncMarketStream {
+        AsyncMarketStream::new(self)
+    }
+
+    #[cfg(feature = "barter")]
+    pub fn into_barter(self) -> BarterMarketStream {
+        BarterMarketStream { events: self.into_async() }
+    }
+}
+
+impl Iterator for MarketStream {
+    type Item = MarketEvent;
+
+    fn next(&mut self) -> Option<MarketEvent> {
+        while self.pending.is_empty() {
+            let event: Arc<Event> = self.connection.events_rx.recv().ok()?;
+            self.queue(&event);
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(feature = "futures")]
+#[derive(Default)]
+struct Shared {
+    queue: VecDeque<MarketEvent>,
+    waker: Option<Waker>,
+    // the connection ended
+    ended: bool,
+    // the stream was dropped, so the forwarder stops
+    dropped: bool
+}
+
+// a thread moves events from the connection into a queue and wakes the task polling it; the
+// queue holds about as many events as the connection's backlog before the forwarder waits
+#[cfg(feature = "futures")]
+pub struct AsyncMarketStream {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+    _connection: Connection
+}
+
+#[cfg(feature = "futures")]
+impl AsyncMarketStream {
+    fn new(stream: MarketStream) -> AsyncMarketStream {
+        let MarketStream { mut connection, pending, backlog } = stream;
+        let events_rx = mem::replace(&mut connection.events_rx, mpsc::sync_channel(0).1);
+        let shared = Arc::new((Mutex::new(Shared { queue: pending, ..Shared::default() }), Condvar::new()));
+        let forwarder_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let (state, space) = &*forwarder_shared;
+            for event in events_rx.iter() {
+                let events = market_events(&event, Utc::now());
+                let mut shared = match state.lock() {
+                    Ok(shared) => shared,
+                    Err(_) => return,
+                };
+                while shared.queue.len() >= backlog && !shared.dropped {
+                    shared = match space.wait(shared) {
+                        Ok(shared) => shared,
+                        Err(_) => return,
+                    };
+                }
+                // dropping the receiver unblocks the api so the connection can close
+                if shared.dropped {
+                    return;
+                }
+                shared.queue.extend(events);
+                if let Some(waker) = shared.waker.take() {
+                    waker.wake();
+                }
+            }
+            if let Ok(mut shared) = state.lock() {
+                shared.ended = true;
+                if let Some(waker) = shared.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+        AsyncMarketStream { shared, _connection: connection }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl Stream for AsyncMarketStream {
+    type Item = MarketEvent;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<MarketEvent>> {
+        let (state, space) = &*self.shared;
+        let mut shared = match state.lock() {
+            Ok(shared) => shared,
+            Err(_) => return Poll::Ready(None),
+        };
+        match shared.queue.pop_front() {
+            Some(event) => {
+                space.notify_one();
+                Poll::Ready(Some(event))
+            }
+            None if shared.ended => Poll::Ready(None),
+            None => {
+                shared.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl Drop for AsyncMarketStream {
+    // runs before the connection closes, so a forwarder waiting for space lets go of the events
+    fn drop(&mut self) {
+        let (state, space) = &*self.shared;
+        if let Ok(mut shared) = state.lock() {
+            shared.dropped = true;
+        }
+        space.notify_all();
+    }
+}
+
+// the market events barter-data has a kind for, as the MarketStreamEvent items barter's engine
+// consumes from the streams of its own exchange connectors
+#[cfg(feature = "barter")]
+pub struct BarterMarketStream {
+    events: AsyncMarketStream
+}
+
+#[cfg(feature = "barter")]
+impl Stream for BarterMarketStream {
+    type Item = MarketStreamEvent<String, DataKind>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.events).poll_next(context) {
+                Poll::Ready(Some(event)) => if let Some(event) = event.to_barter() {
+                    return Poll::Ready(Some(MarketStreamEvent::Item(event)));
+                },
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
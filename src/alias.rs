@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::event::Event;
+use crate::symbol;
+
+#[derive(Debug, Error)]
+pub enum AliasError {
+    #[error("couldn't read alias file {path:?}")]
+    Read { path: PathBuf, #[source] source: io::Error },
+    #[error("line {line}: {reason}")]
+    Line { line: usize, reason: String },
+    #[error("{symbol} is the target of both {first} and {second}")]
+    Ambiguous { symbol: String, first: String, second: String }
+}
+
+// the application's tickers and the TradingView symbols they stand for, e.g. BTCUSD for
+// BINANCE:BTCUSDT. Symbols going in are looked up ignoring case and surrounding space, and every
+// event comes out under the alias again; symbols without an alias pass through as they are
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolAliases {
+    // keyed by the upper cased alias
+    symbols: HashMap<String, String>,
+    aliases: HashMap<String, String>
+}
+
+impl SymbolAliases {
+    // one "ALIAS = EXCHANGE:SYMBOL" per line, # starts a comment
+    pub fn parse(text: &str) -> Result<SymbolAliases, AliasError> {
+        let mut aliases = SymbolAliases::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |reason: String| AliasError::Line { line: index + 1, reason };
+            let (alias, symbol) = line.split_once('=').ok_or_else(|| error(format!("expected ALIAS = SYMBOL, got {:?}", line)))?;
+            let (alias, symbol) = (alias.trim(), symbol.trim());
+            if alias.is_empty() {
+                return Err(error("the alias is empty".to_string()));
+            }
+            symbol::validate(symbol).map_err(|err| error(err.to_string()))?;
+            aliases.insert(alias, symbol)?;
+        }
+        Ok(aliases)
+    }
+
+    pub fn load(path: &Path) -> Result<SymbolAliases, AliasError> {
+        let text = fs::read_to_string(path).map_err(|source| AliasError::Read { path: path.to_path_buf(), source })?;
+        SymbolAliases::parse(&text)
+    }
+
+    // an alias given again points at the later symbol; two aliases for one symbol couldn't be
+    // told apart on the way back
+    pub fn insert(&mut self, alias: &str, symbol: &str) -> Result<(), AliasError> {
+        match self.aliases.get(symbol) {
+            Some(first) if !first.eq_ignore_ascii_case(alias) => return Err(AliasError::Ambiguous {
+                symbol: symbol.to_string(),
+                first: first.clone(),
+                second: alias.to_string()
+            }),
+            _ => {}
+        }
+        if let Some(previous) = self.symbols.insert(alias.to_uppercase(), symbol.to_string()) {
+            self.aliases.remove(&previous);
+        }
+        self.aliases.insert(symbol.to_string(), alias.to_string());
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    // the TradingView symbol to subscribe for what the application asked for
+    pub fn resolve(&self, symbol: &str) -> String {
+        let symbol = symbol.trim();
+        self.symbols.get(&symbol.to_uppercase()).cloned().unwrap_or_else(|| symbol.to_string())
+    }
+
+    // the application's name for a TradingView symbol
+    pub fn alias<'a>(&'a self, symbol: &'a str) -> &'a str {
+        self.aliases.get(symbol).map_or(symbol, String::as_str)
+    }
+
+    fn rename(&self, symbol: &mut String) {
+        if let Some(alias) = self.aliases.get(symbol.as_str()) {
+            symbol.clone_from(alias);
+        }
+    }
+
+    // the event with its symbols under their aliases, including the tags of a news item and
+    // the symbol of a quote_add_symbols result
+    pub fn rewrite(&self, mut event: Event) -> Event {
+        if self.is_empty() {
+            return event;
+        }
+        if let Some(symbol) = event.symbol_mut() {
+            self.rename(symbol);
+        }
+        match event {
+            Event::NewsItem { ref mut item, .. } => item.symbols.iter_mut().for_each(|symbol| self.rename(symbol)),
+            Event::CommandResult { ref command, ref mut target, .. } if command == "quote_add_symbols" => self.rename(target),
+            _ => {}
+        }
+        event
+    }
+}
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::sync::Mutex;
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::http;
+
+pub const ANONYMOUS_TOKEN: &str = "unauthorized_user_token";
+const SIGNIN_URL: &str = "https://www.tradingview.com/accounts/signin/";
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("no credentials to refresh the auth token with")]
+    NoCredentials,
+    #[error("login response parse error")]
+    ResponseParseError(#[source] serde_json::Error),
+    #[error("login failed: {0}")]
+    LoginFailed(String)
+}
+
+impl From<AuthError> for Box<dyn std::error::Error + Send> {
+    fn from(error: AuthError) -> Self {
+        Box::new(error)
+    }
+}
+
+// refresh is called when the server rejects the current token or drops us to delayed data
+pub trait AuthProvider: Send + Sync {
+    fn token(&self) -> Result<String, Box<dyn Error + Send>>;
+    fn refresh(&self) -> Result<String, Box<dyn Error + Send>>;
+}
+
+pub struct Anonymous;
+
+impl AuthProvider for Anonymous {
+    fn token(&self) -> Result<String, Box<dyn Error + Send>> {
+        Ok(ANONYMOUS_TOKEN.to_string())
+    }
+
+    fn refresh(&self) -> Result<String, Box<dyn Error + Send>> {
+        Err(AuthError::NoCredentials.into())
+    }
+}
+
+pub struct StaticToken(pub String);
+
+impl AuthProvider for StaticToken {
+    fn token(&self) -> Result<String, Box<dyn Error + Send>> {
+        Ok(self.0.clone())
+    }
+
+    fn refresh(&self) -> Result<String, Box<dyn Error + Send>> {
+        Err(AuthError::NoCredentials.into())
+    }
+}
+
+// signs in with a username and password, caching the token until it's refreshed
+pub struct Login {
+    username: String,
+    password: String,
+    token: Mutex<Option<String>>
+}
+
+impl Login {
+    pub fn new(username: String, password: String) -> Login {
+        Login {
+            username,
+            password,
+            token: Mutex::new(None)
+        }
+    }
+
+    fn sign_in(&self) -> Result<String, Box<dyn Error + Send>> {
+        let fields = [("username", self.username.as_str()), ("password", self.password.as_str()), ("remember", "on")];
+        let response = http::post_form(SIGNIN_URL, &[("Referer", "https://www.tradingview.com/")], &fields)?;
+        let parsed: Value = serde_json::from_str(&response.body).map_err(AuthError::ResponseParseError)?;
+        match parsed.pointer("/user/auth_token").and_then(Value::as_str) {
+            Some(token) => Ok(token.to_string()),
+            None => {
+                let reason = parsed.get("error").and_then(Value::as_str).unwrap_or("no auth token in response");
+                Err(AuthError::LoginFailed(reason.to_string()).into())
+            }
+        }
+    }
+}
+
+impl AuthProvider for Login {
+    fn token(&self) -> Result<String, Box<dyn Error + Send>> {
+        if let Some(ref token) = *self.token.lock().map_err(|_| AuthError::LoginFailed("token lock poisoned".to_string()))? {
+            return Ok(token.clone());
+        }
+        self.refresh()
+    }
+
+    fn refresh(&self) -> Result<String, Box<dyn Error + Send>> {
+        let token = self.sign_in()?;
+        if let Ok(mut cached) = self.token.lock() {
+            *cached = Some(token.clone());
+        }
+        Ok(token)
+    }
+}
+
+// for tokens that come from somewhere else, e.g. a secrets manager
+pub struct Callback<F>(pub F);
+
+impl<F> AuthProvider for Callback<F>
+where
+    F: Fn() -> Result<String, Box<dyn Error + Send>> + Send + Sync
+{
+    fn token(&self) -> Result<String, Box<dyn Error + Send>> {
+        (self.0)()
+    }
+
+    fn refresh(&self) -> Result<String, Box<dyn Error + Send>> {
+        (self.0)()
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+
+#[cfg(feature = "ndarray")]
+use ndarray::Array1;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Bar {
+    // unix seconds
+    pub time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64
+}
+
+impl Bar {
+    // series entries look like {"i": 0, "v": [time, open, high, low, close, volume]}
+    pub fn from_series_entry(entry: &Value) -> Option<Bar> {
+        let values = entry.get("v")?.as_array()?;
+        Some(Bar {
+            time: values.first()?.as_f64()? as i64,
+            open: values.get(1)?.as_f64()?,
+            high: values.get(2)?.as_f64()?,
+            low: values.get(3)?.as_f64()?,
+            close: values.get(4)?.as_f64()?,
+            // indices and some spreads don't report volume
+            volume: values.get(5).and_then(|volume| volume.as_f64()).unwrap_or(0.0)
+        })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let prices = [self.open, self.high, self.low, self.close];
+        prices.iter().all(|price| price.is_finite())
+            && self.high >= self.low
+            && self.volume.is_finite()
+    }
+
+    // moves the prices, e.g. onto another contract's level
+    pub fn shift(&mut self, offset: f64) {
+        self.open += offset;
+        self.high += offset;
+        self.low += offset;
+        self.close += offset;
+    }
+}
+
+pub fn parse_series_bars(series: &Value) -> Vec<Bar> {
+    match series.get("s").and_then(|entries| entries.as_array()) {
+        Some(entries) => entries.iter().filter_map(Bar::from_series_entry).collect(),
+        None => vec![]
+    }
+}
+
+// bars as aligned columns, row i of each coming from the i-th bar, for ta-lib style libraries
+// that take one slice per input
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Columns {
+    // unix seconds
+    pub time: Vec<i64>,
+    pub open: Vec<f64>,
+    pub high: Vec<f64>,
+    pub low: Vec<f64>,
+    pub close: Vec<f64>,
+    pub volume: Vec<f64>
+}
+
+impl Columns {
+    pub fn with_capacity(capacity: usize) -> Columns {
+        Columns {
+            time: Vec::with_capacity(capacity),
+            open: Vec::with_capacity(capacity),
+            high: Vec::with_capacity(capacity),
+            low: Vec::with_capacity(capacity),
+            close: Vec::with_capacity(capacity),
+            volume: Vec::with_capacity(capacity)
+        }
+    }
+
+    pub fn push(&mut self, bar: &Bar) {
+        self.time.push(bar.time);
+        self.open.push(bar.open);
+        self.high.push(bar.high);
+        self.low.push(bar.low);
+        self.close.push(bar.close);
+        self.volume.push(bar.volume);
+    }
+
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    // moves the columns into arrays without copying them
+    #[cfg(feature = "ndarray")]
+    pub fn into_ndarray(self) -> ArrayColumns {
+        ArrayColumns {
+            time: Array1::from(self.time),
+            open: Array1::from(self.open),
+            high: Array1::from(self.high),
+            low: Array1::from(self.low),
+            close: Array1::from(self.close),
+            volume: Array1::from(self.volume)
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a Bar> for Columns {
+    fn from_iter<I: IntoIterator<Item = &'a Bar>>(bars: I) -> Columns {
+        let bars = bars.into_iter();
+        let mut columns = Columns::with_capacity(bars.size_hint().0);
+        for bar in bars {
+            columns.push(bar);
+        }
+        columns
+    }
+}
+
+#[cfg(feature = "ndarray")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayColumns {
+    pub time: Array1<i64>,
+    pub open: Array1<f64>,
+    pub high: Array1<f64>,
+    pub low: Array1<f64>,
+    pub close: Array1<f64>,
+    pub volume: Array1<f64>
+}
+
+// e.g. bars.to_columns().close for a moving average
+pub trait ToColumns {
+    fn to_columns(&self) -> Columns;
+}
+
+impl ToColumns for [Bar] {
+    fn to_columns(&self) -> Columns {
+        self.iter().collect()
+    }
+}
+
+impl ToColumns for VecDeque<Bar> {
+    fn to_columns(&self) -> Columns {
+        self.iter().collect()
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::bar::Bar;
+
+// open, high, low, close and volume, little endian
+const VALUE_SIZE: usize = 40;
+
+#[derive(Debug, Error)]
+pub enum BarStoreError {
+    #[error("couldn't open bar store {path:?}")]
+    Open { path: String, #[source] source: sled::Error },
+    #[error("bar store error")]
+    Store(#[from] sled::Error),
+    #[error("corrupt bar at {time} in {tree}")]
+    Corrupt { tree: String, time: i64 }
+}
+
+// keys sort like the times they encode, negative ones included
+fn key(time: i64) -> [u8; 8] {
+    ((time as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+fn time_of(key: &[u8]) -> Option<i64> {
+    Some((u64::from_be_bytes(key.try_into().ok()?) ^ (1 << 63)) as i64)
+}
+
+fn encode(bar: &Bar) -> [u8; VALUE_SIZE] {
+    let mut value = [0; VALUE_SIZE];
+    for (chunk, number) in value.chunks_exact_mut(8).zip([bar.open, bar.high, bar.low, bar.close, bar.volume]) {
+        chunk.copy_from_slice(&number.to_le_bytes());
+    }
+    value
+}
+
+fn decode(time: i64, value: &[u8]) -> Option<Bar> {
+    if value.len() != VALUE_SIZE {
+        return None;
+    }
+    let number = |index: usize| value[index * 8..(index + 1) * 8].try_into().ok().map(f64::from_le_bytes);
+    Some(Bar { time, open: number(0)?, high: number(1)?, low: number(2)?, close: number(3)?, volume: number(4)? })
+}
+
+// downloaded bars kept between runs, one sled tree per symbol and interval keyed by bar time,
+// so a later download only needs what came after the newest stored bar
+pub struct BarStore {
+    db: sled::Db
+}
+
+impl BarStore {
+    pub fn open(path: &Path) -> Result<BarStore, BarStoreError> {
+        let db = sled::open(path).map_err(|source| BarStoreError::Open { path: path.display().to_string(), source })?;
+        Ok(BarStore { db })
+    }
+
+    fn tree(&self, symbol: &str, interval: &str) -> Result<sled::Tree, BarStoreError> {
+        Ok(self.db.open_tree(format!("{}@{}", symbol, interval))?)
+    }
+
+    // stored bars at the same time are replaced, e.g. a bar that was still forming last time
+    pub fn insert(&self, symbol: &str, interval: &str, bars: &[Bar]) -> Result<(), BarStoreError> {
+        let tree = self.tree(symbol, interval)?;
+        let mut batch = sled::Batch::default();
+        for bar in bars {
+            batch.insert(&key(bar.time), &encode(bar)[..]);
+        }
+        tree.apply_batch(batch)?;
+        Ok(())
+    }
+
+    // bars from from to to, both included, oldest first
+    pub fn range(&self, symbol: &str, interval: &str, from: i64, to: i64) -> Result<Vec<Bar>, BarStoreError> {
+        let tree = self.tree(symbol, interval)?;
+        let mut bars = vec![];
+        for entry in tree.range(key(from)..=key(to)) {
+            let (key, value) = entry?;
+            let time = time_of(&key).unwrap_or_default();
+            bars.push(decode(time, &value).ok_or_else(|| BarStoreError::Corrupt { tree: format!("{}@{}", symbol, interval), time })?);
+        }
+        Ok(bars)
+    }
+
+    // (oldest, newest) stored bar time
+    pub fn span(&self, symbol: &str, interval: &str) -> Result<Option<(i64, i64)>, BarStoreError> {
+        let tree = self.tree(symbol, interval)?;
+        let first = tree.first()?.and_then(|(key, _)| time_of(&key));
+        let last = tree.last()?.and_then(|(key, _)| time_of(&key));
+        Ok(first.zip(last))
+    }
+
+    // how far back the stored bars of the symbol are complete, i.e. the earliest start a finished
+    // download asked for; kept in the default tree
+    pub fn complete_from(&self, symbol: &str, interval: &str) -> Result<Option<i64>, BarStoreError> {
+        Ok(self.db.get(format!("{}@{}", symbol, interval))?.and_then(|value| time_of(&value)))
+    }
+
+    // stores what a download from from to to brought back and moves the complete_from mark. A
+    // finished download that joins up with the complete bars extends it, one that reaches the
+    // newest bar but leaves a gap before it starts it over at its own from, and an older one
+    // leaves it alone. A cut short one only has its newest pages, so the mark is dropped once
+    // those go past the stored bars
+    pub fn insert_download(&self, symbol: &str, interval: &str, from: i64, to: i64, bars: &[Bar], finished: bool) -> Result<(), BarStoreError> {
+        let span = self.span(symbol, interval)?;
+        self.insert(symbol, interval, bars)?;
+        let mark = format!("{}@{}", symbol, interval);
+        let complete_from = self.complete_from(symbol, interval)?;
+        if !finished {
+            let past_stored = bars.last().is_some_and(|bar| span.is_none_or(|(_, last)| bar.time > last));
+            if past_stored {
+                self.db.remove(mark)?;
+            }
+            return Ok(());
+        }
+        let complete_from = match (complete_from, span) {
+            (_, None) => Some(from),
+            (Some(complete_from), Some((_, last))) if from <= last && to >= complete_from => Some(complete_from.min(from)),
+            (_, Some((_, last))) if to >= last => Some(from),
+            // older than the complete stored bars, which stay complete from where they were
+            (complete_from, Some(_)) => complete_from,
+        };
+        if let Some(complete_from) = complete_from {
+            self.db.insert(mark, &key(complete_from))?;
+        }
+        Ok(())
+    }
+
+    // the stored bars a download from from to to can use and where the server has to take over,
+    // None when the store has all of it; the newest stored bar may have still been forming, so
+    // the server is asked for it again
+    pub fn top_up(&self, symbol: &str, interval: &str, from: i64, to: i64) -> Result<(Vec<Bar>, Option<i64>), BarStoreError> {
+        let fetch_from = match (self.complete_from(symbol, interval)?, self.span(symbol, interval)?) {
+            (Some(complete_from), Some((_, last))) if complete_from <= from => last.max(from),
+            _ => from,
+        };
+        let stored = self.range(symbol, interval, from, (fetch_from - 1).min(to))?;
+        Ok((stored, (fetch_from <= to).then_some(fetch_from)))
+    }
+
+    pub fn flush(&self) -> Result<(), BarStoreError> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// a book that hasn't changed for this long is reported stale
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BookError {
+    #[error("invalid level {price} x {size}")]
+    InvalidLevel { price: f64, size: f64 },
+    // the updates in between are lost, so only a snapshot can repair the book
+    #[error("update {sequence} doesn't follow {last}, waiting for a snapshot")]
+    Gap { last: u64, sequence: u64 },
+    #[error("no snapshot since the last gap")]
+    NeedsSnapshot
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSide {
+    Bid,
+    Ask
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub price: f64,
+    pub size: f64
+}
+
+// one change to the ladder, a size of 0 removes the level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthUpdate {
+    pub side: BookSide,
+    pub price: f64,
+    pub size: f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookStatus {
+    Ok,
+    // nothing on one side or both
+    Empty,
+    // the best bid is above the best ask, an update was missed or applied out of order
+    Crossed,
+    // the best bid and ask are at the same price, which a fast market can show for a moment
+    Locked,
+    // no update for stale_after
+    Stale,
+    // a sequence gap, updates are ignored until the next snapshot
+    NeedsSnapshot
+}
+
+// the best levels of each side, best first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+    pub sequence: Option<u64>
+}
+
+// prices are finite, so total_cmp orders them like numbers
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Price) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Price) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn validate(price: f64, size: f64) -> Result<(), BookError> {
+    if price.is_finite() && price > 0.0 && size.is_finite() && size >= 0.0 {
+        Ok(())
+    } else {
+        Err(BookError::InvalidLevel { price, size })
+    }
+}
+
+// a price ladder per side built from a depth snapshot and the incremental updates after it.
+// Updates carrying a sequence number must follow the last one without gaps
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    pub symbol: String,
+    bids: BTreeMap<Price, f64>,
+    asks: BTreeMap<Price, f64>,
+    sequence: Option<u64>,
+    updated: Option<Instant>,
+    stale_after: Duration,
+    needs_snapshot: bool
+}
+
+impl OrderBook {
+    pub fn new(symbol: &str, stale_after: Duration) -> OrderBook {
+        OrderBook {
+            symbol: symbol.to_string(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            sequence: None,
+            updated: None,
+            stale_after,
+            needs_snapshot: false
+        }
+    }
+
+    // replaces both sides; levels with a size of 0 are left out
+    pub fn apply_snapshot(&mut self, bids: &[Level], asks: &[Level], sequence: Option<u64>) -> Result<(), BookError> {
+        for level in bids.iter().chain(asks) {
+            validate(level.price, level.size)?;
+        }
+        let ladder = |levels: &[Level]| levels.iter().filter(|level| level.size > 0.0).map(|level| (Price(level.price), level.size)).collect();
+        self.bids = ladder(bids);
+        self.asks = ladder(asks);
+        self.sequence = sequence;
+        self.updated = Some(Instant::now());
+        self.needs_snapshot = false;
+        Ok(())
+    }
+
+    // all of the updates or none of them
+    pub fn apply(&mut self, updates: &[DepthUpdate], sequence: Option<u64>) -> Result<(), BookError> {
+        if self.needs_snapshot {
+            return Err(BookError::NeedsSnapshot);
+        }
+        if let (Some(last), Some(sequence)) = (self.sequence, sequence) {
+            // already applied, e.g. an update the snapshot included
+            if sequence <= last {
+                return Ok(());
+            }
+            if sequence != last + 1 {
+                self.needs_snapshot = true;
+                return Err(BookError::Gap { last, sequence });
+            }
+        }
+        for update in updates {
+            validate(update.price, update.size)?;
+        }
+        for update in updates {
+            let ladder = match update.side {
+                BookSide::Bid => &mut self.bids,
+                BookSide::Ask => &mut self.asks,
+            };
+            if update.size == 0.0 {
+                ladder.remove(&Price(update.price));
+            } else {
+                ladder.insert(Price(update.price), update.size);
+            }
+        }
+        self.sequence = sequence.or(self.sequence);
+        self.updated = Some(Instant::now());
+        Ok(())
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids.iter().next_back().map(|(price, &size)| Level { price: price.0, size })
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks.iter().next().map(|(price, &size)| Level { price: price.0, size })
+    }
+
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_ask()?.price + self.best_bid()?.price) / 2.0)
+    }
+
+    pub fn is_crossed(&self) -> bool {
+        self.spread().is_some_and(|spread| spread < 0.0)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.spread() == Some(0.0)
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.updated.is_none_or(|updated| updated.elapsed() > self.stale_after)
+    }
+
+    // the most pressing problem first
+    pub fn status(&self) -> BookStatus {
+        if self.needs_snapshot {
+            BookStatus::NeedsSnapshot
+        } else if self.is_stale() {
+            BookStatus::Stale
+        } else if self.bids.is_empty() || self.asks.is_empty() {
+            BookStatus::Empty
+        } else if self.is_crossed() {
+            BookStatus::Crossed
+        } else if self.is_locked() {
+            BookStatus::Locked
+        } else {
+            BookStatus::Ok
+        }
+    }
+
+    // up to levels of each side
+    pub fn depth(&self, levels: usize) -> DepthSnapshot {
+        let level = |(price, &size): (&Price, &f64)| Level { price: price.0, size };
+        DepthSnapshot {
+            bids: self.bids.iter().rev().take(levels).map(level).collect(),
+            asks: self.asks.iter().take(levels).map(level).collect(),
+            sequence: self.sequence
+        }
+    }
+
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use crate::accounts::Accounts;
+use crate::auth::AuthProvider;
+use crate::scanner::{self, ScannerError};
+use crate::tradingview_api::Mode;
+
+// kept well under what a single quote session handles before updates start lagging
+pub const MAX_SYMBOLS_PER_CONNECTION: usize = 500;
+
+// as few connections as the limit allows, with the symbols spread evenly over them
+pub fn partition(symbols: &[String], per_connection: usize) -> Vec<Vec<String>> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let connections = symbols.len().div_ceil(per_connection.max(1));
+    symbols.chunks(symbols.len().div_ceil(connections)).map(<[String]>::to_vec).collect()
+}
+
+// one quote mode per connection for every symbol the exchange lists, e.g. BINANCE; each
+// connection still adds its symbols in frames of QUOTE_SYMBOLS_PER_MESSAGE
+pub fn exchange_subscriptions(exchange: &str, per_connection: usize) -> Result<Vec<Mode>, ScannerError> {
+    let symbols = scanner::exchange_symbols(exchange)?;
+    Ok(partition(&symbols, per_connection).into_iter().map(Mode::Quotes).collect())
+}
+
+// each account's symbols on connections of its own, with the auth they should log in with;
+// None for symbols no account serves
+pub fn account_subscriptions(symbols: &[String], per_connection: usize, accounts: &Accounts) -> Vec<(Option<Arc<dyn AuthProvider>>, Mode)> {
+    accounts.assign(symbols).into_iter().flat_map(|group| {
+        let auth = group.auth;
+        partition(&group.symbols, per_connection).into_iter().map(move |symbols| (auth.clone(), Mode::Quotes(symbols)))
+    }).collect()
+}
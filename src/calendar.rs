@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::event::Event;
+use crate::http;
+use crate::poll::Poller;
+
+const EVENTS_URL: &str = "https://economic-calendar.tradingview.com/events";
+
+#[derive(Debug, Error)]
+pub enum CalendarError {
+    #[error("calendar request failed")]
+    Request(#[source] Box<dyn Error + Send>),
+    #[error("unexpected HTTP status {0}")]
+    Status(u16),
+    #[error("calendar response parse error")]
+    Parse(#[source] serde_json::Error)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Importance {
+    Low,
+    Medium,
+    // CPI, FOMC, NFP and the like
+    High
+}
+
+impl Importance {
+    // the calendar rates events -1, 0 or 1
+    fn from_level(level: i64) -> Importance {
+        match level {
+            level if level > 0 => Importance::High,
+            0 => Importance::Medium,
+            _ => Importance::Low,
+        }
+    }
+}
+
+impl std::str::FromStr for Importance {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Importance, String> {
+        match value {
+            "low" => Ok(Importance::Low),
+            "medium" => Ok(Importance::Medium),
+            "high" => Ok(Importance::High),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EconomicEvent {
+    pub id: String,
+    pub title: String,
+    // two letter code, e.g. US or EU
+    pub country: String,
+    pub currency: Option<String>,
+    pub importance: Importance,
+    pub time: i64,
+    // None until released
+    pub actual: Option<f64>,
+    pub forecast: Option<f64>,
+    pub previous: Option<f64>,
+    // e.g. "%"
+    pub unit: Option<String>
+}
+
+impl EconomicEvent {
+    // events look like {"id": "...", "title": "Inflation Rate YoY", "country": "US", "currency": "USD",
+    // "importance": 1, "date": "2024-04-10T12:30:00.000Z", "actual": null, "forecast": 3.4, ...}
+    fn from_json(event: &Value) -> Option<EconomicEvent> {
+        let text = |name: &str| event.get(name).and_then(Value::as_str).filter(|text| !text.is_empty()).map(str::to_string);
+        let number = |name: &str| event.get(name).and_then(Value::as_f64);
+        Some(EconomicEvent {
+            id: text("id")?,
+            title: text("title")?,
+            country: text("country").unwrap_or_default(),
+            currency: text("currency"),
+            importance: Importance::from_level(event.get("importance").and_then(Value::as_i64).unwrap_or(-1)),
+            time: DateTime::parse_from_rfc3339(&text("date")?).ok()?.timestamp(),
+            actual: number("actual"),
+            forecast: number("forecast"),
+            previous: number("previous"),
+            unit: text("unit")
+        })
+    }
+}
+
+// events between from and to, every country when countries is empty
+pub fn fetch_events(from: DateTime<Utc>, to: DateTime<Utc>, countries: &[String]) -> Result<Vec<EconomicEvent>, CalendarError> {
+    let from = from.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let to = to.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let countries = countries.join(",");
+    let mut params = vec![("from", from.as_str()), ("to", to.as_str())];
+    if !countries.is_empty() {
+        params.push(("countries", countries.as_str()));
+    }
+    let url = http::with_query(EVENTS_URL, &params);
+    // the api turns away requests that don't look like they come from the website
+    let response = http::get(&url, &[("Origin", "https://www.tradingview.com"), ("Referer", "https://www.tradingview.com/")]).map_err(CalendarError::Request)?;
+    if !(200..300).contains(&response.status) {
+        return Err(CalendarError::Status(response.status));
+    }
+    let parsed: Value = serde_json::from_str(&response.body).map_err(CalendarError::Parse)?;
+    let events = parsed.get("result").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let mut events: Vec<EconomicEvent> = events.iter().filter_map(EconomicEvent::from_json).collect();
+    events.sort_by_key(|event| event.time);
+    Ok(events)
+}
+
+#[derive(Debug, Clone)]
+pub struct CalendarOptions {
+    pub every: Duration,
+    // how far ahead to look for releases
+    pub lookahead: Duration,
+    // empty for every country
+    pub countries: Vec<String>,
+    pub min_importance: Importance
+}
+
+impl Default for CalendarOptions {
+    fn default() -> CalendarOptions {
+        CalendarOptions {
+            every: Duration::from_secs(900),
+            lookahead: Duration::from_secs(7 * 86_400),
+            countries: vec![],
+            min_importance: Importance::Medium
+        }
+    }
+}
+
+// reports each event once, and again whenever it changes, e.g. when the actual figure comes out
+#[derive(Default)]
+pub struct CalendarTracker {
+    seen: HashMap<String, EconomicEvent>
+}
+
+impl CalendarTracker {
+    pub fn poll(&mut self, options: &CalendarOptions) -> Result<Vec<EconomicEvent>, CalendarError> {
+        let now = Utc::now();
+        let lookahead = chrono::Duration::from_std(options.lookahead).unwrap_or(chrono::Duration::MAX);
+        // the last day too, so a release that just came out gets its actual figure
+        let from = now - chrono::Duration::days(1);
+        let events = fetch_events(from, now.checked_add_signed(lookahead).unwrap_or(now), &options.countries)?;
+        let changed: Vec<EconomicEvent> = events.into_iter()
+            .filter(|event| event.importance >= options.min_importance)
+            .filter(|event| self.seen.get(&event.id) != Some(event))
+            .collect();
+        for event in changed.iter() {
+            self.seen.insert(event.id.clone(), event.clone());
+        }
+        // past events can't change any more once they drop out of the window
+        let cutoff = from.timestamp();
+        self.seen.retain(|_, event| event.time >= cutoff);
+        Ok(changed)
+    }
+}
+
+// polls the calendar for the client, its events go to the first connection attached
+pub fn poller(options: CalendarOptions) -> Poller {
+    let mut tracker = CalendarTracker::default();
+    Poller::spawn(options.every, move |_| match tracker.poll(&options) {
+        Ok(events) => events.into_iter().map(|event| Event::EconomicEvent { event }).collect(),
+        Err(err) => {
+            warn!("couldn't fetch the economic calendar: {}", err);
+            vec![]
+        }
+    })
+}
@@ -0,0 +1,85 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::bar::Bar;
+
+#[derive(Default)]
+pub struct Merged {
+    pub bars: Vec<Bar>,
+    // (previous, revised) pairs for bars older than the latest one
+    pub corrections: Vec<(Bar, Bar)>
+}
+
+// bars seen this session per symbol, keyed by bar time; the oldest are evicted past max_bars
+pub struct CandleCache {
+    series: HashMap<String, BTreeMap<i64, Bar>>,
+    max_bars: usize
+}
+
+impl CandleCache {
+    pub fn new(max_bars: usize) -> CandleCache {
+        CandleCache {
+            series: HashMap::new(),
+            max_bars
+        }
+    }
+
+    // pagination and live updates overlap, so batches get sorted and anything already
+    // seen unchanged is dropped; the latest bar changing is just the live candle ticking
+    pub fn merge(&mut self, symbol: &str, mut bars: Vec<Bar>) -> Merged {
+        bars.sort_by_key(|bar| bar.time);
+        let series = self.series.entry(symbol.to_string()).or_default();
+        let mut merged = Merged::default();
+        for bar in bars {
+            let latest_time = series.keys().next_back().copied();
+            match series.insert(bar.time, bar.clone()) {
+                None => merged.bars.push(bar),
+                Some(previous) if previous == bar => {},
+                Some(_) if latest_time == Some(bar.time) => merged.bars.push(bar),
+                Some(previous) => merged.corrections.push((previous, bar)),
+            }
+        }
+        while series.len() > self.max_bars {
+            series.pop_first();
+        }
+        merged
+    }
+
+    // bars from `from` on, preceded by up to lookback older ones, oldest first
+    pub fn window(&self, symbol: &str, from: i64, lookback: usize) -> Vec<Bar> {
+        let series = match self.series.get(symbol) {
+            Some(series) => series,
+            None => return vec![],
+        };
+        let mut bars: Vec<Bar> = series.range(..from).rev().take(lookback).map(|(_, bar)| bar.clone()).collect();
+        bars.reverse();
+        bars.extend(series.range(from..).map(|(_, bar)| bar.clone()));
+        bars
+    }
+
+    // after a roll the new contract trades at a different level: the gap to the cached bars of
+    // the old one at the newest bar where the two overlap, with that bar's time; None until an
+    // overlapping bar arrives
+    pub fn roll_offset(&self, symbol: &str, bars: &[Bar]) -> Option<(i64, f64)> {
+        let series = self.series.get(symbol)?;
+        bars.iter().rev().find_map(|bar| series.get(&bar.time).map(|cached| (bar.time, bar.close - cached.close)))
+    }
+
+    // every cached bar of the symbol, oldest first
+    pub fn bars(&self, symbol: &str) -> Vec<Bar> {
+        self.series.get(symbol).map(|series| series.values().cloned().collect()).unwrap_or_default()
+    }
+
+    // moves the symbol's cached bars by the offset, as (previous, shifted) pairs for consumers
+    // holding the old ones
+    pub fn shift(&mut self, symbol: &str, offset: f64) -> Vec<(Bar, Bar)> {
+        let series = match self.series.get_mut(symbol) {
+            Some(series) => series,
+            None => return vec![],
+        };
+        series.values_mut().map(|bar| {
+            let previous = bar.clone();
+            bar.shift(offset);
+            (previous, bar.clone())
+        }).collect()
+    }
+}
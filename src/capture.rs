@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::json;
+
+pub const DEFAULT_LIMIT: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("couldn't read capture file {path:?}")]
+    Read { path: PathBuf, #[source] source: io::Error },
+    #[error("couldn't write capture file {path:?}")]
+    Write { path: PathBuf, #[source] source: io::Error }
+}
+
+// one line of the capture file
+#[derive(Serialize)]
+struct Entry<'a> {
+    // unix milliseconds
+    time: i64,
+    // the "m" of the message, when it has one
+    method: Option<&'a str>,
+    // the chart or quote session it was sent for, when p.0 names one
+    session: Option<&'a str>,
+    symbol: &'a str,
+    raw: &'a str
+}
+
+struct Ring {
+    // the last limit lines, oldest first
+    lines: VecDeque<String>,
+    // lines in the file, which may run up to twice the limit before it's cut back
+    written: usize
+}
+
+// messages no MessageType matches, one json line each with what they came with, so new variants
+// can be worked out from real traffic. Only the last limit entries are kept
+pub struct UnknownCapture {
+    path: PathBuf,
+    limit: usize,
+    ring: Mutex<Ring>
+}
+
+impl UnknownCapture {
+    // keeps the entries of earlier runs
+    pub fn open(path: &Path, limit: usize) -> Result<UnknownCapture, CaptureError> {
+        let limit = limit.max(1);
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(source) => return Err(CaptureError::Read { path: path.to_path_buf(), source }),
+        };
+        let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+        let ring = Ring {
+            lines: lines[lines.len().saturating_sub(limit)..].iter().map(|line| line.to_string()).collect(),
+            written: lines.len()
+        };
+        Ok(UnknownCapture { path: path.to_path_buf(), limit, ring: Mutex::new(ring) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn capture(&self, raw: &str, symbol: &str) -> Result<(), CaptureError> {
+        let parsed = json::parse(raw).ok();
+        let method = parsed.as_ref().and_then(|parsed| parsed.get("m")).and_then(Value::as_str);
+        let session = parsed.as_ref().and_then(|parsed| parsed.get("p")?.get(0)).and_then(Value::as_str);
+        let entry = Entry {
+            time: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as i64),
+            method,
+            session,
+            symbol,
+            raw
+        };
+        let line = serde_json::to_string(&entry).map_err(|err| self.write_error(io::Error::other(err)))?;
+        let mut ring = match self.ring.lock() {
+            Ok(ring) => ring,
+            Err(_) => return Ok(()),
+        };
+        ring.lines.push_back(line);
+        if ring.lines.len() > self.limit {
+            ring.lines.pop_front();
+        }
+        // appending is cheap, so the file is only cut back to the ring once it's twice as long
+        if ring.written >= self.limit * 2 {
+            self.rewrite(&ring.lines)?;
+            ring.written = ring.lines.len();
+        } else {
+            self.append(ring.lines.back().map_or("", String::as_str))?;
+            ring.written += 1;
+        }
+        Ok(())
+    }
+
+    fn append(&self, line: &str) -> Result<(), CaptureError> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).map_err(|err| self.write_error(err))?;
+        writeln!(file, "{}", line).map_err(|err| self.write_error(err))
+    }
+
+    // written aside and renamed so a crash mid-write can't lose the earlier entries
+    fn rewrite(&self, lines: &VecDeque<String>) -> Result<(), CaptureError> {
+        let partial = self.path.with_extension("partial");
+        let write = || -> io::Result<()> {
+            let mut file = File::create(&partial)?;
+            for line in lines {
+                writeln!(file, "{}", line)?;
+            }
+            file.sync_all()?;
+            fs::rename(&partial, &self.path)
+        };
+        write().map_err(|err| self.write_error(err))
+    }
+
+    fn write_error(&self, source: io::Error) -> CaptureError {
+        CaptureError::Write { path: self.path.clone(), source }
+    }
+}
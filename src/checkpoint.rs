@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::bar::Bar;
+use crate::quote::QuoteField;
+
+// newest bar seen per symbol, kept across reconnects so gaps can be backfilled
+#[derive(Default)]
+pub struct Checkpoints {
+    last_bar_times: Mutex<HashMap<String, i64>>,
+    // the quote symbols after the last SetQuoteSymbols, None until one was applied
+    quote_symbols: Mutex<Option<Vec<String>>>,
+    // the quote fields after the last SetQuoteFields
+    quote_fields: Mutex<Option<Vec<QuoteField>>>,
+    // the contract each continuous symbol followed when it was last resolved
+    contracts: Mutex<HashMap<String, String>>,
+    // a continuous symbol's cached bars per series, keyed like last_bar_times, from the end of
+    // its last connection; the next one stitches them if the symbol rolled in between
+    held_bars: Mutex<HashMap<String, Vec<Bar>>>,
+    // (roll time, offset) per stitched symbol, for bars of the old contracts that come again
+    stitches: Mutex<HashMap<String, Vec<(i64, f64)>>>
+}
+
+impl Checkpoints {
+    pub fn last_bar_time(&self, symbol: &str) -> Option<i64> {
+        self.last_bar_times.lock().ok()?.get(symbol).copied()
+    }
+
+    pub fn record(&self, symbol: &str, time: i64) {
+        if let Ok(mut last_bar_times) = self.last_bar_times.lock() {
+            let last_bar_time = last_bar_times.entry(symbol.to_string()).or_insert(time);
+            *last_bar_time = (*last_bar_time).max(time);
+        }
+    }
+
+    // every symbol's newest bar, sorted so exported state diffs cleanly
+    pub fn last_bar_times(&self) -> BTreeMap<String, i64> {
+        self.last_bar_times.lock().map(|last_bar_times| last_bar_times.iter().map(|(symbol, time)| (symbol.clone(), *time)).collect()).unwrap_or_default()
+    }
+
+    // merged like record, so restoring older state never moves a checkpoint back
+    pub fn restore(&self, last_bar_times: &BTreeMap<String, i64>) {
+        for (symbol, &time) in last_bar_times {
+            self.record(symbol, time);
+        }
+    }
+
+    pub fn quote_symbols(&self) -> Option<Vec<String>> {
+        self.quote_symbols.lock().ok()?.clone()
+    }
+
+    pub fn record_quote_symbols(&self, symbols: &[String]) {
+        if let Ok(mut quote_symbols) = self.quote_symbols.lock() {
+            *quote_symbols = Some(symbols.to_vec());
+        }
+    }
+
+    pub fn quote_fields(&self) -> Option<Vec<QuoteField>> {
+        self.quote_fields.lock().ok()?.clone()
+    }
+
+    pub fn record_quote_fields(&self, fields: &[QuoteField]) {
+        if let Ok(mut quote_fields) = self.quote_fields.lock() {
+            *quote_fields = Some(fields.to_vec());
+        }
+    }
+
+    // the contract the symbol followed before, when it was another one
+    pub fn record_contract(&self, symbol: &str, contract: &str) -> Option<String> {
+        let mut contracts = self.contracts.lock().ok()?;
+        contracts.insert(symbol.to_string(), contract.to_string()).filter(|previous| previous != contract)
+    }
+
+    pub fn contracts(&self) -> BTreeMap<String, String> {
+        self.contracts.lock().map(|contracts| contracts.iter().map(|(symbol, contract)| (symbol.clone(), contract.clone())).collect()).unwrap_or_default()
+    }
+
+    // a contract resolved since stays, restored state is older
+    pub fn restore_contracts(&self, contracts: &BTreeMap<String, String>) {
+        if let Ok(mut current) = self.contracts.lock() {
+            for (symbol, contract) in contracts {
+                current.entry(symbol.clone()).or_insert_with(|| contract.clone());
+            }
+        }
+    }
+
+    pub fn hold_bars(&self, key: &str, bars: Vec<Bar>) {
+        if let Ok(mut held_bars) = self.held_bars.lock() {
+            held_bars.insert(key.to_string(), bars);
+        }
+    }
+
+    pub fn take_held_bars(&self, key: &str) -> Vec<Bar> {
+        self.held_bars.lock().ok().and_then(|mut held_bars| held_bars.remove(key)).unwrap_or_default()
+    }
+
+    pub fn record_stitch(&self, symbol: &str, time: i64, offset: f64) {
+        if let Ok(mut stitches) = self.stitches.lock() {
+            stitches.entry(symbol.to_string()).or_default().push((time, offset));
+        }
+    }
+
+    // how far a bar of the symbol at time moves, by every roll stitched after it
+    pub fn stitch_offset(&self, symbol: &str, time: i64) -> f64 {
+        let stitches = match self.stitches.lock() {
+            Ok(stitches) => stitches,
+            Err(_) => return 0.0,
+        };
+        stitches.get(symbol).map_or(0.0, |rolls| rolls.iter().filter(|(rolled_at, _)| time < *rolled_at).map(|(_, offset)| offset).sum())
+    }
+}
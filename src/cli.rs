@@ -0,0 +1,813 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::LevelFilter;
+use tradingview_websocket::accounts::AccountSpec;
+use tradingview_websocket::alias::SymbolAliases;
+use tradingview_websocket::bulk;
+use tradingview_websocket::calendar::CalendarOptions;
+use tradingview_websocket::connect_script::ConnectScript;
+use tradingview_websocket::duration;
+use tradingview_websocket::hotlist::Hotlist;
+use tradingview_websocket::capture;
+use tradingview_websocket::fault::FaultConfig;
+use tradingview_websocket::filter::EventFilter;
+use tradingview_websocket::indicators::Indicator;
+use tradingview_websocket::invariants::InvariantMode;
+use tradingview_websocket::keepalive::PingReply;
+use tradingview_websocket::quote::QuoteField;
+use tradingview_websocket::replay::{ReplaySpeed, StepMode};
+use tradingview_websocket::scanner;
+use tradingview_websocket::series::{Adjustment, ChartStyle};
+use tradingview_websocket::sink::FlushPolicy;
+use tradingview_websocket::study::StudySpec;
+use tradingview_websocket::symbol::{self, SymbolError};
+use tradingview_websocket::symbol_info;
+use tradingview_websocket::timestamp::TimeFormat;
+use tradingview_websocket::tradingview_api::{ErrorPolicy, MemoryLimits, ParseMode};
+
+#[derive(Debug)]
+pub enum CliError {
+    MissingSymbol,
+    MissingInput,
+    MissingOutput,
+    MissingValue(String),
+    UnknownArgument(String),
+    InvalidDate(String),
+    InvalidLogLevel(String),
+    InvalidLogFormat(String),
+    InvalidTimezone(String),
+    InvalidTimeFormat(String),
+    InvalidRoute(String),
+    InvalidNumber(String),
+    InvalidDuration(String),
+    InvalidErrorPolicy(String),
+    InvalidSymbol(SymbolError),
+    InvalidSize(String),
+    InvalidBacklogPolicy(String),
+    InvalidParseMode(String),
+    InvalidQuoteField(String),
+    InvalidChartStyle(String),
+    InvalidAdjustment(String),
+    InvalidStudy(String),
+    InvalidIndicator(String),
+    InvalidReplaySpeed(String),
+    InvalidStepMode(String),
+    InvalidHotlist(String),
+    InvalidAccount(String),
+    InvalidImportance(String),
+    InvalidFaults(String),
+    InvalidAliases(String),
+    InvalidConnectScript(String),
+    InvalidFilter(String),
+    InvalidPingReply(String),
+    InvalidInvariants(String),
+    UnknownExchange(String)
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            CliError::MissingSymbol => write!(f, "Missing symbol"),
+            CliError::MissingInput => write!(f, "Missing --input"),
+            CliError::MissingOutput => write!(f, "Missing --out"),
+            CliError::MissingValue(ref flag) => write!(f, "Missing value for {}", flag),
+            CliError::UnknownArgument(ref argument) => write!(f, "Unknown argument: {}", argument),
+            CliError::InvalidDate(ref date) => write!(f, "Invalid date: {} (expected YYYY-MM-DD or now)", date),
+            CliError::InvalidLogFormat(ref format) => write!(f, "Invalid log format: {} (expected text or json)", format),
+            CliError::InvalidLogLevel(ref level) => write!(f, "Invalid log level: {} (expected off, error, warn, info, debug or trace)", level),
+            CliError::InvalidTimezone(ref tz) => write!(f, "Invalid timezone: {} (expected an IANA name like America/Chicago)", tz),
+            CliError::InvalidTimeFormat(ref format) => write!(f, "Invalid time format: {} (expected rfc3339, unix or ms)", format),
+            CliError::InvalidRoute(ref route) => write!(f, "Invalid route: {} (expected SYMBOL=PATH)", route),
+            CliError::InvalidNumber(ref value) => write!(f, "Invalid number: {}", value),
+            CliError::InvalidDuration(ref value) => write!(f, "Invalid duration: {} (expected e.g. 90, 60s, 5m or 1h)", value),
+            CliError::InvalidErrorPolicy(ref value) => write!(f, "Invalid error policy: {} (expected ignore, drop-session or reconnect)", value),
+            CliError::InvalidSymbol(ref err) => write!(f, "Invalid symbol: {}", err),
+            CliError::InvalidSize(ref value) => write!(f, "Invalid size: {} (expected e.g. 65536, 512K, 16M or 1G)", value),
+            CliError::InvalidBacklogPolicy(ref value) => write!(f, "Invalid backlog policy: {} (expected block, drop, fail or conflate)", value),
+            CliError::InvalidParseMode(ref value) => write!(f, "Invalid parse mode: {} (expected strict or lenient)", value),
+            CliError::InvalidQuoteField(ref value) => write!(f, "Invalid quote field: {} (see the fields command)", value),
+            CliError::UnknownExchange(ref value) => write!(f, "Unknown exchange: {} (the scanner has no market for it)", value),
+            CliError::InvalidImportance(ref value) => write!(f, "Invalid importance: {} (expected low, medium or high)", value),
+            CliError::InvalidFilter(ref value) => write!(f, "Invalid filter: {} (expected e.g. symbols=BINANCE:*,events=quote+bar_closed,fields=lp,every=1s)", value),
+            CliError::InvalidPingReply(ref value) => write!(f, "Invalid ping reply: {} (expected immediate, coalesce=DURATION or jitter=DURATION, e.g. jitter=200ms)", value),
+            CliError::InvalidInvariants(ref value) => write!(f, "Invalid invariant mode: {} (expected off, report or assert)", value),
+            CliError::InvalidAliases(ref reason) => write!(f, "Invalid aliases: {}", reason),
+            CliError::InvalidConnectScript(ref reason) => write!(f, "Invalid connect script: {}", reason),
+            CliError::InvalidFaults(ref value) => write!(f, "Invalid faults: {} (expected e.g. drop=0.01,duplicate=0.01,truncate=0.01,delay=0.05:200ms,disconnect=0.001,split=0.1,seed=7)", value),
+            CliError::InvalidAccount(ref value) => write!(f, "Invalid account: {} (expected NAME or NAME=EXCHANGE,EXCHANGE)", value),
+            CliError::InvalidHotlist(ref value) => write!(f, "Invalid hotlist: {} (expected gainers, losers, volume or active:EXCHANGE[:COUNT])", value),
+            CliError::InvalidReplaySpeed(ref value) => write!(f, "Invalid replay speed: {} (expected e.g. x1, x10 or max)", value),
+            CliError::InvalidStepMode(ref value) => write!(f, "Invalid step mode: {} (expected timer or ack)", value),
+            CliError::InvalidIndicator(ref value) => write!(f, "Invalid indicator: {} (expected sma:N, ema:N, rsi:N or vwap)", value),
+            CliError::InvalidStudy(ref value) => write!(f, "Invalid study: {} (expected ID=SCRIPT or ID=SCRIPT@PARENT)", value),
+            CliError::InvalidAdjustment(ref value) => write!(f, "Invalid adjustment: {} (expected raw, splits or dividends)", value),
+            CliError::InvalidChartStyle(ref value) => write!(f, "Invalid chart style: {} (expected candles, heikin-ashi, renko:BOX, range:SIZE or line-break[:LINES])", value),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+pub struct DownloadArgs {
+    pub symbol: String,
+    pub interval: String,
+    pub from: i64,
+    pub to: i64,
+    pub out: Option<String>,
+    // sled database the bars are kept in, topped up instead of downloaded again
+    pub store: Option<String>
+}
+
+pub struct PlotArgs {
+    pub input: String,
+    pub out: String,
+    pub last: Option<usize>,
+    pub sma: Vec<usize>,
+    // StudyValues events as json lines, e.g. from stream --study-out
+    pub studies: Option<String>,
+    pub width: u32,
+    pub height: u32
+}
+
+pub struct BenchArgs {
+    pub symbols: usize,
+    pub symbols_file: Option<String>,
+    pub duration: Duration,
+    pub connections: usize
+}
+
+pub struct ReplayArgs {
+    pub input: String,
+    pub interval: String,
+    pub speed: ReplaySpeed,
+    pub step_mode: StepMode
+}
+
+pub struct HotlistArgs {
+    pub hotlist: Hotlist,
+    pub refresh: Duration
+}
+
+pub struct ExchangeArgs {
+    pub exchange: String,
+    pub per_connection: usize
+}
+
+pub struct QuoteArgs {
+    pub symbol: String,
+    pub fields: Vec<QuoteField>
+}
+
+pub enum Command {
+    Stream,
+    Download(DownloadArgs),
+    Fields,
+    // the json schema of serialized events
+    Schema,
+    Plot(PlotArgs),
+    Bench(BenchArgs),
+    Quote(QuoteArgs),
+    Replay(ReplayArgs),
+    // all saved lists, or the members of the named one
+    Watchlists(Option<String>),
+    Hotlist(HotlistArgs),
+    Exchange(ExchangeArgs),
+    // metadata from the symbol cache, looked up through symbol search when missing
+    SymbolInfo(Vec<String>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    // one object per line with the span fields, for log pipelines
+    Json
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<LogFormat, String> {
+        match value {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+pub struct Cli {
+    pub command: Command,
+    pub dry_run: bool,
+    pub log_level: LevelFilter,
+    pub log_format: LogFormat,
+    pub daemon: bool,
+    pub pid_file: String,
+    // where a daemon's stdout and stderr go, nowhere without it
+    pub log_file: Option<String>,
+    pub restart: bool,
+    pub tz: Tz,
+    pub time_format: TimeFormat,
+    pub routes: Vec<(String, String)>,
+    pub study_out: Option<String>,
+    pub error_policy: ErrorPolicy,
+    pub parse_mode: ParseMode,
+    pub heartbeat_timeout: Option<Duration>,
+    pub command_timeout: Option<Duration>,
+    pub emit_corrections: bool,
+    pub validate_bars: bool,
+    pub auth_token: Option<String>,
+    pub limits: MemoryLimits,
+    pub propagate_panics: bool,
+    pub parse_workers: Option<usize>,
+    pub flush_policy: FlushPolicy,
+    pub chart_style: ChartStyle,
+    pub currency: Option<String>,
+    pub unit: Option<String>,
+    pub adjustment: Option<Adjustment>,
+    pub back_adjust: bool,
+    pub stitch_rolls: bool,
+    pub extra_intervals: Vec<String>,
+    pub studies: Vec<StudySpec>,
+    pub corporate_events: bool,
+    pub widget: bool,
+    pub trades: bool,
+    pub local_indicators: Vec<Indicator>,
+    pub resample_intervals: Vec<String>,
+    pub session_stats_every: Option<Duration>,
+    // message, byte and per-symbol update rates of each connection
+    pub stats_every: Option<Duration>,
+    pub session_anchors: Vec<i64>,
+    pub news_every: Option<Duration>,
+    pub calendar: Option<CalendarOptions>,
+    // fields reported in Quote events
+    pub quote_fields: Vec<QuoteField>,
+    // fields reported as QuoteFieldChanged events when they move
+    pub quote_changes: Vec<QuoteField>,
+    pub symbol_cache: Option<String>,
+    pub symbol_cache_ttl: Duration,
+    // json lines of the messages the parser doesn't know, the last capture_limit of them
+    pub capture_unknown: Option<String>,
+    pub capture_limit: usize,
+    // subscriptions and bar checkpoints restored at start and written back on shutdown
+    pub state: Option<String>,
+    // from the --aliases file
+    pub aliases: Option<Arc<SymbolAliases>>,
+    // applied in order, the first one matching a symbol decides
+    pub filters: Vec<EventFilter>,
+    pub clock_events: bool,
+    pub normalize_times: bool,
+    pub ping_reply: PingReply,
+    pub invariants: InvariantMode,
+    // replaces the stream's built-in sessions
+    pub connect_script: Option<ConnectScript>,
+    // host:port of the /healthz endpoint, and how long a session may go quiet before it's unhealthy
+    pub health_addr: Option<String>,
+    pub health_max_age: Option<Duration>,
+    // mangles incoming frames, for soak runs against the reconnect and gap filling paths
+    pub faults: Option<FaultConfig>,
+    // logins read from TRADINGVIEW_TOKEN_<NAME> or TRADINGVIEW_USERNAME_<NAME>/TRADINGVIEW_PASSWORD_<NAME>
+    pub accounts: Vec<AccountSpec>
+}
+
+// dates are midnight in the output timezone
+fn parse_date(value: &str, tz: Tz) -> Result<i64, CliError> {
+    if value == "now" {
+        return Ok(Utc::now().timestamp());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| CliError::InvalidDate(value.to_string()))?;
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is valid");
+    let local = tz.from_local_datetime(&midnight).earliest().ok_or_else(|| CliError::InvalidDate(value.to_string()))?;
+    Ok(local.timestamp())
+}
+
+// the io or json error is the useful part when a file can't be loaded
+fn with_source(err: &dyn std::error::Error) -> String {
+    match err.source() {
+        Some(source) => format!("{}: {}", err, source),
+        None => err.to_string(),
+    }
+}
+
+fn load_aliases(path: &str) -> Result<Arc<SymbolAliases>, CliError> {
+    SymbolAliases::load(Path::new(path)).map(Arc::new).map_err(|err| CliError::InvalidAliases(with_source(&err)))
+}
+
+fn load_connect_script(path: &str) -> Result<ConnectScript, CliError> {
+    ConnectScript::load(Path::new(path)).map_err(|err| CliError::InvalidConnectScript(with_source(&err)))
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, CliError> {
+    args.next().ok_or_else(|| CliError::MissingValue(flag.to_string()))
+}
+
+fn parse_download(mut args: impl Iterator<Item = String>, tz: Tz) -> Result<DownloadArgs, CliError> {
+    let mut symbol = None;
+    let mut interval = "D".to_string();
+    let mut from = 0;
+    let mut to = Utc::now().timestamp();
+    let mut out = None;
+    let mut store = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interval" => interval = next_value(&mut args, &arg)?,
+            "--store" => store = Some(next_value(&mut args, &arg)?),
+            "--from" => from = parse_date(&next_value(&mut args, &arg)?, tz)?,
+            "--to" => to = parse_date(&next_value(&mut args, &arg)?, tz)?,
+            "--out" => out = Some(next_value(&mut args, &arg)?),
+            _ if !arg.starts_with("--") && symbol.is_none() => symbol = Some(arg),
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    let symbol = symbol.ok_or(CliError::MissingSymbol)?;
+    symbol::validate(&symbol).map_err(CliError::InvalidSymbol)?;
+    // a store alone just keeps it up to date
+    if out.is_none() && store.is_none() {
+        return Err(CliError::MissingOutput);
+    }
+    Ok(DownloadArgs {
+        symbol,
+        interval,
+        from,
+        to,
+        out,
+        store
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(value: String) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::InvalidNumber(value))
+}
+
+fn parse_plot(mut args: impl Iterator<Item = String>) -> Result<PlotArgs, CliError> {
+    let mut input = None;
+    let mut out = None;
+    let mut last = None;
+    let mut sma = vec![];
+    let mut studies = None;
+    let mut width = 1200;
+    let mut height = 700;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input = Some(next_value(&mut args, &arg)?),
+            "--out" => out = Some(next_value(&mut args, &arg)?),
+            "--last" => last = Some(parse_number(next_value(&mut args, &arg)?)?),
+            "--sma" => sma.push(parse_number(next_value(&mut args, &arg)?)?),
+            "--studies" => studies = Some(next_value(&mut args, &arg)?),
+            "--width" => width = parse_number(next_value(&mut args, &arg)?)?,
+            "--height" => height = parse_number(next_value(&mut args, &arg)?)?,
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    Ok(PlotArgs {
+        input: input.ok_or(CliError::MissingInput)?,
+        out: out.ok_or(CliError::MissingOutput)?,
+        last,
+        sma,
+        studies,
+        width,
+        height
+    })
+}
+
+// a bare number is seconds
+fn parse_duration(value: String) -> Result<Duration, CliError> {
+    let parsed = match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => duration::parse(&value),
+    };
+    parsed.ok_or(CliError::InvalidDuration(value))
+}
+
+fn parse_size(value: String) -> Result<usize, CliError> {
+    let (number, multiplier) = match value.strip_suffix(['K', 'k']) {
+        Some(number) => (number, 1024),
+        None => match value.strip_suffix(['M', 'm']) {
+            Some(number) => (number, 1024 * 1024),
+            None => match value.strip_suffix(['G', 'g']) {
+                Some(number) => (number, 1024 * 1024 * 1024),
+                None => (value.as_str(), 1),
+            },
+        },
+    };
+    match number.parse::<usize>() {
+        Ok(number) if number > 0 => Ok(number * multiplier),
+        _ => Err(CliError::InvalidSize(value)),
+    }
+}
+
+// ID=SCRIPT on the series, ID=SCRIPT@PARENT on another study's output; SCRIPT is a pine id,
+// a script url or its slug
+fn parse_study(value: String) -> Result<StudySpec, CliError> {
+    let (id, script) = match value.split_once('=') {
+        Some((id, script)) if !id.is_empty() && !script.is_empty() => (id, script),
+        _ => return Err(CliError::InvalidStudy(value)),
+    };
+    match script.rsplit_once('@') {
+        Some((pine_id, parent)) if !pine_id.is_empty() && !parent.is_empty() => Ok(StudySpec::pine(id, pine_id, Some(parent))),
+        Some(_) => Err(CliError::InvalidStudy(value)),
+        None => Ok(StudySpec::pine(id, script, None)),
+    }
+}
+
+fn parse_bench(mut args: impl Iterator<Item = String>) -> Result<BenchArgs, CliError> {
+    let mut symbols = 50;
+    let mut symbols_file = None;
+    let mut duration = Duration::from_secs(60);
+    let mut connections = 1;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--symbols" => symbols = parse_number(next_value(&mut args, &arg)?)?,
+            "--symbols-file" => symbols_file = Some(next_value(&mut args, &arg)?),
+            "--duration" => duration = parse_duration(next_value(&mut args, &arg)?)?,
+            "--connections" => connections = parse_number(next_value(&mut args, &arg)?)?,
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    Ok(BenchArgs {
+        symbols,
+        symbols_file,
+        duration,
+        connections: connections.max(1)
+    })
+}
+
+// comma separated wire names, e.g. lp,ch,chp
+fn parse_quote_fields(value: &str) -> Result<Vec<QuoteField>, CliError> {
+    value.split(',')
+        .map(|name| QuoteField::from_name(name).ok_or_else(|| CliError::InvalidQuoteField(name.to_string())))
+        .collect()
+}
+
+fn parse_quote(mut args: impl Iterator<Item = String>) -> Result<QuoteArgs, CliError> {
+    let mut symbol = None;
+    let mut fields = vec![QuoteField::LastPrice, QuoteField::Change, QuoteField::ChangePercent, QuoteField::LastPriceTime];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fields" => fields = parse_quote_fields(&next_value(&mut args, &arg)?)?,
+            _ if !arg.starts_with("--") && symbol.is_none() => symbol = Some(arg),
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    let symbol = symbol.ok_or(CliError::MissingSymbol)?;
+    symbol::validate(&symbol).map_err(CliError::InvalidSymbol)?;
+    Ok(QuoteArgs { symbol, fields })
+}
+
+fn parse_exchange(mut args: impl Iterator<Item = String>) -> Result<ExchangeArgs, CliError> {
+    let mut exchange = None;
+    let mut per_connection = bulk::MAX_SYMBOLS_PER_CONNECTION;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--per-connection" => per_connection = parse_number(next_value(&mut args, &arg)?)?,
+            _ if !arg.starts_with("--") && exchange.is_none() => {
+                let name = arg.to_uppercase();
+                if scanner::market(&name).is_none() {
+                    return Err(CliError::UnknownExchange(arg));
+                }
+                exchange = Some(name);
+            },
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    Ok(ExchangeArgs {
+        exchange: exchange.ok_or(CliError::MissingValue("exchange".to_string()))?,
+        per_connection: per_connection.max(1)
+    })
+}
+
+fn parse_hotlist(mut args: impl Iterator<Item = String>) -> Result<HotlistArgs, CliError> {
+    let mut hotlist = None;
+    let mut refresh = Duration::from_secs(300);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--refresh" => {
+                let value = next_value(&mut args, &arg)?;
+                refresh = parse_duration(value.clone())?;
+                if refresh.is_zero() {
+                    return Err(CliError::InvalidDuration(value));
+                }
+            },
+            _ if !arg.starts_with("--") && hotlist.is_none() => hotlist = Some(arg.parse().map_err(CliError::InvalidHotlist)?),
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    Ok(HotlistArgs {
+        hotlist: hotlist.ok_or(CliError::MissingValue("hotlist".to_string()))?,
+        refresh
+    })
+}
+
+fn parse_replay(mut args: impl Iterator<Item = String>) -> Result<ReplayArgs, CliError> {
+    let mut input = None;
+    let mut interval = "1".to_string();
+    let mut speed = ReplaySpeed::Multiple(1.0);
+    let mut step_mode = StepMode::Timer;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input = Some(next_value(&mut args, &arg)?),
+            "--interval" => interval = next_value(&mut args, &arg)?,
+            "--speed" => {
+                let value = next_value(&mut args, &arg)?;
+                speed = value.parse().map_err(CliError::InvalidReplaySpeed)?;
+            },
+            "--step" => {
+                let value = next_value(&mut args, &arg)?;
+                step_mode = value.parse().map_err(CliError::InvalidStepMode)?;
+            },
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    Ok(ReplayArgs {
+        input: input.ok_or(CliError::MissingInput)?,
+        interval,
+        speed,
+        step_mode
+    })
+}
+
+fn parse_command(mut args: impl Iterator<Item = String>, tz: Tz) -> Result<Command, CliError> {
+    match args.next() {
+        None => Ok(Command::Stream),
+        Some(command) if command == "download" => Ok(Command::Download(parse_download(args, tz)?)),
+        Some(command) if command == "fields" => Ok(Command::Fields),
+        Some(command) if command == "schema" => Ok(Command::Schema),
+        Some(command) if command == "plot" => Ok(Command::Plot(parse_plot(args)?)),
+        Some(command) if command == "bench" => Ok(Command::Bench(parse_bench(args)?)),
+        Some(command) if command == "quote" => Ok(Command::Quote(parse_quote(args)?)),
+        Some(command) if command == "exchange" => Ok(Command::Exchange(parse_exchange(args)?)),
+        Some(command) if command == "hotlist" => Ok(Command::Hotlist(parse_hotlist(args)?)),
+        Some(command) if command == "replay" => Ok(Command::Replay(parse_replay(args)?)),
+        Some(command) if command == "watchlists" => match args.next() {
+            Some(arg) if arg.starts_with("--") => Err(CliError::UnknownArgument(arg)),
+            name => Ok(Command::Watchlists(name)),
+        },
+        Some(command) if command == "symbol-info" => {
+            let symbols: Vec<String> = args.collect();
+            if let Some(arg) = symbols.iter().find(|arg| arg.starts_with("--")) {
+                return Err(CliError::UnknownArgument(arg.clone()));
+            }
+            if symbols.is_empty() {
+                return Err(CliError::MissingSymbol);
+            }
+            Ok(Command::SymbolInfo(symbols))
+        },
+        Some(command) => Err(CliError::UnknownArgument(command)),
+    }
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Cli, CliError> {
+    // global flags can appear anywhere, everything else belongs to the command
+    let mut dry_run = false;
+    let mut log_level = LevelFilter::Info;
+    let mut log_format = LogFormat::Text;
+    let mut daemon = false;
+    let mut pid_file = "tradingview-websocket.pid".to_string();
+    let mut log_file = None;
+    let mut restart = false;
+    let mut tz = Tz::UTC;
+    let mut time_format = TimeFormat::Unix;
+    let mut routes = vec![];
+    let mut study_out = None;
+    let mut error_policy = ErrorPolicy::DropSession;
+    let mut parse_mode = ParseMode::Lenient;
+    let mut heartbeat_timeout = None;
+    let mut command_timeout = None;
+    let mut emit_corrections = false;
+    let mut validate_bars = false;
+    let mut auth_token = None;
+    let mut limits = MemoryLimits::default();
+    let mut propagate_panics = false;
+    let mut parse_workers = None;
+    let mut flush_policy = FlushPolicy::default();
+    let mut chart_style = ChartStyle::default();
+    let mut currency = None;
+    let mut unit = None;
+    let mut adjustment = None;
+    let mut back_adjust = false;
+    let mut stitch_rolls = false;
+    let mut extra_intervals = vec![];
+    let mut studies = vec![];
+    let mut corporate_events = false;
+    let mut widget = false;
+    let mut trades = false;
+    let mut local_indicators = vec![];
+    let mut resample_intervals = vec![];
+    let mut session_stats_every = None;
+    let mut stats_every = None;
+    let mut news_every = None;
+    let mut calendar: Option<CalendarOptions> = None;
+    let mut quote_fields = vec![];
+    let mut quote_changes = vec![];
+    let mut symbol_cache = None;
+    let mut accounts = vec![];
+    let mut symbol_cache_ttl = symbol_info::DEFAULT_TTL;
+    let mut capture_unknown = None;
+    let mut capture_limit = capture::DEFAULT_LIMIT;
+    let mut state = None;
+    let mut aliases = None;
+    let mut filters = vec![];
+    let mut clock_events = false;
+    let mut normalize_times = false;
+    let mut ping_reply = PingReply::Immediate;
+    let mut invariants = InvariantMode::Off;
+    let mut connect_script = None;
+    let mut health_addr = None;
+    let mut health_max_age = None;
+    let mut faults = None;
+    // dates are read in --tz, which may come later
+    let mut anchor_dates = vec![];
+    let mut command_args = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--log-level" => {
+                let level = next_value(&mut args, &arg)?;
+                log_level = level.parse().map_err(|_| CliError::InvalidLogLevel(level))?;
+            },
+            // -q only leaves warnings and errors, -v shows message payloads, -vv raw frames
+            "--log-format" => {
+                let format = next_value(&mut args, &arg)?;
+                log_format = format.parse().map_err(|_| CliError::InvalidLogFormat(format))?;
+            },
+            "-q" | "--quiet" => log_level = LevelFilter::Warn,
+            "-v" | "--verbose" => log_level = LevelFilter::Debug,
+            "-vv" => log_level = LevelFilter::Trace,
+            "--daemon" => daemon = true,
+            "--pid-file" => pid_file = next_value(&mut args, &arg)?,
+            "--log-file" => log_file = Some(next_value(&mut args, &arg)?),
+            "--restart" => restart = true,
+            "--tz" => {
+                let name = next_value(&mut args, &arg)?;
+                tz = name.parse().map_err(|_| CliError::InvalidTimezone(name))?;
+            },
+            "--time-format" => {
+                let format = next_value(&mut args, &arg)?;
+                time_format = format.parse().map_err(CliError::InvalidTimeFormat)?;
+            },
+            "--on-error" => {
+                let policy = next_value(&mut args, &arg)?;
+                error_policy = policy.parse().map_err(CliError::InvalidErrorPolicy)?;
+            },
+            "--emit-corrections" => emit_corrections = true,
+            "--validate-bars" => validate_bars = true,
+            "--auth-token" => auth_token = Some(next_value(&mut args, &arg)?),
+            "--propagate-panics" => propagate_panics = true,
+            "--flush-rows" => flush_policy.max_rows = parse_number(next_value(&mut args, &arg)?)?,
+            "--flush-interval" => flush_policy.max_delay = parse_duration(next_value(&mut args, &arg)?)?,
+            "--parse-workers" => parse_workers = Some(parse_number(next_value(&mut args, &arg)?)?),
+            "--max-buffer" => limits.max_rx_buffer = parse_size(next_value(&mut args, &arg)?)?,
+            "--max-bars" => limits.max_cached_bars = parse_number(next_value(&mut args, &arg)?)?,
+            "--max-backlog" => limits.max_event_backlog = parse_number(next_value(&mut args, &arg)?)?,
+            "--on-backlog-full" => {
+                let policy = next_value(&mut args, &arg)?;
+                limits.backlog_policy = policy.parse().map_err(CliError::InvalidBacklogPolicy)?;
+            },
+            "--parse-mode" => {
+                let mode = next_value(&mut args, &arg)?;
+                parse_mode = mode.parse().map_err(CliError::InvalidParseMode)?;
+            },
+            "--chart-style" => {
+                let style = next_value(&mut args, &arg)?;
+                chart_style = style.parse().map_err(CliError::InvalidChartStyle)?;
+            },
+            "--currency" => currency = Some(next_value(&mut args, &arg)?.to_uppercase()),
+            "--unit" => unit = Some(next_value(&mut args, &arg)?),
+            "--adjustment" => {
+                let value = next_value(&mut args, &arg)?;
+                adjustment = Some(value.parse().map_err(CliError::InvalidAdjustment)?);
+            },
+            "--back-adjust" => back_adjust = true,
+            "--stitch-rolls" => stitch_rolls = true,
+            "--extra-intervals" => extra_intervals.extend(next_value(&mut args, &arg)?.split(',').filter(|interval| !interval.is_empty()).map(str::to_string)),
+            "--corporate-events" => corporate_events = true,
+            "--widget" => widget = true,
+            "--trades" => trades = true,
+            "--account" => accounts.push(next_value(&mut args, &arg)?.parse().map_err(CliError::InvalidAccount)?),
+            "--symbol-cache" => symbol_cache = Some(next_value(&mut args, &arg)?),
+            "--symbol-cache-ttl" => symbol_cache_ttl = parse_duration(next_value(&mut args, &arg)?)?,
+            "--capture-unknown" => capture_unknown = Some(next_value(&mut args, &arg)?),
+            "--health" => health_addr = Some(next_value(&mut args, &arg)?),
+            "--health-max-age" => health_max_age = Some(parse_duration(next_value(&mut args, &arg)?)?),
+            "--inject-faults" => faults = Some(next_value(&mut args, &arg)?.parse().map_err(CliError::InvalidFaults)?),
+            "--capture-limit" => capture_limit = parse_number(next_value(&mut args, &arg)?)?,
+            "--state" => state = Some(next_value(&mut args, &arg)?),
+            "--clock-skew" => clock_events = true,
+            "--normalize-times" => normalize_times = true,
+            "--ping-reply" => ping_reply = next_value(&mut args, &arg)?.parse().map_err(CliError::InvalidPingReply)?,
+            "--invariants" => invariants = next_value(&mut args, &arg)?.parse().map_err(CliError::InvalidInvariants)?,
+            "--connect-script" => connect_script = Some(load_connect_script(&next_value(&mut args, &arg)?)?),
+            "--filter" => filters.push(next_value(&mut args, &arg)?.parse().map_err(CliError::InvalidFilter)?),
+            "--aliases" => aliases = Some(load_aliases(&next_value(&mut args, &arg)?)?),
+            "--quote-fields" => quote_fields = parse_quote_fields(&next_value(&mut args, &arg)?)?,
+            "--quote-changes" => quote_changes = parse_quote_fields(&next_value(&mut args, &arg)?)?,
+            "--resample" => resample_intervals.extend(next_value(&mut args, &arg)?.split(',').filter(|interval| !interval.is_empty()).map(str::to_string)),
+            "--session-stats" => session_stats_every = Some(parse_duration(next_value(&mut args, &arg)?)?),
+            "--feed-stats" => stats_every = Some(parse_duration(next_value(&mut args, &arg)?)?),
+            "--news" => {
+                let value = next_value(&mut args, &arg)?;
+                let every = parse_duration(value.clone())?;
+                if every.is_zero() {
+                    return Err(CliError::InvalidDuration(value));
+                }
+                news_every = Some(every);
+            },
+            // comma separated country codes, or "all"
+            "--calendar" => {
+                let countries = next_value(&mut args, &arg)?;
+                let calendar = calendar.get_or_insert_with(CalendarOptions::default);
+                calendar.countries = match countries.as_str() {
+                    "all" => vec![],
+                    _ => countries.split(',').filter(|country| !country.is_empty()).map(str::to_uppercase).collect(),
+                };
+            },
+            "--calendar-importance" => {
+                let value = next_value(&mut args, &arg)?;
+                calendar.get_or_insert_with(CalendarOptions::default).min_importance = value.parse().map_err(CliError::InvalidImportance)?;
+            },
+            "--anchor" => anchor_dates.push(next_value(&mut args, &arg)?),
+            "--indicator" => {
+                let indicator = next_value(&mut args, &arg)?;
+                local_indicators.push(indicator.parse().map_err(CliError::InvalidIndicator)?);
+            },
+            "--study" => studies.push(parse_study(next_value(&mut args, &arg)?)?),
+            "--heartbeat-timeout" => heartbeat_timeout = Some(parse_duration(next_value(&mut args, &arg)?)?),
+            "--command-timeout" => command_timeout = Some(parse_duration(next_value(&mut args, &arg)?)?),
+            "--study-out" => study_out = Some(next_value(&mut args, &arg)?),
+            "--route" => {
+                let route = next_value(&mut args, &arg)?;
+                match route.split_once('=') {
+                    Some((symbol, path)) if !symbol.is_empty() && !path.is_empty() => routes.push((symbol.to_string(), path.to_string())),
+                    _ => return Err(CliError::InvalidRoute(route)),
+                }
+            },
+            _ => command_args.push(arg),
+        }
+    }
+    let session_anchors = anchor_dates.iter().map(|date| parse_date(date, tz)).collect::<Result<Vec<_>, _>>()?;
+    Ok(Cli {
+        command: parse_command(command_args.into_iter(), tz)?,
+        dry_run,
+        log_level,
+        log_format,
+        daemon,
+        pid_file,
+        log_file,
+        restart,
+        tz,
+        time_format,
+        routes,
+        study_out,
+        error_policy,
+        parse_mode,
+        heartbeat_timeout,
+        command_timeout,
+        emit_corrections,
+        validate_bars,
+        auth_token,
+        limits,
+        propagate_panics,
+        parse_workers,
+        flush_policy,
+        chart_style,
+        currency,
+        unit,
+        adjustment,
+        back_adjust,
+        stitch_rolls,
+        extra_intervals,
+        studies,
+        corporate_events,
+        widget,
+        trades,
+        local_indicators,
+        resample_intervals,
+        session_stats_every,
+        stats_every,
+        session_anchors,
+        news_every,
+        calendar,
+        quote_fields,
+        quote_changes,
+        symbol_cache,
+        symbol_cache_ttl,
+        capture_unknown,
+        capture_limit,
+        state,
+        aliases,
+        filters,
+        clock_events,
+        normalize_times,
+        ping_reply,
+        invariants,
+        connect_script,
+        health_addr,
+        health_max_age,
+        faults,
+        accounts
+    })
+}
@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::ack::CorrelationId;
+use crate::bar::Bar;
+use crate::event::Event;
+use crate::journal::EventJournal;
+use crate::quote::{QuoteData, QuoteField};
+use crate::server_info::ServerInfo;
+use crate::tradingview_api::{ApiCommand, ApiOptions, HistoryRequest, Mode, SessionKind, TradingViewApi};
+use crate::websocket::{Incoming, WebSocket};
+
+// one-shot requests give up after this, whatever the heartbeat timeout is
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+type ThreadResult = Result<(), Box<dyn Error + Send>>;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("no answer within {0:?}")]
+    Timeout(Duration),
+    #[error("subscription to {symbol} failed: {reason}")]
+    SubscriptionFailed { symbol: String, reason: String },
+    #[error("connection closed before the request completed")]
+    Incomplete,
+    #[error("connection failed")]
+    Connection(#[source] Box<dyn Error + Send>),
+    #[error("connection thread panicked")]
+    Panicked
+}
+
+fn join(handle: Option<JoinHandle<ThreadResult>>) -> Result<(), ClientError> {
+    match handle.map(JoinHandle::join) {
+        None => Ok(()),
+        Some(Ok(result)) => result.map_err(ClientError::Connection),
+        Some(Err(_)) => Err(ClientError::Panicked),
+    }
+}
+
+// the api and websocket threads of one connection; events come in on events_rx until either
+// side stops
+pub struct Connection {
+    // taken by the websocket thread when it ends, so the api sees the hang up
+    incoming_tx: Arc<Mutex<Option<Sender<Incoming>>>>,
+    pub events_rx: Receiver<Arc<Event>>,
+    stop: Arc<AtomicBool>,
+    // of the mode's subscriptions, the commands count on from it
+    correlation_id: CorrelationId,
+    next_correlation_id: AtomicU64,
+    api: Option<JoinHandle<ThreadResult>>,
+    websocket: Option<JoinHandle<ThreadResult>>,
+    // set once journal() took over events_rx, with the thread feeding it
+    journal: Option<Arc<EventJournal>>,
+    pump: Option<JoinHandle<ThreadResult>>,
+    // sessions whose handle was given out, each has one owner
+    handed_out: Mutex<Vec<SessionKind>>,
+    server_info: Arc<Mutex<Option<ServerInfo>>>
+}
+
+impl Connection {
+    pub fn open(mode: Mode, options: ApiOptions) -> Connection {
+        let correlation_id = 1;
+        let options = ApiOptions { correlation_id: Some(correlation_id), ..options };
+        let stop = Arc::new(AtomicBool::new(false));
+        let (incoming_tx, incoming_rx) = mpsc::channel();
+        let (outgoing_tx, outgoing_rx) = mpsc::channel();
+        let (events_tx, events_rx) = mpsc::sync_channel(options.limits.max_event_backlog);
+        let max_rx_buffer = options.limits.max_rx_buffer;
+        let endpoint = options.endpoint;
+        let frame_handler = options.frame_handler.clone();
+        let server_info = Arc::clone(&options.server_info);
+        let api = thread::spawn(move || -> ThreadResult {
+            TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, mode, options)?.handler()
+        });
+        let shared_incoming_tx = Arc::new(Mutex::new(Some(incoming_tx.clone())));
+        let (websocket_incoming_tx, websocket_stop) = (Arc::clone(&shared_incoming_tx), Arc::clone(&stop));
+        let websocket = thread::spawn(move || -> ThreadResult {
+            let result = WebSocket::new(endpoint, incoming_tx, outgoing_rx, websocket_stop, max_rx_buffer).and_then(|websocket| {
+                let mut websocket = match frame_handler {
+                    Some(frame_handler) => websocket.with_frame_handler(frame_handler),
+                    None => websocket,
+                };
+                websocket.handle_stream()
+            });
+            if let Ok(mut incoming_tx) = websocket_incoming_tx.lock() {
+                incoming_tx.take();
+            }
+            result
+        });
+        Connection { incoming_tx: shared_incoming_tx, events_rx, stop, correlation_id, next_correlation_id: AtomicU64::new(correlation_id + 1), api: Some(api), websocket: Some(websocket), journal: None, pump: None, handed_out: Mutex::default(), server_info }
+    }
+
+    // the id the Quote and Bars events of the mode's symbols carry, and the CommandResults of
+    // what the connection sends on its own
+    pub fn correlation_id(&self) -> CorrelationId {
+        self.correlation_id
+    }
+
+    // the id the command's CommandResult and SubscriptionFailed events carry, and the Quote and
+    // Bars events of the symbols it adds; None once the connection has ended
+    pub fn command(&self, command: ApiCommand) -> Option<CorrelationId> {
+        let incoming_tx = self.incoming_tx.lock().ok().and_then(|incoming_tx| incoming_tx.clone())?;
+        let correlation_id = self.next_correlation_id.fetch_add(1, Ordering::Relaxed);
+        incoming_tx.send(Incoming::Command(command, Some(correlation_id))).ok()?;
+        Some(correlation_id)
+    }
+
+    // what the server announced when the connection opened, None until then
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        self.server_info.lock().ok()?.clone()
+    }
+
+    fn hand_out(&self, kind: SessionKind) -> bool {
+        match self.handed_out.lock() {
+            Ok(mut handed_out) if !handed_out.contains(&kind) => {
+                handed_out.push(kind);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // the handle of the quote session, None if it was handed out before; the session stays
+    // open without one
+    pub fn quote_session(&self) -> Option<QuoteSession<'_>> {
+        self.hand_out(SessionKind::Quote).then_some(QuoteSession { connection: self })
+    }
+
+    pub fn chart_session(&self) -> Option<ChartSession<'_>> {
+        self.hand_out(SessionKind::Chart).then_some(ChartSession { connection: self })
+    }
+
+    // moves the events from events_rx into a journal keeping the last window events of each
+    // symbol, for several consumers to read through their own cursors; events_rx gets nothing
+    // after the first call and later calls return the same journal
+    pub fn journal(&mut self, window: usize) -> Arc<EventJournal> {
+        if let Some(ref journal) = self.journal {
+            return Arc::clone(journal);
+        }
+        let journal = Arc::new(EventJournal::new(window));
+        let events_rx = std::mem::replace(&mut self.events_rx, mpsc::sync_channel(0).1);
+        let pump_journal = Arc::clone(&journal);
+        self.pump = Some(thread::spawn(move || -> ThreadResult {
+            for event in events_rx {
+                pump_journal.append(event);
+            }
+            pump_journal.close();
+            Ok(())
+        }));
+        self.journal = Some(Arc::clone(&journal));
+        journal
+    }
+
+    // closes the session politely and waits for both threads, with the error that ended the
+    // connection if it ended on its own; later calls return Ok
+    pub fn close(&mut self) -> Result<(), ClientError> {
+        self.stop.store(true, Ordering::SeqCst);
+        // replacing the receiver unblocks an api waiting on a full backlog
+        self.events_rx = mpsc::sync_channel(0).1;
+        let websocket_result = join(self.websocket.take());
+        let api_result = join(self.api.take());
+        // ends with the api's sender
+        let pump_result = join(self.pump.take());
+        websocket_result.and(api_result).and(pump_result)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+// dropping it deletes the quote session on the server, which stops its updates while the
+// connection and the chart session carry on
+pub struct QuoteSession<'a> {
+    connection: &'a Connection
+}
+
+impl QuoteSession<'_> {
+    pub fn set_symbols(&self, symbols: Vec<String>) -> Option<CorrelationId> {
+        self.connection.command(ApiCommand::SetQuoteSymbols(symbols))
+    }
+
+    pub fn set_fields(&self, fields: Vec<QuoteField>) -> Option<CorrelationId> {
+        self.connection.command(ApiCommand::SetQuoteFields(fields))
+    }
+
+    pub fn hibernate(&self) -> Option<CorrelationId> {
+        self.connection.command(ApiCommand::HibernateQuotes)
+    }
+
+    pub fn wake(&self) -> Option<CorrelationId> {
+        self.connection.command(ApiCommand::WakeQuotes)
+    }
+}
+
+impl Drop for QuoteSession<'_> {
+    fn drop(&mut self) {
+        self.connection.command(ApiCommand::DeleteSession(SessionKind::Quote));
+    }
+}
+
+// dropping it deletes the chart session on the server, like QuoteSession
+pub struct ChartSession<'a> {
+    connection: &'a Connection
+}
+
+impl Drop for ChartSession<'_> {
+    fn drop(&mut self) {
+        self.connection.command(ApiCommand::DeleteSession(SessionKind::Chart));
+    }
+}
+
+// what a one-shot request got, with the correlation id of its subscription
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response<T> {
+    pub value: T,
+    pub correlation_id: CorrelationId
+}
+
+// runs a throwaway connection until on_event has an answer, then tears it down; on_event gets
+// the id of the connection's subscription with each event
+fn run_until<T>(mode: Mode, options: ApiOptions, mut on_event: impl FnMut(&Event, CorrelationId) -> Option<Result<T, ClientError>>) -> Result<Response<T>, ClientError> {
+    let mut connection = Connection::open(mode, options);
+    let correlation_id = connection.correlation_id();
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let answer = loop {
+        match connection.events_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(event) => match on_event(&event, correlation_id) {
+                Some(answer) => break Some(answer),
+                None => continue,
+            },
+            Err(RecvTimeoutError::Timeout) => break Some(Err(ClientError::Timeout(REQUEST_TIMEOUT))),
+            Err(RecvTimeoutError::Disconnected) => break None,
+        }
+    };
+    let closed = connection.close();
+    match answer {
+        Some(answer) => answer.map(|value| Response { value, correlation_id }),
+        // the connection ended on its own, so one of the threads knows why
+        None => closed.and(Err(ClientError::Incomplete)),
+    }
+}
+
+// blocks until the server has sent every requested field for the symbol, or quote_completed
+pub fn get_quote(symbol: &str, fields: &[QuoteField], options: ApiOptions) -> Result<Response<QuoteData>, ClientError> {
+    let options = ApiOptions { quote_fields: fields.to_vec(), ..options };
+    // events come back under the alias as it's spelled in the alias list
+    let reported = match options.aliases {
+        Some(ref aliases) => aliases.alias(&aliases.resolve(symbol)).to_string(),
+        None => symbol.to_string(),
+    };
+    let mut quote = QuoteData::new(&reported);
+    run_until(Mode::Quotes(vec![symbol.to_string()]), options, |event, subscription| match event {
+        Event::Quote { symbol: quoted, values, correlation_id, .. } if *quoted == quote.symbol && *correlation_id == Some(subscription) => {
+            quote.update(values);
+            quote.has_all(fields).then(|| Ok(quote.clone()))
+        }
+        Event::QuoteCompleted { symbol: completed } if *completed == quote.symbol => Some(Ok(quote.clone())),
+        Event::SubscriptionFailed { symbol, reason, .. } => Some(Err(ClientError::SubscriptionFailed { symbol: symbol.clone(), reason: reason.clone() })),
+        _ => None,
+    })
+}
+
+// the most recent count bars, oldest first; fewer if the symbol has less history
+pub fn get_ohlcv(symbol: &str, interval: &str, count: usize, options: ApiOptions) -> Result<Response<Vec<Bar>>, ClientError> {
+    let request = HistoryRequest {
+        symbol: symbol.to_string(),
+        interval: interval.to_string(),
+        from: 0,
+        to: i64::MAX,
+        count: Some(count)
+    };
+    // keyed by bar time so overlapping pages dedupe themselves
+    let mut bars = BTreeMap::new();
+    run_until(Mode::Download(request), options, |event, subscription| match event {
+        Event::Bars { bars: page, correlation_id, .. } if *correlation_id == Some(subscription) => {
+            bars.extend(page.iter().map(|bar| (bar.time, bar.clone())));
+            None
+        }
+        Event::HistoryCompleted { .. } => {
+            let bars: Vec<Bar> = std::mem::take(&mut bars).into_values().collect();
+            Some(Ok(bars[bars.len().saturating_sub(count)..].to_vec()))
+        }
+        Event::SubscriptionFailed { symbol, reason, .. } => Some(Err(ClientError::SubscriptionFailed { symbol: symbol.clone(), reason: reason.clone() })),
+        _ => None,
+    })
+}
+
+// a one-shot request for async code: it runs on its own thread, so the executor isn't blocked,
+// and the future completes with its answer
+pub struct ResponseFuture<T> {
+    shared: Arc<Mutex<PendingResponse<T>>>
+}
+
+struct PendingResponse<T> {
+    result: Option<Result<Response<T>, ClientError>>,
+    waker: Option<Waker>
+}
+
+impl<T: Send + 'static> ResponseFuture<T> {
+    fn spawn(request: impl FnOnce() -> Result<Response<T>, ClientError> + Send + 'static) -> ResponseFuture<T> {
+        let shared = Arc::new(Mutex::new(PendingResponse { result: None, waker: None }));
+        let thread_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(request)).unwrap_or(Err(ClientError::Panicked));
+            if let Ok(mut pending) = thread_shared.lock() {
+                pending.result = Some(result);
+                if let Some(waker) = pending.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+        ResponseFuture { shared }
+    }
+}
+
+impl<T> Future for ResponseFuture<T> {
+    type Output = Result<Response<T>, ClientError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut pending = match self.shared.lock() {
+            Ok(pending) => pending,
+            Err(_) => return Poll::Ready(Err(ClientError::Panicked)),
+        };
+        match pending.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                pending.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+pub fn get_quote_async(symbol: &str, fields: &[QuoteField], options: ApiOptions) -> ResponseFuture<QuoteData> {
+    let (symbol, fields) = (symbol.to_string(), fields.to_vec());
+    ResponseFuture::spawn(move || get_quote(&symbol, &fields, options))
+}
+
+pub fn get_ohlcv_async(symbol: &str, interval: &str, count: usize, options: ApiOptions) -> ResponseFuture<Vec<Bar>> {
+    let (symbol, interval) = (symbol.to_string(), interval.to_string());
+    ResponseFuture::spawn(move || get_ohlcv(&symbol, &interval, count, options))
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// enough to ride out a burst of slow frames without forgetting the fast ones
+const MAX_QUOTE_SAMPLES: usize = 512;
+// the clocks drift, old connections stop saying much
+const MAX_CONNECT_SAMPLES: usize = 16;
+
+// the lp_time of a symbol that hasn't traded in a while says nothing about the clock
+const MAX_DELAY_MS: i64 = 60_000;
+
+// an estimate that moved less than this since the last ClockSkew event isn't reported again
+pub const SKEW_REPORT_STEP_MS: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockEstimate {
+    // local clock minus server clock including the fastest delivery seen, positive when the
+    // local clock is ahead; one-way timestamps can't tell the two apart
+    pub skew_ms: i64,
+    // median extra delay of a message over the fastest one
+    pub delay_ms: i64,
+    pub samples: usize
+}
+
+impl ClockEstimate {
+    // a server timestamp on the local clock, to line it up with other feeds
+    pub fn to_local_ms(&self, server_ms: i64) -> i64 {
+        server_ms + self.skew_ms
+    }
+
+    pub fn to_server_ms(&self, local_ms: i64) -> i64 {
+        local_ms - self.skew_ms
+    }
+}
+
+// server timestamps against local receipt times, shared across reconnects like Stats. Each
+// sample is the receipt time minus the server's time for the same message in ms: the local
+// clock's skew plus how long the message took. The skew comes from the connect messages'
+// timestampMs once there is one; quote lp_times, read as the middle of their whole second,
+// fill in until then and give the delay
+#[derive(Default)]
+pub struct ClockSkew {
+    // one per connection
+    connects: Mutex<VecDeque<i64>>,
+    quotes: Mutex<VecDeque<i64>>
+}
+
+fn epoch_ms(time: SystemTime) -> Option<i64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|since_epoch| since_epoch.as_millis() as i64)
+}
+
+fn push(samples: &Mutex<VecDeque<i64>>, offset: i64, max: usize) {
+    if let Ok(mut samples) = samples.lock() {
+        if samples.len() == max {
+            samples.pop_front();
+        }
+        samples.push_back(offset);
+    }
+}
+
+impl ClockSkew {
+    pub fn record_connect(&self, server_ms: i64, received: SystemTime) {
+        if let Some(received_ms) = epoch_ms(received) {
+            push(&self.connects, received_ms - server_ms, MAX_CONNECT_SAMPLES);
+        }
+    }
+
+    pub fn record_last_price_time(&self, server_secs: i64, received: SystemTime) {
+        if let Some(received_ms) = epoch_ms(received) {
+            push(&self.quotes, received_ms - (server_secs * 1000 + 500), MAX_QUOTE_SAMPLES);
+        }
+    }
+
+    // None until a server timestamp has been seen
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        let connects = self.connects.lock().ok()?;
+        let quotes = self.quotes.lock().ok()?;
+        let skew_ms = connects.iter().min().or_else(|| quotes.iter().min()).copied()?;
+        let mut delays: Vec<i64> = quotes.iter().map(|offset| offset - skew_ms).filter(|delay| *delay < MAX_DELAY_MS).collect();
+        delays.sort_unstable();
+        let delay_ms = delays.get(delays.len() / 2).map_or(0, |delay| (*delay).max(0));
+        Some(ClockEstimate { skew_ms, delay_ms, samples: connects.len() + quotes.len() })
+    }
+}
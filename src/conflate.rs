@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::ack::CorrelationId;
+use crate::event::Event;
+use crate::quote::{QuoteData, QuoteField};
+
+// how often the handler retries held quotes while no frames arrive
+pub const CONFLATION_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+// quotes that didn't fit in the consumer's backlog, at most one per symbol with every value it
+// missed merged in, so a lagging consumer gets the latest snapshot instead of each tick and
+// memory stays bounded by the number of symbols
+#[derive(Debug, Default)]
+pub struct QuoteConflation {
+    // in the order the symbols fell behind
+    held: VecDeque<HeldQuote>
+}
+
+#[derive(Debug)]
+struct HeldQuote {
+    quote: QuoteData,
+    correlation_id: Option<CorrelationId>,
+    // of the newest lp_time merged in
+    local_time_ms: Option<i64>
+}
+
+impl HeldQuote {
+    fn into_event(self) -> Event {
+        let HeldQuote { quote: QuoteData { symbol, values }, correlation_id, local_time_ms } = self;
+        Event::Quote { symbol, values, correlation_id, local_time_ms }
+    }
+}
+
+impl QuoteConflation {
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    // true when the values were merged into a quote already held for the symbol
+    pub fn hold(&mut self, symbol: String, values: Vec<(QuoteField, Value)>, correlation_id: Option<CorrelationId>, local_time_ms: Option<i64>) -> bool {
+        match self.held.iter_mut().find(|held| held.quote.symbol == symbol) {
+            Some(held) => {
+                held.quote.update(&values);
+                held.local_time_ms = local_time_ms.or(held.local_time_ms);
+                true
+            }
+            None => {
+                self.held.push_back(HeldQuote { quote: QuoteData { symbol, values }, correlation_id, local_time_ms });
+                false
+            }
+        }
+    }
+
+    pub fn pop_front(&mut self) -> Option<Event> {
+        self.held.pop_front().map(HeldQuote::into_event)
+    }
+
+    // for a quote that still didn't fit, it keeps its place
+    pub fn push_front(&mut self, event: Event) {
+        if let Event::Quote { symbol, values, correlation_id, local_time_ms } = event {
+            self.held.push_front(HeldQuote { quote: QuoteData { symbol, values }, correlation_id, local_time_ms });
+        }
+    }
+}
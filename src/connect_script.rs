@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::quote::QuoteField;
+use crate::study::StudySpec;
+use crate::symbol;
+
+// bars asked for when the series is created, as the built-in stream does
+pub const DEFAULT_CHART_BARS: usize = 300;
+
+#[derive(Debug, Error)]
+pub enum ConnectScriptError {
+    #[error("couldn't read connect script {path:?}")]
+    Read { path: PathBuf, #[source] source: io::Error },
+    #[error("connect script is invalid")]
+    Invalid(#[from] serde_json::Error),
+    #[error("{symbol}: {reason}")]
+    Symbol { symbol: String, reason: String },
+    #[error("the script opens neither a quote nor a chart session")]
+    Empty
+}
+
+// the chart session of a connect script, one symbol and its series
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChartScript {
+    pub symbol: String,
+    #[serde(default = "default_interval")]
+    pub interval: String,
+    #[serde(default = "default_bars")]
+    pub bars: usize,
+    // added after ApiOptions::studies, on the series or on each other
+    #[serde(default)]
+    pub studies: Vec<StudySpec>
+}
+
+fn default_interval() -> String {
+    "1".to_string()
+}
+
+fn default_bars() -> usize {
+    DEFAULT_CHART_BARS
+}
+
+impl ChartScript {
+    pub fn new(symbol: &str, interval: &str) -> ChartScript {
+        ChartScript { symbol: symbol.to_string(), interval: interval.to_string(), bars: DEFAULT_CHART_BARS, studies: vec![] }
+    }
+}
+
+// what Mode::Stream opens on every connect instead of its built-in sessions, and opens again
+// after each reconnect. Written as json, e.g.
+// {"quote_symbols": ["BINANCE:BTCUSDT"], "chart": {"symbol": "BINANCE:BTCUSDT", "interval": "5"}}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectScript {
+    // no quote session when empty
+    pub quote_symbols: Vec<String>,
+    // what the quote session is asked for, every QuoteField when empty
+    pub quote_fields: Vec<QuoteField>,
+    // None for quotes only
+    pub chart: Option<ChartScript>
+}
+
+impl ConnectScript {
+    pub fn parse(text: &str) -> Result<ConnectScript, ConnectScriptError> {
+        let script: ConnectScript = serde_json::from_str(text)?;
+        script.validate()?;
+        Ok(script)
+    }
+
+    pub fn load(path: &Path) -> Result<ConnectScript, ConnectScriptError> {
+        let text = fs::read_to_string(path).map_err(|source| ConnectScriptError::Read { path: path.to_path_buf(), source })?;
+        ConnectScript::parse(&text)
+    }
+
+    // symbols built in code are checked when the connection opens instead, bad quote symbols
+    // are reported as SubscriptionFailed there
+    pub fn validate(&self) -> Result<(), ConnectScriptError> {
+        if self.quote_symbols.is_empty() && self.chart.is_none() {
+            return Err(ConnectScriptError::Empty);
+        }
+        for symbol in self.quote_symbols.iter().chain(self.chart.iter().map(|chart| &chart.symbol)) {
+            symbol::validate(symbol).map_err(|err| ConnectScriptError::Symbol { symbol: symbol.clone(), reason: err.to_string() })?;
+        }
+        Ok(())
+    }
+}
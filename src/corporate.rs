@@ -0,0 +1,79 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::study;
+
+// the server marks missing plot values with 1e100 rather than null
+const NA_THRESHOLD: f64 = 1e99;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EsdKind {
+    Earnings,
+    Dividends,
+    Splits
+}
+
+impl EsdKind {
+    pub const ALL: [EsdKind; 3] = [EsdKind::Earnings, EsdKind::Dividends, EsdKind::Splits];
+
+    pub fn study_id(&self) -> &'static str {
+        match *self {
+            EsdKind::Earnings => "esd_earnings",
+            EsdKind::Dividends => "esd_dividends",
+            EsdKind::Splits => "esd_splits",
+        }
+    }
+
+    pub fn script(&self) -> &'static str {
+        match *self {
+            EsdKind::Earnings => "Earnings@tv-basicstudies-251",
+            EsdKind::Dividends => "Dividends@tv-basicstudies-251",
+            EsdKind::Splits => "Splits@tv-basicstudies-251",
+        }
+    }
+
+    pub fn from_study_id(id: &str) -> Option<EsdKind> {
+        EsdKind::ALL.into_iter().find(|kind| kind.study_id() == id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CorporateEvent {
+    // estimate only until the report is out
+    Earnings { time: i64, actual: Option<f64>, estimate: Option<f64> },
+    Dividend { time: i64, amount: f64 },
+    // numerator new shares for every denominator old ones
+    Split { time: i64, numerator: f64, denominator: f64 }
+}
+
+impl CorporateEvent {
+    pub fn time(&self) -> i64 {
+        match *self {
+            CorporateEvent::Earnings { time, .. } | CorporateEvent::Dividend { time, .. } | CorporateEvent::Split { time, .. } => time,
+        }
+    }
+}
+
+fn value(values: &[f64], index: usize) -> Option<f64> {
+    values.get(index).copied().filter(|value| value.is_finite() && value.abs() < NA_THRESHOLD)
+}
+
+// the esd studies plot one row per event, bars without one come through as na
+pub fn parse_events(kind: EsdKind, study: &Value) -> Vec<CorporateEvent> {
+    study::parse_study_points(study).into_iter().filter_map(|point| {
+        let time = point.time;
+        match kind {
+            EsdKind::Earnings => {
+                let (actual, estimate) = (value(&point.values, 0), value(&point.values, 1));
+                (actual.is_some() || estimate.is_some()).then_some(CorporateEvent::Earnings { time, actual, estimate })
+            }
+            EsdKind::Dividends => value(&point.values, 0).map(|amount| CorporateEvent::Dividend { time, amount }),
+            EsdKind::Splits => match (value(&point.values, 0), value(&point.values, 1)) {
+                (Some(numerator), Some(denominator)) => Some(CorporateEvent::Split { time, numerator, denominator }),
+                _ => None,
+            },
+        }
+    }).collect()
+}
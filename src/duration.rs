@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+// a whole number with a unit, like 250ms, 30s, 5m, 2h or 1d; the settings of --ping-reply,
+// --fault, --filter and the cli's durations, and mock script sleeps
+pub fn parse(value: &str) -> Option<Duration> {
+    let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit())?);
+    let number: u64 = number.parse().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(number)),
+        "s" => Some(Duration::from_secs(number)),
+        "m" => number.checked_mul(60).map(Duration::from_secs),
+        "h" => number.checked_mul(3600).map(Duration::from_secs),
+        "d" => number.checked_mul(86_400).map(Duration::from_secs),
+        _ => None,
+    }
+}
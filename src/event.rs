@@ -0,0 +1,214 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ack::{CommandOutcome, CommandResult, CorrelationId};
+use crate::bar::Bar;
+use crate::calendar::EconomicEvent;
+use crate::corporate::CorporateEvent;
+use crate::invariants::Violation;
+use crate::market::TradingHours;
+use crate::news::NewsItem;
+use crate::quality::DataQualityIssue;
+use crate::quote::QuoteField;
+use crate::rates::{QueueDepths, SessionRate, SymbolRate};
+use crate::server_info::ServerInfo;
+use crate::session::RangeStats;
+use crate::study::{PlotInfo, StudyPoint};
+use crate::trade::Trade;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerErrorKind {
+    Study,
+    Critical,
+    Protocol
+}
+
+// in json an object with a snake_case "type" naming the variant next to its fields, see
+// schema::event_schema; renaming or retyping a field needs a new schema::SCHEMA_VERSION
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    // interval tells apart the series of a chart that follows several resolutions; correlation_id
+    // is the subscription that asked for the symbol, like Quote's
+    Bars {
+        symbol: String,
+        interval: String,
+        bars: Vec<Bar>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        correlation_id: Option<CorrelationId>
+    },
+    HistoryCompleted { symbol: String },
+    GapFilled { symbol: String, interval: String, bars: Vec<Bar> },
+    BarCorrected { symbol: String, interval: String, previous: Bar, bar: Bar },
+    ServerError { kind: ServerErrorKind, payload: Arc<Value> },
+    // correlation_id is set when an ApiCommand asked for the symbol
+    SubscriptionFailed {
+        symbol: String,
+        reason: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        correlation_id: Option<CorrelationId>
+    },
+    DataQualityWarning { symbol: String, time: i64, issue: DataQualityIssue },
+    FeedStale {
+        #[serde(rename = "silent_for_ms", with = "crate::schema::millis")]
+        #[schemars(with = "u64")]
+        silent_for: Duration
+    },
+    // correlation_id is the subscription that asked for the symbol: the one of Connection's mode,
+    // or the ApiCommand that added it. local_time_ms is the newest lp_time on the local clock,
+    // with ApiOptions::normalize_times once there is a clock::ClockEstimate
+    Quote {
+        symbol: String,
+        values: Vec<(QuoteField, Value)>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        correlation_id: Option<CorrelationId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        local_time_ms: Option<i64>
+    },
+    // one per field whose value differs from the last one seen for the symbol, after its Quote;
+    // old is None the first time, only with ApiOptions::quote_changes
+    QuoteFieldChanged { symbol: String, field: QuoteField, old: Option<Value>, new: Value },
+    // every field the server had for the symbol has been sent at least once
+    QuoteCompleted { symbol: String },
+    // continuous futures only, contract is the front month the series follows
+    ContractResolved { symbol: String, root: Option<String>, contract: String },
+    ContractRolled { symbol: String, previous: String, contract: String },
+    // output of a study from ApiOptions::studies, parent set for a study on another study;
+    // plots names each point's values, e.g. points[0].get(&plots, "RSI")
+    StudyValues { symbol: String, study: String, parent: Option<String>, plots: Arc<[PlotInfo]>, points: Vec<StudyPoint> },
+    // earnings, dividends and splits; the server may send the same ones again with later updates
+    CorporateEvents { symbol: String, events: Vec<CorporateEvent> },
+    // the chart symbol's schedule, from symbol_resolved
+    TradingHours { symbol: String, hours: TradingHours },
+    // one per trade from the tick series, apart from the aggregated lp quotes; local_time_ms as
+    // for Quote
+    Trade {
+        symbol: String,
+        trade: Trade,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        local_time_ms: Option<i64>
+    },
+    // a higher timeframe bar from ApiOptions::resample_intervals, sent once its bucket is over
+    BarClosed { symbol: String, interval: String, bar: Bar },
+    // every ApiOptions::session_stats_every, anchored in anchor order
+    SessionStats { symbol: String, session: RangeStats, anchored: Vec<RangeStats> },
+    // a headline first seen for one of the subscribed symbols, polled by ApiOptions::news
+    NewsItem { symbol: String, item: NewsItem },
+    // an upcoming or just released figure from ApiOptions::calendar, again whenever it changes
+    EconomicEvent { event: EconomicEvent },
+    // how a resolve_symbol, create_series, create_study or quote_add_symbols ended, see ack::AckTracker
+    CommandResult {
+        command: String,
+        session: String,
+        target: String,
+        outcome: CommandOutcome,
+        #[serde(rename = "elapsed_ms", with = "crate::schema::millis")]
+        #[schemars(with = "u64")]
+        elapsed: Duration,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        correlation_id: Option<CorrelationId>
+    },
+    // the local clock against the server's, see clock::ClockEstimate; only with
+    // ApiOptions::clock_events, on connect and whenever the skew moves
+    ClockSkew { skew_ms: i64, delay_ms: i64, samples: usize },
+    // the server's banner, first on every connection
+    Connected { server: ServerInfo },
+    // every ApiOptions::stats_every, the connection's rates over the interval since the last one
+    Stats {
+        #[serde(rename = "interval_ms", with = "crate::schema::millis")]
+        #[schemars(with = "u64")]
+        interval: Duration,
+        frames_per_sec: f64,
+        messages_per_sec: f64,
+        bytes_per_sec: f64,
+        sessions: Vec<SessionRate>,
+        symbols: Vec<SymbolRate>,
+        queues: QueueDepths
+    },
+    // a message breaking one of the protocol's invariants, only with ApiOptions::invariants
+    ProtocolViolation { violation: Violation }
+}
+
+// the "type" of every variant in json, see Event::type_name
+pub const EVENT_TYPES: [&str; 26] = [
+    "bars", "history_completed", "gap_filled", "bar_corrected", "server_error", "subscription_failed",
+    "data_quality_warning", "feed_stale", "quote", "quote_field_changed", "quote_completed", "contract_resolved",
+    "contract_rolled", "study_values", "corporate_events", "trading_hours", "trade", "bar_closed", "session_stats",
+    "news_item", "economic_event", "command_result", "clock_skew", "connected", "stats", "protocol_violation"
+];
+
+impl Event {
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Event::Bars { .. } => "bars",
+            Event::HistoryCompleted { .. } => "history_completed",
+            Event::GapFilled { .. } => "gap_filled",
+            Event::BarCorrected { .. } => "bar_corrected",
+            Event::ServerError { .. } => "server_error",
+            Event::SubscriptionFailed { .. } => "subscription_failed",
+            Event::DataQualityWarning { .. } => "data_quality_warning",
+            Event::FeedStale { .. } => "feed_stale",
+            Event::Quote { .. } => "quote",
+            Event::QuoteFieldChanged { .. } => "quote_field_changed",
+            Event::QuoteCompleted { .. } => "quote_completed",
+            Event::ContractResolved { .. } => "contract_resolved",
+            Event::ContractRolled { .. } => "contract_rolled",
+            Event::StudyValues { .. } => "study_values",
+            Event::CorporateEvents { .. } => "corporate_events",
+            Event::TradingHours { .. } => "trading_hours",
+            Event::Trade { .. } => "trade",
+            Event::BarClosed { .. } => "bar_closed",
+            Event::SessionStats { .. } => "session_stats",
+            Event::NewsItem { .. } => "news_item",
+            Event::EconomicEvent { .. } => "economic_event",
+            Event::CommandResult { .. } => "command_result",
+            Event::ClockSkew { .. } => "clock_skew",
+            Event::Connected { .. } => "connected",
+            Event::Stats { .. } => "stats",
+            Event::ProtocolViolation { .. } => "protocol_violation",
+        }
+    }
+
+    // the symbol the event is about, None for connection and calendar events
+    pub fn symbol(&self) -> Option<&str> {
+        match *self {
+            Event::Bars { ref symbol, .. } | Event::HistoryCompleted { ref symbol } | Event::GapFilled { ref symbol, .. }
+            | Event::BarCorrected { ref symbol, .. } | Event::SubscriptionFailed { ref symbol, .. }
+            | Event::DataQualityWarning { ref symbol, .. } | Event::Quote { ref symbol, .. }
+            | Event::QuoteFieldChanged { ref symbol, .. } | Event::QuoteCompleted { ref symbol }
+            | Event::ContractResolved { ref symbol, .. } | Event::ContractRolled { ref symbol, .. }
+            | Event::StudyValues { ref symbol, .. } | Event::CorporateEvents { ref symbol, .. }
+            | Event::TradingHours { ref symbol, .. } | Event::Trade { ref symbol, .. } | Event::BarClosed { ref symbol, .. }
+            | Event::SessionStats { ref symbol, .. } | Event::NewsItem { ref symbol, .. } => Some(symbol),
+            Event::ServerError { .. } | Event::FeedStale { .. } | Event::EconomicEvent { .. } | Event::CommandResult { .. }
+            | Event::ClockSkew { .. } | Event::Connected { .. } | Event::Stats { .. } | Event::ProtocolViolation { .. } => None,
+        }
+    }
+
+    pub fn symbol_mut(&mut self) -> Option<&mut String> {
+        match *self {
+            Event::Bars { ref mut symbol, .. } | Event::HistoryCompleted { ref mut symbol } | Event::GapFilled { ref mut symbol, .. }
+            | Event::BarCorrected { ref mut symbol, .. } | Event::SubscriptionFailed { ref mut symbol, .. }
+            | Event::DataQualityWarning { ref mut symbol, .. } | Event::Quote { ref mut symbol, .. }
+            | Event::QuoteFieldChanged { ref mut symbol, .. } | Event::QuoteCompleted { ref mut symbol }
+            | Event::ContractResolved { ref mut symbol, .. } | Event::ContractRolled { ref mut symbol, .. }
+            | Event::StudyValues { ref mut symbol, .. } | Event::CorporateEvents { ref mut symbol, .. }
+            | Event::TradingHours { ref mut symbol, .. } | Event::Trade { ref mut symbol, .. } | Event::BarClosed { ref mut symbol, .. }
+            | Event::SessionStats { ref mut symbol, .. } | Event::NewsItem { ref mut symbol, .. } => Some(symbol),
+            Event::ServerError { .. } | Event::FeedStale { .. } | Event::EconomicEvent { .. } | Event::CommandResult { .. }
+            | Event::ClockSkew { .. } | Event::Connected { .. } | Event::Stats { .. } | Event::ProtocolViolation { .. } => None,
+        }
+    }
+}
+
+impl From<CommandResult> for Event {
+    fn from(result: CommandResult) -> Event {
+        let CommandResult { command, session, target, outcome, elapsed, correlation_id } = result;
+        Event::CommandResult { command, session, target, outcome, elapsed, correlation_id }
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use log::info;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::duration;
+use crate::websocket::encode_server_frame;
+
+// how often each fault hits a text frame from the server, from 0 to 1; a seed makes a run repeatable
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultConfig {
+    pub drop: f64,
+    pub duplicate: f64,
+    // cuts the payload in half, leaving a ~m~ length that doesn't match
+    pub truncate: f64,
+    pub delay: f64,
+    pub delay_for: Duration,
+    // hands over half the frame, then the connection ends
+    pub disconnect: f64,
+    // hands the frame over in two reads, so it has to be reassembled
+    pub split: f64,
+    pub seed: Option<u64>
+}
+
+// e.g. "drop=0.01,duplicate=0.01,truncate=0.005,delay=0.05:200ms,disconnect=0.001,split=0.2,seed=7"
+impl std::str::FromStr for FaultConfig {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<FaultConfig, String> {
+        let mut config = FaultConfig { delay_for: Duration::from_millis(100), ..FaultConfig::default() };
+        for part in value.split(',').filter(|part| !part.is_empty()) {
+            let (name, setting) = part.split_once('=').ok_or_else(|| value.to_string())?;
+            let probability = |text: &str| text.parse::<f64>().ok().filter(|p| (0.0..=1.0).contains(p)).ok_or_else(|| value.to_string());
+            match name {
+                "drop" => config.drop = probability(setting)?,
+                "duplicate" => config.duplicate = probability(setting)?,
+                "truncate" => config.truncate = probability(setting)?,
+                "disconnect" => config.disconnect = probability(setting)?,
+                "split" => config.split = probability(setting)?,
+                "delay" => {
+                    let (chance, duration) = setting.split_once(':').unwrap_or((setting, ""));
+                    config.delay = probability(chance)?;
+                    if !duration.is_empty() {
+                        config.delay_for = duration::parse(duration).ok_or_else(|| value.to_string())?;
+                    }
+                }
+                "seed" => config.seed = Some(setting.parse().map_err(|_| value.to_string())?),
+                _ => return Err(value.to_string()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+// (opcode, header size, payload size) of the whole server frame at the start of the buffer
+fn frame_header(buffer: &[u8]) -> Option<(u8, usize, usize)> {
+    let opcode = *buffer.first()? & 0x0F;
+    let (length, header_size) = match *buffer.get(1)? & 0x7F {
+        126 => (u16::from_be_bytes(buffer.get(2..4)?.try_into().ok()?) as u64, 4usize),
+        127 => (u64::from_be_bytes(buffer.get(2..10)?.try_into().ok()?), 10),
+        length => (length as u64, 2),
+    };
+    let length = usize::try_from(length).ok()?;
+    (buffer.len() >= header_size.checked_add(length)?).then_some((opcode, header_size, length))
+}
+
+// wraps the websocket's byte stream and mangles whole server frames on the way in, to exercise
+// reassembly, reconnects and gap filling; writes pass through untouched
+pub struct FaultyTransport<T> {
+    inner: T,
+    config: FaultConfig,
+    rng: StdRng,
+    // bytes read but not yet a whole frame
+    raw: Vec<u8>,
+    // what the next reads hand over, one chunk each
+    pending: VecDeque<Vec<u8>>,
+    // the read after the pending chunks reports end of stream
+    disconnected: bool
+}
+
+impl<T> FaultyTransport<T> {
+    pub fn new(inner: T, config: FaultConfig) -> FaultyTransport<T> {
+        let rng = config.seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+        FaultyTransport { inner, config, rng, raw: vec![], pending: VecDeque::new(), disconnected: false }
+    }
+
+    // bytes read off the stream before it was wrapped, like the frames that came with the
+    // handshake response; they get the same faults as the ones read later
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.raw.extend_from_slice(bytes);
+        while let Some((opcode, header_size, length)) = frame_header(&self.raw) {
+            let frame: Vec<u8> = self.raw.drain(..header_size + length).collect();
+            self.inject(frame, opcode, header_size);
+        }
+    }
+
+    fn hits(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.gen_bool(probability)
+    }
+
+    fn inject(&mut self, frame: Vec<u8>, opcode: u8, header_size: usize) {
+        // the rest of the read never made it
+        if self.disconnected {
+            return;
+        }
+        // close frames and the like always go through
+        if opcode != 0x01 {
+            self.pending.push_back(frame);
+            return;
+        }
+        if self.hits(self.config.drop) {
+            info!("fault injection: dropped a {} byte frame", frame.len());
+            return;
+        }
+        if self.hits(self.config.disconnect) {
+            info!("fault injection: disconnecting mid-frame");
+            self.pending.push_back(frame[..frame.len() / 2].to_vec());
+            self.disconnected = true;
+            return;
+        }
+        let mut frame = frame;
+        if self.hits(self.config.truncate) {
+            let payload = &frame[header_size..];
+            info!("fault injection: truncated a {} byte frame", frame.len());
+            frame = encode_server_frame(opcode, &payload[..payload.len() / 2]);
+        }
+        if self.hits(self.config.delay) {
+            info!("fault injection: delaying a frame by {:?}", self.config.delay_for);
+            thread::sleep(self.config.delay_for);
+        }
+        let copies = if self.hits(self.config.duplicate) {
+            info!("fault injection: duplicated a frame");
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            if frame.len() > 1 && self.hits(self.config.split) {
+                let (first, second) = frame.split_at(frame.len() / 2);
+                self.pending.push_back(first.to_vec());
+                self.pending.push_back(second.to_vec());
+            } else {
+                self.pending.push_back(frame.clone());
+            }
+        }
+    }
+}
+
+impl<T: Read> Read for FaultyTransport<T> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(mut chunk) = self.pending.pop_front() {
+                let size = chunk.len().min(buffer.len());
+                buffer[..size].copy_from_slice(&chunk[..size]);
+                if size < chunk.len() {
+                    self.pending.push_front(chunk.split_off(size));
+                }
+                return Ok(size);
+            }
+            if self.disconnected {
+                return Ok(0);
+            }
+            let mut chunk = [0u8; 65536];
+            let read = self.inner.read(&mut chunk)?;
+            if read == 0 {
+                // whatever is left of a partial frame goes out as is
+                if !self.raw.is_empty() {
+                    self.pending.push_back(std::mem::take(&mut self.raw));
+                    self.disconnected = true;
+                    continue;
+                }
+                return Ok(0);
+            }
+            self.feed(&chunk[..read]);
+        }
+    }
+}
+
+impl<T: Write> Write for FaultyTransport<T> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.inner.write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
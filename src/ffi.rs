@@ -0,0 +1,221 @@
+// extern "C" interface for embedding a quote feed in C and C++ programs, see
+// include/tradingview_websocket.h. Nothing here panics across the boundary: a panic comes out as
+// the function's failure value
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::ack::CorrelationId;
+use crate::client::Connection;
+use crate::event::Event;
+use crate::quote::QuoteField;
+use crate::symbol;
+use crate::tradingview_api::{ApiCommand, ApiOptions, Mode};
+
+/// The call succeeded.
+pub const TV_OK: i32 = 0;
+/// A pointer was null, a string wasn't UTF-8 or a symbol isn't valid.
+pub const TV_INVALID_ARGUMENT: i32 = -1;
+/// The connection has ended; destroy the client and create a new one.
+pub const TV_CLOSED: i32 = -2;
+/// Something went wrong inside the library.
+pub const TV_INTERNAL_ERROR: i32 = -3;
+
+const DEFAULT_FIELDS: [QuoteField; 3] = [QuoteField::LastPrice, QuoteField::Change, QuoteField::ChangePercent];
+
+/// A connection streaming quotes for the subscribed symbols. Only use it from one thread at a time.
+pub struct TvClient {
+    connection: Connection,
+    // with the correlation id of the subscribe that added each
+    symbols: Vec<(String, CorrelationId)>,
+    // the closed event has been handed out
+    closed: bool
+}
+
+impl TvClient {
+    // the command's correlation id, which the symbols it adds are tagged with; None once the
+    // connection has ended
+    fn set_symbols(&mut self, symbols: Vec<String>) -> Option<CorrelationId> {
+        let correlation_id = self.connection.command(ApiCommand::SetQuoteSymbols(symbols.clone()))?;
+        self.symbols.retain(|(subscribed, _)| symbols.contains(subscribed));
+        for symbol in symbols {
+            if self.subscribed(&symbol).is_none() {
+                self.symbols.push((symbol, correlation_id));
+            }
+        }
+        Some(correlation_id)
+    }
+
+    fn subscribed(&self, symbol: &str) -> Option<CorrelationId> {
+        self.symbols.iter().find(|(subscribed, _)| subscribed == symbol).map(|&(_, correlation_id)| correlation_id)
+    }
+
+    // why the connection ended, from whichever thread knows
+    fn close_reason(&mut self) -> Option<String> {
+        match self.connection.close() {
+            Ok(()) => None,
+            Err(err) => Some(err.to_string()),
+        }
+    }
+}
+
+fn guard<T>(failed: T, call: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or(failed)
+}
+
+unsafe fn text<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    CStr::from_ptr(value).to_str().ok()
+}
+
+// the event in the crate's schema, except that quote values and the values of each study point
+// are objects keyed by field and plot name, which C callers look up without knowing the order;
+// NaN and infinite values come out as null
+pub fn event_json(event: &Event) -> Option<Value> {
+    let mut json = serde_json::to_value(event).ok()?;
+    match *event {
+        Event::Quote { ref values, .. } => {
+            let values: Map<String, Value> = values.iter().map(|(field, value)| (field.as_str().to_string(), value.clone())).collect();
+            json["values"] = Value::Object(values);
+        }
+        Event::StudyValues { ref plots, ref points, .. } => {
+            json["points"] = points.iter().map(|point| {
+                let values: Map<String, Value> = point.named(plots).map(|(name, value)| (name, json!(value))).collect();
+                json!({ "time": point.time, "values": values })
+            }).collect();
+        }
+        _ => {}
+    }
+    Some(json)
+}
+
+fn into_c_string(value: &Value) -> *mut c_char {
+    // json escapes control characters, so there is no nul to trip over
+    CString::new(value.to_string()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Connects and starts an empty quote session. `quote_fields` is a comma separated list of
+/// field names like "lp,ch,chp,volume", or null for "lp,ch,chp". Returns null when a field
+/// isn't known. Free the client with `tv_client_destroy`.
+///
+/// # Safety
+/// `quote_fields` is null or a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tv_client_create(quote_fields: *const c_char) -> *mut TvClient {
+    guard(ptr::null_mut(), || {
+        let fields = if quote_fields.is_null() {
+            DEFAULT_FIELDS.to_vec()
+        } else {
+            let names = match text(quote_fields) {
+                Some(names) => names,
+                None => return ptr::null_mut(),
+            };
+            let fields: Option<Vec<QuoteField>> = names.split(',').map(str::trim).filter(|name| !name.is_empty()).map(QuoteField::from_name).collect();
+            match fields {
+                Some(fields) if !fields.is_empty() => fields,
+                _ => return ptr::null_mut(),
+            }
+        };
+        let options = ApiOptions { quote_fields: fields, ..ApiOptions::default() };
+        let connection = Connection::open(Mode::Quotes(vec![]), options);
+        Box::into_raw(Box::new(TvClient { connection, symbols: vec![], closed: false }))
+    })
+}
+
+/// Adds a symbol like "NASDAQ:AAPL" to the quote session. Returns the correlation id (1 or more)
+/// that the symbol's quote events carry as "correlation_id", the same id again when the symbol
+/// is already subscribed, or `TV_INVALID_ARGUMENT` or `TV_CLOSED`.
+///
+/// # Safety
+/// `client` comes from `tv_client_create` and `symbol` is a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tv_client_subscribe(client: *mut TvClient, symbol: *const c_char) -> i64 {
+    guard(TV_INTERNAL_ERROR.into(), || {
+        let (client, symbol) = match (client.as_mut(), text(symbol)) {
+            (Some(client), Some(symbol)) if symbol::validate(symbol).is_ok() => (client, symbol),
+            _ => return TV_INVALID_ARGUMENT.into(),
+        };
+        let correlation_id = client.subscribed(symbol).or_else(|| {
+            let mut symbols: Vec<String> = client.symbols.iter().map(|(subscribed, _)| subscribed.clone()).collect();
+            symbols.push(symbol.to_string());
+            client.set_symbols(symbols)
+        });
+        match correlation_id.and_then(|correlation_id| i64::try_from(correlation_id).ok()) {
+            Some(correlation_id) => correlation_id,
+            None => TV_CLOSED.into(),
+        }
+    })
+}
+
+/// Removes a symbol from the quote session. Unsubscribing a symbol that isn't subscribed succeeds.
+///
+/// # Safety
+/// `client` comes from `tv_client_create` and `symbol` is a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tv_client_unsubscribe(client: *mut TvClient, symbol: *const c_char) -> i32 {
+    guard(TV_INTERNAL_ERROR, || {
+        let (client, symbol) = match (client.as_mut(), text(symbol)) {
+            (Some(client), Some(symbol)) => (client, symbol),
+            _ => return TV_INVALID_ARGUMENT,
+        };
+        if client.subscribed(symbol).is_none() {
+            return TV_OK;
+        }
+        let symbols = client.symbols.iter().map(|(subscribed, _)| subscribed.clone()).filter(|subscribed| subscribed != symbol).collect();
+        client.set_symbols(symbols).map_or(TV_CLOSED, |_| TV_OK)
+    })
+}
+
+/// Waits up to `timeout_ms` for the next event and returns it as JSON in the crate's event schema,
+/// with quote and study values as objects keyed by name, e.g.
+/// {"type":"quote","symbol":"NASDAQ:AAPL","values":{"lp":189.5}}. Returns null when
+/// nothing came in time. When the connection ends it returns {"type":"closed","error":...} once,
+/// then only null. Free the string with `tv_string_free`.
+///
+/// # Safety
+/// `client` comes from `tv_client_create`.
+#[no_mangle]
+pub unsafe extern "C" fn tv_client_poll(client: *mut TvClient, timeout_ms: u32) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let client = match client.as_mut() {
+            Some(client) if !client.closed => client,
+            _ => return ptr::null_mut(),
+        };
+        match client.connection.events_rx.recv_timeout(Duration::from_millis(timeout_ms.into())) {
+            Ok(event) => event_json(&event).map_or(ptr::null_mut(), |event| into_c_string(&event)),
+            Err(RecvTimeoutError::Timeout) => ptr::null_mut(),
+            Err(RecvTimeoutError::Disconnected) => {
+                client.closed = true;
+                into_c_string(&json!({ "type": "closed", "error": client.close_reason() }))
+            }
+        }
+    })
+}
+
+/// Frees a string returned by `tv_client_poll`; null is ignored.
+///
+/// # Safety
+/// `value` comes from `tv_client_poll` and isn't used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tv_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// Closes the connection and frees the client; null is ignored.
+///
+/// # Safety
+/// `client` comes from `tv_client_create` and isn't used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tv_client_destroy(client: *mut TvClient) {
+    if !client.is_null() {
+        guard((), || drop(Box::from_raw(client)));
+    }
+}
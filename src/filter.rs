@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::ack::CorrelationId;
+use crate::duration;
+use crate::event::{Event, EVENT_TYPES};
+use crate::quote::QuoteField;
+use crate::router;
+
+// what one consumer wants of the symbols matching a route-style pattern ("*", "BINANCE:*",
+// "BTCUSD" or "BINANCE:BTCUSDT"); an empty list keeps everything
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventFilter {
+    pub symbols: String,
+    // event types as in json, e.g. "quote" or "bar_closed"
+    pub events: Vec<String>,
+    // Quote events keep only these values, QuoteFieldChanged only these fields
+    pub quote_fields: Vec<QuoteField>,
+    // at most one quote per symbol this often; bars, trades and study values are never sampled,
+    // a dropped one would be a hole in the series
+    pub every: Option<Duration>
+}
+
+// symbols=BINANCE:*,events=quote+bar_closed,fields=lp+ch,every=1s; lists are joined with +
+impl std::str::FromStr for EventFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<EventFilter, String> {
+        let mut filter = EventFilter { symbols: "*".to_string(), events: vec![], quote_fields: vec![], every: None };
+        for part in value.split(',').filter(|part| !part.is_empty()) {
+            let (name, setting) = part.split_once('=').ok_or_else(|| value.to_string())?;
+            let list = setting.split('+').filter(|item| !item.is_empty());
+            match name {
+                "symbols" if !setting.is_empty() => filter.symbols = setting.to_string(),
+                "events" => for event in list {
+                    if !EVENT_TYPES.contains(&event) {
+                        return Err(value.to_string());
+                    }
+                    filter.events.push(event.to_string());
+                },
+                "fields" => for field in list {
+                    filter.quote_fields.push(QuoteField::from_name(field).ok_or_else(|| value.to_string())?);
+                },
+                "every" => filter.every = Some(duration::parse(setting).filter(|every| !every.is_zero()).ok_or_else(|| value.to_string())?),
+                _ => return Err(value.to_string()),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+// quote values the sampling held back, sent on their own at due unless a quote passes first
+#[derive(Debug)]
+struct HeldQuote {
+    values: Vec<(QuoteField, Value)>,
+    correlation_id: Option<CorrelationId>,
+    local_time_ms: Option<i64>,
+    due: Instant
+}
+
+// the first filter whose pattern matches an event's symbol decides about it; events without a
+// symbol, and those of symbols no filter matches, pass as they are. Quote values dropped by the
+// sampling are carried into the next quote of the symbol that passes, or go out once the
+// symbol's interval is over, so no field is lost or left stale
+#[derive(Debug, Default)]
+pub struct FilterChain {
+    filters: Vec<EventFilter>,
+    last_passed: HashMap<String, Instant>,
+    held_quotes: HashMap<String, HeldQuote>
+}
+
+impl FilterChain {
+    pub fn new(filters: Vec<EventFilter>) -> FilterChain {
+        FilterChain { filters, ..FilterChain::default() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    // what's left of the event for the consumer, None to drop it
+    pub fn apply(&mut self, mut event: Event, now: Instant) -> Option<Event> {
+        let symbol = match event.symbol() {
+            Some(symbol) => symbol.to_string(),
+            None => return Some(event),
+        };
+        let filter = match self.filters.iter().find(|filter| router::symbol_matches(&filter.symbols, &symbol)) {
+            Some(filter) => filter,
+            None => return Some(event),
+        };
+        let type_name = event.type_name();
+        if !filter.events.is_empty() && !filter.events.iter().any(|wanted| wanted == type_name) {
+            return None;
+        }
+        if !filter.quote_fields.is_empty() {
+            match event {
+                Event::Quote { ref mut values, .. } => values.retain(|(field, _)| filter.quote_fields.contains(field)),
+                Event::QuoteFieldChanged { field, .. } if !filter.quote_fields.contains(&field) => return None,
+                _ => {}
+            }
+        }
+        if let Event::Quote { ref values, .. } = event {
+            if values.is_empty() {
+                return None;
+            }
+        }
+        let (every, values, correlation_id, local_time_ms) = match (filter.every, &mut event) {
+            (Some(every), Event::Quote { values, correlation_id, local_time_ms, .. }) => (every, values, *correlation_id, local_time_ms),
+            _ => return Some(event),
+        };
+        if let Some(&last_passed) = self.last_passed.get(&symbol).filter(|last_passed| now.saturating_duration_since(**last_passed) < every) {
+            let held = self.held_quotes.entry(symbol).or_insert_with(|| HeldQuote { values: vec![], correlation_id, local_time_ms: None, due: last_passed + every });
+            merge(&mut held.values, std::mem::take(values));
+            held.local_time_ms = local_time_ms.or(held.local_time_ms);
+            return None;
+        }
+        if let Some(mut held) = self.held_quotes.remove(&symbol) {
+            merge(&mut held.values, std::mem::take(values));
+            *values = held.values;
+            *local_time_ms = local_time_ms.or(held.local_time_ms);
+        }
+        self.last_passed.insert(symbol, now);
+        Some(event)
+    }
+
+    // when the next held quote is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.held_quotes.values().map(|held| held.due).min()
+    }
+
+    // the held quotes whose interval is over, each starting its symbol's next interval
+    pub fn due(&mut self, now: Instant) -> Vec<Event> {
+        let symbols: Vec<String> = self.held_quotes.iter().filter(|(_, held)| held.due <= now).map(|(symbol, _)| symbol.clone()).collect();
+        symbols.into_iter().filter_map(|symbol| {
+            let held = self.held_quotes.remove(&symbol)?;
+            self.last_passed.insert(symbol.clone(), now);
+            Some(Event::Quote { symbol, values: held.values, correlation_id: held.correlation_id, local_time_ms: held.local_time_ms })
+        }).collect()
+    }
+}
+
+// newer values replace older ones of the same field
+fn merge(held: &mut Vec<(QuoteField, Value)>, values: Vec<(QuoteField, Value)>) {
+    for (field, value) in values {
+        match held.iter_mut().find(|(held_field, _)| *held_field == field) {
+            Some(held) => held.1 = value,
+            None => held.push((field, value)),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::stats::Stats;
+
+// a probe that doesn't finish its request in this long is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum HealthError {
+    #[error("couldn't listen on {addr}")]
+    Bind { addr: String, #[source] source: io::Error },
+    #[error("couldn't start the health thread")]
+    Spawn(#[source] io::Error)
+}
+
+// which connections are up and when each of them and their sessions last heard from the server;
+// shared by every api of the process and read by the /healthz endpoint
+pub struct Health {
+    started: Instant,
+    next_connection: AtomicU64,
+    // (connection, session) to the last message for it
+    sessions: Mutex<HashMap<(u64, &'static str), Instant>>,
+    // connection to the last message of any kind, pings included
+    connections: Mutex<HashMap<u64, Instant>>
+}
+
+impl Default for Health {
+    fn default() -> Health {
+        Health {
+            started: Instant::now(),
+            next_connection: AtomicU64::new(1),
+            sessions: Mutex::default(),
+            connections: Mutex::default()
+        }
+    }
+}
+
+impl Health {
+    // counts as up until the returned guard is dropped
+    pub fn connect(self: &Arc<Health>) -> HealthConnection {
+        let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.insert(id, Instant::now());
+        }
+        HealthConnection { health: Arc::clone(self), id }
+    }
+
+    pub fn report(&self, stats: &Stats, max_age: Duration) -> HealthReport {
+        let mut connections: Vec<ConnectionHealth> = self.connections.lock().map(|connections| connections.iter().map(|(&connection, last)| ConnectionHealth {
+            connection,
+            last_message_age: last.elapsed().as_secs_f64()
+        }).collect()).unwrap_or_default();
+        connections.sort_by_key(|connection| connection.connection);
+        let mut sessions: Vec<SessionHealth> = self.sessions.lock().map(|sessions| sessions.iter().map(|(&(connection, session), last)| SessionHealth {
+            connection,
+            session,
+            last_message_age: last.elapsed().as_secs_f64()
+        }).collect()).unwrap_or_default();
+        sessions.sort_by(|a, b| (a.connection, a.session).cmp(&(b.connection, b.session)));
+        // the server pings every connection, so a quiet market leaves sessions silent but not the
+        // connection; only a connection that stopped hearing anything at all is unhealthy
+        let healthy = !connections.is_empty()
+            && connections.iter().all(|connection| connection.last_message_age <= max_age.as_secs_f64());
+        HealthReport {
+            status: if healthy { "ok" } else { "unhealthy" },
+            uptime: self.started.elapsed().as_secs_f64(),
+            connections: connections.len(),
+            reconnects: stats.snapshot().reconnects,
+            connection_ages: connections,
+            sessions
+        }
+    }
+}
+
+// one connection's part of the health state, removed again on drop
+pub struct HealthConnection {
+    health: Arc<Health>,
+    id: u64
+}
+
+impl HealthConnection {
+    // session is the one the message names, None for pings and other connection level ones
+    pub fn record_message(&self, session: Option<&'static str>) {
+        let now = Instant::now();
+        if let Ok(mut connections) = self.health.connections.lock() {
+            connections.insert(self.id, now);
+        }
+        if let (Some(session), Ok(mut sessions)) = (session, self.health.sessions.lock()) {
+            sessions.insert((self.id, session), now);
+        }
+    }
+}
+
+impl Drop for HealthConnection {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.health.connections.lock() {
+            connections.remove(&self.id);
+        }
+        if let Ok(mut sessions) = self.health.sessions.lock() {
+            sessions.retain(|&(connection, _), _| connection != self.id);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConnectionHealth {
+    pub connection: u64,
+    // seconds
+    pub last_message_age: f64
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionHealth {
+    pub connection: u64,
+    pub session: &'static str,
+    // seconds
+    pub last_message_age: f64
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    // "ok" when a connection is up and none has been quiet for longer than the max age
+    pub status: &'static str,
+    // seconds
+    pub uptime: f64,
+    pub connections: usize,
+    pub reconnects: u64,
+    pub connection_ages: Vec<ConnectionHealth>,
+    // for telling which feed went quiet, they don't decide the status
+    pub sessions: Vec<SessionHealth>
+}
+
+// answers GET /healthz with the report as json, 200 when healthy and 503 otherwise, for liveness
+// and readiness probes; runs until the process exits. Each request gets its own thread so a probe
+// that connects and never sends can't hold up the ones behind it
+pub fn serve(addr: &str, health: Arc<Health>, stats: Arc<Stats>, max_age: Duration) -> Result<JoinHandle<()>, HealthError> {
+    let listener = TcpListener::bind(addr).map_err(|source| HealthError::Bind { addr: addr.to_string(), source })?;
+    thread::Builder::new().name("health".to_string()).spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("health request failed: {}", err);
+                    continue;
+                }
+            };
+            let (health, stats) = (Arc::clone(&health), Arc::clone(&stats));
+            let spawned = thread::Builder::new().name("health-request".to_string()).spawn(move || {
+                if let Err(err) = respond(stream, &health, &stats, max_age) {
+                    debug!("health request failed: {}", err);
+                }
+            });
+            if let Err(err) = spawned {
+                warn!("couldn't start a health request thread: {}", err);
+            }
+        }
+        warn!("health endpoint stopped");
+    }).map_err(HealthError::Spawn)
+}
+
+fn respond(stream: TcpStream, health: &Health, stats: &Stats, max_age: Duration) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers aren't needed, but the client expects them to be read
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("");
+    let (status, body) = match (method, path) {
+        ("GET" | "HEAD", "/healthz") => {
+            let report = health.report(stats, max_age);
+            let status = if report.status == "ok" { "200 OK" } else { "503 Service Unavailable" };
+            (status, serde_json::to_string(&report).map_err(io::Error::other)?)
+        }
+        (_, "/healthz") => ("405 Method Not Allowed", String::new()),
+        _ => ("404 Not Found", String::new()),
+    };
+    let mut stream = reader.into_inner();
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len())?;
+    if method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
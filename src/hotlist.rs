@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::scanner::{self, ScannerError};
+use crate::tradingview_api::ApiCommand;
+use crate::websocket::Incoming;
+
+const DEFAULT_COUNT: usize = 20;
+
+// how often a refresher waiting for its next fetch looks at the shutdown flag
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HotlistKind {
+    Gainers,
+    Losers,
+    // most shares traded
+    Volume,
+    // most money traded
+    Active
+}
+
+impl HotlistKind {
+    // the scanner column to sort on and its order
+    fn sort(&self) -> (&'static str, &'static str) {
+        match *self {
+            HotlistKind::Gainers => ("change", "desc"),
+            HotlistKind::Losers => ("change", "asc"),
+            HotlistKind::Volume => ("volume", "desc"),
+            HotlistKind::Active => ("Value.Traded", "desc"),
+        }
+    }
+}
+
+// e.g. the top 20 gainers on NASDAQ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotlist {
+    pub kind: HotlistKind,
+    pub exchange: String,
+    pub count: usize
+}
+
+impl Hotlist {
+    pub fn fetch(&self) -> Result<Vec<String>, ScannerError> {
+        let (symbols, _) = scanner::scan(&self.exchange, Some(self.kind.sort()), (0, self.count))?;
+        Ok(symbols)
+    }
+}
+
+// gainers:NASDAQ, losers:NYSE:50, volume:AMEX or active:LSE:10
+impl std::str::FromStr for Hotlist {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Hotlist, String> {
+        let mut parts = value.split(':');
+        let kind = match parts.next() {
+            Some("gainers") => HotlistKind::Gainers,
+            Some("losers") => HotlistKind::Losers,
+            Some("volume") => HotlistKind::Volume,
+            Some("active") => HotlistKind::Active,
+            _ => return Err(value.to_string()),
+        };
+        let exchange = match parts.next() {
+            Some(exchange) if scanner::market(&exchange.to_uppercase()).is_some() => exchange.to_uppercase(),
+            _ => return Err(value.to_string()),
+        };
+        let count = match parts.next() {
+            Some(count) => count.parse().ok().filter(|count| *count > 0).ok_or_else(|| value.to_string())?,
+            None => DEFAULT_COUNT,
+        };
+        if parts.next().is_some() {
+            return Err(value.to_string());
+        }
+        Ok(Hotlist { kind, exchange, count })
+    }
+}
+
+// fetches the list again every so often and hands the api the new symbol set; a failed fetch
+// keeps the previous one. Stops on shutdown or once the connection is gone; the sender is only
+// borrowed for each send, so the api sees the websocket hang up
+pub fn spawn_refresher(hotlist: Hotlist, every: Duration, incoming_tx: Arc<Mutex<Option<Sender<Incoming>>>>, shutdown: Arc<AtomicBool>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut next_fetch = Instant::now() + every;
+        while !shutdown.load(Ordering::SeqCst) {
+            if Instant::now() < next_fetch {
+                thread::sleep(POLL_INTERVAL.min(next_fetch.saturating_duration_since(Instant::now())));
+                continue;
+            }
+            next_fetch = Instant::now() + every;
+            match hotlist.fetch() {
+                Ok(symbols) => {
+                    info!("refreshed {:?} {} hotlist, {} symbols", hotlist.kind, hotlist.exchange, symbols.len());
+                    let incoming_tx = match incoming_tx.lock().ok().and_then(|incoming_tx| incoming_tx.clone()) {
+                        Some(incoming_tx) => incoming_tx,
+                        None => return,
+                    };
+                    if incoming_tx.send(Incoming::Command(ApiCommand::SetQuoteSymbols(symbols), None)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => warn!("couldn't refresh the {} hotlist: {}", hotlist.exchange, err),
+            }
+        }
+    })
+}
@@ -0,0 +1,177 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use native_tls::TlsConnector;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum HttpError {
+    UrlParseError,
+    AddressParseError,
+    ConnectError,
+    TlsCreationError,
+    TlsConnectError,
+    WriteError,
+    ReadError,
+    ResponseParseError,
+    StatusError(u16)
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            HttpError::UrlParseError => write!(f, "URL parse error"),
+            HttpError::AddressParseError => write!(f, "Address parse error"),
+            HttpError::ConnectError => write!(f, "Connect error"),
+            HttpError::TlsCreationError => write!(f, "TLS creation error"),
+            HttpError::TlsConnectError => write!(f, "TLS connect error"),
+            HttpError::WriteError => write!(f, "Write error"),
+            HttpError::ReadError => write!(f, "Read error"),
+            HttpError::ResponseParseError => write!(f, "Response parse error"),
+            HttpError::StatusError(status) => write!(f, "Unexpected HTTP status {}", status),
+        }
+    }
+}
+
+impl Error for HttpError {}
+
+impl From<HttpError> for Box<dyn std::error::Error + Send> {
+    fn from(error: HttpError) -> Self {
+        Box::new(error)
+    }
+}
+
+// callers such as a token refresh block on the request, so a server that stops answering can't
+// hold them up for longer than this at each step
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String
+}
+
+// splits an https url into (host, path)
+fn split_url(url: &str) -> Result<(&str, &str), HttpError> {
+    let rest = url.strip_prefix("https://").ok_or(HttpError::UrlParseError)?;
+    match rest.find('/') {
+        Some(index) => Ok((&rest[..index], &rest[index..])),
+        None => Ok((rest, "/"))
+    }
+}
+
+// a chunked transfer-encoded body, which must end in its zero-size chunk
+pub fn decode_chunked(body: &[u8]) -> Result<Vec<u8>, HttpError> {
+    let mut decoded = vec![];
+    let mut position = 0;
+    loop {
+        let line_end = body[position..].windows(2).position(|window| window == b"\r\n").ok_or(HttpError::ResponseParseError)?;
+        let size_line = std::str::from_utf8(&body[position..position + line_end]).map_err(|_| HttpError::ResponseParseError)?;
+        let size_hex = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| HttpError::ResponseParseError)?;
+        position += line_end + 2;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        // the chunk and the CRLF after it
+        let chunk_end = position.checked_add(size).ok_or(HttpError::ResponseParseError)?;
+        if !body.get(chunk_end..).is_some_and(|rest| rest.starts_with(b"\r\n")) {
+            return Err(HttpError::ResponseParseError);
+        }
+        decoded.extend_from_slice(&body[position..chunk_end]);
+        position = chunk_end + 2;
+    }
+}
+
+pub fn request(method: &str, url: &str, headers: &[(&str, &str)], body: Option<&str>) -> Result<HttpResponse, Box<dyn Error + Send>> {
+    let (host, path) = split_url(url)?;
+    let addr = format!("{}:443", host).to_socket_addrs().map_err(|_| HttpError::AddressParseError)?.next().ok_or(HttpError::AddressParseError)?;
+    let stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(|_| HttpError::ConnectError)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|_| HttpError::ConnectError)?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(|_| HttpError::ConnectError)?;
+    let connector = TlsConnector::new().map_err(|_| HttpError::TlsCreationError)?;
+    let mut tls_stream = connector.connect(host, stream).map_err(|_| HttpError::TlsConnectError)?;
+
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: tradingview-websocket\r\n", method, path, host);
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if let Some(body) = body {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    if let Some(body) = body {
+        request.push_str(body);
+    }
+    tls_stream.write_all(request.as_bytes()).map_err(|_| HttpError::WriteError)?;
+
+    // the server closes the connection once the response is sent
+    let mut raw_response = vec![];
+    tls_stream.read_to_end(&mut raw_response).map_err(|_| HttpError::ReadError)?;
+
+    let header_end = raw_response.windows(4).position(|window| window == b"\r\n\r\n").ok_or(HttpError::ResponseParseError)?;
+    let head = std::str::from_utf8(&raw_response[..header_end]).map_err(|_| HttpError::ResponseParseError)?;
+    let status = head.split(' ').nth(1).and_then(|status| status.parse::<u16>().ok()).ok_or(HttpError::ResponseParseError)?;
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    let raw_body = &raw_response[header_end + 4..];
+    let body_bytes = if chunked { decode_chunked(raw_body)? } else { raw_body.to_vec() };
+
+    Ok(HttpResponse {
+        status,
+        body: String::from_utf8_lossy(&body_bytes).into_owned()
+    })
+}
+
+pub fn get(url: &str, headers: &[(&str, &str)]) -> Result<HttpResponse, Box<dyn Error + Send>> {
+    request("GET", url, headers, None)
+}
+
+pub fn post_json(url: &str, body: &serde_json::Value) -> Result<HttpResponse, Box<dyn Error + Send>> {
+    let body = serde_json::to_string(body).map_err(|_| HttpError::WriteError)?;
+    let response = request("POST", url, &[("Content-Type", "application/json")], Some(&body))?;
+    if !(200..300).contains(&response.status) {
+        return Err(HttpError::StatusError(response.status).into());
+    }
+    Ok(response)
+}
+
+// also right for a value in a url's query
+fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// name=value pairs for a form body or a url's query
+fn form(fields: &[(&str, &str)]) -> String {
+    fields.iter()
+        .map(|(name, value)| format!("{}={}", form_encode(name), form_encode(value)))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+// the url with the params as its query, so a search text with & or spaces can't break out
+pub fn with_query(url: &str, params: &[(&str, &str)]) -> String {
+    format!("{}?{}", url, form(params))
+}
+
+pub fn post_form(url: &str, headers: &[(&str, &str)], fields: &[(&str, &str)]) -> Result<HttpResponse, Box<dyn Error + Send>> {
+    let body = form(fields);
+    let mut all_headers = vec![("Content-Type", "application/x-www-form-urlencoded")];
+    all_headers.extend_from_slice(headers);
+    let response = request("POST", url, &all_headers, Some(&body))?;
+    if !(200..300).contains(&response.status) {
+        return Err(HttpError::StatusError(response.status).into());
+    }
+    Ok(response)
+}
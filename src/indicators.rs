@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use crate::bar::Bar;
+use crate::study::PlotInfo;
+
+// simple moving average of closes, None until the window is full
+pub fn sma(bars: &[Bar], period: usize) -> Vec<Option<f64>> {
+    let mut values = Vec::with_capacity(bars.len());
+    let mut sum = 0.0;
+    for (index, bar) in bars.iter().enumerate() {
+        sum += bar.close;
+        if index >= period {
+            sum -= bars[index - period].close;
+        }
+        if period > 0 && index + 1 >= period {
+            values.push(Some(sum / period as f64));
+        } else {
+            values.push(None);
+        }
+    }
+    values
+}
+
+// exponential moving average of closes, seeded with the sma of the first period
+pub fn ema(bars: &[Bar], period: usize) -> Vec<Option<f64>> {
+    let mut values = Vec::with_capacity(bars.len());
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut current: Option<f64> = None;
+    let mut seed = 0.0;
+    for (index, bar) in bars.iter().enumerate() {
+        current = match current {
+            Some(previous) => Some(previous + alpha * (bar.close - previous)),
+            None => {
+                seed += bar.close;
+                (period > 0 && index + 1 == period).then(|| seed / period as f64)
+            }
+        };
+        values.push(current);
+    }
+    values
+}
+
+// wilder's relative strength index, None until period changes have been seen
+pub fn rsi(bars: &[Bar], period: usize) -> Vec<Option<f64>> {
+    let mut values = Vec::with_capacity(bars.len());
+    let (mut gain, mut loss) = (0.0, 0.0);
+    for (index, bar) in bars.iter().enumerate() {
+        let change = match index {
+            0 => {
+                values.push(None);
+                continue;
+            }
+            _ => bar.close - bars[index - 1].close,
+        };
+        let (up, down) = (change.max(0.0), (-change).max(0.0));
+        if period == 0 {
+            values.push(None);
+        } else if index <= period {
+            gain += up / period as f64;
+            loss += down / period as f64;
+            values.push((index == period).then(|| relative_strength(gain, loss)));
+        } else {
+            gain = (gain * (period - 1) as f64 + up) / period as f64;
+            loss = (loss * (period - 1) as f64 + down) / period as f64;
+            values.push(Some(relative_strength(gain, loss)));
+        }
+    }
+    values
+}
+
+fn relative_strength(gain: f64, loss: f64) -> f64 {
+    if loss == 0.0 {
+        return if gain == 0.0 { 50.0 } else { 100.0 };
+    }
+    100.0 - 100.0 / (1.0 + gain / loss)
+}
+
+// volume weighted typical price, starting over at every utc midnight
+pub fn vwap(bars: &[Bar]) -> Vec<Option<f64>> {
+    let mut values = Vec::with_capacity(bars.len());
+    let (mut day, mut weighted, mut volume) = (None, 0.0, 0.0);
+    for bar in bars {
+        let bar_day = bar.time.div_euclid(86400);
+        if day != Some(bar_day) {
+            day = Some(bar_day);
+            weighted = 0.0;
+            volume = 0.0;
+        }
+        weighted += (bar.high + bar.low + bar.close) / 3.0 * bar.volume;
+        volume += bar.volume;
+        values.push((volume > 0.0).then(|| weighted / volume));
+    }
+    values
+}
+
+// computed from cached bars for anyone who can't attach the pine study
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indicator {
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    Vwap
+}
+
+impl Indicator {
+    // reported as the study id of its StudyValues events
+    pub fn id(&self) -> String {
+        match *self {
+            Indicator::Sma(period) => format!("sma_{}", period),
+            Indicator::Ema(period) => format!("ema_{}", period),
+            Indicator::Rsi(period) => format!("rsi_{}", period),
+            Indicator::Vwap => "vwap".to_string(),
+        }
+    }
+
+    // a single line, named the way the built-in study names it
+    pub fn plots(&self) -> Arc<[PlotInfo]> {
+        let name = match *self {
+            Indicator::Sma(_) => "SMA",
+            Indicator::Ema(_) => "EMA",
+            Indicator::Rsi(_) => "RSI",
+            Indicator::Vwap => "VWAP",
+        };
+        Arc::from([PlotInfo { id: "plot_0".to_string(), name: name.to_string(), kind: "line".to_string(), color: None }])
+    }
+
+    pub fn compute(&self, bars: &[Bar]) -> Vec<Option<f64>> {
+        match *self {
+            Indicator::Sma(period) => sma(bars, period),
+            Indicator::Ema(period) => ema(bars, period),
+            Indicator::Rsi(period) => rsi(bars, period),
+            Indicator::Vwap => vwap(bars),
+        }
+    }
+
+    // bars of history that make the latest value settle; ema and rsi never fully forget,
+    // but ten periods back the difference is far below price precision
+    pub fn lookback(&self) -> usize {
+        match *self {
+            Indicator::Sma(period) => period,
+            Indicator::Ema(period) | Indicator::Rsi(period) => period.saturating_mul(10),
+            // a day of one minute bars
+            Indicator::Vwap => 1440,
+        }
+    }
+}
+
+// sma:20, ema:50, rsi:14 or vwap
+impl std::str::FromStr for Indicator {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Indicator, String> {
+        let period = |period: &str| period.parse::<usize>().ok().filter(|period| *period > 0).ok_or_else(|| value.to_string());
+        match value.split_once(':') {
+            Some(("sma", period_value)) => Ok(Indicator::Sma(period(period_value)?)),
+            Some(("ema", period_value)) => Ok(Indicator::Ema(period(period_value)?)),
+            Some(("rsi", period_value)) => Ok(Indicator::Rsi(period(period_value)?)),
+            None if value == "vwap" => Ok(Indicator::Vwap),
+            _ => Err(value.to_string()),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// what happens when the server breaks one of the protocol's invariants, see Violation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvariantMode {
+    // nothing is checked
+    #[default]
+    Off,
+    // each violation is logged and reported as a ProtocolViolation event
+    Report,
+    // debug builds panic on the first violation, release builds report it like Report
+    Assert
+}
+
+impl std::str::FromStr for InvariantMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<InvariantMode, String> {
+        match value {
+            "off" => Ok(InvariantMode::Off),
+            "report" => Ok(InvariantMode::Report),
+            "assert" => Ok(InvariantMode::Assert),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+// a message that doesn't fit what the connection asked for, usually the first sign the server's
+// protocol moved; message is the MessageType::name it came in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Violation {
+    // the message names a session that wasn't created, or not the one its type belongs to
+    UnknownSession { message: String, session: String },
+    // bars or study values under an id that no series or study was created with
+    UnknownSeries { message: String, series: String },
+    // a bar no later than the one before it in the same update, or a live update older than the
+    // newest bar of its series
+    BarTimeRegressed { message: String, series: String, previous: i64, time: i64 }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::UnknownSession { ref message, ref session } => write!(f, "{} for unknown session {:?}", message, session),
+            Violation::UnknownSeries { ref message, ref series } => write!(f, "{} for unknown series {:?}", message, series),
+            Violation::BarTimeRegressed { ref message, ref series, previous, time } => write!(f, "{} bar of {} at {} after {}", message, series, time, previous),
+        }
+    }
+}
+
+// the checks that need to remember earlier messages, one per connection
+pub struct InvariantChecker {
+    mode: InvariantMode,
+    // newest bar time per series id
+    newest: HashMap<String, i64>
+}
+
+impl InvariantChecker {
+    pub fn new(mode: InvariantMode) -> InvariantChecker {
+        InvariantChecker { mode, newest: HashMap::new() }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.mode != InvariantMode::Off
+    }
+
+    // whether a violation should stop the thread instead of being reported
+    pub fn panics(&self) -> bool {
+        self.mode == InvariantMode::Assert && cfg!(debug_assertions)
+    }
+
+    // times must rise within an update; live ones (du) also can't go back past the newest bar,
+    // history pages (timescale_update) are older by design
+    pub fn check_bar_times(&mut self, message: &str, series: &str, times: &[i64], live: bool) -> Vec<Violation> {
+        let newest = self.newest.get(series).copied();
+        let mut previous = if live { newest } else { None };
+        let mut violations = vec![];
+        for (index, &time) in times.iter().enumerate() {
+            // the open bar of a live series is sent again until it closes
+            let repeated = live && index == 0;
+            match previous {
+                Some(previous) if time < previous || (time == previous && !repeated) => {
+                    violations.push(Violation::BarTimeRegressed { message: message.to_string(), series: series.to_string(), previous, time });
+                }
+                _ => {}
+            }
+            previous = Some(time);
+        }
+        if let Some(&last) = times.iter().max() {
+            self.newest.insert(series.to_string(), newest.map_or(last, |newest| newest.max(last)));
+        }
+        violations
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use crate::event::Event;
+use crate::router;
+
+// the last events of one symbol, "" for those without one
+#[derive(Default)]
+struct Ring {
+    // how many were evicted, so the index of the front entry
+    first: u64,
+    // with the journal-wide sequence number that orders them across symbols
+    events: VecDeque<(u64, Arc<Event>)>
+}
+
+#[derive(Default)]
+struct Inner {
+    next_seq: u64,
+    rings: HashMap<String, Ring>,
+    closed: bool
+}
+
+// the events of one connection kept per symbol, the last window of each, for any number of
+// cursors to read at their own pace. A busy symbol can only push out its own history, so a
+// slow consumer loses old ticks of that symbol and nothing of the quiet ones
+pub struct EventJournal {
+    window: usize,
+    inner: Mutex<Inner>,
+    appended: Condvar
+}
+
+impl EventJournal {
+    pub fn new(window: usize) -> EventJournal {
+        EventJournal { window: window.max(1), inner: Mutex::default(), appended: Condvar::new() }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // the journal stays consistent between statements, so a panicking reader doesn't taint it
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn append(&self, event: Arc<Event>) {
+        let mut inner = self.lock();
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        let ring = inner.rings.entry(event.symbol().unwrap_or("").to_string()).or_default();
+        if ring.events.len() == self.window {
+            ring.events.pop_front();
+            ring.first += 1;
+        }
+        ring.events.push_back((seq, event));
+        drop(inner);
+        self.appended.notify_all();
+    }
+
+    // no more events will come, cursors get Disconnected once they have read the rest
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.appended.notify_all();
+    }
+
+    // reads the symbols matching a route-style pattern ("*", "BINANCE:*", "BTCUSD") from the
+    // oldest events still held; events without a symbol go to every cursor
+    pub fn cursor(self: &Arc<Self>, symbols: &str) -> JournalCursor {
+        let positions = self.lock().rings.iter().map(|(symbol, ring)| (symbol.clone(), ring.first)).collect();
+        JournalCursor { journal: Arc::clone(self), symbols: symbols.to_string(), positions, missed: 0 }
+    }
+}
+
+// one consumer's place in each symbol's ring
+pub struct JournalCursor {
+    journal: Arc<EventJournal>,
+    symbols: String,
+    // rings first seen after the cursor was made start at 0
+    positions: HashMap<String, u64>,
+    missed: u64
+}
+
+impl JournalCursor {
+    // events of the cursor's symbols that were evicted before it got to them
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    fn take(&mut self, inner: &Inner) -> Option<Arc<Event>> {
+        let mut oldest: Option<(&str, u64, &Arc<Event>)> = None;
+        for (symbol, ring) in &inner.rings {
+            if !symbol.is_empty() && !router::symbol_matches(&self.symbols, symbol) {
+                continue;
+            }
+            let position = self.positions.get(symbol).copied().unwrap_or(0).max(ring.first);
+            if let Some((seq, event)) = ring.events.get((position - ring.first) as usize) {
+                if oldest.is_none_or(|(_, oldest_seq, _)| *seq < oldest_seq) {
+                    oldest = Some((symbol, *seq, event));
+                }
+            }
+        }
+        let (symbol, _, event) = oldest?;
+        let event = Arc::clone(event);
+        // everything before the front of its ring that the cursor hadn't read is gone
+        let ring = &inner.rings[symbol];
+        let position = self.positions.entry(symbol.to_string()).or_insert(0);
+        self.missed += ring.first.saturating_sub(*position);
+        *position = (*position).max(ring.first) + 1;
+        Some(event)
+    }
+
+    pub fn try_next(&mut self) -> Option<Arc<Event>> {
+        let journal = Arc::clone(&self.journal);
+        let inner = journal.lock();
+        self.take(&inner)
+    }
+
+    // waits for the next event, Disconnected once the journal is closed and read to the end
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Arc<Event>, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let journal = Arc::clone(&self.journal);
+        let mut inner = journal.lock();
+        loop {
+            if let Some(event) = self.take(&inner) {
+                return Ok(event);
+            }
+            if inner.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            inner = journal.appended.wait_timeout(inner, left).unwrap_or_else(|poisoned| poisoned.into_inner()).0;
+        }
+    }
+}
+
+// blocks until the journal is closed and read to the end
+impl Iterator for JournalCursor {
+    type Item = Arc<Event>;
+
+    fn next(&mut self) -> Option<Arc<Event>> {
+        let journal = Arc::clone(&self.journal);
+        let mut inner = journal.lock();
+        loop {
+            if let Some(event) = self.take(&inner) {
+                return Some(event);
+            }
+            if inner.closed {
+                return None;
+            }
+            inner = journal.appended.wait(inner).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
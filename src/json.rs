@@ -0,0 +1,70 @@
+use serde_json::Value;
+#[cfg(feature = "simd-json")]
+use serde_json::{Map, Number};
+#[cfg(feature = "simd-json")]
+use simd_json::{Node, StaticNode};
+
+#[cfg(not(feature = "simd-json"))]
+pub type JsonError = serde_json::Error;
+#[cfg(feature = "simd-json")]
+pub type JsonError = simd_json::Error;
+
+// the hot parse path, build with --features simd-json to swap in simd-json
+#[cfg(not(feature = "simd-json"))]
+pub fn parse(message: &str) -> Result<Value, JsonError> {
+    serde_json::from_str(message)
+}
+
+// going through serde's visitors costs more than simd-json saves, so the tape is walked into
+// the tree directly; its setup only pays off on larger messages, short ones such as quotes go to
+// serde_json
+#[cfg(feature = "simd-json")]
+pub fn parse(message: &str) -> Result<Value, JsonError> {
+    if message.len() < SIMD_MIN_LEN {
+        return serde_json::from_str(message).map_err(|err| simd_json::Error::generic(simd_json::ErrorType::Serde(err.to_string())));
+    }
+    SCRATCH.with(|scratch| {
+        let (ref mut bytes, ref mut buffers) = *scratch.borrow_mut();
+        // simd-json parses in place, so it needs a mutable copy
+        bytes.clear();
+        bytes.extend_from_slice(message.as_bytes());
+        let tape = simd_json::to_tape_with_buffers(bytes, buffers)?;
+        Ok(tape_value(&mut tape.0.iter()))
+    })
+}
+
+// below this many bytes serde_json is faster, see benches/parse.rs
+#[cfg(feature = "simd-json")]
+const SIMD_MIN_LEN: usize = 4096;
+
+#[cfg(feature = "simd-json")]
+thread_local! {
+    // the copy and simd-json's buffers, kept for the next message of the thread
+    static SCRATCH: std::cell::RefCell<(Vec<u8>, simd_json::Buffers)> = std::cell::RefCell::new((vec![], simd_json::Buffers::default()));
+}
+
+// the value starting at the next node, consuming its children
+#[cfg(feature = "simd-json")]
+fn tape_value<'a>(nodes: &mut std::slice::Iter<'_, Node<'a>>) -> Value {
+    match nodes.next() {
+        Some(Node::String(text)) => Value::String(text.to_string()),
+        Some(&Node::Array { len, .. }) => Value::Array((0..len).map(|_| tape_value(nodes)).collect()),
+        Some(&Node::Object { len, .. }) => {
+            let mut object = Map::new();
+            for _ in 0..len {
+                // keys are always strings on the tape
+                let key = match nodes.next() {
+                    Some(Node::String(key)) => key.to_string(),
+                    _ => String::new(),
+                };
+                object.insert(key, tape_value(nodes));
+            }
+            Value::Object(object)
+        }
+        Some(&Node::Static(StaticNode::I64(value))) => Value::from(value),
+        Some(&Node::Static(StaticNode::U64(value))) => Value::from(value),
+        Some(&Node::Static(StaticNode::F64(value))) => Number::from_f64(value).map_or(Value::Null, Value::Number),
+        Some(&Node::Static(StaticNode::Bool(value))) => Value::Bool(value),
+        Some(&Node::Static(StaticNode::Null)) | None => Value::Null,
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::duration;
+use crate::stats::Stats;
+use crate::tradingview_api::frame_message;
+
+// how the server's ~h~ pings are answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PingReply {
+    // echoed while the frame that carried it is handled
+    #[default]
+    Immediate,
+    // held for the window, then every ping of it is echoed in one frame, fewer writes when pings
+    // pile up behind a stall; the server still gets each id back, in order
+    Coalesce(Duration),
+    // each answered after a random delay of up to this, so many connections don't reply in
+    // lockstep
+    Jitter(Duration)
+}
+
+// immediate, coalesce=500ms or jitter=200ms
+impl std::str::FromStr for PingReply {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<PingReply, String> {
+        let (name, setting) = value.split_once('=').unwrap_or((value, ""));
+        let duration = || duration::parse(setting).filter(|duration| !duration.is_zero()).ok_or_else(|| value.to_string());
+        match name {
+            "immediate" if setting.is_empty() => Ok(PingReply::Immediate),
+            "coalesce" => Ok(PingReply::Coalesce(duration()?)),
+            "jitter" => Ok(PingReply::Jitter(duration()?)),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+struct PendingPing {
+    id: usize,
+    received: Instant,
+    due: Instant
+}
+
+// answers one connection's pings as PingReply says and records them in the stats
+pub struct Keepalive {
+    reply: PingReply,
+    // in arrival order, which is also due order; Coalesce's all share the first one's due
+    pending: VecDeque<PendingPing>,
+    last_ping: Option<Instant>
+}
+
+impl Keepalive {
+    pub fn new(reply: PingReply) -> Keepalive {
+        Keepalive { reply, pending: VecDeque::new(), last_ping: None }
+    }
+
+    fn answer(ping: &PendingPing, now: Instant, stats: &Stats) -> String {
+        stats.record_ping_reply(now.saturating_duration_since(ping.received));
+        frame_message(&format!("~h~{}", ping.id))
+    }
+
+    // the reply to send with the frame's other responses, None when it's held back for due,
+    // whose replies are written on their own between frames
+    pub fn ping(&mut self, id: usize, received: Instant, now: Instant, stats: &Stats) -> Option<String> {
+        stats.record_ping(self.last_ping.map(|last_ping| received.saturating_duration_since(last_ping)));
+        self.last_ping = Some(received);
+        let ping = PendingPing { id, received, due: now };
+        match self.reply {
+            PingReply::Immediate => return Some(Keepalive::answer(&ping, now, stats)),
+            PingReply::Coalesce(window) => {
+                let due = match self.pending.front() {
+                    Some(first) => {
+                        stats.record_coalesced_ping();
+                        first.due
+                    }
+                    None => now + window,
+                };
+                self.pending.push_back(PendingPing { due, ..ping });
+            }
+            PingReply::Jitter(max) => {
+                // never ahead of an earlier ping's reply, the server expects them in order
+                let due = (now + rand::thread_rng().gen_range(Duration::ZERO..=max)).max(self.last_due().unwrap_or(now));
+                self.pending.push_back(PendingPing { due, ..ping });
+            }
+        }
+        None
+    }
+
+    fn last_due(&self) -> Option<Instant> {
+        self.pending.back().map(|pending| pending.due)
+    }
+
+    // replies held back and not yet sent
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.front().map(|pending| pending.due)
+    }
+
+    // the held replies whose time has come, one frame for all of Coalesce's
+    pub fn due(&mut self, now: Instant, stats: &Stats) -> Vec<String> {
+        let mut replies = vec![];
+        while let Some(pending) = self.pending.front().filter(|pending| pending.due <= now) {
+            replies.push(Keepalive::answer(pending, now, stats));
+            self.pending.pop_front();
+        }
+        match self.reply {
+            PingReply::Coalesce(_) if !replies.is_empty() => vec![replies.concat()],
+            _ => replies,
+        }
+    }
+}
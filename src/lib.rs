@@ -0,0 +1,63 @@
+pub mod accounts;
+pub mod ack;
+pub mod adapters;
+pub mod alias;
+pub mod auth;
+pub mod bar;
+pub mod bar_store;
+pub mod book;
+pub mod bulk;
+pub mod calendar;
+pub mod candles;
+pub mod capture;
+pub mod checkpoint;
+pub mod client;
+pub mod clock;
+pub mod conflate;
+pub mod connect_script;
+pub mod corporate;
+pub mod duration;
+pub mod event;
+pub mod fault;
+pub mod filter;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod health;
+pub mod hotlist;
+pub mod http;
+pub mod indicators;
+pub mod invariants;
+pub mod journal;
+pub mod json;
+pub mod keepalive;
+pub mod market;
+pub mod news;
+pub mod notifier;
+pub mod parse_pool;
+pub mod pine;
+pub mod plot;
+pub mod poll;
+pub mod quality;
+pub mod quote;
+pub mod rates;
+pub mod replay;
+pub mod resample;
+pub mod router;
+pub mod schema;
+pub mod scanner;
+pub mod series;
+pub mod server_info;
+pub mod session;
+pub mod sink;
+pub mod state;
+pub mod stats;
+pub mod study;
+pub mod symbol;
+pub mod symbol_info;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod timestamp;
+pub mod trade;
+pub mod tradingview_api;
+pub mod watchlist;
+pub mod websocket;
@@ -1,30 +1,840 @@
-mod websocket;
-mod tradingview_api;
+mod cli;
+mod supervisor;
 
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::sync::mpsc;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
 
-use tradingview_api::TradingViewApi;
-use websocket::WebSocket;
+use log::{debug, error, info, warn};
+use signal_hook::consts::{SIGINT, SIGTERM};
 
-fn main() -> Result<(), Box<dyn Error>> {
+use tradingview_websocket::accounts::{Account, Accounts};
+use tradingview_websocket::auth::{AuthProvider, Login, StaticToken};
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::bar_store::BarStore;
+use tradingview_websocket::bulk;
+use tradingview_websocket::calendar;
+use tradingview_websocket::capture::UnknownCapture;
+use tradingview_websocket::client;
+use tradingview_websocket::health::{self, Health};
+use tradingview_websocket::hotlist;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::indicators;
+use tradingview_websocket::news;
+use tradingview_websocket::parse_pool::ParsePool;
+use tradingview_websocket::pine;
+use tradingview_websocket::plot::{self, Overlay};
+use tradingview_websocket::quote::QuoteField;
+use tradingview_websocket::replay::{Replay, ReplayCommand, StepMode};
+use tradingview_websocket::router::SinkRouter;
+use tradingview_websocket::scanner;
+use tradingview_websocket::schema;
+use tradingview_websocket::series::SeriesOptions;
+use tradingview_websocket::sink::{self, BatchingSink};
+use tradingview_websocket::state::{self, ClientState};
+use tradingview_websocket::stats::{Stats, StatsSnapshot};
+use tradingview_websocket::symbol_info::{SymbolCache, SymbolInfoError};
+use tradingview_websocket::timestamp::TimestampFormatter;
+use tradingview_websocket::tradingview_api::{ApiOptions, HistoryRequest, Mode, TradingViewApi};
+use tradingview_websocket::watchlist;
+use tradingview_websocket::websocket::{Endpoint, Incoming, WebSocket};
+use tracing_subscriber::EnvFilter;
+
+use cli::{BenchArgs, Cli, Command, DownloadArgs, ExchangeArgs, HotlistArgs, LogFormat, PlotArgs, QuoteArgs, ReplayArgs};
+use supervisor::{Side, Supervisor};
+
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+// numbers the connection spans
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(1);
+
+// shared by every pipeline of the run, summarized on shutdown
+static STATS: LazyLock<Arc<Stats>> = LazyLock::new(Arc::default);
+static HEALTH: LazyLock<Arc<Health>> = LazyLock::new(Arc::default);
+
+// liquid symbols across asset classes, used when bench isn't given a symbols file
+const BENCH_SYMBOLS: [&str; 60] = [
+    "NASDAQ:AAPL", "NASDAQ:MSFT", "NASDAQ:AMZN", "NASDAQ:NVDA", "NASDAQ:GOOGL", "NASDAQ:META", "NASDAQ:TSLA", "NASDAQ:AVGO",
+    "NASDAQ:COST", "NASDAQ:NFLX", "NASDAQ:AMD", "NASDAQ:ADBE", "NASDAQ:PEP", "NASDAQ:CSCO", "NASDAQ:INTC", "NASDAQ:QCOM",
+    "NYSE:JPM", "NYSE:V", "NYSE:WMT", "NYSE:XOM", "NYSE:UNH", "NYSE:MA", "NYSE:JNJ", "NYSE:PG", "NYSE:HD", "NYSE:KO",
+    "NYSE:BAC", "NYSE:CVX", "NYSE:DIS", "NYSE:MCD", "AMEX:SPY", "NASDAQ:QQQ", "AMEX:IWM", "AMEX:GLD", "AMEX:TLT",
+    "BINANCE:BTCUSDT", "BINANCE:ETHUSDT", "BINANCE:SOLUSDT", "BINANCE:BNBUSDT", "BINANCE:XRPUSDT", "BINANCE:DOGEUSDT",
+    "BINANCE:ADAUSDT", "COINBASE:BTCUSD", "COINBASE:ETHUSD", "CRYPTO:BTCUSD", "FX:EURUSD", "FX:GBPUSD", "FX:USDJPY",
+    "FX:AUDUSD", "FX:USDCAD", "FX:USDCHF", "OANDA:XAUUSD", "OANDA:XAGUSD", "TVC:USOIL", "TVC:DXY", "TVC:VIX",
+    "SP:SPX", "TVC:NDX", "TVC:DJI", "TVC:US10Y",
+];
+
+// joins an error with its sources so the underlying io/tls/serde cause isn't lost
+fn error_chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+fn run_pipeline(mode: Mode, options: ApiOptions, shutdown: &Arc<AtomicBool>, on_event: impl FnMut(Option<Arc<Event>>)) -> Result<(), Box<dyn Error>> {
     let (incoming_tx, incoming_rx) = mpsc::channel();
+    run_pipeline_with(mode, options, shutdown, Arc::new(Mutex::new(Some(incoming_tx))), incoming_rx, on_event)
+}
+
+// with an incoming channel the caller also holds a handle to, to send api commands on; the
+// websocket thread takes the sender out of it when it ends, so the api still sees the hang up
+fn run_pipeline_with(mode: Mode, options: ApiOptions, shutdown: &Arc<AtomicBool>, shared_incoming_tx: Arc<Mutex<Option<Sender<Incoming>>>>, incoming_rx: Receiver<Incoming>, on_event: impl FnMut(Option<Arc<Event>>)) -> Result<(), Box<dyn Error>> {
+    let mut supervisor = Supervisor::new();
+    let stop = supervisor.stop_flag();
+    let incoming_tx = shared_incoming_tx.lock().ok().and_then(|incoming_tx| incoming_tx.clone()).ok_or("the incoming channel was already closed")?;
     let (outgoing_tx, outgoing_rx) = mpsc::channel();
-    let trading_view_thread = std::thread::spawn(move || {
-        let trading_view_api = TradingViewApi::new(incoming_rx, outgoing_tx)?;
+    let (events_tx, events_rx) = mpsc::sync_channel(options.limits.max_event_backlog);
+    let max_rx_buffer = options.limits.max_rx_buffer;
+    let endpoint = options.endpoint;
+    let propagate_panics = options.propagate_panics;
+    let faults = options.faults;
+    let frame_handler = options.frame_handler.clone();
+    // both sides of the connection log under the same span
+    let span = tracing::info_span!("connection", id = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed), host = endpoint.host());
+    let api_span = span.clone();
+    supervisor.spawn(Side::TradingView, move || {
+        let _connection = api_span.entered();
+        let mut trading_view_api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, mode, options)?;
         trading_view_api.handler()
     });
-    let websocket_thread = std::thread::spawn(move || {
-        let mut websocket = WebSocket::new(incoming_tx, outgoing_rx)?;
-        websocket.handle_stream()
+    supervisor.spawn(Side::WebSocket, move || {
+        let _connection = span.entered();
+        let result = WebSocket::new(endpoint, incoming_tx, outgoing_rx, stop, max_rx_buffer).and_then(|mut websocket| {
+            if let Some(faults) = faults {
+                websocket = websocket.with_faults(faults);
+            }
+            if let Some(frame_handler) = frame_handler {
+                websocket = websocket.with_frame_handler(frame_handler);
+            }
+            websocket.handle_stream()
+        });
+        if let Ok(mut incoming_tx) = shared_incoming_tx.lock() {
+            incoming_tx.take();
+        }
+        result
+    });
+    // runs until the api thread hangs up
+    supervisor.watch(events_rx, shutdown, propagate_panics, on_event);
+    if let Err(err) = supervisor.join() {
+        error!("{}", error_chain(&err));
+        return Err(Box::new(err));
+    }
+    Ok(())
+}
+
+fn history_request(args: &DownloadArgs) -> HistoryRequest {
+    HistoryRequest {
+        symbol: args.symbol.clone(),
+        interval: args.interval.clone(),
+        from: args.from,
+        to: args.to,
+        count: None
+    }
+}
+
+// prints the framed bootstrap messages without opening a connection
+fn dry_run(mode: Mode, cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let (_incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, _outgoing_rx) = mpsc::channel();
+    let (events_tx, _events_rx) = mpsc::sync_channel(1);
+    // everything that shapes the bootstrap, but not the auth provider, which may log in
+    let options = ApiOptions {
+        series: series_options(cli),
+        studies: cli.studies.clone(),
+        corporate_events: cli.corporate_events,
+        endpoint: endpoint(cli),
+        trades: cli.trades,
+        aliases: cli.aliases.clone(),
+        ..ApiOptions::default()
+    };
+    let trading_view_api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, mode, options).map_err(|err| err.to_string())?;
+    for message in trading_view_api.bootstrap_messages() {
+        println!("{}", message);
+    }
+    Ok(())
+}
+
+fn download(args: &DownloadArgs, formatter: TimestampFormatter, options: ApiOptions, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let mut sink = match args.out {
+        Some(ref out) => Some(sink::open_sink(out, formatter).map_err(|err| err.to_string())?),
+        None => None,
+    };
+    let store = match args.store {
+        Some(ref path) => Some(BarStore::open(Path::new(path)).map_err(|err| error_chain(&err))?),
+        None => None,
+    };
+    let mut request = history_request(args);
+    // keyed by bar time so overlapping pages dedupe themselves
+    let mut bars_by_time = BTreeMap::new();
+    // where the server takes over, None when the store has everything
+    let mut fetch_from = Some(args.from);
+    if let Some(ref store) = store {
+        let (stored, top_up_from) = store.top_up(&args.symbol, &args.interval, args.from, args.to).map_err(|err| error_chain(&err))?;
+        match top_up_from {
+            Some(top_up_from) if top_up_from > args.from => info!("{} bars stored up to {}, topping up", args.symbol, top_up_from),
+            Some(_) => {},
+            None => info!("{} bars stored up to {}, nothing to fetch", args.symbol, args.to),
+        }
+        fetch_from = top_up_from;
+        bars_by_time.extend(stored.into_iter().map(|bar| (bar.time, bar)));
+    }
+    let mut downloaded = BTreeMap::new();
+    if let Some(fetch_from) = fetch_from {
+        request.from = fetch_from;
+        // an interrupted download still writes whatever was collected
+        run_pipeline(Mode::Download(request), options, shutdown, |event| {
+            // the only holder of the event, so its bars are moved out rather than copied
+            if let Some(Event::Bars { bars, .. }) = event.map(Arc::unwrap_or_clone) {
+                for bar in bars {
+                    downloaded.insert(bar.time, bar);
+                }
+            }
+        })?;
+    }
+    if let Some(ref store) = store {
+        let bars: Vec<Bar> = downloaded.values().filter(|bar| bar.is_valid()).cloned().collect();
+        // a cut short download leaves a gap the next run has to fill from the start
+        let finished = !shutdown.load(Ordering::SeqCst);
+        store.insert_download(&args.symbol, &args.interval, args.from, args.to, &bars, finished).map_err(|err| error_chain(&err))?;
+        store.flush().map_err(|err| error_chain(&err))?;
+        info!("stored {} new or updated bars", bars.len());
+    }
+    bars_by_time.append(&mut downloaded);
+    let (sink, out) = match (sink.as_mut(), args.out.as_deref()) {
+        (Some(sink), Some(out)) => (sink, out),
+        _ => return Ok(()),
+    };
+    let mut written = 0;
+    for bar in bars_by_time.values().filter(|bar| bar.time >= args.from && bar.time <= args.to) {
+        if !bar.is_valid() {
+            warn!("skipping invalid bar at {}", bar.time);
+            continue;
+        }
+        sink.write_bar(&args.symbol, bar).map_err(|err| err.to_string())?;
+        written += 1;
+    }
+    sink.flush().map_err(|err| err.to_string())?;
+    info!("wrote {} bars to {}", written, out);
+    Ok(())
+}
+
+fn print_quote(args: &QuoteArgs, options: ApiOptions) -> Result<(), Box<dyn Error>> {
+    let quote = client::get_quote(&args.symbol, &args.fields, options).map_err(|err| error_chain(&err))?.value;
+    for field in &args.fields {
+        match quote.get(*field) {
+            Some(value) => println!("{:<22} {}", field.as_str(), value),
+            None => println!("{:<22} -", field.as_str()),
+        }
+    }
+    Ok(())
+}
+
+// quotes for a whole exchange, spread over as many connections as the per-connection limit needs
+fn stream_exchange(args: &ExchangeArgs, accounts: &Accounts, options: ApiOptions, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let symbols = scanner::exchange_symbols(&args.exchange).map_err(|err| error_chain(&err))?;
+    let connections = bulk::account_subscriptions(&symbols, args.per_connection, accounts);
+    info!("streaming {} {} symbols over {} connections", symbols.len(), args.exchange, connections.len());
+    let results: Vec<Result<(), String>> = std::thread::scope(|scope| {
+        let pipelines: Vec<_> = connections.into_iter().map(|(auth, mode)| {
+            let options = match auth {
+                Some(auth) => ApiOptions { auth, ..options.clone() },
+                None => options.clone(),
+            };
+            scope.spawn(move || run_pipeline(mode, options, shutdown, |event| {
+                match event.as_deref() {
+                    Some(Event::Quote { symbol, values, .. }) => debug!("{} {:?}", symbol, values),
+                    Some(Event::QuoteFieldChanged { symbol, field, old, new }) => info!("{} {} {:?} -> {}", symbol, field.as_str(), old, new),
+                    Some(stats @ Event::Stats { .. }) => log_feed_stats(stats),
+                    _ => {}
+                }
+            }).map_err(|err| error_chain(err.as_ref())))
+        }).collect();
+        pipelines.into_iter().map(|pipeline| pipeline.join().unwrap_or_else(|_| Err("pipeline panicked".to_string()))).collect()
+    });
+    match results.into_iter().find_map(Result::err) {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+// streams quotes for the list's symbols, swapping them for the current ones every refresh
+fn stream_hotlist(args: &HotlistArgs, options: ApiOptions, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let symbols = args.hotlist.fetch().map_err(|err| error_chain(&err))?;
+    info!("streaming {} {} symbols, refreshed every {}s", symbols.len(), args.hotlist.exchange, args.refresh.as_secs());
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let incoming_tx = Arc::new(Mutex::new(Some(incoming_tx)));
+    let refresher_stop = Arc::new(AtomicBool::new(false));
+    let refresher = hotlist::spawn_refresher(args.hotlist.clone(), args.refresh, Arc::clone(&incoming_tx), Arc::clone(&refresher_stop));
+    let result = run_pipeline_with(Mode::Quotes(symbols), options, shutdown, incoming_tx, incoming_rx, |event| {
+        match event.as_deref() {
+            Some(Event::Quote { symbol, values, .. }) => {
+                let fields: Vec<String> = values.iter().map(|(field, value)| format!("{}={}", field.as_str(), value)).collect();
+                info!("{} {}", symbol, fields.join(" "));
+            }
+            Some(Event::QuoteFieldChanged { symbol, field, old, new }) => debug!("{} {} {:?} -> {}", symbol, field.as_str(), old, new),
+            Some(stats @ Event::Stats { .. }) => log_feed_stats(stats),
+            _ => {}
+        }
+    });
+    refresher_stop.store(true, Ordering::SeqCst);
+    let _ = refresher.join();
+    result
+}
+
+// the rest api wants the browser's sessionid cookie rather than an auth token
+fn print_watchlists(name: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let session_id = std::env::var("TRADINGVIEW_SESSIONID").map_err(|_| "watchlists need TRADINGVIEW_SESSIONID set to the sessionid cookie")?;
+    match name {
+        Some(name) => {
+            for symbol in watchlist::find_watchlist(&session_id, name).map_err(|err| error_chain(&err))?.symbols {
+                println!("{}", symbol);
+            }
+        }
+        None => {
+            for list in watchlist::fetch_watchlists(&session_id).map_err(|err| error_chain(&err))? {
+                println!("{:<30} {} symbols", list.name, list.symbols.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+// the --symbol-cache file, or one that only lasts the run
+fn open_symbol_cache(cli: &Cli) -> Result<SymbolCache, SymbolInfoError> {
+    match cli.symbol_cache {
+        Some(ref path) => SymbolCache::open(Path::new(path), cli.symbol_cache_ttl),
+        None => Ok(SymbolCache::in_memory(cli.symbol_cache_ttl)),
+    }
+}
+
+fn print_symbol_info(symbols: &[String], cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let cache = open_symbol_cache(cli).map_err(|err| error_chain(&err))?;
+    for symbol in symbols {
+        match cache.lookup(symbol).map_err(|err| error_chain(&err))? {
+            Some(info) => println!("{:<24} {:<10} {:<8} {:<5} {:<6} {}", info.symbol, info.exchange, info.kind,
+                info.currency.as_deref().unwrap_or("-"), info.price_scale.map_or_else(|| "-".to_string(), |scale| scale.to_string()), info.description),
+            None => println!("{:<24} not found", symbol),
+        }
+    }
+    Ok(())
+}
+
+fn print_fields() -> Result<(), Box<dyn Error>> {
+    println!("{:<22} {:<14} DESCRIPTION", "FIELD", "ACCESS");
+    for field in QuoteField::ALL {
+        println!("{:<22} {:<14} {}", field.as_str(), field.required_access().as_str(), field.description());
+    }
+    Ok(())
+}
+
+fn print_schema() -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(&schema::event_schema())?);
+    Ok(())
+}
+
+fn plot_chart(args: &PlotArgs) -> Result<(), Box<dyn Error>> {
+    let rows = sink::read_bars(&args.input).map_err(|err| err.to_string())?;
+    let symbol = rows.first().map(|(symbol, _)| symbol.clone()).unwrap_or_default();
+    let mut bars: Vec<_> = rows.into_iter().filter(|(row_symbol, _)| *row_symbol == symbol).map(|(_, bar)| bar).collect();
+    bars.sort_by_key(|bar| bar.time);
+    if let Some(last) = args.last {
+        bars.drain(..bars.len().saturating_sub(last));
+    }
+    let overlays: Vec<Overlay> = args.sma.iter().map(|period| Overlay {
+        name: format!("SMA {}", period),
+        values: indicators::sma(&bars, *period)
+    }).collect();
+    let studies = match args.studies {
+        Some(ref path) => plot::read_study_series(path, &symbol, &bars).map_err(|err| err.to_string())?,
+        None => vec![],
+    };
+    plot::render_chart(&args.out, &symbol, &bars, &overlays, &studies, (args.width, args.height)).map_err(|err| err.to_string())?;
+    info!("wrote chart of {} bars to {}", bars.len(), args.out);
+    Ok(())
+}
+
+fn bench_symbols(args: &BenchArgs) -> Result<Vec<String>, Box<dyn Error>> {
+    let available: Vec<String> = match args.symbols_file {
+        Some(ref path) => std::fs::read_to_string(path)?
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect(),
+        None => BENCH_SYMBOLS.iter().map(|symbol| symbol.to_string()).collect(),
+    };
+    // a bench with fewer symbols than asked for measures something else
+    if available.len() < args.symbols {
+        return Err(match args.symbols_file {
+            Some(ref path) => format!("--symbols {} but {} only lists {}", args.symbols, path, available.len()),
+            None => format!("--symbols {} but the built-in list only has {}, pass a --symbols-file", args.symbols, available.len()),
+        }.into());
+    }
+    Ok(available.into_iter().take(args.symbols).collect())
+}
+
+// (current, peak) resident memory in kB, linux only
+fn resident_memory_kb() -> Option<(u64, u64)> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let field = |name: &str| status.lines()
+        .find(|line| line.starts_with(name))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|value| value.parse::<u64>().ok());
+    Some((field("VmRSS:")?, field("VmHWM:")?))
+}
+
+fn bench(args: &BenchArgs, options: ApiOptions, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let symbols = bench_symbols(args)?;
+    let symbol_count = symbols.len();
+    let stats = Arc::clone(&options.stats);
+    let timer_shutdown = Arc::clone(shutdown);
+    let duration = args.duration;
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        timer_shutdown.store(true, Ordering::SeqCst);
+    });
+    // symbols are spread over the connections, each running its own pipeline
+    let partitions: Vec<Vec<String>> = symbols.chunks(symbol_count.div_ceil(args.connections).max(1)).map(<[String]>::to_vec).collect();
+    let connections = partitions.len();
+    let parse_workers = options.parse_pool.as_ref().map_or(0, |pool| pool.workers());
+    info!("benchmarking {} symbols over {} connections for {}s", symbol_count, connections, duration.as_secs());
+    let started = Instant::now();
+    let results: Vec<Result<(), String>> = std::thread::scope(|scope| {
+        let pipelines: Vec<_> = partitions.into_iter().map(|symbols| {
+            let mode = Mode::Quotes(symbols);
+            let options = options.clone();
+            scope.spawn(move || run_pipeline(mode, options, shutdown, |_| {}).map_err(|err| error_chain(err.as_ref())))
+        }).collect();
+        pipelines.into_iter().map(|pipeline| pipeline.join().unwrap_or_else(|_| Err("pipeline panicked".to_string()))).collect()
+    });
+    let elapsed = started.elapsed().as_secs_f64().max(f64::EPSILON);
+    let snapshot = stats.snapshot();
+    println!("symbols:          {}", symbol_count);
+    println!("connections:      {}", connections);
+    println!("parse workers:    {}", parse_workers);
+    println!("elapsed:          {:.1}s", elapsed);
+    println!("messages:         {} ({:.1}/s)", snapshot.messages, snapshot.messages as f64 / elapsed);
+    println!("payload bytes:    {} ({:.1} kB/s)", snapshot.bytes, snapshot.bytes as f64 / elapsed / 1024.0);
+    println!("parse time mean:  {:?}", snapshot.mean_parse_time);
+    println!("parse time p50:   <= {:?}", snapshot.p50_parse_time);
+    println!("parse time p99:   <= {:?}", snapshot.p99_parse_time);
+    println!("parse time max:   {:?}", snapshot.max_parse_time);
+    println!("dropped events:   {}", snapshot.dropped_events);
+    println!("conflated quotes: {}", snapshot.conflated_quotes);
+    println!("pings:            {} ({} coalesced)", snapshot.pings, snapshot.coalesced_pings);
+    for (name, latency) in [("receive to parse", snapshot.parse_latency), ("receive to event", snapshot.delivery_latency), ("lp_time to receive", snapshot.server_lag), ("ping interval", snapshot.ping_interval), ("ping to reply", snapshot.ping_reply_latency)] {
+        println!("{:<20} p50 <= {:?}, p99 <= {:?}, max {:?} ({} samples)", format!("{}:", name), latency.p50, latency.p99, latency.max, latency.count);
+    }
+    if let Some((current, peak)) = resident_memory_kb() {
+        println!("resident memory:  {} kB (peak {} kB)", current, peak);
+    }
+    match results.into_iter().find_map(Result::err) {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+// sleeps in short steps so a signal during the backoff isn't ignored
+fn wait_unless_shutdown(delay: Duration, shutdown: &AtomicBool) {
+    let step = Duration::from_millis(500);
+    let mut waited = Duration::ZERO;
+    while waited < delay && !shutdown.load(Ordering::SeqCst) {
+        std::thread::sleep(step);
+        waited += step;
+    }
+}
+
+// one line per report, with the busiest symbols
+fn log_feed_stats(event: &Event) {
+    if let Event::Stats { messages_per_sec, bytes_per_sec, symbols, queues, .. } = event {
+        let busiest: Vec<String> = symbols.iter().take(5).map(|rate| format!("{} {:.1}/s", rate.symbol, rate.per_sec)).collect();
+        info!("feed: {:.1} messages/s, {:.0} bytes/s, {} held quotes, {} pending commands, {} dropped events; {}",
+            messages_per_sec, bytes_per_sec, queues.held_quotes, queues.pending_commands, queues.dropped_events, busiest.join(", "));
+    }
+}
+
+// also runs on idle ticks (None), and after every event so a quiet route isn't held back by a busy one
+fn route_event(router: &mut SinkRouter, event: Option<&Event>) {
+    // sinks only take bars, so study output, corporate events, session and feed stats, news, releases and clock skew are just logged
+    match event {
+        Some(stats @ Event::Stats { .. }) => log_feed_stats(stats),
+        Some(Event::StudyValues { study, plots, points, .. }) => {
+            if let Some(point) = points.last() {
+                let values: Vec<String> = point.named(plots).map(|(name, value)| format!("{}={}", name, value)).collect();
+                info!("{} at {}: {}", study, point.time, values.join(" "));
+            }
+        }
+        Some(Event::Trade { symbol, trade, .. }) => info!("{} trade {:?}", symbol, trade),
+        Some(Event::ClockSkew { skew_ms, delay_ms, samples }) => info!("local clock {}ms off the server's, {}ms typical delay ({} samples)", skew_ms, delay_ms, samples),
+        Some(Event::EconomicEvent { event }) => info!("{} {} at {} ({:?}): actual {:?}, forecast {:?}, previous {:?}",
+            event.country, event.title, event.time, event.importance, event.actual, event.forecast, event.previous),
+        Some(Event::NewsItem { symbol, item }) => info!("{} news from {}: {}", symbol, item.provider, item.title),
+        Some(Event::SessionStats { symbol, session, anchored }) => {
+            info!("{} session: {:?}", symbol, session);
+            for stats in anchored {
+                info!("{} since {}: {:?}", symbol, stats.from, stats);
+            }
+        }
+        Some(Event::CorporateEvents { symbol, events }) => {
+            for corporate_event in events {
+                info!("{}: {:?}", symbol, corporate_event);
+            }
+        }
+        _ => {},
+    }
+    let result = match event {
+        Some(event) => router.handle_event(event),
+        None => Ok(()),
+    };
+    if let Err(err) = result.and_then(|_| router.flush_if_due()) {
+        error!("failed to write event: {}", err);
+    }
+}
+
+fn run_stream_with_restart(router: &mut SinkRouter, options: ApiOptions, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let mut delay = Duration::from_secs(1);
+    loop {
+        let result = run_pipeline(Mode::Stream, options.clone(), shutdown, |event| route_event(router, event.as_deref()));
+        let err = match result {
+            Err(err) if !shutdown.load(Ordering::SeqCst) => err,
+            result => return result,
+        };
+        // nothing new arrives during the backoff, so don't sit on what was batched
+        if let Err(err) = router.flush() {
+            error!("failed to flush sinks: {}", err);
+        }
+        warn!("stream stopped ({}), restarting in {}s", err, delay.as_secs());
+        options.stats.record_reconnect();
+        wait_unless_shutdown(delay, shutdown);
+        if shutdown.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        delay = (delay * 2).min(MAX_RESTART_DELAY);
+    }
+}
+
+fn open_router(cli: &Cli) -> Result<SinkRouter, Box<dyn Error>> {
+    let mut router = SinkRouter::default();
+    router.set_propagate_panics(cli.propagate_panics);
+    for (symbol, path) in &cli.routes {
+        let sink = sink::open_sink(path, formatter(cli)).map_err(|err| err.to_string())?;
+        router.add_route(symbol, Box::new(BatchingSink::new(sink, cli.flush_policy)));
+    }
+    if let Some(ref path) = cli.study_out {
+        let file = std::fs::File::create(path).map_err(|err| format!("{}: {}", path, err))?;
+        router.set_study_out(Box::new(std::io::BufWriter::new(file)));
+    }
+    Ok(router)
+}
+
+// the --state file's subscriptions joined with the command line's, with their checkpoints
+fn restore_stream_state(path: &str, options: ApiOptions) -> Result<ApiOptions, Box<dyn Error>> {
+    let state = match ClientState::load(Path::new(path)).map_err(|err| error_chain(&err))? {
+        Some(state) => state,
+        None => return Ok(options),
+    };
+    match state::merge_state(state, options) {
+        (Mode::Stream, options, warnings) => {
+            for warning in warnings {
+                warn!("{}: {}", path, warning);
+            }
+            info!("restored {} studies and {} checkpoints from {}", options.studies.len(), options.checkpoints.last_bar_times().len(), path);
+            Ok(options)
+        }
+        _ => Err(format!("{} holds the state of another command, not stream", path).into()),
+    }
+}
+
+// writes the stream's state when it goes out of scope, however the stream ended
+struct SaveState<'a> {
+    path: &'a str,
+    options: ApiOptions
+}
+
+impl Drop for SaveState<'_> {
+    fn drop(&mut self) {
+        // the checkpoints are shared, so they hold the newest bars the pipeline saw
+        if let Err(err) = state::export_state(&Mode::Stream, &self.options).save(Path::new(self.path)) {
+            error!("failed to save state: {}", error_chain(&err));
+        }
+    }
+}
+
+fn stream(cli: &Cli, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let mut options = api_options(cli);
+    if let Some(ref path) = cli.state {
+        options = restore_stream_state(path, options)?;
+    }
+    // script urls and published ids need their compiled source before create_study
+    pine::resolve_studies(&mut options.studies)?;
+    if let Some(chart) = options.connect_script.as_mut().and_then(|script| script.chart.as_mut()) {
+        pine::resolve_studies(&mut chart.studies)?;
+    }
+    let _save_state = cli.state.as_deref().map(|path| SaveState { path, options: options.clone() });
+    let mut router = open_router(cli)?;
+    let result = if cli.restart {
+        run_stream_with_restart(&mut router, options, shutdown)
+    } else {
+        run_pipeline(Mode::Stream, options, shutdown, |event| route_event(&mut router, event.as_deref()))
+    };
+    router.flush().map_err(|err| err.to_string())?;
+    result
+}
+
+// plays a recorded file back through the routes as if its bars were being streamed, no
+// connection is made
+fn replay(args: &ReplayArgs, cli: &Cli, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let rows = sink::read_bars(&args.input).map_err(|err| err.to_string())?;
+    let symbol = rows.first().map(|(symbol, _)| symbol.clone()).unwrap_or_default();
+    let bars: Vec<_> = rows.into_iter().filter(|(row_symbol, _)| *row_symbol == symbol).map(|(_, bar)| bar).collect();
+    let count = bars.len();
+    let mut router = open_router(cli)?;
+    let (events_tx, events_rx) = mpsc::sync_channel(cli.limits.max_event_backlog);
+    let (commands_tx, commands_rx) = mpsc::channel();
+    let player = Replay::new(&symbol, &args.interval, bars, args.speed, args.step_mode);
+    let result = std::thread::scope(|scope| {
+        let handle = scope.spawn(move || player.run(&events_tx, &commands_rx, shutdown));
+        for event in events_rx.iter() {
+            route_event(&mut router, Some(&event));
+            // the consumer is done with the bar once it's routed
+            if args.step_mode == StepMode::Ack {
+                let _ = commands_tx.send(ReplayCommand::Ack);
+            }
+        }
+        handle.join()
     });
-    let trading_view_result = trading_view_thread.join().expect("TradingView thread panicked");
-    let websocket_result = websocket_thread.join().expect("WebSocket thread panicked");
-    if let Err(err) = trading_view_result {
-        eprintln!("Error in TradingView thread: {:?}", err);
+    // a recording's last bar is as complete as the others
+    router.write_forming().map_err(|err| err.to_string())?;
+    router.flush().map_err(|err| err.to_string())?;
+    result.map_err(|_| "replay thread panicked")?.map_err(|err| err.to_string())?;
+    info!("replayed {} bars of {}", count, symbol);
+    Ok(())
+}
+
+fn formatter(cli: &Cli) -> TimestampFormatter {
+    TimestampFormatter::new(cli.tz, cli.time_format)
+}
+
+// credentials come from the environment so they stay out of shell history and ps
+fn auth_provider(cli: &Cli) -> Option<Arc<dyn AuthProvider>> {
+    if let (Ok(username), Ok(password)) = (std::env::var("TRADINGVIEW_USERNAME"), std::env::var("TRADINGVIEW_PASSWORD")) {
+        return Some(Arc::new(Login::new(username, password)));
     }
-    if let Err(err) = websocket_result {
-        eprintln!("Error in WebSocket thread: {:?}", err);
+    cli.auth_token.as_ref().map(|token| Arc::new(StaticToken(token.clone())) as Arc<dyn AuthProvider>)
+}
+
+// every --account with the credentials its environment variables name
+fn accounts(cli: &Cli) -> Result<Accounts, String> {
+    cli.accounts.iter().map(|spec| {
+        let suffix = spec.name.to_uppercase();
+        let env = |name: &str| std::env::var(format!("TRADINGVIEW_{}_{}", name, suffix)).ok();
+        let auth: Arc<dyn AuthProvider> = match (env("USERNAME"), env("PASSWORD"), env("TOKEN")) {
+            (Some(username), Some(password), _) => Arc::new(Login::new(username, password)),
+            (_, _, Some(token)) => Arc::new(StaticToken(token)),
+            _ => return Err(format!("account {} needs TRADINGVIEW_TOKEN_{} or TRADINGVIEW_USERNAME_{} and TRADINGVIEW_PASSWORD_{}", spec.name, suffix, suffix, suffix)),
+        };
+        Ok(Account { name: spec.name.clone(), auth, exchanges: spec.exchanges.clone() })
+    }).collect::<Result<_, _>>().map(Accounts::new)
+}
+
+// the account serving the symbol logs in, or the default auth when none does
+fn account_options(accounts: &Accounts, symbol: &str, options: ApiOptions) -> ApiOptions {
+    match accounts.auth_for(symbol) {
+        Some(auth) => ApiOptions { auth, ..options },
+        None => options,
+    }
+}
+
+fn endpoint(cli: &Cli) -> Endpoint {
+    if cli.widget {
+        Endpoint::WidgetData
+    } else {
+        Endpoint::Chart
     }
+}
+
+fn series_options(cli: &Cli) -> SeriesOptions {
+    SeriesOptions {
+        style: cli.chart_style,
+        currency: cli.currency.clone(),
+        unit: cli.unit.clone(),
+        adjustment: cli.adjustment,
+        back_adjust: cli.back_adjust,
+        stitch_rolls: cli.stitch_rolls,
+        extra_intervals: cli.extra_intervals.clone()
+    }
+}
+
+fn api_options(cli: &Cli) -> ApiOptions {
+    let defaults = ApiOptions::default();
+    ApiOptions {
+        error_policy: cli.error_policy,
+        parse_mode: cli.parse_mode,
+        heartbeat_timeout: cli.heartbeat_timeout.unwrap_or(defaults.heartbeat_timeout),
+        command_timeout: cli.command_timeout.unwrap_or(defaults.command_timeout),
+        emit_corrections: cli.emit_corrections,
+        validate_bars: cli.validate_bars,
+        auth: auth_provider(cli).unwrap_or(defaults.auth.clone()),
+        limits: cli.limits,
+        propagate_panics: cli.propagate_panics,
+        stats: Arc::clone(&STATS),
+        health: Arc::clone(&HEALTH),
+        parse_pool: cli.parse_workers.map(|workers| Arc::new(ParsePool::new(workers))),
+        series: series_options(cli),
+        studies: cli.studies.clone(),
+        corporate_events: cli.corporate_events,
+        endpoint: endpoint(cli),
+        trades: cli.trades,
+        local_indicators: cli.local_indicators.clone(),
+        resample_intervals: cli.resample_intervals.clone(),
+        session_anchors: cli.session_anchors.clone(),
+        session_stats_every: cli.session_stats_every,
+        stats_every: cli.stats_every,
+        news: cli.news_every.map(|every| Arc::new(news::poller(every))),
+        calendar: cli.calendar.clone().map(|calendar| Arc::new(calendar::poller(calendar))),
+        quote_fields: cli.quote_fields.clone(),
+        quote_changes: cli.quote_changes.clone(),
+        symbol_cache: cli.symbol_cache.as_ref()
+            .and_then(|_| open_symbol_cache(cli).map_err(|err| warn!("running without the symbol cache: {}", error_chain(&err))).ok())
+            .map(Arc::new),
+        faults: cli.faults,
+        aliases: cli.aliases.clone(),
+        filters: cli.filters.clone(),
+        clock_events: cli.clock_events,
+        normalize_times: cli.normalize_times,
+        ping_reply: cli.ping_reply,
+        invariants: cli.invariants,
+        connect_script: cli.connect_script.clone(),
+        unknown_capture: cli.capture_unknown.as_ref()
+            .and_then(|path| UnknownCapture::open(Path::new(path), cli.capture_limit)
+                .map_err(|err| warn!("running without the unknown message capture: {}", error_chain(&err))).ok())
+            .map(Arc::new),
+        ..defaults
+    }
+}
+
+fn run_command(cli: &Cli, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    let accounts = accounts(cli)?;
+    match cli.command {
+        Command::Stream => stream(cli, shutdown),
+        Command::Download(ref args) => download(args, formatter(cli), account_options(&accounts, &args.symbol, api_options(cli)), shutdown),
+        Command::Fields => print_fields(),
+        Command::Schema => print_schema(),
+        Command::Plot(ref args) => plot_chart(args),
+        Command::Bench(ref args) => bench(args, api_options(cli), shutdown),
+        Command::Quote(ref args) => print_quote(args, account_options(&accounts, &args.symbol, api_options(cli))),
+        Command::Replay(ref args) => replay(args, cli, shutdown),
+        Command::Watchlists(ref name) => print_watchlists(name.as_deref()),
+        Command::Hotlist(ref args) => {
+            let options = account_options(&accounts, &format!("{}:", args.hotlist.exchange), api_options(cli));
+            stream_hotlist(args, options, shutdown)
+        }
+        Command::Exchange(ref args) => stream_exchange(args, &accounts, api_options(cli), shutdown),
+        Command::SymbolInfo(ref symbols) => print_symbol_info(symbols, cli),
+    }
+}
+
+// forks into the background and detaches from the terminal before any thread is started; the
+// working directory stays, so relative paths still resolve. The pid file is locked, a second
+// daemon with the same one fails to start
+#[cfg(unix)]
+fn daemonize(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let mut daemon = daemonize::Daemonize::new().pid_file(&cli.pid_file).working_directory(std::env::current_dir()?);
+    if let Some(ref path) = cli.log_file {
+        let log = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        daemon = daemon.stdout(log.try_clone()?).stderr(log);
+    }
+    daemon.start().map_err(|err| format!("couldn't daemonize: {}", err))?;
     Ok(())
 }
+
+#[cfg(not(unix))]
+fn daemonize(_cli: &Cli) -> Result<(), Box<dyn Error>> {
+    Err("--daemon is only supported on unix".into())
+}
+
+fn run_daemon(cli: &Cli, shutdown: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    info!("running as daemon, pid {}, pid file {}", std::process::id(), cli.pid_file);
+    let result = run_command(cli, shutdown);
+    if let Err(err) = std::fs::remove_file(&cli.pid_file) {
+        warn!("failed to remove pid file {}: {}", cli.pid_file, err);
+    }
+    result
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = cli::parse_args(std::env::args().skip(1))?;
+    // RUST_LOG takes precedence over -v/-q, e.g. RUST_LOG=info,tradingview_websocket::tradingview_api=debug;
+    // the log records the library still writes are bridged in by the subscriber
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(cli.log_level.as_str().to_lowercase()));
+    // a daemon's log is a file, without colors
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr).with_ansi(!cli.daemon);
+    match cli.log_format {
+        LogFormat::Text => subscriber.init(),
+        // the enclosing connection and session spans become fields of each line
+        LogFormat::Json => subscriber.json().with_current_span(true).with_span_list(true).init(),
+    }
+    if cli.dry_run {
+        return match cli.command {
+            Command::Stream => dry_run(Mode::Stream, &cli),
+            Command::Download(ref args) => dry_run(Mode::Download(history_request(args)), &cli),
+            Command::Fields => print_fields(),
+            Command::Schema => print_schema(),
+            Command::Plot(ref args) => plot_chart(args),
+            Command::Bench(ref args) => dry_run(Mode::Quotes(bench_symbols(args)?), &cli),
+            Command::Quote(ref args) => dry_run(Mode::Quotes(vec![args.symbol.clone()]), &cli),
+            // nothing goes over the wire
+            Command::Replay(_) | Command::Watchlists(_) | Command::SymbolInfo(_) => Ok(()),
+            // the symbols come from the scanner, so the bootstrap shows an empty quote session
+            Command::Hotlist(_) | Command::Exchange(_) => dry_run(Mode::Quotes(vec![]), &cli),
+        };
+    }
+    if cli.daemon {
+        daemonize(&cli)?;
+    }
+    // the first signal asks for a graceful shutdown, a second one exits immediately
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&shutdown))?;
+        signal_hook::flag::register(signal, Arc::clone(&shutdown))?;
+    }
+    if let Some(ref addr) = cli.health_addr {
+        // quiet for longer than the heartbeat timeout means the feed is about to be restarted anyway
+        let max_age = cli.health_max_age.or(cli.heartbeat_timeout).unwrap_or(ApiOptions::default().heartbeat_timeout);
+        health::serve(addr, Arc::clone(&HEALTH), Arc::clone(&STATS), max_age).map_err(|err| error_chain(&err))?;
+        info!("serving /healthz on {}", addr);
+    }
+    let result = if cli.daemon { run_daemon(&cli, &shutdown) } else { run_command(&cli, &shutdown) };
+    // bench prints its own report
+    if !matches!(cli.command, Command::Bench(_)) {
+        log_stats_summary(&STATS.snapshot());
+    }
+    result
+}
+
+// so a long collection run can be sanity checked from its last lines
+fn log_stats_summary(snapshot: &StatsSnapshot) {
+    if snapshot.messages == 0 {
+        return;
+    }
+    let counts = |counts: &[(&str, u64)]| counts.iter().map(|(name, count)| format!("{}={}", name, count)).collect::<Vec<_>>().join(" ");
+    info!("{} messages, {} bytes, {} reconnects, {} dropped events, {} conflated quotes", snapshot.messages, snapshot.bytes, snapshot.reconnects, snapshot.dropped_events, snapshot.conflated_quotes);
+    info!("messages by type: {}", counts(&snapshot.message_types));
+    if snapshot.pings > 0 {
+        info!("{} pings ({} coalesced), every {:?} on average, answered within {:?} at p99", snapshot.pings, snapshot.coalesced_pings, snapshot.ping_interval.mean, snapshot.ping_reply_latency.p99);
+    }
+    if !snapshot.bars.is_empty() {
+        let bars: Vec<(&str, u64)> = snapshot.bars.iter().map(|(symbol, count)| (symbol.as_str(), *count)).collect();
+        info!("bars by symbol: {}", counts(&bars));
+    }
+    if !snapshot.errors.is_empty() {
+        info!("errors: {}", counts(&snapshot.errors));
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::event::Event;
+use crate::quote::QuoteField;
+
+// far enough to get past long holiday stretches, short enough to stay cheap
+const LOOKAHEAD_DAYS: i64 = 14;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MarketError {
+    #[error("unknown timezone {0:?}")]
+    Timezone(String),
+    #[error("invalid session {0:?}")]
+    Session(String),
+    #[error("invalid holiday {0:?}")]
+    Holiday(String)
+}
+
+// one "HHMM-HHMM:days" range; days are 1 for Sunday through 7 for Saturday and name the
+// day the session ends on, so "1700-1600:23456" opens Sunday evening for Monday
+#[derive(Debug, Clone, PartialEq)]
+struct SessionRange {
+    start: NaiveTime,
+    end: NaiveTime,
+    days: [bool; 7]
+}
+
+impl SessionRange {
+    fn parse(range: &str) -> Option<SessionRange> {
+        let (hours, days) = match range.split_once(':') {
+            Some((hours, days)) => (hours, Some(days)),
+            None => (range, None),
+        };
+        let (start, end) = hours.split_once('-')?;
+        let mut trading_days = [false; 7];
+        match days {
+            Some(days) => {
+                for day in days.chars() {
+                    let day = day.to_digit(10).filter(|day| (1..=7).contains(day))?;
+                    trading_days[day as usize - 1] = true;
+                }
+            }
+            // weekdays unless the session says otherwise
+            None => trading_days[1..6].fill(true),
+        }
+        Some(SessionRange {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            days: trading_days
+        })
+    }
+
+    // local open and close for the trading day, the open lands on the day before for overnight sessions
+    fn bounds(&self, day: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+        // "2400" and "0000" close at the end of the day
+        if self.end == NaiveTime::MIN {
+            return (day.and_time(self.start), (day + Duration::days(1)).and_time(NaiveTime::MIN));
+        }
+        let open_day = if self.start < self.end { day } else { day - Duration::days(1) };
+        (open_day.and_time(self.start), day.and_time(self.end))
+    }
+}
+
+// "2400" closes at midnight, kept as 00:00 and handled by bounds
+fn parse_time(time: &str) -> Option<NaiveTime> {
+    if time.len() != 4 {
+        return None;
+    }
+    let hour: u32 = time[..2].parse().ok()?;
+    let minute: u32 = time[2..].parse().ok()?;
+    match (hour, minute) {
+        (24, 0) => Some(NaiveTime::MIN),
+        _ => NaiveTime::from_hms_opt(hour, minute, 0),
+    }
+}
+
+// when a symbol trades, from the session, timezone and session_holidays of symbol_resolved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "TradingHoursFields", try_from = "TradingHoursFields")]
+pub struct TradingHours {
+    timezone: Tz,
+    // as parsed, for serializing
+    session: String,
+    ranges: Vec<SessionRange>,
+    always_open: bool,
+    holidays: HashSet<NaiveDate>,
+    // e.g. "Regular Trading Hours", when the server describes the session
+    pub description: Option<String>
+}
+
+impl TradingHours {
+    // session like "0930-1600" or "0400-0930,0930-1600:23456", holidays like "20241225,20250101"
+    pub fn parse(timezone: &str, session: &str, holidays: &str) -> Result<TradingHours, MarketError> {
+        let timezone: Tz = timezone.parse().map_err(|_| MarketError::Timezone(timezone.to_string()))?;
+        let always_open = session == "24x7";
+        let ranges = if always_open {
+            vec![]
+        } else {
+            session.split(['|', ',']).filter(|range| !range.is_empty())
+                .map(|range| SessionRange::parse(range).ok_or_else(|| MarketError::Session(session.to_string())))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let holidays = holidays.split(',').filter(|holiday| !holiday.is_empty())
+            .map(|holiday| NaiveDate::parse_from_str(holiday, "%Y%m%d").map_err(|_| MarketError::Holiday(holiday.to_string())))
+            .collect::<Result<HashSet<_>, _>>()?;
+        Ok(TradingHours { timezone, session: session.to_string(), ranges, always_open, holidays, description: None })
+    }
+
+    // the p[2] object of a symbol_resolved message
+    pub fn from_symbol_info(info: &Value) -> Result<TradingHours, MarketError> {
+        let field = |name: &str| info.get(name).and_then(Value::as_str).unwrap_or("");
+        let timezone = match field("timezone") {
+            "" => "Etc/UTC",
+            timezone => timezone,
+        };
+        let session = match field("session") {
+            "" => "24x7",
+            session => session,
+        };
+        let mut hours = TradingHours::parse(timezone, session, field("session_holidays"))?;
+        hours.description = info.get("subsessions").and_then(Value::as_array)
+            .and_then(|subsessions| subsessions.iter().find(|subsession| subsession.get("id").and_then(Value::as_str) == Some("regular")))
+            .and_then(|subsession| subsession.get("description")?.as_str())
+            .map(str::to_string);
+        Ok(hours)
+    }
+
+    // open intervals in utc from the day before `from` for LOOKAHEAD_DAYS, sorted and merged
+    fn intervals(&self, from: DateTime<Utc>) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let first_day = from.with_timezone(&self.timezone).date_naive() - Duration::days(1);
+        let mut intervals = vec![];
+        for offset in 0..=LOOKAHEAD_DAYS + 1 {
+            let day = first_day + Duration::days(offset);
+            if self.holidays.contains(&day) {
+                continue;
+            }
+            let weekday = day.weekday().num_days_from_sunday() as usize;
+            for range in self.ranges.iter().filter(|range| range.days[weekday]) {
+                let (open, close) = range.bounds(day);
+                // earliest/latest get around the hour that doesn't exist or happens twice on dst changes
+                let open = self.timezone.from_local_datetime(&open).earliest();
+                let close = self.timezone.from_local_datetime(&close).latest();
+                if let (Some(open), Some(close)) = (open, close) {
+                    intervals.push((open.with_timezone(&Utc), close.with_timezone(&Utc)));
+                }
+            }
+        }
+        intervals.sort();
+        let mut merged: Vec<(DateTime<Utc>, DateTime<Utc>)> = vec![];
+        for (open, close) in intervals {
+            match merged.last_mut() {
+                Some(last) if open <= last.1 => last.1 = last.1.max(close),
+                _ => merged.push((open, close)),
+            }
+        }
+        merged
+    }
+
+    pub fn is_open_at(&self, time: DateTime<Utc>) -> bool {
+        self.always_open || self.intervals(time).iter().any(|&(open, close)| open <= time && time < close)
+    }
+
+    // None when already open, or for 24x7 markets
+    pub fn next_open(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.is_open_at(after) {
+            return None;
+        }
+        self.intervals(after).into_iter().map(|(open, _)| open).find(|open| *open > after)
+    }
+
+    // when the current session opened, or the last one while closed; None for 24x7 markets
+    pub fn session_open(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.always_open {
+            return None;
+        }
+        self.intervals(at).into_iter().map(|(open, _)| open).rfind(|open| *open <= at)
+    }
+
+    // the close of the current session, or of the next one while closed
+    pub fn next_close(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.always_open {
+            return None;
+        }
+        self.intervals(after).into_iter().map(|(_, close)| close).find(|close| *close > after)
+    }
+}
+
+// what TradingHours is in json, the same strings it's parsed from
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TradingHoursFields {
+    // e.g. "America/New_York"
+    timezone: String,
+    // e.g. "0930-1600:23456" or "24x7"
+    session: String,
+    // e.g. "20241225,20250101"
+    holidays: String,
+    description: Option<String>
+}
+
+impl From<TradingHours> for TradingHoursFields {
+    fn from(hours: TradingHours) -> TradingHoursFields {
+        let mut holidays: Vec<NaiveDate> = hours.holidays.into_iter().collect();
+        holidays.sort();
+        TradingHoursFields {
+            timezone: hours.timezone.name().to_string(),
+            session: hours.session,
+            holidays: holidays.iter().map(|holiday| holiday.format("%Y%m%d").to_string()).collect::<Vec<String>>().join(","),
+            description: hours.description
+        }
+    }
+}
+
+impl TryFrom<TradingHoursFields> for TradingHours {
+    type Error = MarketError;
+
+    fn try_from(fields: TradingHoursFields) -> Result<TradingHours, MarketError> {
+        let mut hours = TradingHours::parse(&fields.timezone, &fields.session, &fields.holidays)?;
+        hours.description = fields.description;
+        Ok(hours)
+    }
+}
+
+impl JsonSchema for TradingHours {
+    fn schema_name() -> String {
+        "TradingHours".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        TradingHoursFields::json_schema(gen)
+    }
+}
+
+// the latest current_session quote wins over the schedule, which can't know about halts
+#[derive(Debug, Clone)]
+pub struct MarketStatus {
+    pub symbol: String,
+    pub hours: Option<TradingHours>,
+    // market, pre_market, post_market or out_of_session
+    pub current_session: Option<String>
+}
+
+impl MarketStatus {
+    pub fn new(symbol: &str) -> MarketStatus {
+        MarketStatus { symbol: symbol.to_string(), hours: None, current_session: None }
+    }
+
+    // feed it every event, it picks out the ones about its symbol
+    pub fn update(&mut self, event: &Event) {
+        match event {
+            Event::TradingHours { symbol, hours } if *symbol == self.symbol => self.hours = Some(hours.clone()),
+            Event::Quote { symbol, values, .. } if *symbol == self.symbol => {
+                let session = values.iter().find(|(field, _)| *field == QuoteField::CurrentSession);
+                if let Some(session) = session.and_then(|(_, session)| session.as_str()) {
+                    self.current_session = Some(session.to_string());
+                }
+            }
+            _ => {},
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        match (self.current_session.as_deref(), self.hours.as_ref()) {
+            (Some(session), _) => session == "market",
+            (None, Some(hours)) => hours.is_open_at(Utc::now()),
+            (None, None) => false,
+        }
+    }
+
+    pub fn next_open(&self) -> Option<DateTime<Utc>> {
+        self.hours.as_ref()?.next_open(Utc::now())
+    }
+
+    pub fn next_close(&self) -> Option<DateTime<Utc>> {
+        self.hours.as_ref()?.next_close(Utc::now())
+    }
+}
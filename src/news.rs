@@ -0,0 +1,111 @@
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::event::Event;
+use crate::http;
+use crate::poll::Poller;
+
+const HEADLINES_URL: &str = "https://news-headlines.tradingview.com/v2/headlines";
+
+// ids remembered per poller so a headline is reported once, even as it ages out of the feed
+const MAX_SEEN: usize = 10_000;
+
+#[derive(Debug, Error)]
+pub enum NewsError {
+    #[error("news request failed")]
+    Request(#[source] Box<dyn Error + Send>),
+    #[error("unexpected HTTP status {0}")]
+    Status(u16),
+    #[error("news response parse error")]
+    Parse(#[source] serde_json::Error)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NewsItem {
+    pub id: String,
+    pub title: String,
+    // e.g. "reuters"
+    pub provider: String,
+    pub published: i64,
+    // every symbol the story is tagged with, not just the one it was fetched for
+    pub symbols: Vec<String>,
+    pub link: Option<String>
+}
+
+impl NewsItem {
+    // items look like {"id": "...", "title": "...", "provider": "...", "published": 1700000000,
+    // "relatedSymbols": [{"symbol": "NASDAQ:AAPL"}], "storyPath": "/news/..."}
+    fn from_json(item: &Value) -> Option<NewsItem> {
+        let text = |name: &str| item.get(name).and_then(Value::as_str).map(str::to_string);
+        Some(NewsItem {
+            id: text("id")?,
+            title: text("title")?,
+            provider: text("provider").or_else(|| text("source")).unwrap_or_default(),
+            published: item.get("published")?.as_i64()?,
+            symbols: item.get("relatedSymbols").and_then(Value::as_array).map(|related| {
+                related.iter().filter_map(|related| related.get("symbol")?.as_str().map(str::to_string)).collect()
+            }).unwrap_or_default(),
+            link: text("link").or_else(|| text("storyPath").map(|path| format!("https://www.tradingview.com{}", path)))
+        })
+    }
+}
+
+// the latest headlines for the symbol, newest first
+pub fn fetch_headlines(symbol: &str) -> Result<Vec<NewsItem>, NewsError> {
+    let url = http::with_query(HEADLINES_URL, &[("client", "web"), ("lang", "en"), ("symbol", symbol)]);
+    let response = http::get(&url, &[("Referer", "https://www.tradingview.com/")]).map_err(NewsError::Request)?;
+    if !(200..300).contains(&response.status) {
+        return Err(NewsError::Status(response.status));
+    }
+    let parsed: Value = serde_json::from_str(&response.body).map_err(NewsError::Parse)?;
+    let items = parsed.get("items").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    Ok(items.iter().filter_map(NewsItem::from_json).collect())
+}
+
+// remembers which stories were already reported, oldest ids forgotten first
+#[derive(Default)]
+pub struct SeenNews {
+    ids: HashSet<String>,
+    order: VecDeque<String>
+}
+
+impl SeenNews {
+    // the items not seen before, oldest first
+    pub fn filter_new(&mut self, mut items: Vec<NewsItem>) -> Vec<NewsItem> {
+        items.retain(|item| !self.ids.contains(&item.id));
+        items.sort_by_key(|item| item.published);
+        for item in items.iter() {
+            self.ids.insert(item.id.clone());
+            self.order.push_back(item.id.clone());
+        }
+        while self.order.len() > MAX_SEEN {
+            if let Some(id) = self.order.pop_front() {
+                self.ids.remove(&id);
+            }
+        }
+        items
+    }
+}
+
+// polls the headlines of every attached connection's symbols; one story tagged with several of
+// them is reported for the first only
+pub fn poller(every: Duration) -> Poller {
+    let mut seen = SeenNews::default();
+    Poller::spawn(every, move |symbols| {
+        let mut events = vec![];
+        for symbol in symbols {
+            match fetch_headlines(symbol) {
+                Ok(items) => events.extend(seen.filter_new(items).into_iter().map(|item| Event::NewsItem { symbol: symbol.clone(), item })),
+                Err(err) => warn!("couldn't fetch news for {}: {}", symbol, err),
+            }
+        }
+        events
+    })
+}
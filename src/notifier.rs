@@ -0,0 +1,100 @@
+use std::error::Error;
+
+use serde_json::json;
+
+use crate::http;
+
+pub struct Alert {
+    pub symbol: String,
+    pub price: f64,
+    pub rule: String,
+    pub chart_link: String
+}
+
+impl Alert {
+    pub fn new(symbol: &str, price: f64, rule: &str) -> Alert {
+        Alert {
+            symbol: symbol.to_string(),
+            price,
+            rule: rule.to_string(),
+            chart_link: format!("https://www.tradingview.com/chart/?symbol={}", symbol)
+        }
+    }
+
+    pub fn format_message(&self) -> String {
+        format!("{} @ {}\n{}\n{}", self.symbol, self.price, self.rule, self.chart_link)
+    }
+}
+
+pub trait Notifier {
+    fn notify(&self, alert: &Alert) -> Result<(), Box<dyn Error + Send>>;
+}
+
+pub struct TelegramNotifier {
+    bot_token: String,
+    chat_id: String
+}
+
+impl TelegramNotifier {
+    pub fn new(bot_token: &str, chat_id: &str) -> TelegramNotifier {
+        TelegramNotifier {
+            bot_token: bot_token.to_string(),
+            chat_id: chat_id.to_string()
+        }
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn notify(&self, alert: &Alert) -> Result<(), Box<dyn Error + Send>> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+        http::post_json(&url, &json!({
+            "chat_id": self.chat_id,
+            "text": alert.format_message(),
+            "disable_web_page_preview": true
+        }))?;
+        Ok(())
+    }
+}
+
+pub struct DiscordNotifier {
+    webhook_url: String
+}
+
+impl DiscordNotifier {
+    pub fn new(webhook_url: &str) -> DiscordNotifier {
+        DiscordNotifier {
+            webhook_url: webhook_url.to_string()
+        }
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn notify(&self, alert: &Alert) -> Result<(), Box<dyn Error + Send>> {
+        http::post_json(&self.webhook_url, &json!({
+            "content": alert.format_message()
+        }))?;
+        Ok(())
+    }
+}
+
+pub struct SlackNotifier {
+    webhook_url: String
+}
+
+impl SlackNotifier {
+    pub fn new(webhook_url: &str) -> SlackNotifier {
+        SlackNotifier {
+            webhook_url: webhook_url.to_string()
+        }
+    }
+}
+
+impl Notifier for SlackNotifier {
+    fn notify(&self, alert: &Alert) -> Result<(), Box<dyn Error + Send>> {
+        // slack renders <url|label> as a link
+        http::post_json(&self.webhook_url, &json!({
+            "text": format!("*{}* @ {}\n{}\n<{}|Open chart>", alert.symbol, alert.price, alert.rule, alert.chart_link)
+        }))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use thiserror::Error;
+
+use crate::tradingview_api::{self, ParsedFrame, ParsedMessage};
+
+#[derive(Debug, Error)]
+pub enum ParsePoolError {
+    #[error("parse worker stopped")]
+    WorkerStopped
+}
+
+// a run of consecutive messages of one frame
+struct Job {
+    frame: Arc<String>,
+    spans: Vec<Range<usize>>,
+    chunk: usize,
+    reply: Sender<(usize, Vec<ParsedMessage>)>
+}
+
+// parses frames for many connections on a fixed set of threads
+pub struct ParsePool {
+    workers: Vec<Sender<Job>>,
+    next_worker: AtomicUsize
+}
+
+impl ParsePool {
+    pub fn new(workers: usize) -> ParsePool {
+        let workers = (0..workers.max(1)).map(|index| {
+            let (job_tx, job_rx) = mpsc::channel::<Job>();
+            thread::Builder::new()
+                .name(format!("parse-{}", index))
+                .spawn(move || {
+                    // exits once every session and the pool itself are dropped
+                    for Job { frame, spans, chunk, reply } in job_rx {
+                        let messages = spans.into_iter().map(|span| tradingview_api::parse_span(&frame, &frame[span])).collect();
+                        // the frame goes back to its session only once no worker holds it
+                        drop(frame);
+                        let _ = reply.send((chunk, messages));
+                    }
+                })
+                .expect("failed to spawn parse worker");
+            job_tx
+        }).collect();
+        ParsePool {
+            workers,
+            next_worker: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    // sessions start their frames on different workers so single message frames spread out too
+    pub fn session(&self) -> ParseSession {
+        let (reply, replies) = mpsc::channel();
+        ParseSession {
+            workers: self.workers.clone(),
+            first_worker: self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len(),
+            reply,
+            replies
+        }
+    }
+}
+
+pub struct ParseSession {
+    workers: Vec<Sender<Job>>,
+    first_worker: usize,
+    reply: Sender<(usize, Vec<ParsedMessage>)>,
+    replies: Receiver<(usize, Vec<ParsedMessage>)>
+}
+
+impl ParseSession {
+    // the frame's messages are cut into one run per worker and parsed at the same time, then put
+    // back in frame order
+    pub fn parse(&self, frame: String) -> Result<ParsedFrame, ParsePoolError> {
+        let spans: Vec<Range<usize>> = tradingview_api::split_messages(&frame).map(|message| {
+            let start = message.as_ptr() as usize - frame.as_ptr() as usize;
+            start..start + message.len()
+        }).collect();
+        let per_chunk = spans.len().div_ceil(self.workers.len()).max(1);
+        let frame = Arc::new(frame);
+        let mut chunks = 0;
+        for (chunk, spans) in spans.chunks(per_chunk).enumerate() {
+            let job = Job { frame: Arc::clone(&frame), spans: spans.to_vec(), chunk, reply: self.reply.clone() };
+            self.workers[(self.first_worker + chunk) % self.workers.len()].send(job).map_err(|_| ParsePoolError::WorkerStopped)?;
+            chunks += 1;
+        }
+        let mut parsed: Vec<Option<Vec<ParsedMessage>>> = (0..chunks).map(|_| None).collect();
+        for _ in 0..chunks {
+            let (chunk, messages) = self.replies.recv().map_err(|_| ParsePoolError::WorkerStopped)?;
+            parsed[chunk] = Some(messages);
+        }
+        let frame = Arc::try_unwrap(frame).map_err(|_| ParsePoolError::WorkerStopped)?;
+        Ok(ParsedFrame { frame, messages: parsed.into_iter().flatten().flatten().collect() })
+    }
+}
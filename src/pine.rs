@@ -0,0 +1,186 @@
+use std::error::Error;
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::http;
+use crate::study::{PlotInfo, StudySpec};
+
+const TRANSLATE_URL: &str = "https://pine-facade.tradingview.com/pine-facade/translate";
+const SCRIPT_URL_PREFIX: &str = "https://www.tradingview.com/script/";
+
+#[derive(Debug, Error)]
+pub enum PineError {
+    #[error("{0:?} is neither a pine id nor a script url")]
+    InvalidReference(String),
+    #[error("pine request failed")]
+    Request(#[source] Box<dyn Error + Send>),
+    #[error("unexpected HTTP status {0}")]
+    Status(u16),
+    #[error("pine response parse error")]
+    Parse(#[source] serde_json::Error),
+    #[error("no pine id on the page of script {0:?}")]
+    NoPineId(String),
+    #[error("pine-facade couldn't translate {pine_id}: {reason}")]
+    Translate { pine_id: String, reason: String }
+}
+
+// scripts are named by a pine id like "PUB;abc123" or "STD;RSI", their page url or its slug
+pub fn is_pine_id(reference: &str) -> bool {
+    reference.split_once(';').is_some_and(|(prefix, id)| !id.is_empty() && prefix.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+// https://www.tradingview.com/script/AbCd1234-Some-Name/ and AbCd1234-Some-Name both give AbCd1234
+fn script_slug(reference: &str) -> Option<&str> {
+    let rest = reference.split_once("/script/").map_or(reference, |(_, rest)| rest);
+    let slug = rest.split(['/', '-']).next()?;
+    (!slug.is_empty() && slug.chars().all(|c| c.is_ascii_alphanumeric())).then_some(slug)
+}
+
+fn encode(value: &str) -> String {
+    value.replace('%', "%25").replace(';', "%3B").replace(' ', "%20")
+}
+
+fn get_json(url: &str) -> Result<Value, PineError> {
+    let response = http::get(url, &[("Referer", "https://www.tradingview.com/")]).map_err(PineError::Request)?;
+    if !(200..300).contains(&response.status) {
+        return Err(PineError::Status(response.status));
+    }
+    serde_json::from_str(&response.body).map_err(PineError::Parse)
+}
+
+// the script page embeds its id as "scriptIdPart":"..."; published ids are PUB;<that>
+pub fn resolve_pine_id(reference: &str) -> Result<String, PineError> {
+    if is_pine_id(reference) {
+        return Ok(reference.to_string());
+    }
+    let slug = script_slug(reference).ok_or_else(|| PineError::InvalidReference(reference.to_string()))?;
+    let response = http::get(&format!("{}{}/", SCRIPT_URL_PREFIX, slug), &[]).map_err(PineError::Request)?;
+    if !(200..300).contains(&response.status) {
+        return Err(PineError::Status(response.status));
+    }
+    let marker = "\"scriptIdPart\":\"";
+    let start = response.body.find(marker).ok_or_else(|| PineError::NoPineId(slug.to_string()))? + marker.len();
+    let id = response.body[start..].split('"').next().unwrap_or("");
+    if id.is_empty() {
+        return Err(PineError::NoPineId(slug.to_string()));
+    }
+    Ok(if is_pine_id(id) { id.to_string() } else { format!("PUB;{}", id) })
+}
+
+// one input from the script's meta info
+#[derive(Debug, Clone, PartialEq)]
+pub struct PineInput {
+    // in_0, in_1, ...
+    pub id: String,
+    pub name: String,
+    // integer, float, bool, source, text, resolution, ...
+    pub kind: String,
+    pub default: Value
+}
+
+// what create_study needs for a pine script: its compiled il and the inputs it declares
+#[derive(Debug, Clone)]
+pub struct PineScript {
+    pub pine_id: String,
+    pub version: String,
+    // the "text" input, the script compiled to pine's intermediate language
+    pub il_template: String,
+    pub inputs: Vec<PineInput>,
+    pub plots: Vec<PlotInfo>
+}
+
+impl PineScript {
+    // version is "last" or a published one like "3.0"
+    pub fn fetch(reference: &str, version: &str) -> Result<PineScript, PineError> {
+        let pine_id = resolve_pine_id(reference)?;
+        let parsed = get_json(&format!("{}/{}/{}", TRANSLATE_URL, encode(&pine_id), encode(version)))?;
+        if parsed.get("success").and_then(Value::as_bool) != Some(true) {
+            let reason = parsed.get("reason").and_then(Value::as_str).unwrap_or("no reason given");
+            return Err(PineError::Translate { pine_id, reason: reason.to_string() });
+        }
+        let result = parsed.get("result").cloned().unwrap_or(Value::Null);
+        let il_template = result.get("ilTemplate").and_then(Value::as_str).unwrap_or("").to_string();
+        let meta = result.get("metaInfo").cloned().unwrap_or(Value::Null);
+        let inputs = meta.get("inputs").and_then(Value::as_array).map(|inputs| {
+            inputs.iter().filter_map(|input| {
+                let id = input.get("id")?.as_str()?;
+                // text, pineId and pineVersion are filled in by study_spec itself
+                id.starts_with("in_").then(|| PineInput {
+                    id: id.to_string(),
+                    name: input.get("name").and_then(Value::as_str).unwrap_or(id).to_string(),
+                    kind: input.get("type").and_then(Value::as_str).unwrap_or("").to_string(),
+                    default: input.get("defval").cloned().unwrap_or(Value::Null)
+                })
+            }).collect()
+        }).unwrap_or_default();
+        let plots = parse_plots(&meta);
+        let version = meta.pointer("/pine/version").and_then(Value::as_str).unwrap_or(version).to_string();
+        Ok(PineScript { pine_id, version, il_template, inputs, plots })
+    }
+
+    // every input at its default, except the first source input, which reads the parent's
+    // first plot when the study is attached to another one
+    pub fn study_spec(&self, id: &str, parent: Option<&str>) -> StudySpec {
+        let mut inputs = Map::new();
+        inputs.insert("text".to_string(), Value::from(self.il_template.as_str()));
+        inputs.insert("pineId".to_string(), Value::from(self.pine_id.as_str()));
+        inputs.insert("pineVersion".to_string(), Value::from(self.version.as_str()));
+        let parent_input = parent.and_then(|_| self.inputs.iter().position(|input| input.kind == "source"));
+        for (index, input) in self.inputs.iter().enumerate() {
+            let value = match parent {
+                Some(parent) if parent_input == Some(index) => Value::from(format!("{}$0", parent)),
+                _ => input.default.clone(),
+            };
+            inputs.insert(input.id.clone(), serde_json::json!({
+                "v": value,
+                "f": true,
+                "t": input.kind
+            }));
+        }
+        StudySpec {
+            id: id.to_string(),
+            script: "Script@tv-scripting-101!".to_string(),
+            inputs: Value::Object(inputs),
+            parent: parent.map(str::to_string),
+            plots: self.plots.clone().into()
+        }
+    }
+}
+
+// plots look like {"id": "plot_0", "type": "line"}, titled in styles.plot_0.title and colored in
+// defaults.styles.plot_0.color; colorers and the like have no style and are named after their id
+fn parse_plots(meta: &Value) -> Vec<PlotInfo> {
+    let plots = meta.get("plots").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    plots.iter().filter_map(|plot| {
+        let id = plot.get("id")?.as_str()?;
+        let name = meta.get("styles").and_then(|styles| styles.get(id)?.get("title")?.as_str()).unwrap_or(id);
+        let color = meta.get("defaults").and_then(|defaults| defaults.get("styles")?.get(id)?.get("color")?.as_str());
+        Some(PlotInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            kind: plot.get("type").and_then(Value::as_str).unwrap_or("").to_string(),
+            color: color.map(str::to_string)
+        })
+    }).collect()
+}
+
+// fills in the il and inputs of pine studies that only name their script, e.g. from StudySpec::pine
+// with a script url; built-ins the facade can't translate are left for the server to resolve
+pub fn resolve_studies(studies: &mut [StudySpec]) -> Result<(), PineError> {
+    for study in studies.iter_mut() {
+        if study.inputs.get("text").is_some() {
+            continue;
+        }
+        let reference = match study.inputs.get("pineId").and_then(Value::as_str) {
+            Some(reference) => reference.to_string(),
+            None => continue,
+        };
+        match PineScript::fetch(&reference, "last") {
+            Ok(script) => *study = script.study_spec(&study.id, study.parent.as_deref()),
+            Err(err) if reference.starts_with("STD;") => log::warn!("couldn't translate {}, sending it as is: {}", reference, err),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::sync::OnceLock;
+
+use chrono::{TimeZone, Utc};
+use plotters::coord::types::{RangedCoordf64, RangedCoordusize};
+use plotters::prelude::*;
+use plotters::style::{register_font, FontStyle};
+
+use crate::bar::Bar;
+use crate::event::Event;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum PlotError {
+    EmptyError,
+    ReadError(String),
+    DrawError(String),
+    UnsupportedFormatError(String)
+}
+
+impl std::fmt::Display for PlotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PlotError::EmptyError => write!(f, "No bars to plot"),
+            PlotError::ReadError(ref message) => write!(f, "Read error: {}", message),
+            PlotError::DrawError(ref message) => write!(f, "Draw error: {}", message),
+            PlotError::UnsupportedFormatError(ref path) => write!(f, "Unsupported chart format: {} (use .svg or .png)", path),
+        }
+    }
+}
+
+impl Error for PlotError {}
+
+impl From<PlotError> for Box<dyn std::error::Error + Send> {
+    fn from(error: PlotError) -> Self {
+        Box::new(error)
+    }
+}
+
+pub struct Overlay {
+    pub name: String,
+    pub values: Vec<Option<f64>>
+}
+
+const OVERLAY_COLORS: [RGBColor; 4] = [BLUE, MAGENTA, CYAN, BLACK];
+
+const FONT_PATHS: [&str; 3] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/Library/Fonts/Arial.ttf",
+];
+
+// the share of the height the candles get when studies are plotted below them
+const PRICE_PANEL_SHARE: f64 = 0.65;
+
+// plotters ships without fonts, so borrow one from the system; without it the chart has no text.
+// The first one found is read and registered once per process
+fn register_system_font() -> bool {
+    static FONT: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+    static REGISTERED: OnceLock<bool> = OnceLock::new();
+    *REGISTERED.get_or_init(|| {
+        match FONT.get_or_init(|| FONT_PATHS.iter().find_map(|path| std::fs::read(path).ok())) {
+            Some(bytes) => register_font("sans-serif", FontStyle::Normal, bytes).is_ok(),
+            None => false,
+        }
+    })
+}
+
+// the line plots of the symbol's StudyValues events in a file of events, one json object per
+// line as the event schema describes them (what `stream --study-out` writes), lined up with the
+// bars; plots of the same study and name from several events are merged, the newest value wins
+pub fn read_study_series(path: &str, symbol: &str, bars: &[Bar]) -> Result<Vec<Overlay>, Box<dyn Error + Send>> {
+    let file = std::fs::File::open(path).map_err(|err| PlotError::ReadError(format!("{}: {}", path, err)))?;
+    let mut names: Vec<String> = vec![];
+    let mut values: HashMap<String, HashMap<i64, f64>> = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|err| PlotError::ReadError(format!("{}: {}", path, err)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: Event = serde_json::from_str(&line).map_err(|err| PlotError::ReadError(format!("{}: {}", path, err)))?;
+        let (study, plots, points) = match event {
+            Event::StudyValues { symbol: ref event_symbol, study, plots, points, .. } if event_symbol == symbol => (study, plots, points),
+            _ => continue,
+        };
+        for point in points {
+            for (index, (name, value)) in point.named(&plots).enumerate() {
+                // shapes, colorers and the like aren't values on a scale
+                if plots.get(index).is_some_and(|plot| plot.kind != "line") {
+                    continue;
+                }
+                let name = format!("{} {}", study, name);
+                if !values.contains_key(&name) {
+                    names.push(name.clone());
+                }
+                values.entry(name).or_default().insert(point.time, value);
+            }
+        }
+    }
+    Ok(names.into_iter().filter_map(|name| {
+        let by_time = values.remove(&name)?;
+        let values: Vec<Option<f64>> = bars.iter().map(|bar| by_time.get(&bar.time).copied().filter(|value| value.is_finite())).collect();
+        values.iter().any(Option::is_some).then_some(Overlay { name, values })
+    }).collect())
+}
+
+// bars are plotted by index, values on a linear scale
+type IndexChart<'a, DB> = ChartContext<'a, DB, Cartesian2d<RangedCoordusize, RangedCoordf64>>;
+
+// each overlay as a line over the bar indices, with a legend when there's text
+fn draw_lines<'a, DB: DrawingBackend + 'a>(chart: &mut IndexChart<'a, DB>, overlays: &[Overlay], with_text: bool) -> Result<(), PlotError> {
+    let draw_error = |err: &dyn std::fmt::Display| PlotError::DrawError(err.to_string());
+    for (overlay, color) in overlays.iter().zip(OVERLAY_COLORS.iter().cycle()) {
+        let points = overlay.values.iter().enumerate().filter_map(|(index, value)| value.map(|value| (index, value)));
+        chart.draw_series(LineSeries::new(points, color))
+            .map_err(|err| draw_error(&err))?
+            .label(overlay.name.clone())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    if with_text && !overlays.is_empty() {
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(|err| draw_error(&err))?;
+    }
+    Ok(())
+}
+
+// the range of the values with 5% either side, so lines don't run along the border
+fn padded_range<'a>(values: impl Iterator<Item = &'a f64>) -> (f64, f64) {
+    let (low, high) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), value| (low.min(*value), high.max(*value)));
+    let padding = ((high - low) * 0.05).max(f64::EPSILON);
+    (low - padding, high + padding)
+}
+
+// overlays share the candles' scale, studies get a panel of their own below them
+fn draw_chart<DB: DrawingBackend>(root: DrawingArea<DB, plotters::coord::Shift>, title: &str, bars: &[Bar], overlays: &[Overlay], studies: &[Overlay]) -> Result<(), Box<dyn Error + Send>> {
+    let with_text = register_system_font();
+    let draw_error = |err: &dyn std::fmt::Display| PlotError::DrawError(err.to_string());
+    root.fill(&WHITE).map_err(|err| draw_error(&err))?;
+    let (price_area, study_area) = match studies {
+        [] => (root.clone(), None),
+        _ => {
+            let (_, height) = root.dim_in_pixel();
+            let (price_area, study_area) = root.split_vertically((height as f64 * PRICE_PANEL_SHARE) as u32);
+            (price_area, Some(study_area))
+        }
+    };
+
+    let prices = bars.iter().flat_map(|bar| [bar.low, bar.high]).collect::<Vec<f64>>();
+    let (low, high) = padded_range(prices.iter().chain(overlays.iter().flat_map(|overlay| overlay.values.iter().flatten())));
+
+    let mut builder = ChartBuilder::on(&price_area);
+    builder.margin(10);
+    if with_text {
+        builder.caption(title, ("sans-serif", 20)).x_label_area_size(30).y_label_area_size(60);
+    }
+    let mut chart = builder
+        .build_cartesian_2d(0..bars.len(), low..high)
+        .map_err(|err| draw_error(&err))?;
+
+    // candles are plotted by index so gaps (weekends, halts) don't leave holes
+    let label_time = |index: &usize| match bars.get(*index).and_then(|bar| Utc.timestamp_opt(bar.time, 0).single()) {
+        Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        None => String::new(),
+    };
+    if with_text {
+        chart.configure_mesh()
+            .x_labels(6)
+            .x_label_formatter(&label_time)
+            .draw()
+            .map_err(|err| draw_error(&err))?;
+    }
+
+    let candle_width = ((900 / bars.len().max(1)) as u32).clamp(1, 15);
+    chart.draw_series(bars.iter().enumerate().map(|(index, bar)| {
+        CandleStick::new(index, bar.open, bar.high, bar.low, bar.close, GREEN.filled(), RED.filled(), candle_width)
+    })).map_err(|err| draw_error(&err))?;
+
+    draw_lines(&mut chart, overlays, with_text)?;
+
+    if let Some(study_area) = study_area {
+        let (low, high) = padded_range(studies.iter().flat_map(|study| study.values.iter().flatten()));
+        let mut builder = ChartBuilder::on(&study_area);
+        builder.margin(10);
+        if with_text {
+            builder.x_label_area_size(30).y_label_area_size(60);
+        }
+        let mut chart = builder.build_cartesian_2d(0..bars.len(), low..high).map_err(|err| draw_error(&err))?;
+        if with_text {
+            chart.configure_mesh()
+                .x_labels(6)
+                .x_label_formatter(&label_time)
+                .draw()
+                .map_err(|err| draw_error(&err))?;
+        }
+        draw_lines(&mut chart, studies, with_text)?;
+    }
+
+    root.present().map_err(|err| draw_error(&err))?;
+    Ok(())
+}
+
+pub fn render_chart(path: &str, title: &str, bars: &[Bar], overlays: &[Overlay], studies: &[Overlay], size: (u32, u32)) -> Result<(), Box<dyn Error + Send>> {
+    if bars.is_empty() {
+        return Err(PlotError::EmptyError.into());
+    }
+    if path.ends_with(".svg") {
+        return draw_chart(SVGBackend::new(path, size).into_drawing_area(), title, bars, overlays, studies);
+    }
+    if path.ends_with(".png") {
+        return draw_chart(BitMapBackend::new(path, size).into_drawing_area(), title, bars, overlays, studies);
+    }
+    Err(PlotError::UnsupportedFormatError(path.to_string()).into())
+}
@@ -0,0 +1,120 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::event::Event;
+
+// how often a poller waiting for its next round looks at the stop flag
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// a connection's event channel and the symbols it follows
+struct Attachment {
+    id: u64,
+    events_tx: SyncSender<Arc<Event>>,
+    symbols: Vec<String>
+}
+
+#[derive(Default)]
+struct Attachments {
+    next_id: AtomicU64,
+    attached: Mutex<Vec<Attachment>>
+}
+
+impl Attachments {
+    fn snapshot(&self) -> Vec<(SyncSender<Arc<Event>>, Vec<String>)> {
+        self.attached.lock().map(|attached| {
+            attached.iter().map(|attachment| (attachment.events_tx.clone(), attachment.symbols.clone())).collect()
+        }).unwrap_or_default()
+    }
+}
+
+// an http poller owned by the client rather than by a connection. It lives in ApiOptions, so
+// every connection and reconnect made with them shares it and what it already reported, and
+// stops once the last clone of the options is gone. Connections attach while they're up; fetch
+// runs on its own thread as soon as one is and then every `every`, so slow http calls never hold
+// up the socket. It gets the symbols of all attached connections, and an event goes to the first
+// connection following its symbol, or the first one at all if it has none
+pub struct Poller {
+    attachments: Arc<Attachments>,
+    stop: Arc<AtomicBool>
+}
+
+impl Poller {
+    pub fn spawn(every: Duration, mut fetch: impl FnMut(&[String]) -> Vec<Event> + Send + 'static) -> Poller {
+        let attachments = Arc::new(Attachments::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let (thread_attachments, thread_stop) = (Arc::clone(&attachments), Arc::clone(&stop));
+        thread::spawn(move || {
+            let mut next_round = Instant::now();
+            while !thread_stop.load(Ordering::SeqCst) {
+                let now = Instant::now();
+                if now < next_round {
+                    thread::sleep(STOP_CHECK_INTERVAL.min(next_round - now));
+                    continue;
+                }
+                let connections = thread_attachments.snapshot();
+                if connections.is_empty() {
+                    thread::sleep(STOP_CHECK_INTERVAL);
+                    continue;
+                }
+                next_round = now + every;
+                let mut symbols: Vec<String> = vec![];
+                for symbol in connections.iter().flat_map(|(_, symbols)| symbols) {
+                    if !symbols.contains(symbol) {
+                        symbols.push(symbol.clone());
+                    }
+                }
+                for event in fetch(&symbols) {
+                    let connection = event.symbol()
+                        .and_then(|symbol| connections.iter().find(|(_, symbols)| symbols.iter().any(|followed| followed == symbol)))
+                        .or(connections.first());
+                    // a connection that just went away takes its events with it
+                    if let Some((events_tx, _)) = connection {
+                        let _ = events_tx.send(Arc::new(event));
+                    }
+                }
+            }
+        });
+        Poller { attachments, stop }
+    }
+
+    pub fn attach(&self, events_tx: SyncSender<Arc<Event>>, symbols: Vec<String>) -> PollerHandle {
+        let id = self.attachments.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut attached) = self.attachments.attached.lock() {
+            attached.push(Attachment { id, events_tx, symbols });
+        }
+        PollerHandle { attachments: Arc::clone(&self.attachments), id }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+// a connection's attachment to a Poller, detached when dropped so the events channel can close
+pub struct PollerHandle {
+    attachments: Arc<Attachments>,
+    id: u64
+}
+
+impl PollerHandle {
+    pub fn set_symbols(&self, symbols: &[String]) {
+        if let Ok(mut attached) = self.attachments.attached.lock() {
+            if let Some(attachment) = attached.iter_mut().find(|attachment| attachment.id == self.id) {
+                attachment.symbols = symbols.to_vec();
+            }
+        }
+    }
+}
+
+impl Drop for PollerHandle {
+    fn drop(&mut self) {
+        if let Ok(mut attached) = self.attachments.attached.lock() {
+            attached.retain(|attachment| attachment.id != self.id);
+        }
+    }
+}
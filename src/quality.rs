@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::bar::Bar;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DataQualityIssue {
+    NonFinitePrice,
+    ZeroPrice,
+    HighBelowLow,
+    NonMonotonicTime { previous: i64 },
+    MissingBars { after: i64, count: i64 }
+}
+
+impl fmt::Display for DataQualityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataQualityIssue::NonFinitePrice => write!(f, "non-finite price"),
+            DataQualityIssue::ZeroPrice => write!(f, "zero price"),
+            DataQualityIssue::HighBelowLow => write!(f, "high below low"),
+            DataQualityIssue::NonMonotonicTime { previous } => write!(f, "timestamp goes back from {}", previous),
+            DataQualityIssue::MissingBars { after, count } => write!(f, "{} bars missing after {}", count, after),
+        }
+    }
+}
+
+// "1", "15", "240" are minutes, "1S" seconds, "D"/"W" days and weeks; months vary in length
+pub fn interval_seconds(interval: &str) -> Option<i64> {
+    let (count, unit) = match interval.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => interval.split_at(index),
+        None => (interval, ""),
+    };
+    let count = if count.is_empty() { 1 } else { count.parse::<i64>().ok()? };
+    let unit_seconds = match unit {
+        "" => 60,
+        "S" => 1,
+        "D" => 86_400,
+        "W" => 604_800,
+        _ => return None,
+    };
+    Some(count * unit_seconds)
+}
+
+pub struct BarValidator {
+    interval_seconds: Option<i64>,
+    latest_times: HashMap<String, i64>
+}
+
+impl BarValidator {
+    pub fn new(interval: &str) -> BarValidator {
+        BarValidator {
+            interval_seconds: interval_seconds(interval),
+            latest_times: HashMap::new()
+        }
+    }
+
+    // history pages arrive newest first, so only bars past the latest one seen are checked for gaps;
+    // markets that close overnight will report their session breaks as missing bars
+    pub fn check(&mut self, symbol: &str, bars: &[Bar]) -> Vec<(i64, DataQualityIssue)> {
+        let mut issues = vec![];
+        let mut previous = self.latest_times.get(symbol).copied().filter(|latest| bars.first().is_some_and(|bar| bar.time >= *latest));
+        for bar in bars {
+            let prices = [bar.open, bar.high, bar.low, bar.close];
+            if prices.iter().any(|price| !price.is_finite()) {
+                issues.push((bar.time, DataQualityIssue::NonFinitePrice));
+            } else if prices.contains(&0.0) {
+                issues.push((bar.time, DataQualityIssue::ZeroPrice));
+            }
+            if bar.high < bar.low {
+                issues.push((bar.time, DataQualityIssue::HighBelowLow));
+            }
+            if let Some(previous) = previous {
+                if bar.time < previous {
+                    issues.push((bar.time, DataQualityIssue::NonMonotonicTime { previous }));
+                } else if let Some(interval) = self.interval_seconds {
+                    let count = (bar.time - previous) / interval - 1;
+                    if count > 0 {
+                        issues.push((bar.time, DataQualityIssue::MissingBars { after: previous, count }));
+                    }
+                }
+            }
+            previous = Some(previous.map_or(bar.time, |previous| previous.max(bar.time)));
+        }
+        if let Some(latest) = previous {
+            let entry = self.latest_times.entry(symbol.to_string()).or_insert(latest);
+            *entry = (*entry).max(latest);
+        }
+        issues
+    }
+}
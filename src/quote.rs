@@ -0,0 +1,444 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use serde::{Deserialize, Serialize};
+use serde::de::{Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde_json::Value;
+use serde_json::value::RawValue;
+use thiserror::Error;
+
+use crate::auth;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLevel {
+    Anonymous,
+    Authenticated
+}
+
+impl AccessLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessLevel::Anonymous => "anonymous",
+            AccessLevel::Authenticated => "authenticated",
+        }
+    }
+
+    // what a session logged in with the token may ask for
+    pub fn of_token(token: &str) -> AccessLevel {
+        if token == auth::ANONYMOUS_TOKEN { AccessLevel::Anonymous } else { AccessLevel::Authenticated }
+    }
+
+    pub fn allows(&self, field: QuoteField) -> bool {
+        *self == AccessLevel::Authenticated || field.required_access() == AccessLevel::Anonymous
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum QuoteField {
+    BaseCurrencyLogoId,
+    Change,
+    ChangePercent,
+    CurrencyLogoId,
+    CurrencyCode,
+    CurrencyId,
+    BaseCurrencyId,
+    CurrentSession,
+    Description,
+    Exchange,
+    Format,
+    Fractional,
+    IsTradable,
+    Language,
+    LocalDescription,
+    ListedExchange,
+    LogoId,
+    LastPrice,
+    LastPriceTime,
+    MinMove,
+    MinMove2,
+    OriginalName,
+    PriceScale,
+    ProName,
+    ShortName,
+    Type,
+    TypeSpecs,
+    UpdateMode,
+    Volume,
+    ValueUnitId,
+    RegularChangePercent,
+    RegularClose,
+    CountryCode,
+    ProviderId,
+    MarketCap,
+    PriceEarnings,
+    EarningsPerShare,
+    Sector,
+    Industry,
+    FloatShares
+}
+
+impl QuoteField {
+    pub const ALL: [QuoteField; 40] = [
+        QuoteField::BaseCurrencyLogoId,
+        QuoteField::Change,
+        QuoteField::ChangePercent,
+        QuoteField::CurrencyLogoId,
+        QuoteField::CurrencyCode,
+        QuoteField::CurrencyId,
+        QuoteField::BaseCurrencyId,
+        QuoteField::CurrentSession,
+        QuoteField::Description,
+        QuoteField::Exchange,
+        QuoteField::Format,
+        QuoteField::Fractional,
+        QuoteField::IsTradable,
+        QuoteField::Language,
+        QuoteField::LocalDescription,
+        QuoteField::ListedExchange,
+        QuoteField::LogoId,
+        QuoteField::LastPrice,
+        QuoteField::LastPriceTime,
+        QuoteField::MinMove,
+        QuoteField::MinMove2,
+        QuoteField::OriginalName,
+        QuoteField::PriceScale,
+        QuoteField::ProName,
+        QuoteField::ShortName,
+        QuoteField::Type,
+        QuoteField::TypeSpecs,
+        QuoteField::UpdateMode,
+        QuoteField::Volume,
+        QuoteField::ValueUnitId,
+        QuoteField::RegularChangePercent,
+        QuoteField::RegularClose,
+        QuoteField::CountryCode,
+        QuoteField::ProviderId,
+        QuoteField::MarketCap,
+        QuoteField::PriceEarnings,
+        QuoteField::EarningsPerShare,
+        QuoteField::Sector,
+        QuoteField::Industry,
+        QuoteField::FloatShares,
+    ];
+
+    // wire name used in quote_set_fields and qsd payloads
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuoteField::BaseCurrencyLogoId => "base-currency-logoid",
+            QuoteField::Change => "ch",
+            QuoteField::ChangePercent => "chp",
+            QuoteField::CurrencyLogoId => "currency-logoid",
+            QuoteField::CurrencyCode => "currency_code",
+            QuoteField::CurrencyId => "currency_id",
+            QuoteField::BaseCurrencyId => "base_currency_id",
+            QuoteField::CurrentSession => "current_session",
+            QuoteField::Description => "description",
+            QuoteField::Exchange => "exchange",
+            QuoteField::Format => "format",
+            QuoteField::Fractional => "fractional",
+            QuoteField::IsTradable => "is_tradable",
+            QuoteField::Language => "language",
+            QuoteField::LocalDescription => "local_description",
+            QuoteField::ListedExchange => "listed_exchange",
+            QuoteField::LogoId => "logoid",
+            QuoteField::LastPrice => "lp",
+            QuoteField::LastPriceTime => "lp_time",
+            QuoteField::MinMove => "minmov",
+            QuoteField::MinMove2 => "minmove2",
+            QuoteField::OriginalName => "original_name",
+            QuoteField::PriceScale => "pricescale",
+            QuoteField::ProName => "pro_name",
+            QuoteField::ShortName => "short_name",
+            QuoteField::Type => "type",
+            QuoteField::TypeSpecs => "typespecs",
+            QuoteField::UpdateMode => "update_mode",
+            QuoteField::Volume => "volume",
+            QuoteField::ValueUnitId => "value_unit_id",
+            QuoteField::RegularChangePercent => "rchp",
+            QuoteField::RegularClose => "rtc",
+            QuoteField::CountryCode => "country_code",
+            QuoteField::ProviderId => "provider_id",
+            QuoteField::MarketCap => "market_cap_basic",
+            QuoteField::PriceEarnings => "price_earnings_ttm",
+            QuoteField::EarningsPerShare => "earnings_per_share_basic_ttm",
+            QuoteField::Sector => "sector",
+            QuoteField::Industry => "industry",
+            QuoteField::FloatShares => "float_shares_outstanding",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<QuoteField> {
+        QuoteField::ALL.iter().copied().find(|field| field.as_str() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            QuoteField::BaseCurrencyLogoId => "Logo id of the base currency",
+            QuoteField::Change => "Price change since the previous close",
+            QuoteField::ChangePercent => "Percent change since the previous close",
+            QuoteField::CurrencyLogoId => "Logo id of the quote currency",
+            QuoteField::CurrencyCode => "Quote currency code (USD, EUR, ...)",
+            QuoteField::CurrencyId => "Quote currency id",
+            QuoteField::BaseCurrencyId => "Base currency id for pairs",
+            QuoteField::CurrentSession => "Current market session (market, pre_market, post_market, out_of_session)",
+            QuoteField::Description => "Human readable symbol description",
+            QuoteField::Exchange => "Exchange the quote comes from",
+            QuoteField::Format => "Price display format",
+            QuoteField::Fractional => "Whether prices are quoted as fractions",
+            QuoteField::IsTradable => "Whether the symbol can be traded through a broker",
+            QuoteField::Language => "Language of the description",
+            QuoteField::LocalDescription => "Localized symbol description",
+            QuoteField::ListedExchange => "Exchange the symbol is listed on",
+            QuoteField::LogoId => "Logo id of the symbol",
+            QuoteField::LastPrice => "Last traded price",
+            QuoteField::LastPriceTime => "Time of the last price, unix seconds",
+            QuoteField::MinMove => "Minimum price movement in pricescale units",
+            QuoteField::MinMove2 => "Secondary minimum movement for fractional prices",
+            QuoteField::OriginalName => "Original symbol name",
+            QuoteField::PriceScale => "Price scale, 10^decimals",
+            QuoteField::ProName => "Exchange-qualified symbol name",
+            QuoteField::ShortName => "Short symbol name without exchange",
+            QuoteField::Type => "Instrument type (stock, crypto, futures, ...)",
+            QuoteField::TypeSpecs => "Instrument type details",
+            QuoteField::UpdateMode => "Feed mode, e.g. streaming or delayed_streaming_900",
+            QuoteField::Volume => "Volume for the current session",
+            QuoteField::ValueUnitId => "Unit the value is expressed in",
+            QuoteField::RegularChangePercent => "Percent change of the regular session (extended hours quotes)",
+            QuoteField::RegularClose => "Regular session close (extended hours quotes)",
+            QuoteField::CountryCode => "Country code of the issuer or exchange",
+            QuoteField::ProviderId => "Data provider id",
+            QuoteField::MarketCap => "Market capitalization in the quote currency",
+            QuoteField::PriceEarnings => "Price to earnings ratio, trailing twelve months",
+            QuoteField::EarningsPerShare => "Basic earnings per share, trailing twelve months",
+            QuoteField::Sector => "Sector of the issuer",
+            QuoteField::Industry => "Industry of the issuer",
+            QuoteField::FloatShares => "Shares available for trading",
+        }
+    }
+
+    // fundamentals are only served to logged in users, anonymous sessions never get a value for
+    // them; the rest is open to anyone, though many exchanges only stream delayed data until an
+    // authenticated token with the right plan is used (see update_mode)
+    pub fn required_access(&self) -> AccessLevel {
+        match self {
+            QuoteField::MarketCap | QuoteField::PriceEarnings | QuoteField::EarningsPerShare | QuoteField::FloatShares => AccessLevel::Authenticated,
+            _ => AccessLevel::Anonymous,
+        }
+    }
+}
+
+// in json a field is its wire name, e.g. "lp"
+impl From<QuoteField> for &'static str {
+    fn from(field: QuoteField) -> &'static str {
+        field.as_str()
+    }
+}
+
+impl TryFrom<String> for QuoteField {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<QuoteField, String> {
+        QuoteField::from_name(&name).ok_or_else(|| format!("unknown quote field {:?}", name))
+    }
+}
+
+impl JsonSchema for QuoteField {
+    fn schema_name() -> String {
+        "QuoteField".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(QuoteField::ALL.iter().map(|field| field.as_str().into()).collect()),
+            ..SchemaObject::default()
+        }.into()
+    }
+}
+
+// the latest value of each field for one symbol, merged from however many updates carried them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct QuoteData {
+    pub symbol: String,
+    pub values: Vec<(QuoteField, Value)>
+}
+
+impl QuoteData {
+    pub fn new(symbol: &str) -> QuoteData {
+        QuoteData { symbol: symbol.to_string(), values: vec![] }
+    }
+
+    pub fn update(&mut self, values: &[(QuoteField, Value)]) {
+        for (field, value) in values {
+            match self.values.iter_mut().find(|(candidate, _)| candidate == field) {
+                Some((_, current)) => *current = value.clone(),
+                None => self.values.push((*field, value.clone())),
+            }
+        }
+    }
+
+    pub fn get(&self, field: QuoteField) -> Option<&Value> {
+        self.values.iter().find(|(candidate, _)| *candidate == field).map(|(_, value)| value)
+    }
+
+    pub fn f64(&self, field: QuoteField) -> Option<f64> {
+        self.get(field)?.as_f64()
+    }
+
+    pub fn has_all(&self, fields: &[QuoteField]) -> bool {
+        fields.iter().all(|field| self.get(*field).is_some())
+    }
+
+    fn string(&self, field: QuoteField) -> Option<String> {
+        self.get(field)?.as_str().map(str::to_string)
+    }
+
+    // None for symbols without any, e.g. currencies and most crypto
+    pub fn fundamentals(&self) -> Option<Fundamentals> {
+        let fundamentals = Fundamentals {
+            market_cap: self.f64(QuoteField::MarketCap),
+            price_earnings: self.f64(QuoteField::PriceEarnings),
+            earnings_per_share: self.f64(QuoteField::EarningsPerShare),
+            sector: self.string(QuoteField::Sector),
+            industry: self.string(QuoteField::Industry),
+            float_shares: self.f64(QuoteField::FloatShares)
+        };
+        (fundamentals != Fundamentals::default()).then_some(fundamentals)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fundamentals {
+    pub market_cap: Option<f64>,
+    pub price_earnings: Option<f64>,
+    pub earnings_per_share: Option<f64>,
+    pub sector: Option<String>,
+    pub industry: Option<String>,
+    pub float_shares: Option<f64>
+}
+
+#[derive(Debug, Error)]
+pub enum QuoteDataError {
+    #[error("invalid json")]
+    Json(#[from] serde_json::Error),
+    #[error("update without a symbol")]
+    MissingSymbol
+}
+
+// {"m":"qsd","p":[session,{"n":symbol,"s":status,"errmsg":reason,"v":{field:value,...}}]}
+#[derive(Deserialize)]
+struct QuoteEnvelope<'a> {
+    #[serde(borrow)]
+    p: (IgnoredAny, QuotePayload<'a>)
+}
+
+#[derive(Deserialize)]
+struct QuotePayload<'a> {
+    #[serde(borrow)]
+    n: Cow<'a, str>,
+    #[serde(borrow)]
+    s: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    errmsg: Option<Cow<'a, str>>,
+    #[serde(borrow, default, deserialize_with = "entries")]
+    v: Vec<(&'a str, &'a RawValue)>
+}
+
+// the values object in wire order, a map would cost more than the handful of lookups
+fn entries<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(&'de str, &'de RawValue)>, D::Error> {
+    struct Entries;
+
+    impl<'de> Visitor<'de> for Entries {
+        type Value = Vec<(&'de str, &'de RawValue)>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an object of quote values")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(16));
+            while let Some(entry) = map.next_entry()? {
+                entries.push(entry);
+            }
+            Ok(entries)
+        }
+    }
+
+    deserializer.deserialize_map(Entries)
+}
+
+// a string borrowed from the message unless it had escapes to undo
+fn text(raw: &RawValue) -> Option<Cow<'_, str>> {
+    match serde_json::from_str::<&str>(raw.get()) {
+        Ok(text) => Some(Cow::Borrowed(text)),
+        Err(_) => serde_json::from_str::<String>(raw.get()).ok().map(Cow::Owned),
+    }
+}
+
+// a qsd update cut down to the fields someone asked for, still pointing into the received text
+pub struct QuoteUpdate<'a> {
+    pub symbol: Cow<'a, str>,
+    pub status: Cow<'a, str>,
+    pub error: Option<Cow<'a, str>>,
+    pub update_mode: Option<Cow<'a, str>>,
+    // unix seconds, only present when the last price moved
+    pub last_price_time: Option<i64>,
+    pub fields: Vec<(QuoteField, &'a RawValue)>
+}
+
+impl<'a> QuoteUpdate<'a> {
+    pub fn raw(&self, field: QuoteField) -> Option<&'a RawValue> {
+        self.fields.iter().find(|(candidate, _)| *candidate == field).map(|(_, raw)| *raw)
+    }
+
+    pub fn f64(&self, field: QuoteField) -> Option<f64> {
+        self.raw(field)?.get().parse().ok()
+    }
+
+    pub fn str(&self, field: QuoteField) -> Option<Cow<'a, str>> {
+        text(self.raw(field)?)
+    }
+
+    pub fn value(&self, field: QuoteField) -> Option<Value> {
+        serde_json::from_str(self.raw(field)?.get()).ok()
+    }
+}
+
+// reads qsd messages without building a Value tree for the whole update
+#[derive(Debug, Clone, Default)]
+pub struct QuoteExtractor {
+    fields: Vec<QuoteField>
+}
+
+impl QuoteExtractor {
+    pub fn new(fields: &[QuoteField]) -> QuoteExtractor {
+        QuoteExtractor { fields: fields.to_vec() }
+    }
+
+    pub fn extract<'a>(&self, message: &'a str) -> Result<QuoteUpdate<'a>, QuoteDataError> {
+        let QuoteEnvelope { p: (_, payload) } = serde_json::from_str(message)?;
+        // there's nobody to give an update or an error for without one
+        if payload.n.is_empty() {
+            return Err(QuoteDataError::MissingSymbol);
+        }
+        let find = |name: &str| payload.v.iter().find(|(key, _)| *key == name).map(|(_, raw)| *raw);
+        let update_mode = find(QuoteField::UpdateMode.as_str()).and_then(text);
+        let last_price_time = find(QuoteField::LastPriceTime.as_str()).and_then(|raw| raw.get().parse().ok());
+        let fields = self.fields.iter()
+            .filter_map(|field| find(field.as_str()).map(|raw| (*field, raw)))
+            .collect();
+        Ok(QuoteUpdate {
+            symbol: payload.n,
+            // an error status comes without values
+            status: payload.s.unwrap_or(Cow::Borrowed("ok")),
+            error: payload.errmsg,
+            update_mode,
+            last_price_time,
+            fields
+        })
+    }
+}
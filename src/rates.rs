@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::event::Event;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SessionRate {
+    pub session: String,
+    pub messages: u64,
+    pub per_sec: f64
+}
+
+// updates are the bars, quotes, trades and study values of the symbol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SymbolRate {
+    pub symbol: String,
+    pub updates: u64,
+    pub per_sec: f64
+}
+
+// what is waiting inside the api when the report is made, and the events the backlog refused
+// since the last one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct QueueDepths {
+    // quotes held back by BacklogPolicy::Conflate
+    pub held_quotes: usize,
+    // commands sent and not yet acknowledged
+    pub pending_commands: usize,
+    // ping replies held by PingReply::Coalesce or Jitter
+    pub pending_pings: usize,
+    pub dropped_events: u64
+}
+
+// one connection's traffic since its last Event::Stats
+pub struct RateMeter {
+    since: Instant,
+    frames: u64,
+    bytes: u64,
+    messages: u64,
+    dropped_events: u64,
+    sessions: HashMap<String, u64>,
+    symbols: HashMap<String, u64>
+}
+
+fn per_sec(count: u64, interval: Duration) -> f64 {
+    match interval.as_secs_f64() {
+        secs if secs > 0.0 => count as f64 / secs,
+        _ => 0.0,
+    }
+}
+
+impl RateMeter {
+    pub fn new(now: Instant) -> RateMeter {
+        RateMeter { since: now, frames: 0, bytes: 0, messages: 0, dropped_events: 0, sessions: HashMap::new(), symbols: HashMap::new() }
+    }
+
+    pub fn record_frame(&mut self, bytes: usize) {
+        self.frames += 1;
+        self.bytes += bytes as u64;
+    }
+
+    // the session is the one the message names, if any
+    pub fn record_message(&mut self, session: Option<&str>) {
+        self.messages += 1;
+        if let Some(session) = session {
+            *self.sessions.entry(session.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    // counts the market data events per symbol, the rest don't say how busy the feed is
+    pub fn record_event(&mut self, event: &Event) {
+        match *event {
+            Event::Bars { ref symbol, .. } | Event::GapFilled { ref symbol, .. } | Event::BarCorrected { ref symbol, .. }
+            | Event::Quote { ref symbol, .. } | Event::StudyValues { ref symbol, .. } | Event::Trade { ref symbol, .. } => {
+                *self.symbols.entry(symbol.clone()).or_insert(0) += 1;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn record_dropped_event(&mut self) {
+        self.dropped_events += 1;
+    }
+
+    // the rates over the interval, busiest sessions and symbols first, and starts the next one
+    pub fn report(&mut self, now: Instant, queues: QueueDepths) -> Event {
+        let interval = now.saturating_duration_since(self.since);
+        let mut sessions: Vec<SessionRate> = self.sessions.drain().map(|(session, messages)| SessionRate { session, messages, per_sec: per_sec(messages, interval) }).collect();
+        sessions.sort_by(|a, b| b.messages.cmp(&a.messages).then_with(|| a.session.cmp(&b.session)));
+        let mut symbols: Vec<SymbolRate> = self.symbols.drain().map(|(symbol, updates)| SymbolRate { symbol, updates, per_sec: per_sec(updates, interval) }).collect();
+        symbols.sort_by(|a, b| b.updates.cmp(&a.updates).then_with(|| a.symbol.cmp(&b.symbol)));
+        let event = Event::Stats {
+            interval,
+            frames_per_sec: per_sec(self.frames, interval),
+            messages_per_sec: per_sec(self.messages, interval),
+            bytes_per_sec: per_sec(self.bytes, interval),
+            sessions,
+            symbols,
+            queues: QueueDepths { dropped_events: self.dropped_events, ..queues }
+        };
+        *self = RateMeter::new(now);
+        event
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::bar::Bar;
+use crate::event::Event;
+
+// how often a paused or waiting replay looks at the shutdown flag
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("event channel closed")]
+    EventsClosed
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    // the bars' own spacing divided by this, x1 is real time
+    Multiple(f64),
+    AsFastAsPossible
+}
+
+impl ReplaySpeed {
+    fn delay(&self, from: i64, to: i64) -> Duration {
+        match *self {
+            ReplaySpeed::Multiple(multiple) => Duration::from_secs_f64((to - from).max(0) as f64 / multiple),
+            ReplaySpeed::AsFastAsPossible => Duration::ZERO,
+        }
+    }
+}
+
+// x1, x10, x0.5 or max
+impl std::str::FromStr for ReplaySpeed {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ReplaySpeed, String> {
+        if value == "max" {
+            return Ok(ReplaySpeed::AsFastAsPossible);
+        }
+        match value.strip_prefix('x').and_then(|multiple| multiple.parse::<f64>().ok()) {
+            Some(multiple) if multiple.is_finite() && multiple > 0.0 => Ok(ReplaySpeed::Multiple(multiple)),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepMode {
+    // the next bar goes out once the speed says it's due
+    Timer,
+    // the next bar waits for the consumer to send Ack for the last one
+    Ack
+}
+
+impl std::str::FromStr for StepMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<StepMode, String> {
+        match value {
+            "timer" => Ok(StepMode::Timer),
+            "ack" => Ok(StepMode::Ack),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayCommand {
+    SetSpeed(ReplaySpeed),
+    Pause,
+    Resume,
+    // send this many bars right away, also while paused
+    Step(usize),
+    Ack
+}
+
+// plays recorded bars back as Bars events one at a time, paced for backtests. The bars come from
+// a local file, this is not the server's own replay session (replay_create_session)
+pub struct Replay {
+    symbol: String,
+    interval: String,
+    bars: Vec<Bar>,
+    speed: ReplaySpeed,
+    step_mode: StepMode,
+    paused: bool
+}
+
+impl Replay {
+    pub fn new(symbol: &str, interval: &str, mut bars: Vec<Bar>, speed: ReplaySpeed, step_mode: StepMode) -> Replay {
+        bars.sort_by_key(|bar| bar.time);
+        Replay { symbol: symbol.to_string(), interval: interval.to_string(), bars, speed, step_mode, paused: false }
+    }
+
+    // ends with HistoryCompleted once every bar is out, or early on shutdown; once commands
+    // closes a timer replay keeps going, one waiting for acks or paused stops
+    pub fn run(mut self, events_tx: &SyncSender<Arc<Event>>, commands: &Receiver<ReplayCommand>, shutdown: &AtomicBool) -> Result<(), ReplayError> {
+        let mut next = 0;
+        // bars that may go out without waiting, from Step and from acks
+        let mut released = 0;
+        let mut last_sent = Instant::now();
+        let mut commands_open = true;
+        while next < self.bars.len() {
+            if shutdown.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            // None while only a command can release the next bar
+            let due = if released > 0 || (next == 0 && !self.paused) {
+                Some(Instant::now())
+            } else if self.paused || self.step_mode == StepMode::Ack {
+                None
+            } else {
+                Some(last_sent + self.speed.delay(self.bars[next - 1].time, self.bars[next].time))
+            };
+            let wait = due.map_or(POLL_INTERVAL, |due| due.saturating_duration_since(Instant::now())).min(POLL_INTERVAL);
+            if due.is_none() || !wait.is_zero() {
+                if !commands_open {
+                    if due.is_none() {
+                        return Ok(());
+                    }
+                    std::thread::sleep(wait);
+                    continue;
+                }
+                match commands.recv_timeout(wait) {
+                    Ok(command) => self.apply(command, &mut released),
+                    Err(RecvTimeoutError::Timeout) => {},
+                    Err(RecvTimeoutError::Disconnected) => commands_open = false,
+                }
+                // commands and the shutdown flag can change what's due
+                continue;
+            }
+            released = released.saturating_sub(1);
+            let bar = self.bars[next].clone();
+            next += 1;
+            last_sent = Instant::now();
+            let event = Event::Bars { symbol: self.symbol.clone(), interval: self.interval.clone(), bars: vec![bar], correlation_id: None };
+            events_tx.send(Arc::new(event)).map_err(|_| ReplayError::EventsClosed)?;
+        }
+        events_tx.send(Arc::new(Event::HistoryCompleted { symbol: self.symbol.clone() })).map_err(|_| ReplayError::EventsClosed)
+    }
+
+    fn apply(&mut self, command: ReplayCommand, released: &mut usize) {
+        match command {
+            ReplayCommand::SetSpeed(speed) => self.speed = speed,
+            ReplayCommand::Pause => self.paused = true,
+            ReplayCommand::Resume => self.paused = false,
+            ReplayCommand::Step(count) => *released += count,
+            ReplayCommand::Ack if !self.paused && self.step_mode == StepMode::Ack => *released += 1,
+            ReplayCommand::Ack => {},
+        }
+    }
+}
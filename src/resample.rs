@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::bar::Bar;
+use crate::quality;
+
+// 1970-01-01 was a thursday, weekly bars start on the monday after
+const WEEK_OFFSET: i64 = 4 * 86_400;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ResampleError {
+    #[error("can't resample to {0:?}, only second, minute, day and week intervals have a fixed length")]
+    UnknownInterval(String),
+    #[error("{target:?} isn't a whole multiple of the {base:?} bars it would be built from")]
+    NotMultiple { target: String, base: String }
+}
+
+struct Target {
+    interval: String,
+    seconds: i64,
+    // start of the bucket still filling and the source bars in it by time
+    bucket: Option<i64>,
+    bars: BTreeMap<i64, Bar>,
+    // the first bucket when the loaded history starts partway into it
+    partial: bool
+}
+
+impl Target {
+    fn bucket_start(&self, time: i64) -> i64 {
+        let offset = if self.seconds % 604_800 == 0 { WEEK_OFFSET } else { 0 };
+        (time - offset).div_euclid(self.seconds) * self.seconds + offset
+    }
+}
+
+// builds higher timeframe bars from a lower one locally, so one series serves several
+// resolutions; days and weeks roll over at utc midnight rather than at the exchange's session
+pub struct Resampler {
+    targets: Vec<Target>
+}
+
+impl Resampler {
+    pub fn new(source: &str, intervals: &[String]) -> Result<Resampler, ResampleError> {
+        let source_seconds = quality::interval_seconds(source).ok_or_else(|| ResampleError::UnknownInterval(source.to_string()))?;
+        let mut targets: Vec<Target> = vec![];
+        for interval in intervals {
+            let seconds = quality::interval_seconds(interval).ok_or_else(|| ResampleError::UnknownInterval(interval.clone()))?;
+            if seconds <= source_seconds || seconds % source_seconds != 0 {
+                return Err(ResampleError::NotMultiple { target: interval.clone(), base: source.to_string() });
+            }
+            if !targets.iter().any(|target| target.interval == *interval) {
+                targets.push(Target { interval: interval.clone(), seconds, bucket: None, bars: BTreeMap::new(), partial: false });
+            }
+        }
+        Ok(Resampler { targets })
+    }
+
+    // new and updated source bars; returns (interval, bar) for every bucket a later bar closed.
+    // revisions of bars in buckets that already closed are dropped, and so is a first bucket the
+    // history only covers part of
+    pub fn update(&mut self, bars: &[Bar]) -> Vec<(String, Bar)> {
+        let mut bars = bars.to_vec();
+        bars.sort_by_key(|bar| bar.time);
+        let mut closed = vec![];
+        for target in self.targets.iter_mut() {
+            for bar in bars.iter() {
+                let start = target.bucket_start(bar.time);
+                match target.bucket {
+                    Some(bucket) if start < bucket => continue,
+                    Some(bucket) if start > bucket => {
+                        let finished: Vec<Bar> = std::mem::take(&mut target.bars).into_values().collect();
+                        if let Some(aggregated) = aggregate(bucket, &finished).filter(|_| !target.partial) {
+                            closed.push((target.interval.clone(), aggregated));
+                        }
+                        target.bucket = Some(start);
+                        target.partial = false;
+                    }
+                    Some(_) => {},
+                    None => {
+                        target.bucket = Some(start);
+                        target.partial = bar.time != start;
+                    }
+                }
+                target.bars.insert(bar.time, bar.clone());
+            }
+        }
+        closed
+    }
+
+    // the buckets still filling, e.g. to show the live higher timeframe candle
+    pub fn open_bars(&self) -> Vec<(String, Bar)> {
+        self.targets.iter().filter(|target| !target.partial).filter_map(|target| {
+            let bars: Vec<Bar> = target.bars.values().cloned().collect();
+            Some((target.interval.clone(), aggregate(target.bucket?, &bars)?))
+        }).collect()
+    }
+}
+
+// one bar stamped with the bucket start from bars in time order
+pub fn aggregate(time: i64, bars: &[Bar]) -> Option<Bar> {
+    let first = bars.first()?;
+    let last = bars.last()?;
+    Some(Bar {
+        time,
+        open: first.open,
+        high: bars.iter().map(|bar| bar.high).fold(f64::MIN, f64::max),
+        low: bars.iter().map(|bar| bar.low).fold(f64::MAX, f64::min),
+        close: last.close,
+        volume: bars.iter().map(|bar| bar.volume).sum()
+    })
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+
+use log::error;
+
+use crate::bar::Bar;
+use crate::event::Event;
+use crate::sink::{Sink, SinkError};
+
+// a route for "BTCUSD" also matches the exchange-qualified "CRYPTO:BTCUSD", one for "CRYPTO:*"
+// every symbol of that exchange
+pub(crate) fn symbol_matches(pattern: &str, symbol: &str) -> bool {
+    pattern == "*"
+        || pattern == symbol
+        || symbol.rsplit_once(':').is_some_and(|(exchange, ticker)| ticker == pattern || pattern.strip_suffix(":*") == Some(exchange))
+}
+
+// "BTCUSD@15" only takes the 15 minute series, a plain pattern takes every interval
+fn route_matches(pattern: &str, symbol: &str, interval: &str) -> bool {
+    match pattern.rsplit_once('@') {
+        Some((pattern, wanted)) => wanted == interval && symbol_matches(pattern, symbol),
+        None => symbol_matches(pattern, symbol),
+    }
+}
+
+struct Route {
+    pattern: String,
+    sink: Box<dyn Sink + Send>,
+    // set once the sink has panicked, its state can't be trusted after that
+    disabled: bool
+}
+
+fn write_bars(sink: &mut dyn Sink, symbol: &str, bars: &[Bar]) -> Result<(), Box<dyn Error + Send>> {
+    for bar in bars {
+        sink.write_bar(symbol, bar)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct SinkRouter {
+    routes: Vec<Route>,
+    propagate_panics: bool,
+    // the newest bar of each symbol and interval, held until a later one shows it closed
+    forming: HashMap<(String, String), Bar>,
+    // where StudyValues events go as json lines, for plot --studies
+    study_out: Option<Box<dyn Write + Send>>
+}
+
+impl SinkRouter {
+    pub fn add_route(&mut self, pattern: &str, sink: Box<dyn Sink + Send>) {
+        self.routes.push(Route { pattern: pattern.to_string(), sink, disabled: false });
+    }
+
+    // by default a panicking sink is disabled so the other routes keep receiving bars
+    pub fn set_propagate_panics(&mut self, propagate_panics: bool) {
+        self.propagate_panics = propagate_panics;
+    }
+
+    pub fn set_study_out(&mut self, study_out: Box<dyn Write + Send>) {
+        self.study_out = Some(study_out);
+    }
+
+    // sinks are append-only, so every update of the bar still forming would be another row: only
+    // bars a later one has closed are written, and revisions of those are written again
+    fn closed_bars(&mut self, symbol: &str, interval: &str, bars: &[Bar]) -> Vec<Bar> {
+        let mut bars = bars.to_vec();
+        bars.sort_by_key(|bar| bar.time);
+        let mut closed = vec![];
+        for bar in bars {
+            match self.forming.get_mut(&(symbol.to_string(), interval.to_string())) {
+                Some(forming) if bar.time < forming.time => closed.push(bar),
+                Some(forming) if bar.time > forming.time => closed.push(std::mem::replace(forming, bar)),
+                Some(forming) => *forming = bar,
+                None => {
+                    self.forming.insert((symbol.to_string(), interval.to_string()), bar);
+                }
+            }
+        }
+        closed
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> Result<(), Box<dyn Error + Send>> {
+        let (symbol, interval, bars) = match event {
+            Event::Bars { symbol, interval, bars, .. } => (symbol, interval, self.closed_bars(symbol, interval, bars)),
+            Event::BarCorrected { symbol, interval, bar, .. } => (symbol, interval, self.closed_bars(symbol, interval, std::slice::from_ref(bar))),
+            // older than anything still forming, and resampled bars only come out once closed
+            Event::GapFilled { symbol, interval, bars } => (symbol, interval, bars.clone()),
+            Event::BarClosed { symbol, interval, bar } => (symbol, interval, vec![bar.clone()]),
+            Event::StudyValues { .. } => return self.write_study_values(event),
+            _ => return Ok(()),
+        };
+        self.write(symbol, interval, &bars)
+    }
+
+    // for feeds whose last bar is known to be complete, e.g. a replayed recording; a live stream
+    // leaves the bar still forming out
+    pub fn write_forming(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        let mut forming: Vec<((String, String), Bar)> = self.forming.drain().collect();
+        forming.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for ((symbol, interval), bar) in forming {
+            self.write(&symbol, &interval, std::slice::from_ref(&bar))?;
+        }
+        Ok(())
+    }
+
+    fn write_study_values(&mut self, event: &Event) -> Result<(), Box<dyn Error + Send>> {
+        if let Some(ref mut study_out) = self.study_out {
+            serde_json::to_writer(&mut *study_out, event).map_err(|_| SinkError::WriteError)?;
+            study_out.write_all(b"\n").map_err(|_| SinkError::WriteError)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, symbol: &str, interval: &str, bars: &[Bar]) -> Result<(), Box<dyn Error + Send>> {
+        if bars.is_empty() {
+            return Ok(());
+        }
+        for route in self.routes.iter_mut() {
+            if route.disabled || !route_matches(&route.pattern, symbol, interval) {
+                continue;
+            }
+            if self.propagate_panics {
+                write_bars(route.sink.as_mut(), symbol, bars)?;
+                continue;
+            }
+            match panic::catch_unwind(AssertUnwindSafe(|| write_bars(route.sink.as_mut(), symbol, bars))) {
+                Ok(result) => result?,
+                Err(_) => {
+                    error!("sink for {} panicked, disabling it", route.pattern);
+                    route.disabled = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush_if_due(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        for route in self.routes.iter_mut().filter(|route| !route.disabled) {
+            route.sink.flush_if_due()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        for route in self.routes.iter_mut().filter(|route| !route.disabled) {
+            route.sink.flush()?;
+        }
+        if let Some(ref mut study_out) = self.study_out {
+            study_out.flush().map_err(|_| SinkError::WriteError)?;
+        }
+        Ok(())
+    }
+}
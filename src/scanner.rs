@@ -0,0 +1,72 @@
+use std::error::Error;
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::http;
+
+// rows per request when walking a whole exchange
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum ScannerError {
+    #[error("no scanner market known for exchange {0:?}")]
+    UnknownExchange(String),
+    #[error("scanner request failed")]
+    Request(#[source] Box<dyn Error + Send>),
+    #[error("scanner response parse error")]
+    Parse(#[source] serde_json::Error)
+}
+
+// the scanner splits exchanges into markets, one url each
+pub fn market(exchange: &str) -> Option<&'static str> {
+    match exchange {
+        "NASDAQ" | "NYSE" | "AMEX" | "OTC" | "CBOE" => Some("america"),
+        "TSX" | "TSXV" => Some("canada"),
+        "LSE" => Some("uk"),
+        "XETR" | "FWB" => Some("germany"),
+        "EURONEXT" => Some("france"),
+        "TSE" => Some("japan"),
+        "HKEX" => Some("hongkong"),
+        "ASX" => Some("australia"),
+        "NSE" | "BSE" => Some("india"),
+        "BINANCE" | "COINBASE" | "KRAKEN" | "BITSTAMP" | "BYBIT" | "OKX" | "BITFINEX" | "KUCOIN" => Some("crypto"),
+        "FX" | "FX_IDC" | "OANDA" | "FXCM" => Some("forex"),
+        _ => None,
+    }
+}
+
+// (symbols in the order asked for, how many the exchange has in total); sort is (column, "asc" or "desc")
+pub fn scan(exchange: &str, sort: Option<(&str, &str)>, range: (usize, usize)) -> Result<(Vec<String>, usize), ScannerError> {
+    let market = market(exchange).ok_or_else(|| ScannerError::UnknownExchange(exchange.to_string()))?;
+    let mut query = serde_json::json!({
+        "filter": [{ "left": "exchange", "operation": "equal", "right": exchange }],
+        "columns": ["name"],
+        "range": [range.0, range.1]
+    });
+    if let Some((sort_by, sort_order)) = sort {
+        query["sort"] = serde_json::json!({ "sortBy": sort_by, "sortOrder": sort_order });
+    }
+    let url = format!("https://scanner.tradingview.com/{}/scan", market);
+    let response = http::post_json(&url, &query).map_err(ScannerError::Request)?;
+    let parsed: Value = serde_json::from_str(&response.body).map_err(ScannerError::Parse)?;
+    // rows look like {"s": "NASDAQ:AAPL", "d": ["AAPL"]}
+    let symbols: Vec<String> = parsed.get("data").and_then(Value::as_array).map(|rows| {
+        rows.iter().filter_map(|row| row.get("s")?.as_str().map(str::to_string)).collect()
+    }).unwrap_or_default();
+    let total = parsed.get("totalCount").and_then(Value::as_u64).map_or(symbols.len(), |total| total as usize);
+    Ok((symbols, total))
+}
+
+// every symbol the exchange lists, a page at a time
+pub fn exchange_symbols(exchange: &str) -> Result<Vec<String>, ScannerError> {
+    let mut symbols = vec![];
+    loop {
+        let (page, total) = scan(exchange, Some(("name", "asc")), (symbols.len(), symbols.len() + PAGE_SIZE))?;
+        let done = page.len() < PAGE_SIZE;
+        symbols.extend(page);
+        if done || symbols.len() >= total {
+            return Ok(symbols);
+        }
+    }
+}
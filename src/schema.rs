@@ -0,0 +1,44 @@
+use schemars::gen::SchemaSettings;
+use schemars::schema::RootSchema;
+
+use crate::event::Event;
+
+// bumped whenever a field of an event or model is renamed, retyped or removed, so stored events
+// can be told apart; adding a variant or an optional field keeps the version
+pub const SCHEMA_VERSION: u32 = 1;
+
+// the json schema (draft 7) every serialized Event conforms to
+pub fn event_schema() -> RootSchema {
+    let mut schema = SchemaSettings::draft07().into_generator().into_root_schema_for::<Event>();
+    schema.schema.metadata().title = Some(format!("Event v{}", SCHEMA_VERSION));
+    schema
+}
+
+// durations as whole milliseconds
+pub(crate) mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+// json has no NaN, so na values go out as null and come back as NaN
+pub(crate) mod nan_as_null {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(values: &[f64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(|value| Some(*value).filter(|value| value.is_finite())))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f64>, D::Error> {
+        let values = Vec::<Option<f64>>::deserialize(deserializer)?;
+        Ok(values.into_iter().map(|value| value.unwrap_or(f64::NAN)).collect())
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// bar types the server builds itself, sent as a BarSet study wrapped around the symbol
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChartStyle {
+    #[default]
+    Candles,
+    HeikinAshi,
+    Renko { box_size: f64 },
+    Range { range: f64 },
+    LineBreak { lines: u32 }
+}
+
+impl ChartStyle {
+    fn bar_set(&self) -> Option<(&'static str, Value)> {
+        match *self {
+            ChartStyle::Candles => None,
+            ChartStyle::HeikinAshi => Some(("BarSetHeikenAshi@tv-basicstudies-60!", serde_json::json!({}))),
+            ChartStyle::Renko { box_size } => Some(("BarSetRenko@tv-prostudies-40!", serde_json::json!({
+                "source": "close",
+                "sources": "Close",
+                "boxSize": box_size,
+                "style": "Traditional",
+                "atrLength": 14,
+                "wicks": true
+            }))),
+            ChartStyle::Range { range } => Some(("BarSetRange@tv-basicstudies-72!", serde_json::json!({
+                "range": range,
+                "phantomBars": false
+            }))),
+            ChartStyle::LineBreak { lines } => Some(("BarSetPriceBreak@tv-prostudies-34!", serde_json::json!({
+                "source": "close",
+                "lb": lines
+            }))),
+        }
+    }
+
+    // renko, range and line break bars close on price moves, so their times skip around
+    pub fn is_time_based(&self) -> bool {
+        matches!(*self, ChartStyle::Candles | ChartStyle::HeikinAshi)
+    }
+}
+
+// candles, heikin-ashi, renko:BOX, range:SIZE or line-break[:LINES]
+impl std::str::FromStr for ChartStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ChartStyle, String> {
+        let (name, parameter) = match value.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (value, None),
+        };
+        let positive = |parameter: Option<&str>| parameter
+            .and_then(|parameter| parameter.parse::<f64>().ok())
+            .filter(|number| number.is_finite() && *number > 0.0)
+            .ok_or_else(|| value.to_string());
+        match (name, parameter) {
+            ("candles", None) => Ok(ChartStyle::Candles),
+            ("heikin-ashi", None) => Ok(ChartStyle::HeikinAshi),
+            ("renko", _) => Ok(ChartStyle::Renko { box_size: positive(parameter)? }),
+            ("range", _) => Ok(ChartStyle::Range { range: positive(parameter)? }),
+            ("line-break", None) => Ok(ChartStyle::LineBreak { lines: 3 }),
+            ("line-break", Some(lines)) => match lines.parse() {
+                Ok(lines) if lines > 0 => Ok(ChartStyle::LineBreak { lines }),
+                _ => Err(value.to_string()),
+            },
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Adjustment {
+    // prices as traded
+    Raw,
+    Splits,
+    // splits plus dividends
+    Dividends
+}
+
+impl Adjustment {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Adjustment::Raw => "none",
+            Adjustment::Splits => "splits",
+            Adjustment::Dividends => "dividends",
+        }
+    }
+}
+
+impl std::str::FromStr for Adjustment {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Adjustment, String> {
+        match value {
+            "raw" | "none" => Ok(Adjustment::Raw),
+            "splits" => Ok(Adjustment::Splits),
+            "dividends" => Ok(Adjustment::Dividends),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeriesOptions {
+    pub style: ChartStyle,
+    // prices converted to this currency, e.g. EUR, instead of the symbol's own
+    pub currency: Option<String>,
+    // unit conversion for symbols that have one, e.g. a metric weight for gold
+    pub unit: Option<String>,
+    // None keeps what the mode asks for, splits for downloads
+    pub adjustment: Option<Adjustment>,
+    // continuous futures shifted by the gap at each roll instead of jumping
+    pub back_adjust: bool,
+    // shift cached bars of a continuous contract by the price gap when it rolls
+    pub stitch_rolls: bool,
+    // more resolutions of the streamed symbol, each its own series on the same chart
+    pub extra_intervals: Vec<String>
+}
+
+impl SeriesOptions {
+    // the "=" prefixed json resolve_symbol takes in place of a plain symbol name
+    pub fn symbol_spec(&self, symbol: &str, mut settings: Value) -> String {
+        settings["symbol"] = Value::from(symbol);
+        if let Some(ref currency) = self.currency {
+            settings["currency-id"] = Value::from(currency.as_str());
+        }
+        if let Some(ref unit) = self.unit {
+            settings["unit-id"] = Value::from(unit.as_str());
+        }
+        if let Some(adjustment) = self.adjustment {
+            settings["adjustment"] = Value::from(adjustment.as_str());
+        }
+        if self.back_adjust {
+            settings["backadjustment"] = Value::from("default");
+        }
+        let spec = match self.style.bar_set() {
+            Some((bar_set, inputs)) => serde_json::json!({
+                "symbol": settings,
+                "type": bar_set,
+                "inputs": inputs
+            }),
+            None => settings,
+        };
+        format!("={}", spec)
+    }
+}
@@ -0,0 +1,46 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// the only protocol the parser speaks
+pub const JSON_PROTOCOL: &str = "json";
+
+// the banner the server sends first on every connection, e.g.
+// {"session_id":"<0.1.2>_x","timestampMs":1700000000123,"release":"registry.xtools.tv/tvbs_release/webchart:release_206-21",
+//  "studies_metadata_hash":"...","auth_scheme_vsn":2,"protocol":"json","via":"203.0.113.10:443","javastudies":["3.66"]}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ServerInfo {
+    #[serde(default)]
+    pub session_id: String,
+    pub release: String,
+    #[serde(default)]
+    pub protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_scheme_vsn: Option<u64>,
+    // versions of the study engine; studies can't be created without one
+    #[serde(default)]
+    pub javastudies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub studies_metadata_hash: Option<String>,
+    // the front end the connection goes through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub via: Option<String>,
+    #[serde(default, alias = "timestampMs", skip_serializing_if = "Option::is_none")]
+    pub timestamp_ms: Option<i64>
+}
+
+impl ServerInfo {
+    // 206 of "...webchart:release_206-21"
+    pub fn release_number(&self) -> Option<u32> {
+        let (_, release) = self.release.rsplit_once("release_")?;
+        release.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()
+    }
+
+    // a banner without a protocol is from before it was announced, and was json
+    pub fn speaks_json(&self) -> bool {
+        self.protocol.is_empty() || self.protocol == JSON_PROTOCOL
+    }
+
+    pub fn supports_studies(&self) -> bool {
+        !self.javastudies.is_empty()
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::bar::Bar;
+use crate::market::TradingHours;
+
+// what traded from some point on: the current session, or an anchor of the caller's choosing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RangeStats {
+    pub from: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    // None when nothing with volume traded, indices for one
+    pub vwap: Option<f64>,
+    pub bars: usize
+}
+
+// bars in time order
+pub fn range_stats(from: i64, bars: &[Bar]) -> Option<RangeStats> {
+    let first = bars.first()?;
+    let last = bars.last()?;
+    let volume: f64 = bars.iter().map(|bar| bar.volume).sum();
+    let weighted: f64 = bars.iter().map(|bar| (bar.high + bar.low + bar.close) / 3.0 * bar.volume).sum();
+    Some(RangeStats {
+        from,
+        open: first.open,
+        high: bars.iter().map(|bar| bar.high).fold(f64::MIN, f64::max),
+        low: bars.iter().map(|bar| bar.low).fold(f64::MAX, f64::min),
+        close: last.close,
+        volume,
+        vwap: (volume > 0.0).then(|| weighted / volume),
+        bars: bars.len()
+    })
+}
+
+#[derive(Default)]
+struct SymbolSession {
+    hours: Option<TradingHours>,
+    anchors: Vec<i64>,
+    // from the earlier of the session open and the oldest anchor on
+    bars: BTreeMap<i64, Bar>
+}
+
+impl SymbolSession {
+    // the open of the session the latest bar traded in, utc midnight when the hours aren't known
+    fn session_start(&self) -> Option<i64> {
+        let latest = *self.bars.keys().next_back()?;
+        let at = DateTime::<Utc>::from_timestamp(latest, 0)?;
+        let open = self.hours.as_ref().and_then(|hours| hours.session_open(at));
+        Some(open.map(|open| open.timestamp()).unwrap_or(latest - latest.rem_euclid(86_400)))
+    }
+
+    fn range(&self, from: i64) -> Option<RangeStats> {
+        let bars: Vec<Bar> = self.bars.range(from..).map(|(_, bar)| bar.clone()).collect();
+        range_stats(from, &bars)
+    }
+}
+
+// session vwap, volume and high/low per symbol, fed by the stream and readable from any thread
+#[derive(Default)]
+pub struct SessionStats {
+    symbols: Mutex<HashMap<String, SymbolSession>>
+}
+
+impl SessionStats {
+    pub fn set_hours(&self, symbol: &str, hours: TradingHours) {
+        if let Ok(mut symbols) = self.symbols.lock() {
+            symbols.entry(symbol.to_string()).or_default().hours = Some(hours);
+        }
+    }
+
+    // bars from the anchor on are kept for anchored(), so anchor before they arrive
+    pub fn add_anchor(&self, symbol: &str, from: i64) {
+        if let Ok(mut symbols) = self.symbols.lock() {
+            let session = symbols.entry(symbol.to_string()).or_default();
+            if !session.anchors.contains(&from) {
+                session.anchors.push(from);
+            }
+        }
+    }
+
+    // new and updated bars, a bar already held is replaced
+    pub fn record(&self, symbol: &str, bars: &[Bar]) {
+        let mut symbols = match self.symbols.lock() {
+            Ok(symbols) => symbols,
+            Err(_) => return,
+        };
+        let session = symbols.entry(symbol.to_string()).or_default();
+        for bar in bars {
+            session.bars.insert(bar.time, bar.clone());
+        }
+        let keep_from = session.session_start().into_iter().chain(session.anchors.iter().copied()).min();
+        if let Some(keep_from) = keep_from {
+            session.bars = session.bars.split_off(&keep_from);
+        }
+    }
+
+    pub fn session(&self, symbol: &str) -> Option<RangeStats> {
+        let symbols = self.symbols.lock().ok()?;
+        let session = symbols.get(symbol)?;
+        session.range(session.session_start()?)
+    }
+
+    pub fn anchored(&self, symbol: &str, from: i64) -> Option<RangeStats> {
+        self.symbols.lock().ok()?.get(symbol)?.range(from)
+    }
+
+    // every anchor of the symbol that has bars, oldest first
+    pub fn anchors(&self, symbol: &str) -> Vec<RangeStats> {
+        let symbols = match self.symbols.lock() {
+            Ok(symbols) => symbols,
+            Err(_) => return vec![],
+        };
+        let session = match symbols.get(symbol) {
+            Some(session) => session,
+            None => return vec![],
+        };
+        let mut anchors = session.anchors.clone();
+        anchors.sort();
+        anchors.into_iter().filter_map(|from| session.range(from)).collect()
+    }
+}
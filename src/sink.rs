@@ -0,0 +1,499 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use chrono::DateTime;
+use log::error;
+use serde_json::Value;
+
+use crate::bar::Bar;
+use crate::timestamp::TimestampFormatter;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum SinkError {
+    CreateError,
+    WriteError,
+    ReadError,
+    ParseError(String),
+    UnsupportedFormatError(String)
+}
+
+impl std::fmt::Display for SinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            SinkError::CreateError => write!(f, "Create error"),
+            SinkError::WriteError => write!(f, "Write error"),
+            SinkError::ReadError => write!(f, "Read error"),
+            SinkError::ParseError(ref line) => write!(f, "Parse error: {}", line),
+            SinkError::UnsupportedFormatError(ref path) => write!(f, "Unsupported output format: {} (use .csv, .jsonl or, with the parquet feature, .parquet; kafka://BROKERS/TOPIC needs the kafka feature)", path),
+        }
+    }
+}
+
+impl Error for SinkError {}
+
+impl From<SinkError> for Box<dyn std::error::Error + Send> {
+    fn from(error: SinkError) -> Self {
+        Box::new(error)
+    }
+}
+
+pub trait Sink {
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> Result<(), Box<dyn Error + Send>>;
+    fn flush(&mut self) -> Result<(), Box<dyn Error + Send>>;
+
+    // sinks that can write many rows at once (one insert, one produce call) override this
+    fn write_bars(&mut self, symbol: &str, bars: &[Bar]) -> Result<(), Box<dyn Error + Send>> {
+        for bar in bars {
+            self.write_bar(symbol, bar)?;
+        }
+        Ok(())
+    }
+
+    // called while the feed is quiet so time based flushes still happen
+    fn flush_if_due(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        Ok(())
+    }
+}
+
+// a batch goes out once it holds max_rows bars or its oldest bar has waited max_delay
+#[derive(Debug, Clone, Copy)]
+pub struct FlushPolicy {
+    pub max_rows: usize,
+    pub max_delay: Duration
+}
+
+impl Default for FlushPolicy {
+    fn default() -> FlushPolicy {
+        FlushPolicy {
+            max_rows: 1000,
+            max_delay: Duration::from_secs(1)
+        }
+    }
+}
+
+// collects bars in front of any sink and hands them over in batches
+pub struct BatchingSink {
+    inner: Box<dyn Sink + Send>,
+    policy: FlushPolicy,
+    // consecutive bars for the same symbol share an entry
+    pending: Vec<(String, Vec<Bar>)>,
+    pending_rows: usize,
+    oldest: Option<Instant>
+}
+
+impl BatchingSink {
+    pub fn new(inner: Box<dyn Sink + Send>, policy: FlushPolicy) -> BatchingSink {
+        BatchingSink {
+            inner,
+            policy,
+            pending: vec![],
+            pending_rows: 0,
+            oldest: None
+        }
+    }
+
+    fn write_pending(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        for (symbol, bars) in self.pending.drain(..) {
+            self.inner.write_bars(&symbol, &bars)?;
+        }
+        self.pending_rows = 0;
+        self.oldest = None;
+        self.inner.flush()
+    }
+}
+
+impl Sink for BatchingSink {
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> Result<(), Box<dyn Error + Send>> {
+        match self.pending.last_mut() {
+            Some((last_symbol, bars)) if last_symbol == symbol => bars.push(bar.clone()),
+            _ => self.pending.push((symbol.to_string(), vec![bar.clone()])),
+        }
+        self.pending_rows += 1;
+        self.oldest.get_or_insert_with(Instant::now);
+        if self.pending_rows >= self.policy.max_rows {
+            return self.write_pending();
+        }
+        self.flush_if_due()
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        self.write_pending()
+    }
+
+    fn flush_if_due(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        match self.oldest {
+            Some(oldest) if oldest.elapsed() >= self.policy.max_delay => self.write_pending(),
+            _ => Ok(()),
+        }
+    }
+}
+
+// a sink dropped without a flush, e.g. while unwinding, still writes what it holds
+impl Drop for BatchingSink {
+    fn drop(&mut self) {
+        if self.pending_rows > 0 {
+            if let Err(err) = self.write_pending() {
+                error!("failed to write {} buffered bars: {}", self.pending_rows, err);
+            }
+        }
+    }
+}
+
+// ryu writes 37000.0 and 1e-7 where Display writes 37000 and 0.0000001, keep the output as it always was
+fn push_f64(line: &mut Vec<u8>, value: f64) {
+    let mut buffer = ryu::Buffer::new();
+    let formatted = buffer.format(value);
+    if formatted.contains('e') {
+        let _ = write!(line, "{}", value);
+        return;
+    }
+    line.extend_from_slice(formatted.strip_suffix(".0").unwrap_or(formatted).as_bytes());
+}
+
+// json has no NaN or infinity, serde_json writes those as null too
+fn push_json_f64(line: &mut Vec<u8>, value: f64) {
+    if value.is_finite() {
+        line.extend_from_slice(ryu::Buffer::new().format_finite(value).as_bytes());
+    } else {
+        line.extend_from_slice(b"null");
+    }
+}
+
+pub struct CsvSink {
+    writer: BufWriter<File>,
+    formatter: TimestampFormatter,
+    // reused for every row
+    line: Vec<u8>
+}
+
+impl CsvSink {
+    pub fn create(path: &str, formatter: TimestampFormatter) -> Result<CsvSink, Box<dyn Error + Send>> {
+        let file = File::create(path).map_err(|_| SinkError::CreateError)?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "symbol,time,open,high,low,close,volume").map_err(|_| SinkError::WriteError)?;
+        Ok(CsvSink { writer, formatter, line: Vec::with_capacity(128) })
+    }
+}
+
+impl Sink for CsvSink {
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> Result<(), Box<dyn Error + Send>> {
+        let line = &mut self.line;
+        line.clear();
+        line.extend_from_slice(symbol.as_bytes());
+        line.push(b',');
+        self.formatter.push(line, bar.time);
+        for value in [bar.open, bar.high, bar.low, bar.close, bar.volume] {
+            line.push(b',');
+            push_f64(line, value);
+        }
+        line.push(b'\n');
+        self.writer.write_all(line).map_err(|_| SinkError::WriteError)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        self.writer.flush().map_err(|_| SinkError::WriteError)?;
+        Ok(())
+    }
+}
+
+pub struct JsonlSink {
+    writer: BufWriter<File>,
+    formatter: TimestampFormatter,
+    line: Vec<u8>
+}
+
+impl JsonlSink {
+    pub fn create(path: &str, formatter: TimestampFormatter) -> Result<JsonlSink, Box<dyn Error + Send>> {
+        let file = File::create(path).map_err(|_| SinkError::CreateError)?;
+        Ok(JsonlSink { writer: BufWriter::new(file), formatter, line: Vec::with_capacity(192) })
+    }
+}
+
+// one bar as a json object, keys in the sorted order a serde_json object used to write them in
+fn push_json_row(line: &mut Vec<u8>, formatter: &TimestampFormatter, symbol: &str, bar: &Bar) -> Result<(), SinkError> {
+    line.clear();
+    for (key, value) in [("{\"close\":", bar.close), (",\"high\":", bar.high), (",\"low\":", bar.low), (",\"open\":", bar.open)] {
+        line.extend_from_slice(key.as_bytes());
+        push_json_f64(line, value);
+    }
+    line.extend_from_slice(b",\"symbol\":");
+    serde_json::to_writer(&mut *line, symbol).map_err(|_| SinkError::WriteError)?;
+    line.extend_from_slice(b",\"time\":");
+    formatter.push_json(line, bar.time);
+    line.extend_from_slice(b",\"volume\":");
+    push_json_f64(line, bar.volume);
+    line.push(b'}');
+    Ok(())
+}
+
+impl Sink for JsonlSink {
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> Result<(), Box<dyn Error + Send>> {
+        push_json_row(&mut self.line, &self.formatter, symbol, bar)?;
+        self.line.push(b'\n');
+        self.writer.write_all(&self.line).map_err(|_| SinkError::WriteError)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        self.writer.flush().map_err(|_| SinkError::WriteError)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+const PARQUET_SCHEMA: &str = "message bar {
+    REQUIRED BYTE_ARRAY symbol (UTF8);
+    REQUIRED INT64 time (TIMESTAMP(MILLIS, true));
+    REQUIRED DOUBLE open;
+    REQUIRED DOUBLE high;
+    REQUIRED DOUBLE low;
+    REQUIRED DOUBLE close;
+    REQUIRED DOUBLE volume;
+}";
+
+// one row group per flush; the footer is written when the sink is dropped, so the file can't be
+// read before that. Times are stored as utc timestamps, whatever the formatter says
+#[cfg(feature = "parquet")]
+pub struct ParquetSink {
+    writer: Option<parquet::file::writer::SerializedFileWriter<File>>,
+    symbols: Vec<parquet::data_type::ByteArray>,
+    times: Vec<i64>,
+    // open, high, low, close and volume
+    values: [Vec<f64>; 5]
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetSink {
+    pub fn create(path: &str) -> Result<ParquetSink, Box<dyn Error + Send>> {
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+
+        let schema = parquet::schema::parser::parse_message_type(PARQUET_SCHEMA).map_err(|_| SinkError::CreateError)?;
+        let file = File::create(path).map_err(|_| SinkError::CreateError)?;
+        let writer = SerializedFileWriter::new(file, std::sync::Arc::new(schema), std::sync::Arc::new(WriterProperties::builder().build()))
+            .map_err(|_| SinkError::CreateError)?;
+        Ok(ParquetSink { writer: Some(writer), symbols: vec![], times: vec![], values: Default::default() })
+    }
+
+    fn write_row_group(&mut self) -> Result<(), parquet::errors::ParquetError> {
+        use parquet::data_type::{ByteArrayType, DoubleType, Int64Type};
+
+        let writer = match self.writer {
+            Some(ref mut writer) if !self.times.is_empty() => writer,
+            _ => return Ok(()),
+        };
+        let mut row_group = writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match index {
+                0 => column.typed::<ByteArrayType>().write_batch(&self.symbols, None, None)?,
+                1 => column.typed::<Int64Type>().write_batch(&self.times, None, None)?,
+                index => column.typed::<DoubleType>().write_batch(&self.values[index - 2], None, None)?,
+            };
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+        self.symbols.clear();
+        self.times.clear();
+        for values in self.values.iter_mut() {
+            values.clear();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl Sink for ParquetSink {
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> Result<(), Box<dyn Error + Send>> {
+        self.symbols.push(symbol.into());
+        self.times.push(bar.time * 1000);
+        for (values, value) in self.values.iter_mut().zip([bar.open, bar.high, bar.low, bar.close, bar.volume]) {
+            values.push(value);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        self.write_row_group().map_err(|_| SinkError::WriteError)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        let result = self.write_row_group().and_then(|_| match self.writer.take() {
+            Some(writer) => writer.close().map(|_| ()),
+            None => Ok(()),
+        });
+        if let Err(err) = result {
+            error!("failed to finish parquet file: {}", err);
+        }
+    }
+}
+
+// how long a flush waits for the broker to acknowledge what was produced
+#[cfg(feature = "kafka")]
+const KAFKA_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// one json message per bar, the same object JsonlSink writes, keyed by symbol so a symbol's bars
+// stay in order on one partition. librdkafka delivers in the background, a flush waits for it
+#[cfg(feature = "kafka")]
+pub struct KafkaSink {
+    producer: rdkafka::producer::BaseProducer,
+    topic: String,
+    formatter: TimestampFormatter,
+    line: Vec<u8>
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    // kafka://host:9092,other:9092/topic
+    pub fn create(url: &str, formatter: TimestampFormatter) -> Result<KafkaSink, Box<dyn Error + Send>> {
+        let (brokers, topic) = url.strip_prefix("kafka://")
+            .and_then(|rest| rest.split_once('/'))
+            .filter(|(brokers, topic)| !brokers.is_empty() && !topic.is_empty() && !topic.contains('/'))
+            .ok_or_else(|| SinkError::UnsupportedFormatError(url.to_string()))?;
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()
+            .map_err(|_| SinkError::CreateError)?;
+        Ok(KafkaSink { producer, topic: topic.to_string(), formatter, line: Vec::with_capacity(192) })
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl Sink for KafkaSink {
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> Result<(), Box<dyn Error + Send>> {
+        use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+        use rdkafka::producer::BaseRecord;
+
+        push_json_row(&mut self.line, &self.formatter, symbol, bar)?;
+        loop {
+            match self.producer.send(BaseRecord::to(&self.topic).key(symbol).payload(&self.line)) {
+                Ok(()) => break,
+                // the local queue is full until deliveries make room
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => self.producer.poll(Duration::from_millis(100)),
+                Err(_) => return Err(SinkError::WriteError.into()),
+            }
+        }
+        self.producer.poll(Duration::ZERO);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        use rdkafka::producer::Producer;
+
+        self.producer.flush(KAFKA_FLUSH_TIMEOUT).map_err(|_| SinkError::WriteError)?;
+        Ok(())
+    }
+}
+
+// picks the sink implementation from the file extension, or a kafka:// url
+pub fn open_sink(path: &str, formatter: TimestampFormatter) -> Result<Box<dyn Sink + Send>, Box<dyn Error + Send>> {
+    #[cfg(feature = "kafka")]
+    if path.starts_with("kafka://") {
+        return Ok(Box::new(KafkaSink::create(path, formatter)?));
+    }
+    if path.ends_with(".csv") {
+        return Ok(Box::new(CsvSink::create(path, formatter)?));
+    }
+    if path.ends_with(".jsonl") || path.ends_with(".ndjson") {
+        return Ok(Box::new(JsonlSink::create(path, formatter)?));
+    }
+    #[cfg(feature = "parquet")]
+    if path.ends_with(".parquet") {
+        return Ok(Box::new(ParquetSink::create(path)?));
+    }
+    Err(SinkError::UnsupportedFormatError(path.to_string()).into())
+}
+
+// accepts every time format the sinks can write: unix seconds, milliseconds or rfc3339
+fn parse_time(value: &str) -> Option<i64> {
+    if let Ok(number) = value.parse::<i64>() {
+        return Some(if number > 100_000_000_000 { number / 1000 } else { number });
+    }
+    DateTime::parse_from_rfc3339(value).ok().map(|time| time.timestamp())
+}
+
+fn parse_csv_row(line: &str) -> Option<(String, Bar)> {
+    let columns: Vec<&str> = line.split(',').collect();
+    if columns.len() != 7 {
+        return None;
+    }
+    Some((columns[0].to_string(), Bar {
+        time: parse_time(columns[1])?,
+        open: columns[2].parse().ok()?,
+        high: columns[3].parse().ok()?,
+        low: columns[4].parse().ok()?,
+        close: columns[5].parse().ok()?,
+        volume: columns[6].parse().ok()?
+    }))
+}
+
+fn parse_jsonl_row(line: &str) -> Option<(String, Bar)> {
+    let row: Value = serde_json::from_str(line).ok()?;
+    let time = match row.get("time")? {
+        Value::String(time) => parse_time(time)?,
+        time => parse_time(&time.as_i64()?.to_string())?,
+    };
+    Some((row.get("symbol")?.as_str()?.to_string(), Bar {
+        time,
+        open: row.get("open")?.as_f64()?,
+        high: row.get("high")?.as_f64()?,
+        low: row.get("low")?.as_f64()?,
+        close: row.get("close")?.as_f64()?,
+        volume: row.get("volume")?.as_f64()?
+    }))
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet_bars(path: &str) -> Result<Vec<(String, Bar)>, Box<dyn Error + Send>> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let file = File::open(path).map_err(|_| SinkError::ReadError)?;
+    let reader = SerializedFileReader::new(file).map_err(|err| SinkError::ParseError(err.to_string()))?;
+    let mut rows = vec![];
+    for row in reader.get_row_iter(None).map_err(|err| SinkError::ParseError(err.to_string()))? {
+        let row = row.map_err(|err| SinkError::ParseError(err.to_string()))?;
+        let bar = (|| Ok::<_, parquet::errors::ParquetError>((row.get_string(0)?.clone(), Bar {
+            time: row.get_timestamp_millis(1)? / 1000,
+            open: row.get_double(2)?,
+            high: row.get_double(3)?,
+            low: row.get_double(4)?,
+            close: row.get_double(5)?,
+            volume: row.get_double(6)?
+        })))();
+        rows.push(bar.map_err(|err| SinkError::ParseError(err.to_string()))?);
+    }
+    Ok(rows)
+}
+
+// reads back a file written by CsvSink, JsonlSink or ParquetSink
+pub fn read_bars(path: &str) -> Result<Vec<(String, Bar)>, Box<dyn Error + Send>> {
+    #[cfg(feature = "parquet")]
+    if path.ends_with(".parquet") {
+        return read_parquet_bars(path);
+    }
+    let csv = path.ends_with(".csv");
+    if !csv && !path.ends_with(".jsonl") && !path.ends_with(".ndjson") {
+        return Err(SinkError::UnsupportedFormatError(path.to_string()).into());
+    }
+    let file = File::open(path).map_err(|_| SinkError::ReadError)?;
+    let mut rows = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|_| SinkError::ReadError)?;
+        if line.is_empty() || (csv && index == 0) {
+            continue;
+        }
+        let row = if csv { parse_csv_row(&line) } else { parse_jsonl_row(&line) };
+        rows.push(row.ok_or(SinkError::ParseError(line))?);
+    }
+    Ok(rows)
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::connect_script::ConnectScript;
+use crate::quote::QuoteField;
+use crate::series::SeriesOptions;
+use crate::study::StudySpec;
+use crate::tradingview_api::{ApiOptions, HistoryRequest, Mode};
+
+// bumped when a field below changes meaning, older files are refused instead of misread
+pub const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("couldn't read state {path:?}")]
+    Read { path: PathBuf, #[source] source: io::Error },
+    #[error("couldn't write state {path:?}")]
+    Write { path: PathBuf, #[source] source: io::Error },
+    #[error("state is corrupt")]
+    Corrupt(#[from] serde_json::Error),
+    #[error("state version {0} isn't supported, expected {STATE_VERSION}")]
+    Version(u32)
+}
+
+// what the connection is subscribed to, Mode in a form that serializes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionState {
+    Stream,
+    Download { symbol: String, interval: String, from: i64, to: i64, count: Option<usize> },
+    Quotes { symbols: Vec<String> }
+}
+
+// everything needed to open the same sessions again in another process: the mode and its
+// symbols, the series and studies of the chart, and the newest bar per symbol so the first
+// history after the restore is checked for a gap and backfilled like after a reconnect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientState {
+    pub version: u32,
+    pub session: SessionState,
+    pub quote_fields: Vec<QuoteField>,
+    // what the quote session asks for, every field when empty
+    #[serde(default)]
+    pub quote_session_fields: Vec<QuoteField>,
+    pub series: SeriesOptions,
+    pub studies: Vec<StudySpec>,
+    pub last_bar_times: BTreeMap<String, i64>,
+    // the contract each continuous symbol followed, so a roll while stopped is still reported
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub contracts: BTreeMap<String, String>,
+    // the stream's sessions when they came from a connect script
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_script: Option<ConnectScript>
+}
+
+impl ClientState {
+    pub fn to_json(&self) -> Result<String, StateError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<ClientState, StateError> {
+        let state: ClientState = serde_json::from_str(text)?;
+        if state.version != STATE_VERSION {
+            return Err(StateError::Version(state.version));
+        }
+        Ok(state)
+    }
+
+    // None when there is no state file yet
+    pub fn load(path: &Path) -> Result<Option<ClientState>, StateError> {
+        match fs::read_to_string(path) {
+            Ok(text) => ClientState::from_json(&text).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(StateError::Read { path: path.to_path_buf(), source }),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+        let text = self.to_json()?;
+        // written aside and renamed so a crash mid-write leaves the previous state
+        let partial = path.with_extension("partial");
+        fs::write(&partial, text).and_then(|_| fs::rename(&partial, path))
+            .map_err(|source| StateError::Write { path: path.to_path_buf(), source })
+    }
+}
+
+// the state of a connection opened with mode and options; the quote symbols and fields it was
+// last told to use replace the ones it was opened with
+pub fn export_state(mode: &Mode, options: &ApiOptions) -> ClientState {
+    let session = match *mode {
+        Mode::Stream => SessionState::Stream,
+        Mode::Download(ref request) => SessionState::Download {
+            symbol: request.symbol.clone(),
+            interval: request.interval.clone(),
+            from: request.from,
+            to: request.to,
+            count: request.count
+        },
+        Mode::Quotes(ref symbols) => SessionState::Quotes { symbols: options.checkpoints.quote_symbols().unwrap_or_else(|| symbols.clone()) },
+    };
+    ClientState {
+        version: STATE_VERSION,
+        session,
+        // SetQuoteFields sets both
+        quote_fields: options.checkpoints.quote_fields().unwrap_or_else(|| options.quote_fields.clone()),
+        quote_session_fields: options.checkpoints.quote_fields().unwrap_or_else(|| options.quote_session_fields.clone()),
+        series: options.series.clone(),
+        studies: options.studies.clone(),
+        last_bar_times: options.checkpoints.last_bar_times(),
+        contracts: options.checkpoints.contracts(),
+        connect_script: options.connect_script.clone()
+    }
+}
+
+// the mode to open and options carrying the exported subscriptions; everything the state
+// doesn't cover, like auth and limits, is kept from options
+pub fn restore_state(state: ClientState, mut options: ApiOptions) -> (Mode, ApiOptions) {
+    let mode = match state.session {
+        SessionState::Stream => Mode::Stream,
+        SessionState::Download { symbol, interval, from, to, count } => Mode::Download(HistoryRequest { symbol, interval, from, to, count }),
+        SessionState::Quotes { symbols } => Mode::Quotes(symbols),
+    };
+    options.quote_fields = state.quote_fields;
+    options.quote_session_fields = state.quote_session_fields;
+    options.series = state.series;
+    options.studies = state.studies;
+    options.connect_script = state.connect_script;
+    options.checkpoints.restore(&state.last_bar_times);
+    options.checkpoints.restore_contracts(&state.contracts);
+    (mode, options)
+}
+
+fn join<T: Clone + PartialEq>(into: &mut Vec<T>, items: &[T]) {
+    for item in items {
+        if !into.contains(item) {
+            into.push(item.clone());
+        }
+    }
+}
+
+// for the field lists where none means every field: a saved none already covers the given
+// fields, and none given leaves the saved ones
+fn join_fields(into: &mut Vec<QuoteField>, fields: &[QuoteField]) {
+    if !into.is_empty() {
+        join(into, fields);
+    }
+}
+
+// restore_state for a process that was given subscriptions of its own as well, e.g. on the
+// command line. Studies are joined by id, quote fields and symbols are joined, and series options
+// or a chart set in options win over the state's; each saved setting that lost is described in
+// the returned warnings
+pub fn merge_state(state: ClientState, options: ApiOptions) -> (Mode, ApiOptions, Vec<String>) {
+    let given = options.clone();
+    let (mode, mut options) = restore_state(state, options);
+    let mut warnings = vec![];
+    for study in given.studies {
+        match options.studies.iter_mut().find(|restored| restored.id == study.id) {
+            Some(restored) => *restored = study,
+            None => options.studies.push(study),
+        }
+    }
+    join(&mut options.quote_fields, &given.quote_fields);
+    join_fields(&mut options.quote_session_fields, &given.quote_session_fields);
+    if given.series != SeriesOptions::default() && given.series != options.series {
+        if options.series != SeriesOptions::default() {
+            warnings.push("the saved series options are replaced by the given ones".to_string());
+        }
+        options.series = given.series;
+    }
+    match (options.connect_script.as_mut(), given.connect_script) {
+        (Some(restored), Some(script)) => {
+            join(&mut restored.quote_symbols, &script.quote_symbols);
+            join_fields(&mut restored.quote_fields, &script.quote_fields);
+            if let Some(chart) = script.chart {
+                if let Some(ref saved) = restored.chart {
+                    if (&saved.symbol, &saved.interval) != (&chart.symbol, &chart.interval) {
+                        warnings.push(format!("the saved chart of {} at {} is replaced by {} at {}", saved.symbol, saved.interval, chart.symbol, chart.interval));
+                    }
+                }
+                restored.chart = Some(chart);
+            }
+        }
+        (None, Some(script)) => options.connect_script = Some(script),
+        (_, None) => {},
+    }
+    (mode, options, warnings)
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// log2 buckets in nanoseconds, the last one collects everything above ~1s
+const LATENCY_BUCKETS: usize = 32;
+
+#[derive(Default)]
+struct Histogram {
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    buckets: [AtomicU64; LATENCY_BUCKETS]
+}
+
+impl Histogram {
+    fn record(&self, duration: Duration) {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        self.buckets[bucket.min(LATENCY_BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    // upper bound of the bucket holding the given quantile
+    fn quantile(&self, quantile: f64) -> Duration {
+        let target = (self.count.load(Ordering::Relaxed) as f64 * quantile).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count.load(Ordering::Relaxed);
+            if seen >= target && seen > 0 {
+                return Duration::from_nanos(1u64 << bucket);
+            }
+        }
+        Duration::ZERO
+    }
+
+    fn summary(&self) -> LatencySummary {
+        let count = self.count.load(Ordering::Relaxed);
+        LatencySummary {
+            count,
+            mean: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed).checked_div(count).unwrap_or(0)),
+            p50: self.quantile(0.5),
+            p99: self.quantile(0.99),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed))
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Stats {
+    bytes: AtomicU64,
+    dropped_events: AtomicU64,
+    // quotes merged into a held one by BacklogPolicy::Conflate
+    conflated_quotes: AtomicU64,
+    parse_time: Histogram,
+    // frame read off the socket until its messages are parsed
+    parse_latency: Histogram,
+    // frame read off the socket until the events it produced are handed to the consumer
+    delivery_latency: Histogram,
+    // the server's lp_time until the frame carrying it was read
+    server_lag: Histogram,
+    // time between the server's pings, and from a ping's receipt until it's answered
+    ping_interval: Histogram,
+    ping_reply_latency: Histogram,
+    pings: AtomicU64,
+    // pings answered by a later one's reply under PingReply::Coalesce
+    coalesced_pings: AtomicU64,
+    // keyed by MessageType::name
+    message_types: Mutex<HashMap<&'static str, u64>>,
+    bars: Mutex<HashMap<String, u64>>,
+    reconnects: AtomicU64,
+    // server errors, skipped messages and failed subscriptions by kind
+    errors: Mutex<HashMap<&'static str, u64>>
+}
+
+fn count<K: std::hash::Hash + Eq>(counts: &Mutex<HashMap<K, u64>>, key: K, by: u64) {
+    if let Ok(mut counts) = counts.lock() {
+        *counts.entry(key).or_insert(0) += by;
+    }
+}
+
+// largest count first
+fn sorted<K: Clone + Ord>(counts: &Mutex<HashMap<K, u64>>) -> Vec<(K, u64)> {
+    let mut sorted: Vec<(K, u64)> = counts.lock().map(|counts| counts.iter().map(|(key, count)| (key.clone(), *count)).collect()).unwrap_or_default();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sorted
+}
+
+// p50/p99 are bucket upper bounds, so read them as "at most"
+#[derive(Debug, Clone, Copy)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration
+}
+
+pub struct StatsSnapshot {
+    pub messages: u64,
+    pub bytes: u64,
+    pub mean_parse_time: Duration,
+    pub p50_parse_time: Duration,
+    pub p99_parse_time: Duration,
+    pub max_parse_time: Duration,
+    pub dropped_events: u64,
+    pub conflated_quotes: u64,
+    pub parse_latency: LatencySummary,
+    pub delivery_latency: LatencySummary,
+    pub server_lag: LatencySummary,
+    pub pings: u64,
+    pub coalesced_pings: u64,
+    pub ping_interval: LatencySummary,
+    pub ping_reply_latency: LatencySummary,
+    pub message_types: Vec<(&'static str, u64)>,
+    pub bars: Vec<(String, u64)>,
+    pub reconnects: u64,
+    pub errors: Vec<(&'static str, u64)>
+}
+
+impl Stats {
+    pub fn record_message(&self, bytes: usize, parse_time: Duration) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.parse_time.record(parse_time);
+    }
+
+    pub fn record_parse_latency(&self, since_receipt: Duration) {
+        self.parse_latency.record(since_receipt);
+    }
+
+    pub fn record_delivery_latency(&self, since_receipt: Duration) {
+        self.delivery_latency.record(since_receipt);
+    }
+
+    pub fn record_server_lag(&self, lag: Duration) {
+        self.server_lag.record(lag);
+    }
+
+    // the interval is None for a connection's first ping
+    pub fn record_ping(&self, since_last: Option<Duration>) {
+        self.pings.fetch_add(1, Ordering::Relaxed);
+        if let Some(since_last) = since_last {
+            self.ping_interval.record(since_last);
+        }
+    }
+
+    pub fn record_ping_reply(&self, since_receipt: Duration) {
+        self.ping_reply_latency.record(since_receipt);
+    }
+
+    pub fn record_coalesced_ping(&self) {
+        self.coalesced_pings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // returns the running total so callers can log every so often
+    pub fn record_dropped_event(&self) -> u64 {
+        self.dropped_events.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn record_conflated_quote(&self) -> u64 {
+        self.conflated_quotes.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn record_message_type(&self, name: &'static str) {
+        count(&self.message_types, name, 1);
+    }
+
+    pub fn record_bars(&self, symbol: &str, bars: usize) {
+        if let Ok(mut counts) = self.bars.lock() {
+            // the symbol is only cloned the first time
+            match counts.get_mut(symbol) {
+                Some(total) => *total += bars as u64,
+                None => {
+                    counts.insert(symbol.to_string(), bars as u64);
+                }
+            }
+        }
+    }
+
+    pub fn record_reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self, kind: &'static str) {
+        count(&self.errors, kind, 1);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let parse_time = self.parse_time.summary();
+        StatsSnapshot {
+            messages: parse_time.count,
+            bytes: self.bytes.load(Ordering::Relaxed),
+            mean_parse_time: parse_time.mean,
+            p50_parse_time: parse_time.p50,
+            p99_parse_time: parse_time.p99,
+            max_parse_time: parse_time.max,
+            dropped_events: self.dropped_events.load(Ordering::Relaxed),
+            conflated_quotes: self.conflated_quotes.load(Ordering::Relaxed),
+            parse_latency: self.parse_latency.summary(),
+            delivery_latency: self.delivery_latency.summary(),
+            server_lag: self.server_lag.summary(),
+            pings: self.pings.load(Ordering::Relaxed),
+            coalesced_pings: self.coalesced_pings.load(Ordering::Relaxed),
+            ping_interval: self.ping_interval.summary(),
+            ping_reply_latency: self.ping_reply_latency.summary(),
+            message_types: sorted(&self.message_types),
+            bars: sorted(&self.bars),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            errors: sorted(&self.errors)
+        }
+    }
+}
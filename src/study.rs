@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+// ids the chart session already uses for its own series and study
+const RESERVED_IDS: [&str; 3] = ["series_id", "study_id", "symbol_id"];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum StudyError {
+    #[error("study id {0:?} is used twice")]
+    DuplicateId(String),
+    #[error("study id {0:?} is reserved")]
+    ReservedId(String),
+    #[error("study {study:?} is attached to {parent:?}, which isn't declared before it")]
+    UnknownParent { study: String, parent: String }
+}
+
+// a study on the stream's series, or on another study's output when parent is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudySpec {
+    pub id: String,
+    // e.g. "Script@tv-scripting-101!"
+    pub script: String,
+    pub inputs: Value,
+    pub parent: Option<String>,
+    // what each output column is, in order; empty when the script wasn't looked up
+    #[serde(default)]
+    pub plots: Arc<[PlotInfo]>
+}
+
+// one of a study's outputs, e.g. {id: "plot_0", name: "RSI", kind: "line", color: "#7E57C2"}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlotInfo {
+    pub id: String,
+    pub name: String,
+    // line, shapes, chars, colorer, ...
+    pub kind: String,
+    pub color: Option<String>
+}
+
+impl StudySpec {
+    // a published or built-in pine script by id, e.g. "STD;RSI"; a child reads its
+    // parent's first plot as its first input
+    pub fn pine(id: &str, pine_id: &str, parent: Option<&str>) -> StudySpec {
+        let mut inputs = serde_json::json!({
+            "pineId": pine_id,
+            "pineVersion": "last"
+        });
+        if let Some(parent) = parent {
+            inputs["in_0"] = serde_json::json!({
+                "v": format!("{}$0", parent),
+                "f": true,
+                "t": "source"
+            });
+        }
+        StudySpec {
+            id: id.to_string(),
+            script: "Script@tv-scripting-101!".to_string(),
+            inputs,
+            parent: parent.map(str::to_string),
+            plots: Arc::from([])
+        }
+    }
+}
+
+// one row of study output, plots in the order the script declares them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StudyPoint {
+    pub time: i64,
+    // na plots come through as NaN, and as null in json
+    #[serde(with = "crate::schema::nan_as_null")]
+    #[schemars(with = "Vec<Option<f64>>")]
+    pub values: Vec<f64>
+}
+
+impl StudyPoint {
+    // the value of the plot with that name, from the plots of the StudyValues event it came in
+    pub fn get(&self, plots: &[PlotInfo], name: &str) -> Option<f64> {
+        let index = plots.iter().position(|plot| plot.name == name)?;
+        self.values.get(index).copied()
+    }
+
+    // (plot name, value) pairs, plots without a name keep their position as one
+    pub fn named<'a>(&'a self, plots: &'a [PlotInfo]) -> impl Iterator<Item = (String, f64)> + 'a {
+        self.values.iter().enumerate().map(move |(index, value)| {
+            let name = plots.get(index).map_or_else(|| index.to_string(), |plot| plot.name.clone());
+            (name, *value)
+        })
+    }
+}
+
+// study entries look like {"i": 0, "v": [time, plot0, plot1, ...]}
+pub fn parse_study_points(study: &Value) -> Vec<StudyPoint> {
+    let entries = match study.get("st").and_then(Value::as_array) {
+        Some(entries) => entries,
+        None => return vec![],
+    };
+    entries.iter().filter_map(|entry| {
+        let values = entry.get("v")?.as_array()?;
+        Some(StudyPoint {
+            time: values.first()?.as_f64()? as i64,
+            values: values[1..].iter().map(|value| value.as_f64().unwrap_or(f64::NAN)).collect()
+        })
+    }).collect()
+}
+
+// parents are always declared first, so creating studies in order satisfies the server
+#[derive(Debug, Default)]
+pub struct StudyGraph {
+    studies: Vec<StudySpec>,
+    index: HashMap<String, usize>
+}
+
+impl StudyGraph {
+    pub fn new(studies: &[StudySpec]) -> Result<StudyGraph, StudyError> {
+        let mut graph = StudyGraph::default();
+        for study in studies {
+            graph.add(study.clone())?;
+        }
+        Ok(graph)
+    }
+
+    pub fn add(&mut self, study: StudySpec) -> Result<(), StudyError> {
+        if RESERVED_IDS.contains(&study.id.as_str()) || study.id.starts_with("series_id") || study.id.starts_with("esd_") {
+            return Err(StudyError::ReservedId(study.id));
+        }
+        if self.index.contains_key(&study.id) {
+            return Err(StudyError::DuplicateId(study.id));
+        }
+        if let Some(ref parent) = study.parent {
+            if !self.index.contains_key(parent) {
+                return Err(StudyError::UnknownParent { study: study.id.clone(), parent: parent.clone() });
+            }
+        }
+        self.index.insert(study.id.clone(), self.studies.len());
+        self.studies.push(study);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&StudySpec> {
+        self.index.get(id).map(|&index| &self.studies[index])
+    }
+
+    pub fn studies(&self) -> &[StudySpec] {
+        &self.studies
+    }
+
+    // children before grandchildren, in declaration order
+    pub fn descendants<'a>(&'a self, id: &'a str) -> Vec<&'a str> {
+        let mut found = vec![id];
+        let mut next = 0;
+        while next < found.len() {
+            let parent = found[next];
+            found.extend(self.studies.iter().filter(|study| study.parent.as_deref() == Some(parent)).map(|study| study.id.as_str()));
+            next += 1;
+        }
+        found.remove(0);
+        found
+    }
+}
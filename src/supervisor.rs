@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{error, warn};
+use thiserror::Error;
+
+// how often the supervisor checks for a signal while waiting on events
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+type ThreadResult = Result<(), Box<dyn Error + Send>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    TradingView,
+    WebSocket
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Side::TradingView => write!(f, "TradingView"),
+            Side::WebSocket => write!(f, "WebSocket"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{side} thread failed")]
+pub struct PipelineError {
+    pub side: Side,
+    #[source]
+    source: Box<dyn Error + Send>
+}
+
+#[derive(Debug, Error)]
+#[error("thread panicked")]
+struct PanicError;
+
+pub struct Supervisor {
+    stop: Arc<AtomicBool>,
+    exits_tx: Sender<(Side, ThreadResult)>,
+    exits_rx: Receiver<(Side, ThreadResult)>,
+    handles: Vec<JoinHandle<()>>
+}
+
+impl Supervisor {
+    pub fn new() -> Supervisor {
+        let (exits_tx, exits_rx) = mpsc::channel();
+        Supervisor {
+            stop: Arc::new(AtomicBool::new(false)),
+            exits_tx,
+            exits_rx,
+            handles: vec![]
+        }
+    }
+
+    // raised when either side stops or a shutdown signal arrives
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    pub fn spawn<F>(&mut self, side: Side, task: F)
+    where
+        F: FnOnce() -> ThreadResult + Send + 'static
+    {
+        let stop = Arc::clone(&self.stop);
+        let exits_tx = self.exits_tx.clone();
+        self.handles.push(thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(task))
+                .unwrap_or_else(|_| Err(Box::new(PanicError)));
+            // whichever side stops first takes its peer down with it
+            stop.store(true, Ordering::SeqCst);
+            let _ = exits_tx.send((side, result));
+        }));
+    }
+
+    // the callback gets None when nothing arrived for a poll interval, so it can do timed work;
+    // unless panics propagate, a panicking callback only loses the event it was handed
+    pub fn watch<T>(&self, events_rx: Receiver<T>, shutdown: &AtomicBool, propagate_panics: bool, mut on_event: impl FnMut(Option<T>)) {
+        loop {
+            let event = match events_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => {
+                    if shutdown.load(Ordering::SeqCst) {
+                        self.stop.store(true, Ordering::SeqCst);
+                    }
+                    None
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if propagate_panics {
+                on_event(event);
+            } else if panic::catch_unwind(AssertUnwindSafe(|| on_event(event))).is_err() {
+                error!("event callback panicked, skipping event");
+            }
+        }
+    }
+
+    // the first side to fail is reported as the cause
+    pub fn join(self) -> Result<(), PipelineError> {
+        drop(self.exits_tx);
+        let mut failure: Option<PipelineError> = None;
+        for (side, result) in self.exits_rx {
+            if let Err(source) = result {
+                match failure {
+                    Some(ref failure) => warn!("{} thread also failed after {} thread: {}", side, failure.side, source),
+                    None => failure = Some(PipelineError { side, source }),
+                }
+            }
+        }
+        for handle in self.handles {
+            let _ = handle.join();
+        }
+        failure.map_or(Ok(()), Err)
+    }
+}
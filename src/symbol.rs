@@ -0,0 +1,86 @@
+use thiserror::Error;
+
+const MAX_SYMBOL_LENGTH: usize = 128;
+// spreads and other composites are plain arithmetic over symbols, e.g. 2*ES1!-NQ1!
+const EXPRESSION_OPERATORS: [char; 6] = ['+', '-', '*', '/', '(', ')'];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SymbolError {
+    #[error("empty symbol")]
+    Empty,
+    #[error("symbol {0:?} is longer than {MAX_SYMBOL_LENGTH} characters")]
+    TooLong(String),
+    #[error("symbol {0:?} contains {1:?}")]
+    InvalidCharacter(String, char),
+    #[error("symbol {0:?} has an empty exchange or ticker")]
+    MissingPart(String),
+    #[error("expression {0:?} has unbalanced parentheses")]
+    UnbalancedParentheses(String)
+}
+
+// catches typos and anything that would break the json symbols get embedded in,
+// whether the symbol actually exists is still up to the server
+pub fn validate(symbol: &str) -> Result<(), SymbolError> {
+    if symbol.is_empty() {
+        return Err(SymbolError::Empty);
+    }
+    if symbol.len() > MAX_SYMBOL_LENGTH {
+        return Err(SymbolError::TooLong(symbol.to_string()));
+    }
+    if let Some(invalid) = symbol.chars().find(|c| c.is_whitespace() || c.is_control() || "\"\\{}=,".contains(*c)) {
+        return Err(SymbolError::InvalidCharacter(symbol.to_string(), invalid));
+    }
+    if is_expression(symbol) {
+        return validate_expression(symbol);
+    }
+    if symbol.split(':').any(str::is_empty) {
+        return Err(SymbolError::MissingPart(symbol.to_string()));
+    }
+    Ok(())
+}
+
+pub fn is_expression(symbol: &str) -> bool {
+    symbol.contains(EXPRESSION_OPERATORS)
+}
+
+// the symbols an expression refers to, without the numeric constants
+pub fn operands(expression: &str) -> impl Iterator<Item = &str> {
+    expression.split(EXPRESSION_OPERATORS)
+        .filter(|operand| !operand.is_empty() && operand.parse::<f64>().is_err())
+}
+
+// the server does the arithmetic, this only catches what would never resolve
+fn validate_expression(expression: &str) -> Result<(), SymbolError> {
+    let mut depth = 0i32;
+    for c in expression.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {},
+        }
+        if depth < 0 {
+            break;
+        }
+    }
+    if depth != 0 {
+        return Err(SymbolError::UnbalancedParentheses(expression.to_string()));
+    }
+    if operands(expression).next().is_none() {
+        return Err(SymbolError::MissingPart(expression.to_string()));
+    }
+    for operand in operands(expression) {
+        if operand.split(':').any(str::is_empty) {
+            return Err(SymbolError::MissingPart(expression.to_string()));
+        }
+    }
+    Ok(())
+}
+
+// continuous futures end in the contract offset and a bang, e.g. CME_MINI:ES1!
+pub fn is_continuous(symbol: &str) -> bool {
+    if is_expression(symbol) {
+        return false;
+    }
+    let ticker = symbol.rsplit(':').next().unwrap_or(symbol);
+    ticker.strip_suffix('!').is_some_and(|rest| rest.len() > 1 && rest.ends_with(|c: char| c.is_ascii_digit()))
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::http;
+
+const SEARCH_URL: &str = "https://symbol-search.tradingview.com/symbol_search/v3/";
+
+// exchanges, scales and sessions rarely change, so a day between lookups is plenty
+pub const DEFAULT_TTL: Duration = Duration::from_secs(86_400);
+
+#[derive(Debug, Error)]
+pub enum SymbolInfoError {
+    #[error("couldn't read symbol cache {path:?}")]
+    Read { path: PathBuf, #[source] source: io::Error },
+    #[error("couldn't write symbol cache {path:?}")]
+    Write { path: PathBuf, #[source] source: io::Error },
+    #[error("symbol cache {path:?} is corrupt")]
+    Corrupt { path: PathBuf, #[source] source: serde_json::Error },
+    #[error("symbol search failed")]
+    Request(#[source] Box<dyn Error + Send>),
+    #[error("unexpected HTTP status {0}")]
+    Status(u16),
+    #[error("symbol search response parse error")]
+    Parse(#[source] serde_json::Error)
+}
+
+// what symbol_resolved or the search api say about a symbol; raw keeps everything else they sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    // EXCHANGE:NAME
+    pub symbol: String,
+    pub description: String,
+    pub exchange: String,
+    // stock, crypto, futures, forex, index, ...
+    pub kind: String,
+    pub currency: Option<String>,
+    // prices are multiples of min_move / price_scale
+    pub price_scale: Option<u64>,
+    pub min_move: Option<f64>,
+    pub timezone: Option<String>,
+    pub raw: Value
+}
+
+impl SymbolInfo {
+    // symbol_resolved's p.2 looks like {"pro_name": "NASDAQ:AAPL", "description": "Apple Inc.", "exchange": "NASDAQ",
+    // "type": "stock", "currency_code": "USD", "pricescale": 100, "minmov": 1, "timezone": "America/New_York", ...};
+    // search results name it {"symbol": "AAPL", "exchange": "NASDAQ", ...} instead
+    pub fn from_json(symbol: &str, raw: Value) -> SymbolInfo {
+        let text = |name: &str| raw.get(name).and_then(Value::as_str).filter(|text| !text.is_empty()).map(str::to_string);
+        SymbolInfo {
+            symbol: symbol.to_string(),
+            description: text("description").unwrap_or_default(),
+            exchange: text("exchange").or_else(|| text("listed_exchange")).unwrap_or_default(),
+            kind: text("type").unwrap_or_default(),
+            currency: text("currency_code"),
+            price_scale: raw.get("pricescale").and_then(Value::as_u64),
+            min_move: raw.get("minmov").and_then(Value::as_f64),
+            timezone: text("timezone"),
+            raw
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    // unix seconds
+    fetched: i64,
+    info: Value
+}
+
+// symbol infos kept in a json file between runs; entries older than the ttl are treated as missing.
+// Every insert rewrites the file, which is fine for the hundreds of symbols a run resolves
+pub struct SymbolCache {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry>>
+}
+
+impl SymbolCache {
+    // starts empty when the file doesn't exist yet
+    pub fn open(path: &Path, ttl: Duration) -> Result<SymbolCache, SymbolInfoError> {
+        let entries = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|source| SymbolInfoError::Corrupt { path: path.to_path_buf(), source })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(source) => return Err(SymbolInfoError::Read { path: path.to_path_buf(), source }),
+        };
+        Ok(SymbolCache { path: Some(path.to_path_buf()), ttl, entries: Mutex::new(entries) })
+    }
+
+    // kept for the run only
+    pub fn in_memory(ttl: Duration) -> SymbolCache {
+        SymbolCache { path: None, ttl, entries: Mutex::default() }
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<SymbolInfo> {
+        let entries = self.entries.lock().ok()?;
+        let entry = entries.get(symbol)?;
+        let age = now().saturating_sub(entry.fetched);
+        (age >= 0 && (age as u64) < self.ttl.as_secs()).then(|| SymbolInfo::from_json(symbol, entry.info.clone()))
+    }
+
+    pub fn insert(&self, info: &SymbolInfo) -> Result<(), SymbolInfoError> {
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        entries.insert(info.symbol.clone(), Entry { fetched: now(), info: info.raw.clone() });
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let text = serde_json::to_string(&*entries).map_err(|err| SymbolInfoError::Write { path: path.clone(), source: io::Error::other(err) })?;
+        // written aside and renamed so a crash mid-write can't leave half a file
+        let partial = path.with_extension("partial");
+        fs::write(&partial, text).and_then(|_| fs::rename(&partial, path))
+            .map_err(|source| SymbolInfoError::Write { path: path.clone(), source })
+    }
+
+    // the cached info, or the search api's exact match, which is cached in turn
+    pub fn lookup(&self, symbol: &str) -> Result<Option<SymbolInfo>, SymbolInfoError> {
+        if let Some(info) = self.get(symbol) {
+            return Ok(Some(info));
+        }
+        let info = search(symbol)?.into_iter().find(|info| info.symbol.eq_ignore_ascii_case(symbol));
+        if let Some(ref info) = info {
+            if let Err(err) = self.insert(info) {
+                warn!("couldn't cache {}: {}", info.symbol, err);
+            }
+        }
+        Ok(info)
+    }
+}
+
+// search highlights the matched part of names with <em> tags
+fn strip_tags(text: &str) -> String {
+    text.replace("<em>", "").replace("</em>", "")
+}
+
+// symbols matching the text, best match first; an EXCHANGE: prefix narrows the search to it
+pub fn search(text: &str) -> Result<Vec<SymbolInfo>, SymbolInfoError> {
+    let (exchange, name) = text.split_once(':').unwrap_or(("", text));
+    let url = http::with_query(SEARCH_URL, &[("text", name), ("exchange", exchange), ("lang", "en"), ("domain", "production")]);
+    let response = http::get(&url, &[("Origin", "https://www.tradingview.com"), ("Referer", "https://www.tradingview.com/")]).map_err(SymbolInfoError::Request)?;
+    if !(200..300).contains(&response.status) {
+        return Err(SymbolInfoError::Status(response.status));
+    }
+    let parsed: Value = serde_json::from_str(&response.body).map_err(SymbolInfoError::Parse)?;
+    let results = parsed.get("symbols").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    Ok(results.iter().filter_map(|result| {
+        let name = strip_tags(result.get("symbol")?.as_str()?);
+        let prefix = result.get("prefix").or_else(|| result.get("exchange"))?.as_str()?;
+        let mut raw = result.clone();
+        raw["description"] = Value::from(strip_tags(result.get("description").and_then(Value::as_str).unwrap_or("")));
+        Some(SymbolInfo::from_json(&format!("{}:{}", prefix, name), raw))
+    }).collect())
+}
@@ -0,0 +1,292 @@
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use thiserror::Error;
+
+use crate::duration;
+use crate::tradingview_api::{frame_message, split_messages};
+use crate::websocket::{decode_client_frame, encode_server_frame, Endpoint};
+
+// how long an expect step waits for the client
+pub const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+// how often blocked reads and accepts wake up to check for stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// what the server sends first on every connection
+pub const HELLO: &str = r#"{"session_id":"<0.1.2>_mock","timestamp":1700000000,"timestampMs":1700000000000,"release":"registry.xtools.tv/tvbs_release/webchart:release_206-21","studies_metadata_hash":"mock","auth_scheme_vsn":2,"protocol":"json","via":"127.0.0.1:443","javastudies":["3.66"]}"#;
+
+#[derive(Debug, Error)]
+pub enum MockError {
+    #[error("couldn't read script {path:?}")]
+    Read { path: String, #[source] source: io::Error },
+    #[error("script line {line}: {reason}")]
+    Script { line: usize, reason: String },
+    #[error("couldn't start the mock server")]
+    Bind(#[source] io::Error),
+    #[error("mock server connection failed")]
+    Io(#[from] io::Error),
+    #[error("bad websocket handshake: {0}")]
+    Handshake(String),
+    #[error("no client message containing {0:?} within the expect timeout")]
+    Timeout(String),
+    #[error("client closed the connection while waiting for {0:?}")]
+    Closed(String),
+    #[error("mock server thread panicked")]
+    Panicked
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    // one ~m~ framed message in its own websocket frame
+    Send(String),
+    // the same in a binary frame
+    SendBinary(String),
+    // waits for a client message containing the text
+    Expect(String),
+    // sends ~h~id and waits for the client to echo it
+    Ping(u64),
+    Sleep(Duration),
+    // a close frame, then the connection ends
+    Close,
+    // the connection ends without a close frame, like a network failure
+    Drop
+}
+
+// what the server does on each connection, e.g.
+//
+//     # lines starting with > are sent, < waits for a client message containing the rest
+//     hello
+//     < set_auth_token
+//     < chart_create_session
+//     > {"m":"symbol_resolved","p":["chart_session_id","sds_sym_1",{"pro_name":"CRYPTO:BTCUSD"}]}
+//     binary {"m":"quote_completed","p":["quote_session_id","CRYPTO:BTCUSD"]}
+//     ping 1
+//     sleep 100ms
+//     close
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockScript {
+    pub steps: Vec<Step>
+}
+
+impl MockScript {
+    pub fn parse(text: &str) -> Result<MockScript, MockError> {
+        let mut steps = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let error = |reason: &str| MockError::Script { line: index + 1, reason: reason.to_string() };
+            let (word, rest) = line.split_once(' ').map_or((line, ""), |(word, rest)| (word, rest.trim()));
+            let step = match word {
+                "" | "#" => continue,
+                _ if word.starts_with('#') => continue,
+                ">" if !rest.is_empty() => Step::Send(rest.to_string()),
+                "<" if !rest.is_empty() => Step::Expect(rest.to_string()),
+                "binary" if !rest.is_empty() => Step::SendBinary(rest.to_string()),
+                "hello" => Step::Send(HELLO.to_string()),
+                "ping" => Step::Ping(rest.parse().map_err(|_| error("ping needs a numeric id"))?),
+                "sleep" => Step::Sleep(duration::parse(rest).ok_or_else(|| error("sleep needs a duration like 100ms or 2s"))?),
+                "close" => Step::Close,
+                "drop" => Step::Drop,
+                _ => return Err(error(&format!("unknown step {:?}", line))),
+            };
+            steps.push(step);
+        }
+        Ok(MockScript { steps })
+    }
+
+    pub fn load(path: &Path) -> Result<MockScript, MockError> {
+        let text = fs::read_to_string(path).map_err(|source| MockError::Read { path: path.display().to_string(), source })?;
+        MockScript::parse(&text)
+    }
+}
+
+// one accepted client, read through the same buffer by every step
+struct Connection<'a> {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    received: &'a Mutex<Vec<String>>,
+    closed: bool
+}
+
+impl Connection<'_> {
+    fn handshake(&mut self) -> Result<(), MockError> {
+        let deadline = Instant::now() + EXPECT_TIMEOUT;
+        let end = loop {
+            if let Some(end) = self.buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break end + 4;
+            }
+            if !self.read(deadline)? || Instant::now() >= deadline {
+                return Err(MockError::Handshake("no upgrade request".to_string()));
+            }
+        };
+        let request = String::from_utf8_lossy(&self.buffer[..end]).to_lowercase();
+        if !request.contains("upgrade: websocket") {
+            return Err(MockError::Handshake(request.lines().next().unwrap_or_default().to_string()));
+        }
+        self.buffer.drain(..end);
+        // the client doesn't check Sec-WebSocket-Accept
+        self.stream.write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n")?;
+        Ok(())
+    }
+
+    // false once the client has gone
+    fn read(&mut self, deadline: Instant) -> Result<bool, MockError> {
+        let mut chunk = [0u8; 65536];
+        while Instant::now() < deadline {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(read) => {
+                    self.buffer.extend_from_slice(&chunk[..read]);
+                    return Ok(true);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => continue,
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => return Ok(false),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(true)
+    }
+
+    // the client's next messages, recorded; None once it closed
+    fn next_messages(&mut self, deadline: Instant) -> Result<Option<Vec<String>>, MockError> {
+        loop {
+            match decode_client_frame(&mut self.buffer) {
+                Some((0x08, _)) => {
+                    self.closed = true;
+                    let _ = self.stream.write_all(&encode_server_frame(0x08, &1000u16.to_be_bytes()));
+                    return Ok(None);
+                }
+                Some((_, payload)) => {
+                    let text = String::from_utf8_lossy(&payload).into_owned();
+                    let messages: Vec<String> = split_messages(&text).map(str::to_string).collect();
+                    debug!("mock server received {:?}", messages);
+                    if let Ok(mut received) = self.received.lock() {
+                        received.extend(messages.iter().cloned());
+                    }
+                    return Ok(Some(messages));
+                }
+                None => {}
+            }
+            if Instant::now() >= deadline {
+                return Ok(Some(vec![]));
+            }
+            if !self.read(deadline)? {
+                self.closed = true;
+                return Ok(None);
+            }
+        }
+    }
+
+    fn send(&mut self, message: &str) -> Result<(), MockError> {
+        self.send_frame(0x01, message)
+    }
+
+    fn send_frame(&mut self, opcode: u8, message: &str) -> Result<(), MockError> {
+        self.stream.write_all(&encode_server_frame(opcode, frame_message(message).as_bytes()))?;
+        Ok(())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), MockError> {
+        let deadline = Instant::now() + EXPECT_TIMEOUT;
+        while Instant::now() < deadline {
+            match self.next_messages(deadline)? {
+                Some(messages) if messages.iter().any(|message| message.contains(text)) => return Ok(()),
+                Some(_) => {}
+                None => return Err(MockError::Closed(text.to_string())),
+            }
+        }
+        Err(MockError::Timeout(text.to_string()))
+    }
+
+    fn run(&mut self, script: &MockScript, stop: &AtomicBool) -> Result<(), MockError> {
+        self.handshake()?;
+        for step in &script.steps {
+            match *step {
+                Step::Send(ref message) => self.send(message)?,
+                Step::SendBinary(ref message) => self.send_frame(0x02, message)?,
+                Step::Expect(ref text) => self.expect(text)?,
+                Step::Ping(id) => {
+                    self.send(&format!("~h~{}", id))?;
+                    self.expect(&format!("~h~{}", id))?;
+                }
+                Step::Sleep(duration) => thread::sleep(duration),
+                Step::Close => {
+                    self.stream.write_all(&encode_server_frame(0x08, &1000u16.to_be_bytes()))?;
+                    return Ok(());
+                }
+                Step::Drop => return Ok(()),
+            }
+        }
+        // keeps listening, so the client can stay connected and its messages are recorded
+        while !self.closed && !stop.load(Ordering::SeqCst) {
+            self.next_messages(Instant::now() + POLL_INTERVAL)?;
+        }
+        Ok(())
+    }
+}
+
+// an in-process plain ws:// server playing a script to each client that connects, one at a time,
+// so the whole pipeline can be run against it with ApiOptions::endpoint set to endpoint()
+pub struct MockServer {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<String>>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<(), MockError>>
+}
+
+impl MockServer {
+    pub fn start(script: MockScript) -> Result<MockServer, MockError> {
+        let listener = TcpListener::bind("127.0.0.1:0").map_err(MockError::Bind)?;
+        listener.set_nonblocking(true).map_err(MockError::Bind)?;
+        let addr = listener.local_addr().map_err(MockError::Bind)?;
+        let received = Arc::new(Mutex::new(vec![]));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (thread_received, thread_stop) = (Arc::clone(&received), Arc::clone(&stop));
+        let handle = thread::spawn(move || -> Result<(), MockError> {
+            while !thread_stop.load(Ordering::SeqCst) {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(POLL_INTERVAL))?;
+                let mut connection = Connection { stream, buffer: vec![], received: &thread_received, closed: false };
+                connection.run(&script, &thread_stop)?;
+            }
+            Ok(())
+        });
+        Ok(MockServer { addr, received, stop, handle })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::Plain(self.addr)
+    }
+
+    // every message the clients sent so far, ~m~ framing removed
+    pub fn received(&self) -> Vec<String> {
+        self.received.lock().map(|received| received.clone()).unwrap_or_default()
+    }
+
+    // the first step that failed, e.g. an expect the client never satisfied
+    pub fn stop(self) -> Result<Vec<String>, MockError> {
+        let MockServer { received, stop, handle, .. } = self;
+        stop.store(true, Ordering::SeqCst);
+        handle.join().map_err(|_| MockError::Panicked)??;
+        let received = received.lock().map(|received| received.clone()).unwrap_or_default();
+        Ok(received)
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFormat {
+    Rfc3339,
+    Unix,
+    Millis
+}
+
+impl std::str::FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<TimeFormat, String> {
+        match value {
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            "unix" => Ok(TimeFormat::Unix),
+            "ms" => Ok(TimeFormat::Millis),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+// converts TradingView epoch seconds into the user's chosen output representation
+#[derive(Debug, Clone, Copy)]
+pub struct TimestampFormatter {
+    tz: Tz,
+    format: TimeFormat
+}
+
+impl Default for TimestampFormatter {
+    fn default() -> TimestampFormatter {
+        TimestampFormatter::new(Tz::UTC, TimeFormat::Unix)
+    }
+}
+
+impl TimestampFormatter {
+    pub fn new(tz: Tz, format: TimeFormat) -> TimestampFormatter {
+        TimestampFormatter { tz, format }
+    }
+
+    pub fn format(&self, epoch_seconds: i64) -> String {
+        match self.format {
+            TimeFormat::Unix => epoch_seconds.to_string(),
+            TimeFormat::Millis => (epoch_seconds * 1000).to_string(),
+            TimeFormat::Rfc3339 => match Utc.timestamp_opt(epoch_seconds, 0).single() {
+                Some(time) => time.with_timezone(&self.tz).to_rfc3339(),
+                None => epoch_seconds.to_string(),
+            },
+        }
+    }
+
+    // appends to a line being built, without an intermediate String for the numeric formats
+    pub fn push(&self, line: &mut Vec<u8>, epoch_seconds: i64) {
+        match self.format {
+            TimeFormat::Unix => line.extend_from_slice(itoa::Buffer::new().format(epoch_seconds).as_bytes()),
+            TimeFormat::Millis => line.extend_from_slice(itoa::Buffer::new().format(epoch_seconds * 1000).as_bytes()),
+            TimeFormat::Rfc3339 => line.extend_from_slice(self.format(epoch_seconds).as_bytes()),
+        }
+    }
+
+    // same as push, with rfc3339 quoted as a json string
+    pub fn push_json(&self, line: &mut Vec<u8>, epoch_seconds: i64) {
+        if self.format == TimeFormat::Rfc3339 {
+            line.push(b'"');
+            self.push(line, epoch_seconds);
+            line.push(b'"');
+        } else {
+            self.push(line, epoch_seconds);
+        }
+    }
+
+    // numeric formats stay numbers in json output
+    pub fn format_json(&self, epoch_seconds: i64) -> Value {
+        match self.format {
+            TimeFormat::Unix => Value::from(epoch_seconds),
+            TimeFormat::Millis => Value::from(epoch_seconds * 1000),
+            TimeFormat::Rfc3339 => Value::from(self.format(epoch_seconds)),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// a one tick series has one bar per trade
+pub const TICK_INTERVAL: &str = "1T";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+    // the first trades, before the price has moved
+    Unknown
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Trade {
+    pub time: i64,
+    pub price: f64,
+    pub size: f64,
+    // inferred with the tick rule, the feed doesn't say who was the aggressor
+    pub side: TradeSide
+}
+
+// turns tick series updates into trades, each series index at most once
+#[derive(Debug)]
+pub struct TickTracker {
+    last_index: Option<i64>,
+    last_price: Option<f64>,
+    last_side: TradeSide
+}
+
+impl Default for TickTracker {
+    fn default() -> TickTracker {
+        TickTracker { last_index: None, last_price: None, last_side: TradeSide::Unknown }
+    }
+}
+
+impl TickTracker {
+    // series entries look like {"i": 0, "v": [time, price, price, price, price, size]}
+    pub fn update(&mut self, series: &Value) -> Vec<Trade> {
+        let entries = match series.get("s").and_then(Value::as_array) {
+            Some(entries) => entries,
+            None => return vec![],
+        };
+        let mut ticks: Vec<(i64, i64, f64, f64)> = entries.iter().filter_map(|entry| {
+            let index = entry.get("i")?.as_i64()?;
+            let values = entry.get("v")?.as_array()?;
+            let time = values.first()?.as_f64()? as i64;
+            let price = values.get(4)?.as_f64()?;
+            let size = values.get(5).and_then(Value::as_f64).unwrap_or(0.0);
+            Some((index, time, price, size))
+        }).collect();
+        ticks.sort_by_key(|&(index, ..)| index);
+        let mut trades = vec![];
+        for (index, time, price, size) in ticks {
+            if self.last_index.is_some_and(|last_index| index <= last_index) {
+                continue;
+            }
+            self.last_index = Some(index);
+            // equal prices keep the previous side
+            self.last_side = match self.last_price {
+                Some(last_price) if price > last_price => TradeSide::Buy,
+                Some(last_price) if price < last_price => TradeSide::Sell,
+                _ => self.last_side,
+            };
+            self.last_price = Some(price);
+            trades.push(Trade { time, price, size, side: self.last_side });
+        }
+        trades
+    }
+}
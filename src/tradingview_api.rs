@@ -1,34 +1,354 @@
-use std::{error::Error, sync::mpsc::{Receiver, Sender}};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, error::Error, ops::Range, sync::{Arc, Mutex, mpsc::{Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError}}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
 use json_dotpath::DotPaths;
+use log::{debug, info, trace, warn};
 use serde_json::Value;
+use thiserror::Error;
 
-#[derive(Debug)]
+use crate::ack::{self, AckTracker, CommandOutcome, CommandResult, CorrelationId};
+use crate::alias::SymbolAliases;
+use crate::auth::{self, Anonymous, AuthProvider};
+use crate::bar::{self, Bar};
+use crate::candles::{CandleCache, Merged};
+use crate::capture::UnknownCapture;
+use crate::checkpoint::Checkpoints;
+use crate::clock::{self, ClockSkew};
+use crate::conflate::{self, QuoteConflation};
+use crate::connect_script::{self, ConnectScript};
+use crate::corporate::{self, EsdKind};
+use crate::event::{Event, ServerErrorKind};
+use crate::fault::FaultConfig;
+use crate::filter::{EventFilter, FilterChain};
+use crate::health::{Health, HealthConnection};
+use crate::indicators::Indicator;
+use crate::invariants::{InvariantChecker, InvariantMode, Violation};
+use crate::json::{self, JsonError};
+use crate::keepalive::{Keepalive, PingReply};
+use crate::market::TradingHours;
+use crate::parse_pool::{ParsePool, ParsePoolError, ParseSession};
+use crate::poll::{Poller, PollerHandle};
+use crate::quality::BarValidator;
+use crate::quote::{AccessLevel, QuoteData, QuoteDataError, QuoteExtractor, QuoteField};
+use crate::rates::{QueueDepths, RateMeter};
+use crate::resample::{ResampleError, Resampler};
+use crate::series::SeriesOptions;
+use crate::server_info::ServerInfo;
+use crate::session::SessionStats;
+use crate::stats::Stats;
+use crate::study::{self, StudyError, StudyGraph, StudyPoint, StudySpec};
+use crate::symbol;
+use crate::symbol_info::{SymbolCache, SymbolInfo};
+use crate::trade::{self, TickTracker};
+use crate::websocket::{Endpoint, FrameHandler, Incoming, Outgoing};
+
+const STREAM_SYMBOL: &str = "CRYPTO:BTCUSD";
+const STREAM_INTERVAL: &str = "1";
+const HISTORY_BATCH_SIZE: usize = 5000;
+const QUOTE_SYMBOLS_PER_MESSAGE: usize = 100;
+const TICK_SERIES_ID: &str = "series_id_ticks";
+// the one quote and chart session every connection creates
+const QUOTE_SESSION: &str = "quote_session_id";
+const CHART_SESSION: &str = "chart_session_id";
+// the server pings roughly every 10-20 seconds
+const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+// "~m~" twice plus up to 20 length digits
+const FRAME_HEADER_CAPACITY: usize = 26;
+// stops a token the server keeps rejecting from turning into a login loop
+const MIN_REAUTH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum TradingViewError {
-    ParseError,
-    SerializationError,
-    SendError,
-    ReceiveError,
-    StudyError(Value),
-    CriticalError(Value),
-    ProtocolError(Value)
+    #[error("Parse error in message: {}", snippet(.raw))]
+    ParseError { raw: String, #[source] source: Option<JsonError> },
+    #[error("Serialization error")]
+    SerializationError(#[source] serde_json::Error),
+    #[error("Invalid server banner: {}", snippet(.raw))]
+    BannerError { raw: String, #[source] source: serde_json::Error },
+    #[error("Send error: {0} channel closed")]
+    SendError(&'static str),
+    #[error("Study error: {0}")]
+    StudyError(Arc<Value>),
+    #[error("Critical error: {0}")]
+    CriticalError(Arc<Value>),
+    #[error("Protocol error: {0}")]
+    ProtocolError(Arc<Value>),
+    #[error("Symbol error: {0}")]
+    SymbolError(Arc<Value>),
+    #[error("Event backlog full ({0} events)")]
+    BacklogFull(usize),
+    #[error("Feed stale: nothing received for {0:?}")]
+    FeedStale(Duration),
+    #[error("Parse pool error")]
+    ParsePoolError(#[from] ParsePoolError),
+    #[error("Malformed quote data: {}", snippet(.raw))]
+    QuoteDataError { raw: String, #[source] source: QuoteDataError },
+    #[error("The widget data endpoint only serves quotes")]
+    EndpointMismatch,
+    #[error("Invalid study setup: {0}")]
+    StudySetupError(#[from] StudyError),
+    #[error("Invalid resampling: {0}")]
+    ResampleError(#[from] ResampleError)
 }
 
-impl std::fmt::Display for TradingViewError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            TradingViewError::ParseError => write!(f, "Parse error"),
-            TradingViewError::SerializationError => write!(f, "Serialization error"),
-            TradingViewError::SendError => write!(f, "Send error"),
-            TradingViewError::ReceiveError => write!(f, "Receive error"),
-            TradingViewError::StudyError(ref value) => write!(f, "Study error"),
-            TradingViewError::CriticalError(ref value) => write!(f, "Critical error"),
-            TradingViewError::ProtocolError(ref value) => write!(f, "Protocol error"),
+// a frame can carry several "~m~<len>~m~<payload>" messages back to back
+pub fn split_messages(frame: &str) -> MessageSplitter<'_> {
+    MessageSplitter { frame, position: 0 }
+}
+
+// the payloads of a frame, skipping empty and blank ones. A length the next header or the end of
+// the frame follows is trusted; one that counts utf-16 units instead of bytes runs to the next
+// header. Text where a header should be is skipped up to the next one and a message longer than
+// what's left is dropped, both logged as the protocol anomalies they are
+pub struct MessageSplitter<'a> {
+    frame: &'a str,
+    position: usize
+}
+
+// "~m~<len>~m~" at the start of text, as (header length, payload length)
+fn message_header(text: &str) -> Option<(usize, usize)> {
+    let rest = text.strip_prefix("~m~")?;
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 || !rest[digits..].starts_with("~m~") {
+        return None;
+    }
+    Some((digits + 6, rest[..digits].parse().ok()?))
+}
+
+impl<'a> MessageSplitter<'a> {
+    // where the first header at or after from starts, the end of the frame without one
+    fn next_header(&self, from: usize) -> usize {
+        let mut from = from;
+        while let Some(found) = self.frame[from..].find("~m~") {
+            if message_header(&self.frame[from + found..]).is_some() {
+                return from + found;
+            }
+            from += found + 1;
         }
+        self.frame.len()
+    }
+
+    fn skip_garbage(&mut self) {
+        let first = self.frame[self.position..].chars().next().map_or(1, char::len_utf8);
+        let next = self.next_header(self.position + first);
+        let garbage = &self.frame[self.position..next];
+        if !garbage.trim().is_empty() {
+            warn!("skipping {} bytes between messages: {}", garbage.len(), snippet(garbage));
+        }
+        self.position = next;
     }
 }
 
-impl Error for TradingViewError {}
+impl<'a> Iterator for MessageSplitter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let frame = self.frame;
+        while self.position < frame.len() {
+            let (header, length) = match message_header(&frame[self.position..]) {
+                Some(header) => header,
+                None => {
+                    self.skip_garbage();
+                    continue;
+                }
+            };
+            let start = self.position + header;
+            let end = start + length;
+            let exact = end <= frame.len() && frame.is_char_boundary(end);
+            let payload = if exact && (end == frame.len() || message_header(&frame[end..]).is_some()) {
+                &frame[start..end]
+            } else {
+                let next = self.next_header(start);
+                if frame[start..next].encode_utf16().count() == length {
+                    &frame[start..next]
+                } else if exact && end < next {
+                    // what follows is garbage, skipped on the next call
+                    &frame[start..end]
+                } else {
+                    warn!("dropping a truncated message, {} of {} bytes: {}", next - start, length, snippet(&frame[start..next]));
+                    self.position = next;
+                    continue;
+                }
+            };
+            self.position = start + payload.len();
+            if !payload.trim().is_empty() {
+                return Some(payload);
+            }
+        }
+        None
+    }
+}
+
+pub fn parse_message(message: &str) -> Result<MessageType, Box<dyn Error + Send>> {
+    // ping isn't json
+    if let Some(id) = parse_ping(message) {
+        return Ok(MessageType::Ping(id));
+    }
+    // quote updates are read field by field later, without a full tree; the prefix only spares
+    // the parse of the common compact form, the m match below classifies any other spelling the same
+    if message.starts_with(r#"{"m":"qsd","#) {
+        return Ok(MessageType::QuoteData);
+    }
+    // all else is json?
+    let parsed_message: Value = json::parse(message).map_err(|err| TradingViewError::ParseError {
+        raw: message.to_string(),
+        source: Some(err)
+    })?;
+    if has(&parsed_message, "release") {
+        let server = serde_json::from_value(parsed_message).map_err(|source| TradingViewError::BannerError { raw: message.to_string(), source })?;
+        return Ok(MessageType::ConnectedToServer(server));
+    }
+    match parsed_message.get("m").and_then(Value::as_str).unwrap_or("") {
+        "protocol_error" => return Ok(MessageType::ProtocolError(parsed_message)),
+        "study_error" => return Ok(MessageType::StudyError(parsed_message)),
+        "critical_error" => return Ok(MessageType::CriticalError(parsed_message)),
+        "quote_completed" => return Ok(MessageType::QuoteCompleted(parsed_message)),
+        "series_loading" => return Ok(MessageType::SeriesLoading(parsed_message)),
+        "symbol_resolved" => return Ok(MessageType::SymbolResolved(parsed_message)),
+        "timescale_update" => return Ok(MessageType::TimescaleUpdate(parsed_message)),
+        "series_completed" => return Ok(MessageType::SeriesCompleted(parsed_message)),
+        "study_completed" => return Ok(MessageType::StudyCompleted(parsed_message)),
+        "study_loading" => return Ok(MessageType::StudyLoading(parsed_message)),
+        "symbol_error" => return Ok(MessageType::SymbolError(parsed_message)),
+        "qsd" => return Ok(MessageType::QuoteData),
+        _ => {}
+    }
+    if has_series(&parsed_message) { 
+        return Ok(MessageType::SeriesUpdate(parsed_message));
+    }
+    if has_study(&parsed_message) { 
+        return Ok(MessageType::StudyUpdate(parsed_message));
+    }
+    Err(Box::new(TradingViewError::ParseError {
+        raw: message.to_string(),
+        source: None
+    }))
+}
+
+// one message out of a frame, where it sits in the frame and how long it took to parse
+pub struct ParsedMessage {
+    pub span: Range<usize>,
+    pub parse_time: Duration,
+    pub message_type: Result<MessageType, Box<dyn Error + Send>>
+}
+
+// the frame travels with its messages so they can be logged as received instead of re-serialized
+pub struct ParsedFrame {
+    pub frame: String,
+    pub messages: Vec<ParsedMessage>
+}
+
+pub(crate) fn parse_span(frame: &str, message: &str) -> ParsedMessage {
+    // split hands out slices of the frame, so the pointer difference is the offset
+    let start = message.as_ptr() as usize - frame.as_ptr() as usize;
+    let parse_started = Instant::now();
+    let message_type = parse_message(message);
+    ParsedMessage {
+        span: start..start + message.len(),
+        parse_time: parse_started.elapsed(),
+        message_type
+    }
+}
+
+pub fn parse_frame(frame: String) -> ParsedFrame {
+    let messages = split_messages(&frame).map(|message| parse_span(&frame, message)).collect();
+    ParsedFrame { frame, messages }
+}
+
+// same as parse_frame with the message list in an arena the caller resets every read cycle
+pub fn parse_frame_in<'a>(frame: &str, arena: &'a Bump) -> BumpVec<'a, ParsedMessage> {
+    BumpVec::from_iter_in(split_messages(frame).map(|message| parse_span(frame, message)), arena)
+}
+
+// walks a "p.1.v.lp" style path by reference, json_dotpath allocates a String per segment
+fn lookup<'v>(value: &'v Value, path: &str) -> Option<&'v Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        Value::Object(map) => map.get(key),
+        _ => None,
+    })
+}
+
+fn extra_series_id(interval: &str) -> String {
+    format!("series_id_{}", interval)
+}
+
+// the stream's own series is series_id, extra intervals add series_id_<interval>
+fn has_series(value: &Value) -> bool {
+    match lookup(value, "p.1") {
+        Some(Value::Object(map)) => map.iter().any(|(key, series)| key.starts_with("series_id") && series.get("s").is_some_and(|bars| !bars.is_null())),
+        _ => false,
+    }
+}
+
+// study updates are keyed by study id, the built-in one is study_id
+fn has_study(value: &Value) -> bool {
+    match lookup(value, "p.1") {
+        Some(Value::Object(map)) => map.values().any(|study| study.get("st").is_some_and(|points| !points.is_null())),
+        _ => false,
+    }
+}
+
+// null counts as missing, like dot_has
+fn has(value: &Value, path: &str) -> bool {
+    lookup(value, path).is_some_and(|value| !value.is_null())
+}
+
+// "~m~<len>~m~<payload>", sized up front so the length prefix doesn't reallocate
+pub fn frame_message(payload: &str) -> String {
+    let mut framed = String::with_capacity(payload.len() + FRAME_HEADER_CAPACITY);
+    framed.push_str("~m~");
+    framed.push_str(itoa::Buffer::new().format(payload.len()));
+    framed.push_str("~m~");
+    framed.push_str(payload);
+    framed
+}
+
+// "~h~42" -> 42
+fn parse_ping(message: &str) -> Option<usize> {
+    let id = message.strip_prefix("~h~")?;
+    if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    id.parse().ok()
+}
+
+// the payload and id of a frame holding nothing but a ping, e.g. "~m~4~m~~h~1"
+fn ping_frame_payload(frame: &str) -> Option<(&str, usize)> {
+    let (length, payload) = frame.strip_prefix("~m~")?.split_once("~m~")?;
+    if length.parse::<usize>().ok()? != payload.len() {
+        return None;
+    }
+    parse_ping(payload).map(|id| (payload, id))
+}
+
+// what a critical or protocol error names, as its code before any ':' detail, once the server
+// stops taking the auth token
+const AUTH_ERROR_CODES: [&str; 3] = ["invalid_auth_token", "auth_token_expired", "invalid_token"];
+
+// only those codes, so a symbol or reason that happens to say "token" doesn't refresh it; study
+// errors like study_not_auth are about the plan, which a new token doesn't change
+pub fn is_auth_error(kind: ServerErrorKind, message: &Value) -> bool {
+    if kind == ServerErrorKind::Study {
+        return false;
+    }
+    let params = message.get("p").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+    params.iter().filter_map(Value::as_str).any(|param| {
+        let code = param.split(':').next().unwrap_or(param).trim();
+        AUTH_ERROR_CODES.contains(&code)
+    })
+}
+
+// keeps error messages readable when the offending payload is a huge timescale_update
+fn snippet(raw: &str) -> &str {
+    const MAX_SNIPPET_LENGTH: usize = 200;
+    match raw.char_indices().nth(MAX_SNIPPET_LENGTH) {
+        Some((index, _)) => &raw[..index],
+        None => raw,
+    }
+}
 
 impl From<TradingViewError> for Box<dyn std::error::Error + Send> {
     fn from(error: TradingViewError) -> Self {
@@ -37,13 +357,9 @@ impl From<TradingViewError> for Box<dyn std::error::Error + Send> {
 }
 
 pub enum MessageType {
-    ConnectedToServer(Value),
+    ConnectedToServer(ServerInfo),
     Ping(usize),
     ProtocolError(Value),
-    Empty,
-    QsdBidAsk(Value),
-    QsdDescription(Value),
-    QsdLocalPopularity(Value),
     QuoteCompleted(Value),
     SeriesLoading(Value),
     SymbolResolved(Value),
@@ -55,327 +371,2236 @@ pub enum MessageType {
     StudyLoading(Value),
     SeriesUpdate(Value),
     StudyUpdate(Value),
-    QsdLastPriceTime(Value),
-    QsdLastPrice(Value),
+    SymbolError(Value),
+    // every qsd update, read field by field by QuoteExtractor without a full tree
+    QuoteData,
+}
+
+impl MessageType {
+    // for logs and per-type counters
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MessageType::ConnectedToServer(_) => "connected",
+            MessageType::Ping(_) => "ping",
+            MessageType::ProtocolError(_) => "protocol_error",
+            MessageType::QuoteData => "qsd",
+            MessageType::QuoteCompleted(_) => "quote_completed",
+            MessageType::SeriesLoading(_) => "series_loading",
+            MessageType::SymbolResolved(_) => "symbol_resolved",
+            MessageType::TimescaleUpdate(_) => "timescale_update",
+            MessageType::SeriesCompleted(_) => "series_completed",
+            MessageType::StudyCompleted(_) => "study_completed",
+            MessageType::StudyError(_) => "study_error",
+            MessageType::CriticalError(_) => "critical_error",
+            MessageType::StudyLoading(_) => "study_loading",
+            MessageType::SeriesUpdate(_) | MessageType::StudyUpdate(_) => "du",
+            MessageType::SymbolError(_) => "symbol_error",
+        }
+    }
+
+    // the session the message belongs to, None for connection level ones
+    fn session(&self) -> Option<&'static str> {
+        match *self {
+            MessageType::QuoteData | MessageType::QuoteCompleted(_) => Some(QUOTE_SESSION),
+            MessageType::SeriesLoading(_) | MessageType::SymbolResolved(_) | MessageType::TimescaleUpdate(_)
+            | MessageType::SeriesCompleted(_) | MessageType::StudyCompleted(_) | MessageType::StudyError(_)
+            | MessageType::StudyLoading(_) | MessageType::SeriesUpdate(_) | MessageType::StudyUpdate(_)
+            | MessageType::SymbolError(_) => Some(CHART_SESSION),
+            MessageType::ConnectedToServer(_) | MessageType::Ping(_) | MessageType::ProtocolError(_)
+            | MessageType::CriticalError(_) => None,
+        }
+    }
+
+    // the parsed message, None for those read without a tree
+    fn payload(&self) -> Option<&Value> {
+        match *self {
+            MessageType::ProtocolError(ref message) | MessageType::QuoteCompleted(ref message) | MessageType::SeriesLoading(ref message)
+            | MessageType::SymbolResolved(ref message) | MessageType::TimescaleUpdate(ref message) | MessageType::SeriesCompleted(ref message)
+            | MessageType::StudyCompleted(ref message) | MessageType::StudyError(ref message) | MessageType::CriticalError(ref message)
+            | MessageType::StudyLoading(ref message) | MessageType::SeriesUpdate(ref message) | MessageType::StudyUpdate(ref message)
+            | MessageType::SymbolError(ref message) => Some(message),
+            MessageType::ConnectedToServer(_) | MessageType::Ping(_) | MessageType::QuoteData => None,
+        }
+    }
+}
+
+pub struct HistoryRequest {
+    pub symbol: String,
+    pub interval: String,
+    pub from: i64,
+    pub to: i64,
+    // stop paging once this many bars arrived, however far back from goes
+    pub count: Option<usize>
+}
+
+pub enum Mode {
+    Stream,
+    Download(HistoryRequest),
+    Quotes(Vec<String>)
+}
+
+// sent as Incoming::Command by whoever holds a clone of the incoming sender
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiCommand {
+    // stops quote updates without forgetting the symbols, e.g. while a UI is minimized
+    HibernateQuotes,
+    WakeQuotes,
+    // Mode::Quotes only: subscribes the symbols not yet in the session and drops the rest
+    SetQuoteSymbols(Vec<String>),
+    // replaces ApiOptions::filters for the events from now on
+    SetEventFilters(Vec<EventFilter>),
+    // asks the live quote session for these fields and reports them from now on, replacing
+    // ApiOptions::quote_fields and quote_session_fields without losing the symbols' snapshots
+    SetQuoteFields(Vec<QuoteField>),
+    // deletes the session on the server so it stops sending for it, while the connection and
+    // the other session carry on; it isn't created again when the sessions are rebuilt
+    DeleteSession(SessionKind)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionKind {
+    Quote,
+    Chart
+}
+
+impl SessionKind {
+    pub fn id(self) -> &'static str {
+        match self {
+            SessionKind::Quote => QUOTE_SESSION,
+            SessionKind::Chart => CHART_SESSION,
+        }
+    }
+
+    // the session a server message names in p[0], None for one this client didn't open
+    fn from_id(id: &str) -> Option<SessionKind> {
+        [SessionKind::Quote, SessionKind::Chart].into_iter().find(|kind| kind.id() == id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    // report the error and carry on
+    Ignore,
+    // report the error and tear down the study or session it refers to
+    DropSession,
+    // report the error and stop the handler so the caller can reconnect
+    Reconnect
+}
+
+impl std::str::FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ErrorPolicy, String> {
+        match value {
+            "ignore" => Ok(ErrorPolicy::Ignore),
+            "drop-session" => Ok(ErrorPolicy::DropSession),
+            "reconnect" => Ok(ErrorPolicy::Reconnect),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    // fail on the first unknown or malformed message, for running against fixtures
+    Strict,
+    // log it and carry on
+    Lenient
+}
+
+impl std::str::FromStr for ParseMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ParseMode, String> {
+        match value {
+            "strict" => Ok(ParseMode::Strict),
+            "lenient" => Ok(ParseMode::Lenient),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BacklogPolicy {
+    // wait for the consumer, which in turn stalls the websocket
+    Block,
+    // throw away events until the consumer catches up
+    Drop,
+    // stop the handler
+    Fail,
+    // keep only the latest quote per symbol until the consumer catches up, other events wait
+    // like with Block
+    Conflate
+}
+
+impl std::str::FromStr for BacklogPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<BacklogPolicy, String> {
+        match value {
+            "block" => Ok(BacklogPolicy::Block),
+            "drop" => Ok(BacklogPolicy::Drop),
+            "fail" => Ok(BacklogPolicy::Fail),
+            "conflate" => Ok(BacklogPolicy::Conflate),
+            _ => Err(value.to_string()),
+        }
+    }
+}
+
+// caps what a long-running collector can hold in memory during a burst
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryLimits {
+    pub max_rx_buffer: usize,
+    pub max_cached_bars: usize,
+    pub max_event_backlog: usize,
+    pub backlog_policy: BacklogPolicy
+}
+
+impl Default for MemoryLimits {
+    fn default() -> MemoryLimits {
+        MemoryLimits {
+            max_rx_buffer: 16 * 1024 * 1024,
+            max_cached_bars: 100_000,
+            max_event_backlog: 10_000,
+            backlog_policy: BacklogPolicy::Block
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ApiOptions {
+    pub error_policy: ErrorPolicy,
+    pub parse_mode: ParseMode,
+    pub heartbeat_timeout: Duration,
+    // resolve_symbol, create_series and the like still unanswered after this are reported as timed out
+    pub command_timeout: Duration,
+    pub emit_corrections: bool,
+    pub validate_bars: bool,
+    pub auth: Arc<dyn AuthProvider>,
+    pub limits: MemoryLimits,
+    // lets panics in event callbacks and sinks unwind instead of being contained
+    pub propagate_panics: bool,
+    pub stats: Arc<Stats>,
+    // which connections are up and how recently their sessions heard from the server
+    pub health: Arc<Health>,
+    pub checkpoints: Arc<Checkpoints>,
+    // quote fields to report as Quote events, none by default
+    pub quote_fields: Vec<QuoteField>,
+    // what the quote session asks the server for, every field when empty; fewer fields make for
+    // less traffic on large watchlists
+    pub quote_session_fields: Vec<QuoteField>,
+    // quote fields to report as a QuoteFieldChanged event when they move, whether or not
+    // they're among quote_fields
+    pub quote_changes: Vec<QuoteField>,
+    // parses off the api thread, shared between connections
+    pub parse_pool: Option<Arc<ParsePool>>,
+    // chart style applied to the series of every chart session
+    pub series: SeriesOptions,
+    // studies added to the stream's chart, parents before the studies fed from them
+    pub studies: Vec<StudySpec>,
+    // earnings, dividend and split markers for the stream's symbol as CorporateEvents
+    pub corporate_events: bool,
+    // the server to connect to, WidgetData only works with Mode::Quotes
+    pub endpoint: Endpoint,
+    // a tick series next to the stream's bars, reported as Trade events
+    pub trades: bool,
+    // computed from the stream's cached bars and reported like a study
+    pub local_indicators: Vec<Indicator>,
+    // higher timeframes built from the stream's bars, e.g. "5", "60", "D", reported as BarClosed
+    pub resample_intervals: Vec<String>,
+    // vwap, volume and high/low of the stream's session and from each anchor on
+    pub session_stats: Arc<SessionStats>,
+    pub session_anchors: Vec<i64>,
+    // also report them as SessionStats events this often
+    pub session_stats_every: Option<Duration>,
+    // reports the connection's message, byte and per-symbol update rates this often as Stats events
+    pub stats_every: Option<Duration>,
+    // polls headlines for the stream or quote symbols, reported as NewsItem events; see
+    // news::poller, it outlives the connections made with these options
+    pub news: Option<Arc<Poller>>,
+    // economic releases, reported as EconomicEvent events; see calendar::poller, it outlives the
+    // connections made with these options
+    pub calendar: Option<Arc<Poller>>,
+    // every symbol_resolved is stored here for later runs and offline tools
+    pub symbol_cache: Option<Arc<SymbolCache>>,
+    // messages no MessageType matches are written here instead of only being logged
+    pub unknown_capture: Option<Arc<UnknownCapture>>,
+    // applied by the websocket side to what the server sends, for testing the recovery paths
+    pub faults: Option<FaultConfig>,
+    // what the websocket does with binary and unknown frames, websocket::DefaultFrameHandler
+    // when None
+    pub frame_handler: Option<Arc<dyn FrameHandler>>,
+    // the application's tickers for TradingView symbols, resolved going in and restored in events
+    pub aliases: Option<Arc<SymbolAliases>>,
+    // what consumers want per symbol, applied before events are queued, see filter::FilterChain
+    pub filters: Vec<EventFilter>,
+    // skew of the local clock against the server's, estimated from every connection's timestamps
+    pub clock: Arc<ClockSkew>,
+    // also report the estimate as ClockSkew events
+    pub clock_events: bool,
+    // set local_time_ms on Quote and Trade events from the estimate
+    pub normalize_times: bool,
+    // how the server's pings are answered
+    pub ping_reply: PingReply,
+    // checks the server's messages against what the connection asked for, see invariants::Violation
+    pub invariants: InvariantMode,
+    // the banner of the last connection, shared like stats
+    pub server_info: Arc<Mutex<Option<ServerInfo>>>,
+    // the sessions Mode::Stream opens on every connect instead of its built-in ones
+    pub connect_script: Option<ConnectScript>,
+    // carried by the Quote and Bars events of the mode's symbols and the CommandResults of the
+    // messages the connection sends on its own; Connection::open sets a fresh one
+    pub correlation_id: Option<CorrelationId>
+}
+
+impl Default for ApiOptions {
+    fn default() -> ApiOptions {
+        ApiOptions {
+            error_policy: ErrorPolicy::DropSession,
+            parse_mode: ParseMode::Lenient,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            command_timeout: ack::DEFAULT_COMMAND_TIMEOUT,
+            emit_corrections: false,
+            validate_bars: false,
+            auth: Arc::new(Anonymous),
+            limits: MemoryLimits::default(),
+            propagate_panics: false,
+            stats: Arc::default(),
+            health: Arc::default(),
+            checkpoints: Arc::default(),
+            quote_fields: vec![],
+            quote_session_fields: vec![],
+            quote_changes: vec![],
+            parse_pool: None,
+            series: SeriesOptions::default(),
+            studies: vec![],
+            corporate_events: false,
+            endpoint: Endpoint::default(),
+            trades: false,
+            local_indicators: vec![],
+            resample_intervals: vec![],
+            session_stats: Arc::default(),
+            session_anchors: vec![],
+            session_stats_every: None,
+            stats_every: None,
+            news: None,
+            calendar: None,
+            symbol_cache: None,
+            unknown_capture: None,
+            faults: None,
+            frame_handler: None,
+            ping_reply: PingReply::Immediate,
+            invariants: InvariantMode::Off,
+            server_info: Arc::default(),
+            aliases: None,
+            filters: vec![],
+            clock: Arc::default(),
+            clock_events: false,
+            normalize_times: false,
+            connect_script: None,
+            correlation_id: None
+        }
+    }
+}
+
+// bars missing between the last one seen before a reconnect and the first one after it
+struct Backfill {
+    after: i64,
+    before: i64,
+    bars: Vec<Bar>
+}
+
+impl Backfill {
+    // keeps the bars that fall into the gap and returns the rest
+    fn take_gap(&mut self, bars: Vec<Bar>) -> Vec<Bar> {
+        let (gap_bars, new_bars): (Vec<Bar>, Vec<Bar>) = bars.into_iter()
+            .filter(|bar| bar.time > self.after)
+            .partition(|bar| bar.time < self.before);
+        self.bars.extend(gap_bars);
+        new_bars
+    }
+}
+
+// another resolution of the chart symbol, validated, backfilled and checkpointed like the chart interval
+struct ExtraSeries {
+    interval: String,
+    candles: CandleCache,
+    validator: Option<BarValidator>,
+    gap_checked: bool,
+    backfill: Option<Backfill>,
+    oldest_bar_time: Option<i64>,
+    received_older_bars: bool
+}
+
+// the chart interval checkpoints under the bare symbol, which older state files also use
+fn checkpoint_key(symbol: &str, interval: &str) -> String {
+    format!("{}@{}", symbol, interval)
+}
+
+// bars of the old contracts that still come after a stitch, older history pages or the history
+// sent again after a reconnect, go where the stitched ones went
+fn shift_stitched(checkpoints: &Checkpoints, symbol: &str, bars: &mut [Bar]) {
+    for bar in bars {
+        let offset = checkpoints.stitch_offset(symbol, bar.time);
+        if offset != 0.0 {
+            bar.shift(offset);
+        }
+    }
 }
 
 pub struct TradingViewApi {
-   incoming_rx: Receiver<String>,
-   outgoing_tx: Sender<Vec<String>> 
+   incoming_rx: Receiver<Incoming>,
+   outgoing_tx: Sender<Outgoing>,
+   events_tx: SyncSender<Arc<Event>>,
+   mode: Mode,
+   options: ApiOptions,
+   oldest_bar_time: Option<i64>,
+   received_older_bars: bool,
+   history_bars: usize,
+   pending_rolls: HashSet<String>,
+   gap_checked: bool,
+   backfill: Option<Backfill>,
+   candles: CandleCache,
+   // (interval, cache) for the series the stream follows besides its own
+   extra_series: Vec<ExtraSeries>,
+   studies: StudyGraph,
+   ticks: Option<TickTracker>,
+   resampler: Option<Resampler>,
+   validator: Option<BarValidator>,
+   auth_token: String,
+   scratch: RefCell<Vec<u8>>,
+   // filled as messages are formatted, which only needs &self
+   acks: RefCell<AckTracker>,
+   // of the ApiCommand being handled, for the messages and events it leads to, otherwise
+   // ApiOptions::correlation_id
+   correlation_id: Option<CorrelationId>,
+   // the subscription each symbol was asked for by, for its Quote and Bars events
+   subscriptions: HashMap<String, CorrelationId>,
+   filters: RefCell<FilterChain>,
+   // quotes held back by BacklogPolicy::Conflate
+   conflation: RefCell<QuoteConflation>,
+   // skew_ms of the last ClockSkew event
+   reported_skew: Option<i64>,
+   keepalive: Keepalive,
+   invariants: InvariantChecker,
+   // through ApiCommand::DeleteSession
+   deleted_sessions: Vec<SessionKind>,
+   // from the connect banner, None until it came
+   server: Option<ServerInfo>,
+   last_reauth: Option<Instant>,
+   rejected_symbols: Vec<(String, String)>,
+   parser: Option<ParseSession>,
+   quote_extractor: QuoteExtractor,
+   // the last value of each field per symbol, kept for quote_changes
+   last_quotes: HashMap<String, QuoteData>,
+   // when the frame being handled came off the socket
+   frame_received: Option<Instant>,
+   session_stats_due: Option<Instant>,
+   // traffic since the last Stats event, counted only with ApiOptions::stats_every
+   rates: RefCell<RateMeter>,
+   stats_due: Option<Instant>,
+   // this connection's symbols in the client's news poller, kept in step with SetQuoteSymbols
+   news: Option<PollerHandle>,
+   // this connection in the client's calendar poller, held until the api is dropped
+   _calendar: Option<PollerHandle>,
+   // from the server's hello on, dropped with the api
+   health: Option<HealthConnection>
+}
+
+// the stream's chart symbol and interval, None when its connect script only opens quotes
+fn stream_chart(options: &ApiOptions) -> Option<(&str, &str)> {
+    match options.connect_script {
+        Some(ref script) => script.chart.as_ref().map(|chart| (chart.symbol.as_str(), chart.interval.as_str())),
+        None => Some((STREAM_SYMBOL, STREAM_INTERVAL)),
+    }
+}
+
+// what qsd messages are read for, the reported fields and the watched ones
+fn quote_extractor(options: &ApiOptions) -> QuoteExtractor {
+    let mut fields = options.quote_fields.clone();
+    fields.extend(options.quote_changes.iter().filter(|field| !options.quote_fields.contains(field)));
+    QuoteExtractor::new(&fields)
+}
+
+// drops the fields the session's token can't have, the server would never send them
+fn refuse_gated_fields(fields: &mut Vec<QuoteField>, access: AccessLevel) {
+    let refused: Vec<&str> = fields.iter().filter(|field| !access.allows(**field)).map(QuoteField::as_str).collect();
+    if !refused.is_empty() {
+        warn!("quote fields {} need an authenticated session, not requested", refused.join(","));
+    }
+    fields.retain(|field| access.allows(*field));
 }
 
 impl TradingViewApi {
-    pub fn new(incoming_rx: Receiver<String>, outgoing_tx: Sender<Vec<String>>) -> Result<TradingViewApi, Box<dyn Error + Send>> {
+    pub fn new(incoming_rx: Receiver<Incoming>, outgoing_tx: Sender<Outgoing>, events_tx: SyncSender<Arc<Event>>, mode: Mode, mut options: ApiOptions) -> Result<TradingViewApi, Box<dyn Error + Send>> {
+        let aliases = options.aliases.clone();
+        let resolve = |symbol: String| match aliases {
+            Some(ref aliases) => aliases.resolve(&symbol),
+            None => symbol,
+        };
+        // bad symbols are reported once connected instead of failing the whole batch
+        let mut rejected_symbols = vec![];
+        let mode = match mode {
+            Mode::Download(request) => Mode::Download(HistoryRequest { symbol: resolve(request.symbol), ..request }),
+            Mode::Quotes(symbols) => Mode::Quotes(symbols.into_iter().map(resolve).filter(|symbol| match symbol::validate(symbol) {
+                Ok(()) => true,
+                Err(err) => {
+                    rejected_symbols.push((symbol.clone(), err.to_string()));
+                    false
+                }
+            }).collect()),
+            mode => mode,
+        };
+        if let (Mode::Stream, Some(script)) = (&mode, options.connect_script.as_mut()) {
+            let mut valid = |symbol: &String| match symbol::validate(symbol) {
+                Ok(()) => true,
+                Err(err) => {
+                    rejected_symbols.push((symbol.clone(), err.to_string()));
+                    false
+                }
+            };
+            script.quote_symbols = std::mem::take(&mut script.quote_symbols).into_iter().map(resolve).filter(&mut valid).collect();
+            if let Some(ref mut chart) = script.chart {
+                chart.symbol = resolve(std::mem::take(&mut chart.symbol));
+            }
+            script.chart = script.chart.take().filter(|chart| valid(&chart.symbol));
+        }
+        let validate_bars = options.validate_bars && options.series.style.is_time_based();
+        // symbol and interval of the stream's chart
+        let stream = match mode {
+            Mode::Stream => stream_chart(&options).map(|(symbol, interval)| (symbol.to_string(), interval.to_string())),
+            _ => None,
+        };
+        let validator = match mode {
+            Mode::Stream if validate_bars => stream.as_ref().map(|(_, interval)| BarValidator::new(interval)),
+            Mode::Download(ref request) if validate_bars => Some(BarValidator::new(&request.interval)),
+            _ => None,
+        };
+        let auth_token = options.auth.token()?;
+        let access = AccessLevel::of_token(&auth_token);
+        refuse_gated_fields(&mut options.quote_fields, access);
+        refuse_gated_fields(&mut options.quote_changes, access);
+        refuse_gated_fields(&mut options.quote_session_fields, access);
+        if let Some(ref mut script) = options.connect_script {
+            refuse_gated_fields(&mut script.quote_fields, access);
+        }
+        let candles = CandleCache::new(options.limits.max_cached_bars);
+        if options.endpoint == Endpoint::WidgetData && !matches!(mode, Mode::Quotes(_)) {
+            return Err(TradingViewError::EndpointMismatch.into());
+        }
+        let studies = match (&stream, &options.connect_script) {
+            (Some(_), Some(ConnectScript { chart: Some(ref chart), .. })) => {
+                let specs: Vec<StudySpec> = options.studies.iter().chain(chart.studies.iter()).cloned().collect();
+                StudyGraph::new(&specs).map_err(TradingViewError::from)?
+            }
+            (Some(_), _) => StudyGraph::new(&options.studies).map_err(TradingViewError::from)?,
+            (None, _) => StudyGraph::default(),
+        };
+        let ticks = match stream {
+            Some(_) if options.trades => Some(TickTracker::default()),
+            _ => None,
+        };
+        // renko and the like have no fixed bar length to add up
+        let resampler = match stream {
+            Some((_, ref interval)) if !options.resample_intervals.is_empty() && options.series.style.is_time_based() =>
+                Some(Resampler::new(interval, &options.resample_intervals).map_err(TradingViewError::from)?),
+            _ => None,
+        };
+        let mut extra_series: Vec<ExtraSeries> = vec![];
+        if let Some((ref symbol, ref stream_interval)) = stream {
+            for interval in options.series.extra_intervals.iter() {
+                if interval != stream_interval && !extra_series.iter().any(|series| series.interval == *interval) {
+                    extra_series.push(ExtraSeries {
+                        interval: interval.clone(),
+                        candles: CandleCache::new(options.limits.max_cached_bars),
+                        validator: validate_bars.then(|| BarValidator::new(interval)),
+                        gap_checked: false,
+                        backfill: None,
+                        oldest_bar_time: None,
+                        received_older_bars: false
+                    });
+                }
+            }
+            for anchor in options.session_anchors.iter() {
+                options.session_stats.add_anchor(symbol, *anchor);
+            }
+        }
+        let options_session_stats_due = match mode {
+            Mode::Stream => options.session_stats_every.map(|every| Instant::now() + every),
+            _ => None,
+        };
+        let stats_due = options.stats_every.map(|every| Instant::now() + every);
+        let news_symbols = match (&mode, &options.connect_script) {
+            (Mode::Stream, Some(script)) => {
+                let mut symbols: Vec<String> = stream.iter().map(|(symbol, _)| symbol.clone()).collect();
+                for symbol in script.quote_symbols.iter() {
+                    if !symbols.contains(symbol) {
+                        symbols.push(symbol.clone());
+                    }
+                }
+                symbols
+            }
+            (Mode::Stream, None) => vec![STREAM_SYMBOL.to_string()],
+            (Mode::Quotes(symbols), _) => symbols.clone(),
+            (Mode::Download(_), _) => vec![],
+        };
+        let news = match options.news {
+            Some(ref poller) if !matches!(mode, Mode::Download(_)) => Some(poller.attach(events_tx.clone(), news_symbols)),
+            _ => None,
+        };
+        let calendar = match options.calendar {
+            Some(ref poller) if !matches!(mode, Mode::Download(_)) => Some(poller.attach(events_tx.clone(), vec![])),
+            _ => None,
+        };
+        let mode_symbols: Vec<&String> = match mode {
+            Mode::Stream => stream.iter().map(|(symbol, _)| symbol).chain(options.connect_script.iter().flat_map(|script| script.quote_symbols.iter())).collect(),
+            Mode::Download(ref request) => vec![&request.symbol],
+            Mode::Quotes(ref symbols) => symbols.iter().collect(),
+        };
+        let subscriptions: HashMap<String, CorrelationId> = match options.correlation_id {
+            Some(correlation_id) => mode_symbols.into_iter().map(|symbol| (symbol.clone(), correlation_id)).collect(),
+            None => HashMap::new(),
+        };
+        let correlation_id = options.correlation_id;
+        let parser = options.parse_pool.as_ref().map(|pool| pool.session());
+        let quote_extractor = quote_extractor(&options);
+        let keepalive = Keepalive::new(options.ping_reply);
+        let invariants = InvariantChecker::new(options.invariants);
+        let acks = RefCell::new(AckTracker::new(options.command_timeout));
+        let filters = RefCell::new(FilterChain::new(options.filters.clone()));
         Ok(TradingViewApi {
             incoming_rx,
-            outgoing_tx
+            outgoing_tx,
+            events_tx,
+            mode,
+            options,
+            oldest_bar_time: None,
+            received_older_bars: false,
+            history_bars: 0,
+            pending_rolls: HashSet::new(),
+            gap_checked: false,
+            backfill: None,
+            candles,
+            extra_series,
+            studies,
+            ticks,
+            resampler,
+            validator,
+            auth_token,
+            scratch: RefCell::new(Vec::with_capacity(4096)),
+            acks,
+            correlation_id,
+            subscriptions,
+            filters,
+            conflation: RefCell::new(QuoteConflation::default()),
+            reported_skew: None,
+            keepalive,
+            invariants,
+            deleted_sessions: vec![],
+            server: None,
+            last_reauth: None,
+            rejected_symbols,
+            parser,
+            quote_extractor,
+            last_quotes: HashMap::new(),
+            frame_received: None,
+            session_stats_due: options_session_stats_due,
+            rates: RefCell::new(RateMeter::new(Instant::now())),
+            stats_due,
+            news,
+            _calendar: calendar,
+            health: None
         })
     }
 
-    fn determine_incoming_message_type(&self, message: &str) -> Result<MessageType, Box<dyn Error + Send>> {
-        // ping isn't json
-        let ping_re = regex::Regex::new(r"~h~(\d+)").expect("failed to compile regex");
-        if ping_re.is_match(message) {
-            let captures = ping_re.captures(message).expect("failed to capture");
-            let id_string = captures.get(1).expect("id capture missing").as_str();
-            let id = id_string.parse::<usize>().expect("failed to parse");
-            return Ok(MessageType::Ping(id));
+    fn interval(&self) -> &str {
+        match self.mode {
+            Mode::Stream => stream_chart(&self.options).map_or("", |(_, interval)| interval),
+            Mode::Download(ref request) => &request.interval,
+            Mode::Quotes(_) => "",
         }
-        // watch out for empty
-        if message.len() == 0 {
-            return Ok(MessageType::Empty);
+    }
+
+    // true when the message was valid json that no MessageType matches and it went to the capture file
+    fn capture_unknown(&self, raw: &str, err: &(dyn Error + Send + 'static)) -> bool {
+        let capture = match self.options.unknown_capture {
+            Some(ref capture) => capture,
+            None => return false,
+        };
+        if !matches!(err.downcast_ref::<TradingViewError>(), Some(TradingViewError::ParseError { source: None, .. })) {
+            return false;
         }
-        // all else is json?
-        let parsed_message: Value = serde_json::from_str(&message).expect("failed to parse");
-        if parsed_message.dot_has("release") {
-            return Ok(MessageType::ConnectedToServer(parsed_message));
+        match capture.capture(raw, self.symbol()) {
+            Ok(()) => {
+                debug!("captured unknown message to {}", capture.path().display());
+                true
+            },
+            Err(err) => {
+                warn!("{}", err);
+                false
+            }
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "protocol_error" { 
-            return Ok(MessageType::ProtocolError(parsed_message));
+    }
+
+    fn symbol(&self) -> &str {
+        match self.mode {
+            Mode::Stream => match stream_chart(&self.options) {
+                Some((symbol, _)) => symbol,
+                None => self.quote_symbols().first().copied().unwrap_or(""),
+            },
+            Mode::Download(ref request) => &request.symbol,
+            Mode::Quotes(ref symbols) => symbols.first().map_or("", |symbol| symbol.as_str()),
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "study_error" { 
-            return Ok(MessageType::StudyError(parsed_message));
+    }
+
+    // serializes into a reused scratch buffer so the framed string is the only allocation
+    fn format_json_response(&self, message: Value) -> String {
+        self.acks.borrow_mut().sent(&message, self.correlation_id, Instant::now());
+        let mut scratch = self.scratch.borrow_mut();
+        scratch.clear();
+        serde_json::to_writer(&mut *scratch, &message).expect("failed to serialize");
+        frame_message(std::str::from_utf8(&scratch).expect("serde_json writes utf-8"))
+    }
+
+    fn stream_bootstrap_messages(&self) -> Vec<String> {
+        if let Some(ref script) = self.options.connect_script {
+            return self.script_bootstrap_messages(script);
+        }
+        let mut messages = vec![];
+        // login
+        messages.push(self.format_json_response(serde_json::json!({
+            "m": "set_auth_token",
+            "p": [
+                self.auth_token
+            ]
+        })));
+        if self.has_session(SessionKind::Quote) {
+            // create quote
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "quote_create_session",
+                "p": [
+                    "quote_session_id",
+                ]
+            })));
+            messages.push(self.quote_set_fields_message());
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "quote_add_symbols",
+                "p": [
+                    "quote_session_id",
+                    r#"={"session":"regular", "symbol": "CRYPTO:BTCUSD"}"#
+                ]
+            })));
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "quote_fast_symbols",
+                "p": [
+                    "quote_session_id",
+                    "INDEX:BTCUSD"
+                ]
+            })));
+        }
+        if self.has_session(SessionKind::Chart) {
+            messages.extend(self.chart_session_messages(STREAM_SYMBOL, STREAM_INTERVAL, connect_script::DEFAULT_CHART_BARS));
+            if self.studies_supported() {
+                messages.push(self.stream_study_message());
+            }
+        }
+        messages
+    }
+
+    // the indicator the stream adds to its chart
+    fn stream_study_message(&self) -> String {
+        self.format_json_response(serde_json::json!({
+            "m": "create_study",
+            "p": [
+                "chart_session_id",
+                "study_id",
+                "study_parent_id",
+                "series_id",
+                "Script@tv-scripting-101!",
+                serde_json::json!({
+                    "text": "OvVf/cLhRZ8QR5Vpxqne7w==_pKuthoDJLaA6sn40TmHddOk0SwJb9ct8cm5JeGz0a5O4YBeoFgtEgyKwwKcVk+KQMJV96wVs+ms71b8+nds3580VFsC3U3MQvGaF+Xidbsm/vP9HK+rGeR/2iTxMfDT+sRSuAcY4mm/u9CPgHlc/1U5QoLL0+qSxw6spC2g33HJDdjZkWojBpa50yH0oELcUqVKNbKFX/RFReEzTqpc0Moo10cw8IVnBIp5Fu1SPEM2AIASQaI58LmwDyNdo2d/Rqn3u7JyRqt+TYu+asL9NynYoLVtTem2BonOTknu7NoBkQI9GJgMdxE4+jU9efxZk8jOGgP9XQPWAhX5jmZJDefGl1s2c/09TM29lPzUTFJRyyfmtZShBdiP3BqRfYXzEr6vCNetnsebCenWWkQtDjQ80ZgBV+HB8rciWhB34jXZ/MA8sGtT1lbknJbX5koliQ/pDj4tYY3Mp6eon+jvVDO6EyxTNk/9tj5h8b1Jdqy1svNAfr5MF3TfksELRGkzKFLxPNQUZz+Cn60T7vP/Qi+HDM/mfwdiYkaLXSXDQ6VkDc+K8vxJkYWRWONghVnzbeqhCYn747OB0u0xWxs1O+D0KjRq9CEjgsRLmMDqg2KLrdGRGrEpNjwy6jb31SXDQLR+IdKgSD/O71iNXXcd3KGdDXQpi0c70NuaKdUEGWIpBRjp6tFOTGp8yJHkwFJPkic9yGVQRMbqTctqbGHbaxVNvbhZdnhkl2bkTh7wkDXsYjxt2jTtAYlwq6RoJmzlKBBj2VR894emRQyipvvAz6bjxnQZC8zqxR/BF7HnzLtVMIMr+0nE0Ol0TDDkpkMsAiM5zH4212LNyOU4obRzYhwCuOR8L+W3/+fDhOHg+tSseK+d4QrFkn+qFsVHqEpeVoyIQDm1wwHsFiqN6by4Du4LtxHMRuasSzajwmxQNOe+qbbALRtpiVMFL/BVdH0bk0r43mnMC3s9CHcDB2CMCk4TjZZwNfWmQVQGqprukCQJFtqNY+SnK26rYby9/a2WnbnRW6lLcazUfwQHf6wPHfLLlNYiAayuUsPZyNZGnwvBkFZK6GG2eYZYam2XurXk2uMZRusQVuw6nDPk1R6CKg+KILriNHp2b2TM2zb4jogmbrqug3nqGky8oM9n/1lIsht+Jm8GztD99g2j/7crHI6DgZ3Bu8LKdmm7t+cnsPBLLNncdnbQhow1WZTffmi0=",
+                    "pineId": "PUB;N16MOYK6AEJGGAoy40axs0S48GRFYcNn",
+                    "pineVersion": "1.0",
+                    "in_0": {
+                        "v": 1,
+                        "f": true,
+                        "t": "integer"
+                    },
+                    "in_1": {
+                        "v": "close",
+                        "f": true,
+                        "t": "source"
+                    },
+                    "in_2": {
+                        "v": 7,
+                        "f": true,
+                        "t": "integer"
+                    },
+                    "in_3": {
+                        "v": "close",
+                        "f": true,
+                        "t": "source"
+                    },
+                    "in_4": {
+                        "v": 25,
+                        "f": true,
+                        "t": "integer"
+                    },
+                    "in_5": {
+                        "v": 65,
+                        "f": true,
+                        "t": "integer"
+                    },
+                    "in_6": {
+                        "v": 51,
+                        "f": true,
+                        "t": "integer"
+                    },
+                    "in_7": {
+                        "v": 21,
+                        "f": true,
+                        "t": "integer"
+                    }
+                    })
+            ]
+        }))
+    }
+
+    // until the banner says otherwise
+    fn studies_supported(&self) -> bool {
+        self.server.as_ref().is_none_or(ServerInfo::supports_studies)
+    }
+
+    // the chart session for the stream's symbol with everything ApiOptions adds to it
+    fn chart_session_messages(&self, symbol: &str, interval: &str, bars: usize) -> Vec<String> {
+        let mut messages = vec![];
+        // create chart
+        messages.push(self.format_json_response(serde_json::json!({
+            "m": "chart_create_session",
+            "p": [
+                "chart_session_id",
+                ""
+            ]
+        })));
+        // add symbol to chart
+        messages.push(self.format_json_response(serde_json::json!({
+            "m": "resolve_symbol",
+            "p": [
+                "chart_session_id",
+                "symbol_id",
+                self.options.series.symbol_spec(symbol, serde_json::json!({"session": "regular"}))
+            ]
+        })));
+        // add candles to chart
+        messages.push(self.format_json_response(serde_json::json!({
+            "m": "create_series",
+            "p": [
+                "chart_session_id",
+                "series_id",
+                "study_parent_id",
+                "symbol_id",
+                interval,
+                bars,
+                ""
+            ]
+        })));
+        // one bar per trade, only a few back since trades before connecting are of little use
+        if self.ticks.is_some() {
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "create_series",
+                "p": [
+                    "chart_session_id",
+                    TICK_SERIES_ID,
+                    "study_parent_id_ticks",
+                    "symbol_id",
+                    trade::TICK_INTERVAL,
+                    10,
+                    ""
+                ]
+            })));
+        }
+        // more resolutions of the same symbol
+        for ExtraSeries { interval, .. } in self.extra_series.iter() {
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "create_series",
+                "p": [
+                    "chart_session_id",
+                    extra_series_id(interval),
+                    format!("study_parent_id_{}", interval),
+                    "symbol_id",
+                    interval,
+                    300,
+                    ""
+                ]
+            })));
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "critical_error" { 
-            return Ok(MessageType::CriticalError(parsed_message));
+        // a server without a study engine would only answer with study errors
+        if !self.studies_supported() {
+            return messages;
+        }
+        if self.options.corporate_events {
+            for kind in EsdKind::ALL {
+                messages.push(self.format_json_response(serde_json::json!({
+                    "m": "create_study",
+                    "p": [
+                        "chart_session_id",
+                        kind.study_id(),
+                        "study_parent_id",
+                        "series_id",
+                        kind.script(),
+                        {}
+                    ]
+                })));
+            }
+        }
+        for study in self.studies.studies() {
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "create_study",
+                "p": [
+                    "chart_session_id",
+                    study.id,
+                    "study_parent_id",
+                    study.parent.as_deref().unwrap_or("series_id"),
+                    study.script,
+                    study.inputs
+                ]
+            })));
+        }
+        messages
+    }
+
+    fn script_bootstrap_messages(&self, script: &ConnectScript) -> Vec<String> {
+        // login
+        let mut messages = vec![self.format_json_response(serde_json::json!({
+            "m": "set_auth_token",
+            "p": [
+                self.auth_token
+            ]
+        }))];
+        if self.has_session(SessionKind::Quote) {
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "quote_create_session",
+                "p": [
+                    "quote_session_id",
+                ]
+            })));
+            messages.push(self.quote_set_fields_message());
+            messages.extend(self.quote_symbol_messages("quote_add_symbols", &self.quote_symbols()));
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "quote_completed" { 
-            return Ok(MessageType::QuoteCompleted(parsed_message));
+        match script.chart {
+            Some(ref chart) if self.has_session(SessionKind::Chart) => messages.extend(self.chart_session_messages(&chart.symbol, &chart.interval, chart.bars)),
+            _ => {}
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "series_loading" { 
-            return Ok(MessageType::SeriesLoading(parsed_message));
+        messages
+    }
+
+    fn download_bootstrap_messages(&self, request: &HistoryRequest) -> Vec<String> {
+        // login
+        let login = self.format_json_response(serde_json::json!({
+            "m": "set_auth_token",
+            "p": [
+                self.auth_token
+            ]
+        }));
+        if !self.has_session(SessionKind::Chart) {
+            return vec![login];
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "symbol_resolved" { 
-            return Ok(MessageType::SymbolResolved(parsed_message));
+        vec![
+            login,
+            // create chart
+            self.format_json_response(serde_json::json!({
+                "m": "chart_create_session",
+                "p": [
+                    "chart_session_id",
+                    ""
+                ]
+            })),
+            // add symbol to chart
+            self.format_json_response(serde_json::json!({
+                "m": "resolve_symbol",
+                "p": [
+                    "chart_session_id",
+                    "symbol_id",
+                    self.options.series.symbol_spec(&request.symbol, serde_json::json!({"adjustment": "splits"}))
+                ]
+            })),
+            // add candles to chart
+            self.format_json_response(serde_json::json!({
+                "m": "create_series",
+                "p": [
+                    "chart_session_id",
+                    "series_id",
+                    "study_parent_id",
+                    "symbol_id",
+                    request.interval,
+                    request.count.map_or(HISTORY_BATCH_SIZE, |count| count.clamp(1, HISTORY_BATCH_SIZE)),
+                    ""
+                ]
+            }))
+        ]
+    }
+
+    fn quotes_bootstrap_messages(&self, symbols: &[String]) -> Vec<String> {
+        let mut messages = vec![];
+        // login; widgets don't have accounts and announce their locale instead
+        if self.options.endpoint == Endpoint::WidgetData {
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "set_auth_token",
+                "p": [
+                    "widget_user_token"
+                ]
+            })));
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "set_locale",
+                "p": [
+                    "en",
+                    "US"
+                ]
+            })));
+        } else {
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "set_auth_token",
+                "p": [
+                    self.auth_token
+                ]
+            })));
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "timescale_update" { 
-            return Ok(MessageType::TimescaleUpdate(parsed_message));
+        if self.has_session(SessionKind::Quote) {
+            // create quote
+            messages.push(self.format_json_response(serde_json::json!({
+                "m": "quote_create_session",
+                "p": [
+                    "quote_session_id",
+                ]
+            })));
+            messages.push(self.quote_set_fields_message());
+            // add symbols in chunks to keep individual frames small
+            for chunk in symbols.chunks(QUOTE_SYMBOLS_PER_MESSAGE) {
+                let mut add_symbols = vec![Value::from("quote_session_id")];
+                add_symbols.extend(chunk.iter().map(|symbol| Value::from(symbol.as_str())));
+                messages.push(self.format_json_response(serde_json::json!({
+                    "m": "quote_add_symbols",
+                    "p": add_symbols
+                })));
+            }
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "series_completed" { 
-            return Ok(MessageType::SeriesCompleted(parsed_message));
+        messages
+    }
+
+    pub fn bootstrap_messages(&self) -> Vec<String> {
+        match self.mode {
+            Mode::Stream => self.stream_bootstrap_messages(),
+            Mode::Download(ref request) => self.download_bootstrap_messages(request),
+            Mode::Quotes(ref symbols) => self.quotes_bootstrap_messages(symbols),
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "study_completed" { 
-            return Ok(MessageType::StudyCompleted(parsed_message));
+    }
+
+    fn quote_symbols(&self) -> Vec<&str> {
+        match (&self.mode, &self.options.connect_script) {
+            (Mode::Stream, Some(script)) => script.quote_symbols.iter().map(String::as_str).collect(),
+            (Mode::Stream, None) => vec![STREAM_SYMBOL],
+            (Mode::Quotes(symbols), _) => symbols.iter().map(String::as_str).collect(),
+            (Mode::Download(_), _) => vec![],
         }
-        if parsed_message.dot_has("m") && parsed_message.dot_get::<String>("m").unwrap().unwrap() == "study_loading" { 
-            return Ok(MessageType::StudyLoading(parsed_message));
+    }
+
+    // a connect script's fields win over ApiOptions::quote_session_fields, none means all the
+    // token may have
+    fn quote_session_fields(&self) -> Vec<QuoteField> {
+        let fields = match self.options.connect_script {
+            Some(ref script) if !script.quote_fields.is_empty() => &script.quote_fields,
+            _ => &self.options.quote_session_fields,
+        };
+        let fields = if fields.is_empty() { &QuoteField::ALL[..] } else { &fields[..] };
+        let access = AccessLevel::of_token(&self.auth_token);
+        fields.iter().copied().filter(|field| access.allows(*field)).collect()
+    }
+
+    fn quote_set_fields_message(&self) -> String {
+        let mut set_fields = vec![Value::from("quote_session_id")];
+        set_fields.extend(self.quote_session_fields().iter().map(|field| Value::from(field.as_str())));
+        self.format_json_response(serde_json::json!({
+            "m": "quote_set_fields",
+            "p": set_fields
+        }))
+    }
+
+    // quote_add_symbols or quote_remove_symbols for the symbols, in frames of QUOTE_SYMBOLS_PER_MESSAGE
+    fn quote_symbol_messages(&self, method: &str, symbols: &[&str]) -> Vec<String> {
+        symbols.chunks(QUOTE_SYMBOLS_PER_MESSAGE).map(|chunk| {
+            let mut params = vec![Value::from("quote_session_id")];
+            params.extend(chunk.iter().copied().map(Value::from));
+            self.format_json_response(serde_json::json!({
+                "m": method,
+                "p": params
+            }))
+        }).collect()
+    }
+
+    fn set_quote_symbols(&mut self, symbols: &[String]) -> Result<Vec<String>, Box<dyn Error + Send>> {
+        let current = match self.mode {
+            Mode::Quotes(ref current) => current.clone(),
+            _ => {
+                warn!("quote symbols can only be changed in quotes mode");
+                return Ok(vec![]);
+            }
+        };
+        if !self.has_session(SessionKind::Quote) {
+            warn!("quote symbols ignored, the quote session was deleted");
+            return Ok(vec![]);
         }
-        if parsed_message.dot_has("p.1.v.bid_size") { 
-            return Ok(MessageType::QsdBidAsk(parsed_message));
+        let mut wanted: Vec<String> = vec![];
+        for symbol in symbols {
+            let symbol = &match self.options.aliases {
+                Some(ref aliases) => aliases.resolve(symbol),
+                None => symbol.clone(),
+            };
+            if wanted.contains(symbol) {
+                continue;
+            }
+            match symbol::validate(symbol) {
+                Ok(()) => wanted.push(symbol.clone()),
+                Err(err) => self.report_subscription_failure(symbol.clone(), err.to_string(), self.correlation_id)?,
+            }
         }
-        if parsed_message.dot_has("p.1.v.description") { 
-            return Ok(MessageType::QsdDescription(parsed_message));
+        let removed: Vec<&str> = current.iter().filter(|symbol| !wanted.contains(symbol)).map(String::as_str).collect();
+        let added: Vec<&str> = wanted.iter().filter(|symbol| !current.contains(symbol)).map(String::as_str).collect();
+        info!("quote symbols changed: {} added, {} removed", added.len(), removed.len());
+        let mut messages = self.quote_symbol_messages("quote_remove_symbols", &removed);
+        messages.extend(self.quote_symbol_messages("quote_add_symbols", &added));
+        if let Some(ref news) = self.news {
+            news.set_symbols(&wanted);
         }
-        if parsed_message.dot_has("p.1.v.local_popularity") { 
-            return Ok(MessageType::QsdLocalPopularity(parsed_message));
+        self.last_quotes.retain(|symbol, _| wanted.contains(symbol));
+        self.subscriptions.retain(|symbol, _| wanted.contains(symbol));
+        if let Some(correlation_id) = self.correlation_id {
+            for symbol in added.iter() {
+                self.subscriptions.insert(symbol.to_string(), correlation_id);
+            }
         }
-        if parsed_message.dot_has("p.1.v.lp_time") { 
-            return Ok(MessageType::QsdLastPriceTime(parsed_message));
+        self.options.checkpoints.record_quote_symbols(&wanted);
+        self.mode = Mode::Quotes(wanted);
+        Ok(messages)
+    }
+
+    // the session keeps its symbols, so the snapshots of the fields that stay carry on; values of
+    // dropped fields are forgotten so QuoteFieldChanged starts over if they come back
+    fn set_quote_fields(&mut self, fields: &[QuoteField]) -> Vec<String> {
+        let mut wanted: Vec<QuoteField> = vec![];
+        for field in fields {
+            if !wanted.contains(field) {
+                wanted.push(*field);
+            }
         }
-        if parsed_message.dot_has("p.1.v.lp") { 
-            return Ok(MessageType::QsdLastPrice(parsed_message));
+        refuse_gated_fields(&mut wanted, AccessLevel::of_token(&self.auth_token));
+        if wanted.is_empty() {
+            warn!("quote fields can't be set to none");
+            return vec![];
         }
-        if parsed_message.dot_has("p.1.series_id.s") { 
-            return Ok(MessageType::SeriesUpdate(parsed_message));
+        info!("quote fields set to {}", wanted.iter().map(QuoteField::as_str).collect::<Vec<_>>().join(","));
+        for last in self.last_quotes.values_mut() {
+            last.values.retain(|(field, _)| wanted.contains(field) || self.options.quote_changes.contains(field));
         }
-        if parsed_message.dot_has("p.1.study_id.st") { 
-            return Ok(MessageType::StudyUpdate(parsed_message));
+        // the watched fields stay subscribed
+        let mut session_fields = wanted.clone();
+        session_fields.extend(self.options.quote_changes.iter().filter(|field| !wanted.contains(field)));
+        if let Some(ref mut script) = self.options.connect_script {
+            script.quote_fields.clone_from(&session_fields);
         }
-        println!("received unknown message {}", serde_json::to_string_pretty(&parsed_message).unwrap());
-        return Err(Box::new(TradingViewError::ParseError));
+        self.options.quote_session_fields = session_fields;
+        self.options.checkpoints.record_quote_fields(&wanted);
+        self.options.quote_fields = wanted;
+        self.quote_extractor = quote_extractor(&self.options);
+        vec![self.quote_set_fields_message()]
     }
 
-    fn format_json_response(&self, message: Value) -> String {
-        let stringified_response = serde_json::to_string(&message).expect("failed to serialize");
-        return format!("~m~{}~m~{}", stringified_response.len(), stringified_response);
+    pub fn command_messages(&mut self, command: &ApiCommand) -> Result<Vec<String>, Box<dyn Error + Send>> {
+        match *command {
+            ApiCommand::SetQuoteSymbols(ref symbols) => return self.set_quote_symbols(symbols),
+            ApiCommand::SetEventFilters(ref filters) => {
+                info!("{} event filters set", filters.len());
+                self.filters = RefCell::new(FilterChain::new(filters.clone()));
+                return Ok(vec![]);
+            }
+            ApiCommand::DeleteSession(kind) => return Ok(self.delete_session(kind)),
+            _ => {}
+        }
+        if self.quote_symbols().is_empty() || !self.has_session(SessionKind::Quote) {
+            warn!("{:?} ignored, there is no quote session", command);
+            return Ok(vec![]);
+        }
+        Ok(match *command {
+            ApiCommand::HibernateQuotes => {
+                info!("hibernating quote session");
+                vec![self.format_json_response(serde_json::json!({
+                    "m": "quote_hibernate_all",
+                    "p": [
+                        "quote_session_id"
+                    ]
+                }))]
+            }
+            // marking the symbols fast again resumes their updates
+            ApiCommand::WakeQuotes => {
+                info!("waking quote session");
+                self.quote_symbol_messages("quote_fast_symbols", &self.quote_symbols())
+            }
+            ApiCommand::SetQuoteFields(ref fields) => self.set_quote_fields(fields),
+            ApiCommand::SetQuoteSymbols(_) | ApiCommand::SetEventFilters(_) | ApiCommand::DeleteSession(_) => vec![],
+        })
+    }
+
+    // whether the mode creates the session, deleted or not
+    fn opens_session(&self, kind: SessionKind) -> bool {
+        match (kind, &self.mode) {
+            (SessionKind::Quote, Mode::Stream) => !self.quote_symbols().is_empty(),
+            (SessionKind::Chart, Mode::Stream) => stream_chart(&self.options).is_some(),
+            (SessionKind::Quote, Mode::Quotes(_)) | (SessionKind::Chart, Mode::Download(_)) => true,
+            (SessionKind::Quote, Mode::Download(_)) | (SessionKind::Chart, Mode::Quotes(_)) => false,
+        }
+    }
+
+    // whether the mode opens the session and it hasn't been deleted
+    fn has_session(&self, kind: SessionKind) -> bool {
+        self.opens_session(kind) && !self.deleted_sessions.contains(&kind)
+    }
+
+    fn delete_session_message(&self, kind: SessionKind) -> String {
+        let method = match kind {
+            SessionKind::Quote => "quote_delete_session",
+            SessionKind::Chart => "chart_delete_session",
+        };
+        self.format_json_response(serde_json::json!({
+            "m": method,
+            "p": [
+                kind.id()
+            ]
+        }))
+    }
+
+    fn delete_session(&mut self, kind: SessionKind) -> Vec<String> {
+        if !self.has_session(kind) {
+            warn!("no {} to delete", kind.id());
+            return vec![];
+        }
+        info!("deleting {}", kind.id());
+        let message = self.delete_session_message(kind);
+        self.deleted_sessions.push(kind);
+        if kind == SessionKind::Quote {
+            self.last_quotes.clear();
+        }
+        vec![message]
+    }
+
+    pub fn teardown_messages(&self) -> Vec<String> {
+        [SessionKind::Quote, SessionKind::Chart].into_iter()
+            .filter(|kind| self.has_session(*kind))
+            .map(|kind| self.delete_session_message(kind))
+            .collect()
+    }
+
+    // study errors name the study in p[1], session errors only the session in p[0]
+    fn drop_session_messages(&self, kind: ServerErrorKind, message: &Value) -> Vec<String> {
+        let session = message.dot_get::<String>("p.0").ok().flatten().and_then(|session_id| SessionKind::from_id(&session_id));
+        let study_id = message.dot_get::<String>("p.1").ok().flatten();
+        match (kind, session, study_id) {
+            // studies fed from the failing one can't produce anything either
+            (ServerErrorKind::Study, Some(SessionKind::Chart), Some(study_id)) => std::iter::once(study_id.as_str())
+                .chain(self.studies.descendants(&study_id))
+                .map(|study_id| self.format_json_response(serde_json::json!({
+                    "m": "remove_study",
+                    "p": [SessionKind::Chart.id(), study_id]
+                })))
+                .collect(),
+            (ServerErrorKind::Critical, Some(session), _) => vec![self.delete_session_message(session)],
+            // protocol errors aren't tied to a session, and sessions we don't know aren't ours to delete
+            _ => vec![],
+        }
+    }
+
+    // tears the sessions down and builds them again with a fresh token
+    fn reauthenticate(&mut self, responses: &mut Vec<String>) -> bool {
+        if self.last_reauth.is_some_and(|last_reauth| last_reauth.elapsed() < MIN_REAUTH_INTERVAL) {
+            return false;
+        }
+        self.last_reauth = Some(Instant::now());
+        match self.options.auth.refresh() {
+            Ok(auth_token) => {
+                info!("auth token refreshed, re-establishing sessions");
+                self.auth_token = auth_token;
+                responses.extend(self.teardown_messages());
+                responses.extend(self.bootstrap_messages());
+                true
+            }
+            Err(err) => {
+                warn!("couldn't refresh auth token: {}", err);
+                false
+            }
+        }
+    }
+
+    // realtime data drops to delayed_streaming_* once the token stops being accepted
+    fn check_update_mode(&mut self, update_mode: Option<&str>, raw: &str, responses: &mut Vec<String>) {
+        let delayed = update_mode.is_some_and(|mode| mode.starts_with("delayed"));
+        if delayed && self.auth_token != auth::ANONYMOUS_TOKEN {
+            warn!("quotes downgraded to delayed data: {}", raw);
+            self.reauthenticate(responses);
+        }
+    }
+
+    fn handle_server_error(&mut self, kind: ServerErrorKind, message: Value, raw: &str, responses: &mut Vec<String>) -> Result<(), Box<dyn Error + Send>> {
+        warn!("server reported {:?} error: {}", kind, raw);
+        self.options.stats.record_error(match kind {
+            ServerErrorKind::Study => "study_error",
+            ServerErrorKind::Critical => "critical_error",
+            ServerErrorKind::Protocol => "protocol_error",
+        });
+        if is_auth_error(kind, &message) && self.reauthenticate(responses) {
+            return Ok(());
+        }
+        let drop_messages = match self.options.error_policy {
+            ErrorPolicy::DropSession => self.drop_session_messages(kind, &message),
+            _ => vec![],
+        };
+        responses.extend(drop_messages);
+        // the event and the error share one copy of the payload
+        let payload = Arc::new(message);
+        let error = match kind {
+            ServerErrorKind::Study => TradingViewError::StudyError(Arc::clone(&payload)),
+            ServerErrorKind::Critical => TradingViewError::CriticalError(Arc::clone(&payload)),
+            ServerErrorKind::Protocol => TradingViewError::ProtocolError(Arc::clone(&payload)),
+        };
+        self.emit(Event::ServerError { kind, payload })?;
+        if self.options.error_policy == ErrorPolicy::Reconnect {
+            return Err(Box::new(error));
+        }
+        Ok(())
+    }
+
+    fn handle_series_bars(&mut self, message: &Value) -> Result<(), Box<dyn Error + Send>> {
+        self.handle_primary_series(message)?;
+        let ticks = lookup(message, "p.1").and_then(|series| series.get(TICK_SERIES_ID));
+        let trades = match (ticks, self.ticks.as_mut()) {
+            (Some(ticks), Some(tracker)) => tracker.update(ticks),
+            _ => vec![],
+        };
+        if !trades.is_empty() {
+            let symbol = self.symbol().to_string();
+            for trade in trades {
+                let local_time_ms = self.local_time_ms(trade.time * 1000);
+                self.emit(Event::Trade { symbol: symbol.clone(), trade, local_time_ms })?;
+            }
+        }
+        for index in 0..self.extra_series.len() {
+            let series_id = extra_series_id(&self.extra_series[index].interval);
+            let bars = match lookup(message, "p.1").and_then(|series| series.get(&series_id)) {
+                Some(series) => bar::parse_series_bars(series),
+                None => continue,
+            };
+            if !bars.is_empty() {
+                self.handle_extra_series(index, bars)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_extra_series(&mut self, index: usize, mut bars: Vec<Bar>) -> Result<(), Box<dyn Error + Send>> {
+        let symbol = self.symbol().to_string();
+        let key = checkpoint_key(&symbol, &self.extra_series[index].interval);
+        let gap = (!self.extra_series[index].gap_checked).then(|| self.detect_gap(&key, &bars));
+        let series = &mut self.extra_series[index];
+        if let Some(first_bar) = bars.first() {
+            if series.oldest_bar_time.is_none_or(|oldest| first_bar.time < oldest) {
+                series.oldest_bar_time = Some(first_bar.time);
+                series.received_older_bars = true;
+            }
+        }
+        let issues = match series.validator {
+            Some(ref mut validator) => validator.check(&symbol, &bars),
+            None => vec![],
+        };
+        if let Some(gap) = gap {
+            series.gap_checked = true;
+            series.backfill = gap;
+        }
+        if let Some(ref mut backfill) = series.backfill {
+            bars = backfill.take_gap(bars);
+        }
+        if let Some(last_bar_time) = bars.iter().map(|bar| bar.time).max() {
+            self.options.checkpoints.record(&key, last_bar_time);
+        }
+        if self.options.series.stitch_rolls {
+            shift_stitched(&self.options.checkpoints, &symbol, &mut bars);
+        }
+        let merged = series.candles.merge(&symbol, bars);
+        let interval = series.interval.clone();
+        for (time, issue) in issues {
+            warn!("{} {} bar at {}: {}", symbol, interval, time, issue);
+            self.emit(Event::DataQualityWarning { symbol: symbol.clone(), time, issue })?;
+        }
+        self.emit_merged(symbol, interval, merged)
+    }
+
+    fn handle_study_values(&self, message: &Value) -> Result<(), Box<dyn Error + Send>> {
+        let updates = match lookup(message, "p.1") {
+            Some(Value::Object(map)) => map,
+            _ => return Ok(()),
+        };
+        for (id, update) in updates {
+            if let Some(kind) = EsdKind::from_study_id(id) {
+                let events = corporate::parse_events(kind, update);
+                if !events.is_empty() {
+                    self.emit(Event::CorporateEvents { symbol: self.symbol().to_string(), events })?;
+                }
+                continue;
+            }
+            let study = match self.studies.get(id) {
+                Some(study) => study,
+                None => continue,
+            };
+            let _study = tracing::debug_span!("study", id = %study.id, parent = study.parent.as_deref()).entered();
+            let points = study::parse_study_points(update);
+            tracing::trace!(points = points.len(), "study values");
+            if points.is_empty() {
+                continue;
+            }
+            self.emit(Event::StudyValues {
+                symbol: self.symbol().to_string(),
+                study: study.id.clone(),
+                parent: study.parent.clone(),
+                plots: study.plots.clone(),
+                points
+            })?;
+        }
+        Ok(())
+    }
+
+    fn handle_primary_series(&mut self, message: &Value) -> Result<(), Box<dyn Error + Send>> {
+        let mut bars: Vec<Bar> = match message.dot_get::<Value>("p.1.series_id") {
+            Ok(Some(series)) => bar::parse_series_bars(&series),
+            _ => vec![]
+        };
+        if bars.is_empty() {
+            return Ok(());
+        }
+        if let Some(first_bar) = bars.first() {
+            if self.oldest_bar_time.is_none_or(|oldest| first_bar.time < oldest) {
+                self.oldest_bar_time = Some(first_bar.time);
+                self.received_older_bars = true;
+                self.history_bars += bars.len();
+            }
+        }
+        let symbol = self.symbol().to_string();
+        if let Some(ref mut validator) = self.validator {
+            for (time, issue) in validator.check(&symbol, &bars) {
+                warn!("{} bar at {}: {}", symbol, time, issue);
+                self.emit(Event::DataQualityWarning { symbol: symbol.clone(), time, issue })?;
+            }
+        }
+        if !self.gap_checked {
+            self.gap_checked = true;
+            self.backfill = self.detect_gap(&symbol, &bars);
+        }
+        // older bars arriving during a backfill belong to the gap, not the live feed
+        if let Some(ref mut backfill) = self.backfill {
+            bars = backfill.take_gap(bars);
+        }
+        if self.pending_rolls.contains(&symbol) {
+            if let Some((rolled_at, offset)) = self.candles.roll_offset(&symbol, &bars) {
+                info!("shifting cached {} bars by {} across the roll", symbol, offset);
+                self.pending_rolls.remove(&symbol);
+                self.options.checkpoints.record_stitch(&symbol, rolled_at, offset);
+                self.stitch(&symbol, offset)?;
+            }
+        }
+        if self.options.series.stitch_rolls {
+            shift_stitched(&self.options.checkpoints, &symbol, &mut bars);
+        }
+        if let Some(last_bar_time) = bars.iter().map(|bar| bar.time).max() {
+            self.options.checkpoints.record(&symbol, last_bar_time);
+        }
+        let merged = self.candles.merge(&symbol, bars);
+        // new bars and revisions alike
+        let updated: Vec<Bar> = merged.bars.iter().chain(merged.corrections.iter().map(|(_, bar)| bar)).cloned().collect();
+        if let Mode::Stream = self.mode {
+            self.options.session_stats.record(&symbol, &updated);
+        }
+        // indicators change from the earliest new or revised bar on
+        let changed_from = updated.iter().map(|bar| bar.time).min();
+        let closed = match self.resampler {
+            Some(ref mut resampler) => resampler.update(&updated),
+            None => vec![],
+        };
+        let interval = self.interval().to_string();
+        self.emit_merged(symbol.clone(), interval, merged)?;
+        for (interval, bar) in closed {
+            self.emit(Event::BarClosed { symbol: symbol.clone(), interval, bar })?;
+        }
+        match changed_from {
+            Some(changed_from) if matches!(self.mode, Mode::Stream) => self.emit_local_indicators(&symbol, changed_from),
+            _ => Ok(()),
+        }
+    }
+
+    fn emit_local_indicators(&self, symbol: &str, changed_from: i64) -> Result<(), Box<dyn Error + Send>> {
+        for indicator in self.options.local_indicators.iter() {
+            let bars = self.candles.window(symbol, changed_from, indicator.lookback());
+            let points: Vec<StudyPoint> = bars.iter().zip(indicator.compute(&bars))
+                .filter(|(bar, _)| bar.time >= changed_from)
+                .filter_map(|(bar, value)| Some(StudyPoint { time: bar.time, values: vec![value?] }))
+                .collect();
+            if points.is_empty() {
+                continue;
+            }
+            self.emit(Event::StudyValues { symbol: symbol.to_string(), study: indicator.id(), parent: None, plots: indicator.plots(), points })?;
+        }
+        Ok(())
+    }
+
+    // every interval's cached bars move by the offset the chart interval found, and go out again
+    // as revisions
+    fn stitch(&mut self, symbol: &str, offset: f64) -> Result<(), Box<dyn Error + Send>> {
+        let corrections = self.candles.shift(symbol, offset);
+        let interval = self.interval().to_string();
+        self.emit_merged(symbol.to_string(), interval, Merged { bars: vec![], corrections })?;
+        for index in 0..self.extra_series.len() {
+            let series = &mut self.extra_series[index];
+            let interval = series.interval.clone();
+            let corrections = series.candles.shift(symbol, offset);
+            self.emit_merged(symbol.to_string(), interval, Merged { bars: vec![], corrections })?;
+        }
+        Ok(())
+    }
+
+    fn emit_merged(&self, symbol: String, interval: String, mut merged: Merged) -> Result<(), Box<dyn Error + Send>> {
+        if self.options.emit_corrections {
+            for (previous, bar) in merged.corrections {
+                debug!("{} {} bar at {} revised", symbol, interval, bar.time);
+                self.emit(Event::BarCorrected { symbol: symbol.clone(), interval: interval.clone(), previous, bar })?;
+            }
+        } else if !merged.corrections.is_empty() {
+            merged.bars.extend(merged.corrections.into_iter().map(|(_, bar)| bar));
+            merged.bars.sort_by_key(|bar| bar.time);
+        }
+        if merged.bars.is_empty() {
+            return Ok(());
+        }
+        self.options.stats.record_bars(&symbol, merged.bars.len());
+        let correlation_id = self.subscriptions.get(&symbol).copied();
+        self.emit(Event::Bars { symbol, interval, bars: merged.bars, correlation_id })?;
+        Ok(())
+    }
+
+    // key is the series' checkpoint, the bare symbol for the chart interval
+    fn detect_gap(&self, key: &str, bars: &[Bar]) -> Option<Backfill> {
+        if !matches!(self.mode, Mode::Stream) {
+            return None;
+        }
+        let after = self.options.checkpoints.last_bar_time(key)?;
+        let before = bars.first()?.time;
+        // the fresh history still overlaps what we already have
+        if before <= after {
+            return None;
+        }
+        info!("{} has a gap between {} and {}, backfilling", key, after, before);
+        Some(Backfill { after, before, bars: vec![] })
+    }
+
+    fn finish_backfill(&self, backfill: Option<Backfill>, interval: String) -> Result<(), Box<dyn Error + Send>> {
+        let mut backfill = match backfill {
+            Some(backfill) => backfill,
+            None => return Ok(()),
+        };
+        backfill.bars.sort_by_key(|bar| bar.time);
+        backfill.bars.dedup_by_key(|bar| bar.time);
+        let symbol = self.symbol().to_string();
+        if backfill.bars.is_empty() {
+            debug!("no bars missing for {}", symbol);
+            return Ok(());
+        }
+        info!("backfilled {} {} bars for {}", backfill.bars.len(), interval, symbol);
+        self.emit(Event::GapFilled { symbol, interval, bars: backfill.bars })?;
+        Ok(())
+    }
+
+    // returns the request_more_data command if more history is needed, None once the range is covered
+    fn next_history_request(&mut self) -> Option<String> {
+        let (from, remaining) = match (&self.mode, &self.backfill) {
+            (Mode::Download(ref request), _) => (request.from, request.count.map(|count| count.saturating_sub(self.history_bars))),
+            (Mode::Stream, Some(ref backfill)) => (backfill.after, None),
+            _ => return None,
+        };
+        let needs_more = self.received_older_bars && self.oldest_bar_time.is_some_and(|oldest| oldest > from) && remaining != Some(0);
+        self.received_older_bars = false;
+        if !needs_more {
+            return None;
+        }
+        Some(self.format_json_response(serde_json::json!({
+            "m": "request_more_data",
+            "p": [
+                "chart_session_id",
+                "series_id",
+                remaining.map_or(HISTORY_BATCH_SIZE, |remaining| remaining.min(HISTORY_BATCH_SIZE))
+            ]
+        })))
+    }
+
+    // extra intervals only page back through a backfill, the stream never downloads them
+    fn next_extra_history_request(&mut self, index: usize) -> Option<String> {
+        let series = &mut self.extra_series[index];
+        let after = series.backfill.as_ref()?.after;
+        let needs_more = series.received_older_bars && series.oldest_bar_time.is_some_and(|oldest| oldest > after);
+        series.received_older_bars = false;
+        if !needs_more {
+            return None;
+        }
+        let series_id = extra_series_id(&series.interval);
+        Some(self.format_json_response(serde_json::json!({
+            "m": "request_more_data",
+            "p": ["chart_session_id", series_id, HISTORY_BATCH_SIZE]
+        })))
+    }
+
+    // lp_time only has second resolution and a clock behind the server's reads as no lag
+    fn record_server_lag(&self, last_price_time: i64, received: Instant) {
+        let received_at = SystemTime::now() - received.elapsed();
+        self.options.clock.record_last_price_time(last_price_time, received_at);
+        let lag = received_at.duration_since(UNIX_EPOCH).ok()
+            .and_then(|since_epoch| since_epoch.checked_sub(Duration::from_secs(last_price_time.max(0) as u64)));
+        if let Some(lag) = lag {
+            self.options.stats.record_server_lag(lag);
+        }
+    }
+
+    // a server time on the local clock, with ApiOptions::normalize_times
+    fn local_time_ms(&self, server_ms: i64) -> Option<i64> {
+        if !self.options.normalize_times {
+            return None;
+        }
+        self.options.clock.estimate().map(|estimate| estimate.to_local_ms(server_ms))
+    }
+
+    fn report_clock_skew(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        if !self.options.clock_events {
+            return Ok(());
+        }
+        let estimate = match self.options.clock.estimate() {
+            Some(estimate) => estimate,
+            None => return Ok(()),
+        };
+        if self.reported_skew.is_some_and(|reported| (estimate.skew_ms - reported).abs() < clock::SKEW_REPORT_STEP_MS) {
+            return Ok(());
+        }
+        debug!("local clock {}ms off the server's, {}ms typical delay", estimate.skew_ms, estimate.delay_ms);
+        self.reported_skew = Some(estimate.skew_ms);
+        self.emit(Event::ClockSkew { skew_ms: estimate.skew_ms, delay_ms: estimate.delay_ms, samples: estimate.samples })
+    }
+
+    // consumers get a shared handle, so fanning an event out doesn't copy its bars or payload
+    fn emit(&self, event: Event) -> Result<(), Box<dyn Error + Send>> {
+        let event = match self.options.aliases {
+            Some(ref aliases) => aliases.rewrite(event),
+            None => event,
+        };
+        // the feed's rates, whatever consumers filter out of it
+        if self.stats_due.is_some() {
+            self.rates.borrow_mut().record_event(&event);
+        }
+        // filters see the application's symbol names
+        let filtered = match self.filters.borrow_mut() {
+            filters if filters.is_empty() => Some(event),
+            mut filters => filters.apply(event, Instant::now()),
+        };
+        match filtered {
+            Some(event) => self.deliver(event),
+            None => Ok(()),
+        }
+    }
+
+    // hands an event that made it through the filters to the consumers
+    fn deliver(&self, event: Event) -> Result<(), Box<dyn Error + Send>> {
+        let limits = self.options.limits;
+        match limits.backlog_policy {
+            BacklogPolicy::Block => self.events_tx.send(Arc::new(event)).map_err(|_| TradingViewError::SendError("events"))?,
+            BacklogPolicy::Conflate => if !self.send_conflated(event)? {
+                return Ok(());
+            },
+            BacklogPolicy::Drop | BacklogPolicy::Fail => match self.events_tx.try_send(Arc::new(event)) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) if limits.backlog_policy == BacklogPolicy::Drop => {
+                    let dropped = self.options.stats.record_dropped_event();
+                    self.rates.borrow_mut().record_dropped_event();
+                    if dropped == 1 || dropped.is_multiple_of(1000) {
+                        warn!("event backlog full, {} events dropped so far", dropped);
+                    }
+                    return Ok(());
+                }
+                Err(TrySendError::Full(_)) => return Err(TradingViewError::BacklogFull(limits.max_event_backlog).into()),
+                Err(TrySendError::Disconnected(_)) => return Err(TradingViewError::SendError("events").into()),
+            },
+        }
+        if let Some(received) = self.frame_received {
+            self.options.stats.record_delivery_latency(received.elapsed());
+        }
+        Ok(())
+    }
+
+    // false when the quote was held back; anything else first waits for the held quotes so it
+    // can't overtake them
+    fn send_conflated(&self, event: Event) -> Result<bool, Box<dyn Error + Send>> {
+        let mut conflation = self.conflation.borrow_mut();
+        let (symbol, values, correlation_id, local_time_ms) = match event {
+            Event::Quote { symbol, values, correlation_id, local_time_ms } => (symbol, values, correlation_id, local_time_ms),
+            event => {
+                while let Some(held) = conflation.pop_front() {
+                    self.events_tx.send(Arc::new(held)).map_err(|_| TradingViewError::SendError("events"))?;
+                }
+                self.events_tx.send(Arc::new(event)).map_err(|_| TradingViewError::SendError("events"))?;
+                return Ok(true);
+            }
+        };
+        if self.flush_conflated(&mut conflation)? {
+            match self.events_tx.try_send(Arc::new(Event::Quote { symbol, values, correlation_id, local_time_ms })) {
+                Ok(()) => return Ok(true),
+                Err(TrySendError::Full(event)) => conflation.push_front(Arc::unwrap_or_clone(event)),
+                Err(TrySendError::Disconnected(_)) => return Err(TradingViewError::SendError("events").into()),
+            }
+        } else if conflation.hold(symbol, values, correlation_id, local_time_ms) {
+            let conflated = self.options.stats.record_conflated_quote();
+            if conflated == 1 || conflated.is_multiple_of(1000) {
+                debug!("event backlog full, {} quotes conflated so far, {} symbols held", conflated, conflation.len());
+            }
+        }
+        Ok(false)
+    }
+
+    // hands over as many held quotes as the backlog takes, true once none are left
+    fn flush_conflated(&self, conflation: &mut QuoteConflation) -> Result<bool, Box<dyn Error + Send>> {
+        while let Some(held) = conflation.pop_front() {
+            match self.events_tx.try_send(Arc::new(held)) {
+                Ok(()) => {}
+                Err(TrySendError::Full(held)) => {
+                    conflation.push_front(Arc::unwrap_or_clone(held));
+                    return Ok(false);
+                }
+                Err(TrySendError::Disconnected(_)) => return Err(TradingViewError::SendError("events").into()),
+            }
+        }
+        Ok(true)
+    }
+
+    fn report_command_results(&self, results: Vec<CommandResult>) -> Result<(), Box<dyn Error + Send>> {
+        for result in results {
+            match result.outcome {
+                CommandOutcome::Acknowledged => debug!("{} {} acknowledged after {}ms", result.command, result.target, result.elapsed.as_millis()),
+                CommandOutcome::Failed { ref reason } => debug!("{} {} failed: {}", result.command, result.target, reason),
+                CommandOutcome::TimedOut => {
+                    warn!("{} {} not acknowledged after {}s", result.command, result.target, result.elapsed.as_secs());
+                    self.options.stats.record_error("command_timeout");
+                }
+            }
+            self.emit(result.into())?;
+        }
+        Ok(())
+    }
+
+    fn acknowledge(&self, message: &Value) -> Result<(), Box<dyn Error + Send>> {
+        let results = self.acks.borrow_mut().received(message, Instant::now());
+        self.report_command_results(results)
+    }
+
+    fn expire_commands(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        let now = Instant::now();
+        let acks = self.acks.get_mut();
+        if acks.next_deadline().is_none_or(|deadline| deadline > now) {
+            return Ok(());
+        }
+        let results = acks.expire(now);
+        self.report_command_results(results)
+    }
+
+    // a symbol the server turned down after quote_add_symbols, tied to the command that added it
+    fn report_quote_failure(&self, symbol: String, reason: String) -> Result<(), Box<dyn Error + Send>> {
+        let results = self.acks.borrow_mut().quote_failed(QUOTE_SESSION, &symbol, &reason, Instant::now());
+        let correlation_id = results.iter().find_map(|result| result.correlation_id);
+        self.report_command_results(results)?;
+        self.report_subscription_failure(symbol, reason, correlation_id)
+    }
+
+    fn report_subscription_failure(&self, symbol: String, reason: String, correlation_id: Option<CorrelationId>) -> Result<(), Box<dyn Error + Send>> {
+        warn!("subscription to {} failed: {}", symbol, reason);
+        self.options.stats.record_error("subscription");
+        self.emit(Event::SubscriptionFailed { symbol, reason, correlation_id })?;
+        Ok(())
+    }
+
+    fn handle_quote_data(&mut self, raw: &str, responses: &mut Vec<String>) -> Result<(), Box<dyn Error + Send>> {
+        let update = match self.quote_extractor.extract(raw) {
+            Ok(update) => update,
+            Err(source) => {
+                let err = TradingViewError::QuoteDataError { raw: raw.to_string(), source };
+                if self.options.parse_mode == ParseMode::Strict {
+                    return Err(err.into());
+                }
+                warn!("skipping message: {}", err);
+                self.options.stats.record_error("parse");
+                return Ok(());
+            }
+        };
+        tracing::Span::current().record("symbol", &*update.symbol);
+        if update.status == "error" {
+            let reason = update.error.map_or_else(|| "unknown error".to_string(), String::from);
+            return self.report_quote_failure(update.symbol.into_owned(), reason);
+        }
+        self.check_update_mode(update.update_mode.as_deref(), raw, responses);
+        if let (Some(last_price_time), Some(received)) = (update.last_price_time, self.frame_received) {
+            self.record_server_lag(last_price_time, received);
+            self.report_clock_skew()?;
+        }
+        if update.fields.is_empty() {
+            return Ok(());
+        }
+        let mut values: Vec<(QuoteField, Value)> = update.fields.iter()
+            .map(|(field, raw)| (*field, serde_json::from_str(raw.get()).unwrap_or(Value::Null)))
+            .collect();
+        let symbol = update.symbol.into_owned();
+        let correlation_id = self.subscriptions.get(&symbol).copied();
+        let local_time_ms = update.last_price_time.and_then(|last_price_time| self.local_time_ms(last_price_time * 1000));
+        let mut changes = vec![];
+        if !self.options.quote_changes.is_empty() {
+            let watched: Vec<(QuoteField, Value)> = values.iter().filter(|(field, _)| self.options.quote_changes.contains(field)).cloned().collect();
+            let last = self.last_quotes.entry(symbol.clone()).or_insert_with(|| QuoteData::new(&symbol));
+            changes = watched.iter()
+                .filter(|(field, new)| last.get(*field) != Some(new))
+                .map(|(field, new)| Event::QuoteFieldChanged { symbol: symbol.clone(), field: *field, old: last.get(*field).cloned(), new: new.clone() })
+                .collect();
+            last.update(&watched);
+        }
+        // watched fields nobody asked to report only make it into the changes
+        values.retain(|(field, _)| self.options.quote_fields.contains(field));
+        if !values.is_empty() {
+            self.emit(Event::Quote { symbol, values, correlation_id, local_time_ms })?;
+        }
+        for change in changes {
+            self.emit(change)?;
+        }
+        Ok(())
+    }
+
+    // continuous contracts name the front month they currently follow in current_contract
+    fn handle_symbol_resolved(&mut self, message: &Value) -> Result<(), Box<dyn Error + Send>> {
+        let symbol = self.symbol().to_string();
+        if let (Some(cache), Some(info)) = (self.options.symbol_cache.as_ref(), lookup(message, "p.2")) {
+            if let Err(err) = cache.insert(&SymbolInfo::from_json(&symbol, info.clone())) {
+                warn!("couldn't cache symbol info of {}: {}", symbol, err);
+            }
+        }
+        match lookup(message, "p.2").map(TradingHours::from_symbol_info) {
+            Some(Ok(hours)) => {
+                self.options.session_stats.set_hours(&symbol, hours.clone());
+                self.emit(Event::TradingHours { symbol: symbol.clone(), hours })?
+            }
+            Some(Err(err)) => warn!("couldn't read trading hours of {}: {}", symbol, err),
+            None => {},
+        }
+        if !symbol::is_continuous(&symbol) {
+            return Ok(());
+        }
+        let contract = match message.dot_get::<String>("p.2.current_contract") {
+            Ok(Some(contract)) => contract,
+            _ => {
+                debug!("no current contract for {}", symbol);
+                return Ok(());
+            }
+        };
+        let root = message.dot_get::<String>("p.2.root").ok().flatten();
+        // the contract is kept with the checkpoints, a roll mostly shows when a new connection
+        // resolves the symbol again
+        let previous = self.options.checkpoints.record_contract(&symbol, &contract);
+        if self.options.series.stitch_rolls {
+            self.restore_held_bars(&symbol, previous.is_some());
+        }
+        if let Some(previous) = previous {
+            info!("{} rolled from {} to {}", symbol, previous, contract);
+            if self.options.series.stitch_rolls {
+                self.pending_rolls.insert(symbol.clone());
+            }
+            self.emit(Event::ContractRolled { symbol: symbol.clone(), previous, contract: contract.clone() })?;
+        }
+        self.emit(Event::ContractResolved { symbol, root, contract })
+    }
+
+    // the old contract's bars to stitch come from the connection before, which held them as it
+    // ended; they're taken either way so they don't outlive the resolve they were kept for
+    fn restore_held_bars(&mut self, symbol: &str, rolled: bool) {
+        let held = self.options.checkpoints.take_held_bars(symbol);
+        if rolled {
+            self.candles.merge(symbol, held);
+        }
+        for series in self.extra_series.iter_mut() {
+            let held = self.options.checkpoints.take_held_bars(&checkpoint_key(symbol, &series.interval));
+            if rolled {
+                series.candles.merge(symbol, held);
+            }
+        }
+    }
+
+    // the chart only ever holds one symbol, so only a download can't carry on without it
+    fn handle_symbol_error(&self, message: Value) -> Result<(), Box<dyn Error + Send>> {
+        let reason = message.dot_get::<String>("p.2").ok().flatten().unwrap_or_else(|| "unknown error".to_string());
+        self.report_subscription_failure(self.symbol().to_string(), reason, None)?;
+        if let Mode::Download(_) = self.mode {
+            return Err(TradingViewError::SymbolError(Arc::new(message)).into());
+        }
+        Ok(())
     }
 
-    pub fn handler(&self) -> Result<(), Box<dyn Error + Send>> {
+    // a silent session is as good as dead, so hand it to the reconnect path
+    fn handle_stale_feed(&self) -> Result<(), Box<dyn Error + Send>> {
+        let silent_for = self.options.heartbeat_timeout;
+        warn!("no messages for {}s, treating feed as stale", silent_for.as_secs());
+        self.emit(Event::FeedStale { silent_for })?;
+        Err(TradingViewError::FeedStale(silent_for).into())
+    }
+
+    fn emit_session_stats_if_due(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        match (self.session_stats_due, self.options.session_stats_every) {
+            (Some(due), Some(every)) if due <= Instant::now() => self.session_stats_due = Some(Instant::now() + every),
+            _ => return Ok(()),
+        }
+        let symbol = self.symbol().to_string();
+        let session = match self.options.session_stats.session(&symbol) {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+        let anchored = self.options.session_stats.anchors(&symbol);
+        self.emit(Event::SessionStats { symbol, session, anchored })
+    }
+
+    fn emit_stats_if_due(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        let now = Instant::now();
+        match (self.stats_due, self.options.stats_every) {
+            (Some(due), Some(every)) if due <= now => self.stats_due = Some(now + every),
+            _ => return Ok(()),
+        }
+        let queues = QueueDepths {
+            held_quotes: self.conflation.get_mut().len(),
+            pending_commands: self.acks.get_mut().len(),
+            pending_pings: self.keepalive.pending(),
+            dropped_events: 0
+        };
+        let stats = self.rates.get_mut().report(now, queues);
+        self.emit(stats)
+    }
+
+    // a series or study the chart session was asked for
+    fn knows_series(&self, id: &str) -> bool {
+        match id {
+            "series_id" => true,
+            TICK_SERIES_ID => self.ticks.is_some(),
+            id => self.extra_series.iter().any(|series| extra_series_id(&series.interval) == id) || self.studies.get(id).is_some()
+                || (self.options.corporate_events && EsdKind::from_study_id(id).is_some()),
+        }
+    }
+
+    // the message against the sessions, series and bars so far, before it's handled
+    fn check_invariants(&mut self, message_type: &MessageType, raw: &str) -> Result<(), Box<dyn Error + Send>> {
+        let expected = match message_type.session() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+        let message = message_type.name();
+        // quote updates weren't parsed into a tree, they only are here
+        let quote_data = match message_type {
+            MessageType::QuoteData => json::parse(raw).ok(),
+            _ => None,
+        };
+        let session = message_type.payload().or(quote_data.as_ref()).and_then(|payload| lookup(payload, "p.0")).and_then(Value::as_str);
+        let mut violations = vec![];
+        let known = [SessionKind::Quote, SessionKind::Chart].into_iter().any(|kind| kind.id() == expected && self.opens_session(kind));
+        if !known || session != Some(expected) {
+            violations.push(Violation::UnknownSession { message: message.to_string(), session: session.unwrap_or_default().to_string() });
+        }
+        let (payload, live) = match *message_type {
+            MessageType::TimescaleUpdate(ref payload) => (payload, false),
+            MessageType::SeriesUpdate(ref payload) | MessageType::StudyUpdate(ref payload) => (payload, true),
+            _ => return self.report_violations(violations, raw),
+        };
+        if let Some(Value::Object(updates)) = lookup(payload, "p.1") {
+            // next to the series the timescale's own index, zoffset, changes and marks
+            for (id, update) in updates.iter().filter(|(_, update)| update.is_object()) {
+                if !self.knows_series(id) {
+                    violations.push(Violation::UnknownSeries { message: message.to_string(), series: id.clone() });
+                } else if id.starts_with("series_id") && id != TICK_SERIES_ID {
+                    let times: Vec<i64> = bar::parse_series_bars(update).iter().map(|bar| bar.time).collect();
+                    violations.extend(self.invariants.check_bar_times(message, id, &times, live));
+                }
+            }
+        }
+        self.report_violations(violations, raw)
+    }
+
+    fn report_violations(&self, violations: Vec<Violation>, raw: &str) -> Result<(), Box<dyn Error + Send>> {
+        for violation in violations {
+            if self.invariants.panics() {
+                panic!("protocol invariant violated: {} in {}", violation, raw);
+            }
+            warn!("protocol invariant violated: {} in {}", violation, raw);
+            self.options.stats.record_error("protocol_violation");
+            self.emit(Event::ProtocolViolation { violation })?;
+        }
+        Ok(())
+    }
+
+    pub fn handler(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        // per-frame temporaries, reset instead of freed once the frame is handled
+        let mut arena = Bump::new();
+        let mut heartbeat_deadline = Instant::now() + self.options.heartbeat_timeout;
         loop {
-            let incoming_messages: String = self.incoming_rx.recv().map_err(|_| TradingViewError::ReceiveError)?;
+            self.expire_commands()?;
+            self.emit_stats_if_due()?;
+            let sampled = self.filters.get_mut().due(Instant::now());
+            for event in sampled {
+                self.deliver(event)?;
+            }
+            let conflated = !self.flush_conflated(&mut self.conflation.borrow_mut())?;
+            let replies = self.keepalive.due(Instant::now(), &self.options.stats);
+            if !replies.is_empty() {
+                self.outgoing_tx.send(Outgoing::Unprompted(replies)).map_err(|_| TradingViewError::SendError("outgoing"))?;
+            }
+            // every ping or data frame restarts the timeout, commands don't; unanswered commands,
+            // held quotes, held ping replies, quotes the filters sampled away and the next Stats
+            // event wake the loop early
+            let mut wait_until = self.acks.get_mut().next_deadline().map_or(heartbeat_deadline, |deadline| deadline.min(heartbeat_deadline));
+            if conflated {
+                wait_until = wait_until.min(Instant::now() + conflate::CONFLATION_RETRY_INTERVAL);
+            }
+            if let Some(due) = self.keepalive.next_due().into_iter().chain(self.stats_due).chain(self.filters.get_mut().next_due()).min() {
+                wait_until = wait_until.min(due);
+            }
+            let incoming_messages = match self.incoming_rx.recv_timeout(wait_until.saturating_duration_since(Instant::now())) {
+                Ok(Incoming::Frame(incoming_messages, received)) => {
+                    self.frame_received = Some(received);
+                    if self.stats_due.is_some() {
+                        self.rates.get_mut().record_frame(incoming_messages.len());
+                    }
+                    heartbeat_deadline = received + self.options.heartbeat_timeout;
+                    incoming_messages
+                }
+                Ok(Incoming::Command(command, correlation_id)) => {
+                    self.correlation_id = correlation_id;
+                    let messages = self.command_messages(&command);
+                    self.correlation_id = self.options.correlation_id;
+                    let messages = messages?;
+                    if !messages.is_empty() {
+                        self.outgoing_tx.send(Outgoing::Unprompted(messages)).map_err(|_| TradingViewError::SendError("outgoing"))?;
+                    }
+                    continue;
+                }
+                Ok(Incoming::Shutdown) => {
+                    self.outgoing_tx.send(Outgoing::Reply(self.teardown_messages())).map_err(|_| TradingViewError::SendError("outgoing"))?;
+                    return Ok(());
+                }
+                Err(RecvTimeoutError::Timeout) if Instant::now() < heartbeat_deadline => continue,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.frame_received = None;
+                    return self.handle_stale_feed();
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    // the websocket side hung up and reports its own failure
+                    debug!("websocket channel closed, stopping");
+                    return Ok(());
+                }
+            };
+            self.emit_session_stats_if_due()?;
+            // pings are most of the traffic, so they skip the parser
+            let parse_started = Instant::now();
+            if let Some((payload, id)) = ping_frame_payload(&incoming_messages) {
+                trace!("ping:{}", payload);
+                self.options.stats.record_message(payload.len(), parse_started.elapsed());
+                self.options.stats.record_message_type("ping");
+                if self.stats_due.is_some() {
+                    self.rates.get_mut().record_message(None);
+                }
+                // the websocket waits for an answer to every frame, even an empty one
+                let reply = self.keepalive.ping(id, self.frame_received.unwrap_or(parse_started), Instant::now(), &self.options.stats);
+                self.outgoing_tx.send(Outgoing::Reply(reply.into_iter().collect())).map_err(|_| TradingViewError::SendError("outgoing"))?;
+                continue;
+            }
+            arena.reset();
+            // pooled frames come back with their own list, only one of these is ever set
+            let (frame, pooled, inline) = match self.parser {
+                Some(ref parser) => {
+                    let ParsedFrame { frame, messages } = parser.parse(incoming_messages).map_err(TradingViewError::from)?;
+                    (frame, Some(messages), None)
+                }
+                None => {
+                    let messages = parse_frame_in(&incoming_messages, &arena);
+                    (incoming_messages, None, Some(messages))
+                }
+            };
+            if let Some(received) = self.frame_received {
+                self.options.stats.record_parse_latency(received.elapsed());
+            }
             let mut responses = vec![];
-            let re = regex::Regex::new(r"~m~\d+~m~").unwrap();
-            for incoming_message in re.split(&incoming_messages).into_iter() {
-                let message_type = self.determine_incoming_message_type(&incoming_message)?;
+            for ParsedMessage { span, parse_time, message_type } in pooled.into_iter().flatten().chain(inline.into_iter().flatten()) {
+                let raw = &frame[span];
+                if !raw.is_empty() {
+                    self.options.stats.record_message(raw.len(), parse_time);
+                }
+                // one bad message shouldn't take the whole feed down
+                let message_type = match message_type {
+                    Ok(message_type) => message_type,
+                    Err(err) if self.options.parse_mode == ParseMode::Strict => return Err(err),
+                    Err(err) => {
+                        if !self.capture_unknown(raw, &*err) {
+                            warn!("skipping message: {}", err);
+                        }
+                        self.options.stats.record_error("parse");
+                        continue;
+                    }
+                };
+                // quote messages fill in their symbol once it's been read
+                let session_span = match message_type.session() {
+                    Some(QUOTE_SESSION) => tracing::debug_span!("quote_session", id = QUOTE_SESSION, symbol = tracing::field::Empty),
+                    Some(session) => tracing::debug_span!("chart_session", id = session, symbol = self.symbol()),
+                    None => tracing::Span::none(),
+                };
+                let _session = session_span.enter();
+                if let Some(ref health) = self.health {
+                    health.record_message(message_type.session());
+                }
+                let message_name = message_type.name();
+                self.options.stats.record_message_type(message_name);
+                if self.stats_due.is_some() {
+                    self.rates.get_mut().record_message(message_type.session());
+                }
+                let logged = !matches!(message_type, MessageType::Ping(_));
+                if self.invariants.enabled() {
+                    self.check_invariants(&message_type, raw)?;
+                }
                 match message_type {
-                    MessageType::ConnectedToServer(message) => {
-                        println!("{}", message);
-                        // login
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "set_auth_token",
-                            "p": [
-                                "unauthorized_user_token"
-                            ]
-                        })));
-                        // create quote
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "quote_create_session",
-                            "p": [
-                                "quote_session_id",
-                            ]
-                        })));
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "quote_set_fields",
-                            "p": [
-                                "quote_session_id",
-                                "base-currency-logoid",
-                                "ch",
-                                "chp",
-                                "currency-logoid",
-                                "currency_code",
-                                "currency_id",
-                                "base_currency_id",
-                                "current_session",
-                                "description",
-                                "exchange",
-                                "format",
-                                "fractional",
-                                "is_tradable",
-                                "language",
-                                "local_description",
-                                "listed_exchange",
-                                "logoid",
-                                "lp",
-                                "lp_time",
-                                "minmov",
-                                "minmove2",
-                                "original_name",
-                                "pricescale",
-                                "pro_name",
-                                "short_name",
-                                "type",
-                                "typespecs",
-                                "update_mode",
-                                "volume",
-                                "value_unit_id",
-                                "rchp",
-                                "rtc",
-                                "country_code",
-                                "provider_id"
-                            ]
-                        })));
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "quote_add_symbols",
-                            "p": [
-                                "quote_session_id",
-                                r#"={"session":"regular", "symbol": "CRYPTO:BTCUSD"}"#
-                            ]
-                        })));
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "quote_fast_symbols",
-                            "p": [
-                                "quote_session_id",
-                                "INDEX:BTCUSD"
-                            ]
-                        })));
-                        // create chart
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "chart_create_session",
-                            "p": [
-                                "chart_session_id",
-                                ""
-                            ]
-                        })));
-                        // add symbol to chart
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "resolve_symbol",
-                            "p": [
-                                "chart_session_id",
-                                "symbol_id",
-                                r#"={"session":"regular", "symbol": "CRYPTO:BTCUSD"}"#
-                            ]
-                        })));
-                        // add candles to chart
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "create_series",
-                            "p": [
-                                "chart_session_id",
-                                "series_id",
-                                "study_parent_id",
-                                "symbol_id",
-                                "1",
-                                300,
-                                ""
-                            ]
-                        })));
-                        // add indicator to chart
-                        responses.push(self.format_json_response(serde_json::json!({
-                            "m": "create_study",
-                            "p": [
-                                "chart_session_id",
-                                "study_id",
-                                "study_parent_id",
-                                "series_id",
-                                "Script@tv-scripting-101!",
-                                serde_json::json!({
-                                    "text": "OvVf/cLhRZ8QR5Vpxqne7w==_pKuthoDJLaA6sn40TmHddOk0SwJb9ct8cm5JeGz0a5O4YBeoFgtEgyKwwKcVk+KQMJV96wVs+ms71b8+nds3580VFsC3U3MQvGaF+Xidbsm/vP9HK+rGeR/2iTxMfDT+sRSuAcY4mm/u9CPgHlc/1U5QoLL0+qSxw6spC2g33HJDdjZkWojBpa50yH0oELcUqVKNbKFX/RFReEzTqpc0Moo10cw8IVnBIp5Fu1SPEM2AIASQaI58LmwDyNdo2d/Rqn3u7JyRqt+TYu+asL9NynYoLVtTem2BonOTknu7NoBkQI9GJgMdxE4+jU9efxZk8jOGgP9XQPWAhX5jmZJDefGl1s2c/09TM29lPzUTFJRyyfmtZShBdiP3BqRfYXzEr6vCNetnsebCenWWkQtDjQ80ZgBV+HB8rciWhB34jXZ/MA8sGtT1lbknJbX5koliQ/pDj4tYY3Mp6eon+jvVDO6EyxTNk/9tj5h8b1Jdqy1svNAfr5MF3TfksELRGkzKFLxPNQUZz+Cn60T7vP/Qi+HDM/mfwdiYkaLXSXDQ6VkDc+K8vxJkYWRWONghVnzbeqhCYn747OB0u0xWxs1O+D0KjRq9CEjgsRLmMDqg2KLrdGRGrEpNjwy6jb31SXDQLR+IdKgSD/O71iNXXcd3KGdDXQpi0c70NuaKdUEGWIpBRjp6tFOTGp8yJHkwFJPkic9yGVQRMbqTctqbGHbaxVNvbhZdnhkl2bkTh7wkDXsYjxt2jTtAYlwq6RoJmzlKBBj2VR894emRQyipvvAz6bjxnQZC8zqxR/BF7HnzLtVMIMr+0nE0Ol0TDDkpkMsAiM5zH4212LNyOU4obRzYhwCuOR8L+W3/+fDhOHg+tSseK+d4QrFkn+qFsVHqEpeVoyIQDm1wwHsFiqN6by4Du4LtxHMRuasSzajwmxQNOe+qbbALRtpiVMFL/BVdH0bk0r43mnMC3s9CHcDB2CMCk4TjZZwNfWmQVQGqprukCQJFtqNY+SnK26rYby9/a2WnbnRW6lLcazUfwQHf6wPHfLLlNYiAayuUsPZyNZGnwvBkFZK6GG2eYZYam2XurXk2uMZRusQVuw6nDPk1R6CKg+KILriNHp2b2TM2zb4jogmbrqug3nqGky8oM9n/1lIsht+Jm8GztD99g2j/7crHI6DgZ3Bu8LKdmm7t+cnsPBLLNncdnbQhow1WZTffmi0=",
-                                    "pineId": "PUB;N16MOYK6AEJGGAoy40axs0S48GRFYcNn",
-                                    "pineVersion": "1.0",
-                                    "in_0": {
-                                        "v": 1,
-                                        "f": true,
-                                        "t": "integer"
-                                    },
-                                    "in_1": {
-                                        "v": "close",
-                                        "f": true,
-                                        "t": "source"
-                                    },
-                                    "in_2": {
-                                        "v": 7,
-                                        "f": true,
-                                        "t": "integer"
-                                    },
-                                    "in_3": {
-                                        "v": "close",
-                                        "f": true,
-                                        "t": "source"
-                                    },
-                                    "in_4": {
-                                        "v": 25,
-                                        "f": true,
-                                        "t": "integer"
-                                    },
-                                    "in_5": {
-                                        "v": 65,
-                                        "f": true,
-                                        "t": "integer"
-                                    },
-                                    "in_6": {
-                                        "v": 51,
-                                        "f": true,
-                                        "t": "integer"
-                                    },
-                                    "in_7": {
-                                        "v": 21,
-                                        "f": true,
-                                        "t": "integer"
-                                    }
-                                    })
-                            ]
-                        })));
+                    MessageType::ConnectedToServer(server) => {
+                        info!("{}", raw);
+                        if let (Some(server_ms), Some(received)) = (server.timestamp_ms, self.frame_received) {
+                            self.options.clock.record_connect(server_ms, SystemTime::now() - received.elapsed());
+                            self.report_clock_skew()?;
+                        }
+                        if !server.speaks_json() {
+                            warn!("server announced protocol {:?}, only json is understood", server.protocol);
+                        }
+                        if !server.supports_studies() {
+                            warn!("server announced no study engine, studies are left out");
+                        }
+                        if let Ok(mut server_info) = self.options.server_info.lock() {
+                            *server_info = Some(server.clone());
+                        }
+                        self.server = Some(server.clone());
+                        self.emit(Event::Connected { server })?;
+                        self.health = Some(self.options.health.connect());
+                        for (symbol, reason) in std::mem::take(&mut self.rejected_symbols) {
+                            self.report_subscription_failure(symbol, reason, None)?;
+                        }
+                        responses.extend(self.bootstrap_messages());
                     },
                     MessageType::Ping(id) => {
-                        println!("ping:{}", id);
-                        let response = format!("~h~{id}");
-                        let formatted_response = format!("~m~{}~m~{}", response.len(), response);
-                        responses.push(formatted_response);
+                        trace!("ping:{}", id);
+                        let received = self.frame_received.unwrap_or_else(Instant::now);
+                        responses.extend(self.keepalive.ping(id, received, Instant::now(), &self.options.stats));
                     },
-                    MessageType::QsdBidAsk(message) => {
-                        println!("{}", message);
-                    }
-                    MessageType::QsdDescription(message) => {
-                        println!("{}", message);
-                    }
-                    MessageType::QsdLocalPopularity(message) => {
-                        println!("{}", message);
+                    MessageType::QuoteData => {
+                        self.handle_quote_data(raw, &mut responses)?;
                     }
                     MessageType::QuoteCompleted(message) => {
-                        println!("{}", message);
-                    }
-                    MessageType::SeriesLoading(message) => {
-                        println!("{}", message);
+                        // {"m":"quote_completed","p":[session, symbol]}
+                        self.acknowledge(&message)?;
+                        if let Some(symbol) = lookup(&message, "p.1").and_then(Value::as_str) {
+                            self.emit(Event::QuoteCompleted { symbol: symbol.to_string() })?;
+                        }
                     }
+                    MessageType::SeriesLoading(_) => {}
                     MessageType::SymbolResolved(message) => {
-                        println!("{}", message);
+                        self.acknowledge(&message)?;
+                        self.handle_symbol_resolved(&message)?;
                     }
                     MessageType::TimescaleUpdate(message) => {
-                        println!("{}", message);
+                        self.handle_series_bars(&message)?;
+                        self.handle_study_values(&message)?;
                     }
                     MessageType::SeriesCompleted(message) => {
-                        println!("{}", message);
-                    }
-                    MessageType::StudyLoading(message) => {
-                        println!("{}", message);
-                    }
-                    MessageType::QsdLastPriceTime(message) => {
-                        println!("{}", message);
-                    }
-                    MessageType::QsdLastPrice(message) => {
-                        println!("{}", message);
+                        self.acknowledge(&message)?;
+                        let series_id = message.dot_get::<String>("p.1").ok().flatten();
+                        let extra = series_id.and_then(|id| self.extra_series.iter().position(|series| extra_series_id(&series.interval) == id));
+                        if let Some(index) = extra {
+                            match self.next_extra_history_request(index) {
+                                Some(request) => responses.push(request),
+                                None => {
+                                    let backfill = self.extra_series[index].backfill.take();
+                                    let interval = self.extra_series[index].interval.clone();
+                                    self.finish_backfill(backfill, interval)?;
+                                }
+                            }
+                        } else if let Some(request) = self.next_history_request() {
+                            responses.push(request);
+                        } else if let Mode::Download(_) = self.mode {
+                            let symbol = self.symbol().to_string();
+                            self.emit(Event::HistoryCompleted { symbol })?;
+                            // delete the chart session before dropping the outgoing channel closes the websocket
+                            let _ = self.outgoing_tx.send(Outgoing::Reply(self.teardown_messages()));
+                            return Ok(());
+                        } else {
+                            let backfill = self.backfill.take();
+                            let interval = self.interval().to_string();
+                            self.finish_backfill(backfill, interval)?;
+                        }
                     }
+                    MessageType::StudyLoading(_) => {}
                     MessageType::SeriesUpdate(message) => {
-                        println!("{}", message);
+                        self.handle_series_bars(&message)?;
+                        self.handle_study_values(&message)?;
                     }
                     MessageType::StudyUpdate(message) => {
-                        println!("{}", message);
+                        self.handle_study_values(&message)?;
                     }
                     MessageType::StudyCompleted(message) => {
-                        println!("{}", message);
+                        self.acknowledge(&message)?;
+                    }
+                    MessageType::SymbolError(message) => {
+                        self.acknowledge(&message)?;
+                        self.handle_symbol_error(message)?;
                     }
-                    MessageType::Empty => {},
                     MessageType::StudyError(message) => {
-                        return Err(Box::new(TradingViewError::StudyError(message)));
+                        self.acknowledge(&message)?;
+                        self.handle_server_error(ServerErrorKind::Study, message, raw, &mut responses)?;
                     }
                     MessageType::CriticalError(message) => {
-                        return Err(Box::new(TradingViewError::CriticalError(message)));
+                        self.handle_server_error(ServerErrorKind::Critical, message, raw, &mut responses)?;
                     }
                     MessageType::ProtocolError(message) => {
-                        return Err(Box::new(TradingViewError::ProtocolError(message)));
+                        self.handle_server_error(ServerErrorKind::Protocol, message, raw, &mut responses)?;
                     },
                 }
+                // after handling, so the latency covers it and the session span has its symbol
+                if logged {
+                    let latency_us = self.frame_received.map(|received| received.elapsed().as_micros() as u64);
+                    tracing::debug!(message_type = message_name, latency_us, "{}", raw);
+                }
             }
-            self.outgoing_tx.send(responses).map_err(|_| TradingViewError::SendError)?;
+            self.outgoing_tx.send(Outgoing::Reply(responses)).map_err(|_| TradingViewError::SendError("outgoing"))?;
         }
     }
-}
\ No newline at end of file
+}
+
+// a continuous symbol's cached bars outlive the connection, the next one stitches them if the
+// symbol rolled in between
+impl Drop for TradingViewApi {
+    fn drop(&mut self) {
+        let symbol = self.symbol().to_string();
+        if !self.options.series.stitch_rolls || !symbol::is_continuous(&symbol) {
+            return;
+        }
+        self.options.checkpoints.hold_bars(&symbol, self.candles.bars(&symbol));
+        for series in &self.extra_series {
+            self.options.checkpoints.hold_bars(&checkpoint_key(&symbol, &series.interval), series.candles.bars(&symbol));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::http;
+use crate::tradingview_api::Mode;
+
+const CUSTOM_LISTS_URL: &str = "https://www.tradingview.com/api/v1/symbols_list/custom/";
+
+// the lists keep section headers among the symbols, e.g. "###Crypto"
+const SECTION_PREFIX: &str = "###";
+
+#[derive(Debug, Error)]
+pub enum WatchlistError {
+    #[error("watchlist request failed")]
+    Request(#[source] Box<dyn Error + Send>),
+    #[error("the session id was rejected (HTTP {0})")]
+    Unauthorized(u16),
+    #[error("unexpected HTTP status {0}")]
+    Status(u16),
+    #[error("watchlist response parse error")]
+    Parse(#[source] serde_json::Error),
+    #[error("no watchlist named {0:?}")]
+    NotFound(String),
+    #[error("watchlist {0:?} has no symbols")]
+    Empty(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchlist {
+    pub id: i64,
+    pub name: String,
+    // in the list's order, without section headers
+    pub symbols: Vec<String>
+}
+
+impl Watchlist {
+    fn from_json(list: &Value) -> Option<Watchlist> {
+        Some(Watchlist {
+            id: list.get("id")?.as_i64()?,
+            name: list.get("name")?.as_str()?.to_string(),
+            symbols: list.get("symbols")?.as_array()?.iter()
+                .filter_map(Value::as_str)
+                .filter(|symbol| !symbol.starts_with(SECTION_PREFIX))
+                .map(str::to_string)
+                .collect()
+        })
+    }
+}
+
+// the account's saved lists; the rest api goes by the browser's sessionid cookie, not the
+// auth token the websocket takes
+pub fn fetch_watchlists(session_id: &str) -> Result<Vec<Watchlist>, WatchlistError> {
+    let cookie = format!("sessionid={}", session_id);
+    let response = http::get(CUSTOM_LISTS_URL, &[("Cookie", &cookie), ("Referer", "https://www.tradingview.com/")]).map_err(WatchlistError::Request)?;
+    match response.status {
+        200..=299 => {},
+        401 | 403 => return Err(WatchlistError::Unauthorized(response.status)),
+        status => return Err(WatchlistError::Status(status)),
+    }
+    let lists: Value = serde_json::from_str(&response.body).map_err(WatchlistError::Parse)?;
+    Ok(lists.as_array().map(|lists| lists.iter().filter_map(Watchlist::from_json).collect()).unwrap_or_default())
+}
+
+// names are matched ignoring case, the website doesn't keep two lists apart by it either
+pub fn find_watchlist(session_id: &str, name: &str) -> Result<Watchlist, WatchlistError> {
+    fetch_watchlists(session_id)?.into_iter()
+        .find(|list| list.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| WatchlistError::NotFound(name.to_string()))
+}
+
+// quote mode for every member of the list, e.g. subscribe_watchlist(session_id, "Crypto Majors");
+// members that aren't plain symbols are reported as SubscriptionFailed once connected
+pub fn subscribe_watchlist(session_id: &str, name: &str) -> Result<Mode, WatchlistError> {
+    let list = find_watchlist(session_id, name)?;
+    if list.symbols.is_empty() {
+        return Err(WatchlistError::Empty(list.name));
+    }
+    Ok(Mode::Quotes(list.symbols))
+}
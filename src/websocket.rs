@@ -1,47 +1,62 @@
 use std::collections::VecDeque;
 use std::error::Error;
-use std::net::{TcpStream, ToSocketAddrs};
-use std::io::{Write, Read};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::io::{self, ErrorKind, Write, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{Sender, Receiver};
+use std::time::{Duration, Instant};
+use log::{info, trace, warn};
 use native_tls::TlsConnector;
+use thiserror::Error;
+
+use crate::ack::CorrelationId;
+use crate::fault::{FaultConfig, FaultyTransport};
+use crate::tradingview_api::ApiCommand;
 
 trait ReadWrite: Read + Write {}
 impl<T: Read + Write + ?Sized> ReadWrite for T {}
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum WebSocketError {
-    ReadError,
+    #[error("Read error")]
+    ReadError(#[source] io::Error),
+    #[error("Channel send error")]
     ChannelSendError,
+    #[error("Channel receive error")]
     ChannelReceiveError,
-    FrameEncodeError,
-    FrameDecodeError,
-    WriteError,
-    AddressParseError,
-    ConnectError,
-    TlsCreationError,
-    TlsConnectError,
-    StringConversionError
-}
-
-impl std::fmt::Display for WebSocketError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            WebSocketError::ReadError => write!(f, "Read error"),
-            WebSocketError::ChannelSendError => write!(f, "Channel send error"),
-            WebSocketError::ChannelReceiveError => write!(f, "Channel receive error"),
-            WebSocketError::FrameEncodeError => write!(f, "Frame encode error"),
-            WebSocketError::FrameDecodeError => write!(f, "Frame decode error"),
-            WebSocketError::WriteError => write!(f, "Write error"),
-            WebSocketError::AddressParseError => write!(f, "Address parse error"),
-            WebSocketError::ConnectError => write!(f, "Connect error"),
-            WebSocketError::TlsCreationError => write!(f, "TLS creation error"),
-            WebSocketError::TlsConnectError => write!(f, "TLS connect error"),
-            WebSocketError::StringConversionError => write!(f, "String conversion error"),
-        }
-    }
+    #[error("Frame decode error")]
+    FrameDecodeError(#[from] DecodeError),
+    #[error("Write error")]
+    WriteError(#[source] io::Error),
+    #[error("Address parse error: {host}")]
+    AddressParseError { host: &'static str, #[source] source: io::Error },
+    #[error("Connect error")]
+    ConnectError(#[source] io::Error),
+    #[error("TLS creation error")]
+    TlsCreationError(#[source] native_tls::Error),
+    #[error("TLS connect error")]
+    TlsConnectError(#[source] native_tls::HandshakeError<TcpStream>),
+    #[error("Handshake rejected: {0}")]
+    HandshakeError(String),
+    #[error("Connection closed without a close frame ({buffered} bytes unread)")]
+    UnexpectedEof { buffered: usize },
+    #[error("Receive buffer holds {size} bytes, over the {limit} byte limit")]
+    BufferOverflow { size: usize, limit: usize }
 }
 
-impl Error for WebSocketError {}
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("masked frame from server (opcode {opcode:#04x})")]
+    MaskedFrame { opcode: u8 },
+    #[error("payload length {length} too large (opcode {opcode:#04x})")]
+    PayloadTooLarge { opcode: u8, length: u64 },
+    #[error("close frame with invalid payload length {0}")]
+    InvalidClosePayload(usize),
+    #[error("unsupported opcode {0:#04x}")]
+    UnsupportedOpcode(u8)
+}
 
 impl From<WebSocketError> for Box<dyn std::error::Error + Send> {
     fn from(error: WebSocketError) -> Self {
@@ -49,190 +64,491 @@ impl From<WebSocketError> for Box<dyn std::error::Error + Send> {
     }
 }
 
+// 2 byte header, 8 byte extended length and the 4 byte mask
+const MAX_CLIENT_HEADER_SIZE: usize = 14;
+// how often a blocked read wakes up to check for shutdown
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Close(Option<u16>),
+    // pings, pongs, continuations and reserved opcodes, left to the FrameHandler
+    Other { opcode: u8, payload: Vec<u8> }
+}
+
+// what the websocket does with a frame that isn't text or close
+pub enum FrameAction {
+    // handed to the api as if it had come in a text frame
+    Text(String),
+    // a control frame written back right away, e.g. the pong a ping asks for
+    Reply { opcode: u8, payload: Vec<u8> },
+    Ignore,
+    // ends the connection with DecodeError::UnsupportedOpcode
+    Unsupported
+}
+
+// decides about binary and unknown frames, so protocol changes or other endpoints can be
+// handled without touching the decoder; set through ApiOptions::frame_handler
+pub trait FrameHandler: Send + Sync {
+    fn handle(&self, frame: Frame) -> FrameAction;
+}
+
+// binary frames holding utf-8 are read as text, TradingView's own messages sent binary; other
+// binary frames and pongs are dropped, pings are answered with a pong carrying their payload as
+// RFC 6455 asks, and anything else ends the connection as before
+pub struct DefaultFrameHandler;
+
+impl FrameHandler for DefaultFrameHandler {
+    fn handle(&self, frame: Frame) -> FrameAction {
+        match frame {
+            Frame::Binary(payload) => match String::from_utf8(payload) {
+                Ok(text) => FrameAction::Text(text),
+                Err(err) => {
+                    warn!("dropping {} byte binary frame", err.as_bytes().len());
+                    FrameAction::Ignore
+                }
+            },
+            Frame::Other { opcode: 0x09, payload } => FrameAction::Reply { opcode: 0x0A, payload },
+            Frame::Other { opcode: 0x0A, .. } => FrameAction::Ignore,
+            Frame::Other { opcode, payload } => {
+                warn!("unsupported frame: opcode {:#04x}, {} byte payload", opcode, payload.len());
+                FrameAction::Unsupported
+            }
+            Frame::Text(text) => FrameAction::Text(text),
+            Frame::Close(_) => FrameAction::Ignore,
+        }
+    }
+}
+
+pub enum Incoming {
+    // stamped with when the read that completed it returned
+    Frame(String, Instant),
+    Shutdown,
+    // sent by the application rather than the websocket, its messages are written unprompted;
+    // the id ends up in the events the command leads to
+    Command(ApiCommand, Option<CorrelationId>)
+}
+
+// what the api hands the websocket to write; a frame, and the shutdown, get exactly one Reply,
+// which the websocket waits for, and anything else goes out as it comes without taking its place
+pub enum Outgoing {
+    Reply(Vec<String>),
+    // command messages and held ping replies
+    Unprompted(Vec<String>)
+}
+
+impl Outgoing {
+    pub fn into_messages(self) -> Vec<String> {
+        match self {
+            Outgoing::Reply(messages) | Outgoing::Unprompted(messages) => messages,
+        }
+    }
+}
+
+impl Frame {
+    pub fn opcode(&self) -> u8 {
+        match *self {
+            Frame::Text(_) => 0x01,
+            Frame::Binary(_) => 0x02,
+            Frame::Close(_) => 0x08,
+            Frame::Other { opcode, .. } => opcode,
+        }
+    }
+}
+
+pub fn decode_frame(buffer: &mut VecDeque<u8>) -> Result<Option<Frame>, DecodeError> {
+    if buffer.len() < 2 {
+        return Ok(None);  // Not enough data
+    }
+
+    let opcode = buffer[0] & 0x0F;
+    // servers must never mask their frames
+    if buffer[1] & 0x80 != 0 {
+        return Err(DecodeError::MaskedFrame { opcode });
+    }
+
+    let (payload_length, header_size) = match buffer[1] & 0x7F {
+        length @ 0..=125 => (length as u64, 2), // Direct length encoding
+        126 => {
+            if buffer.len() < 4 {
+                return Ok(None);  // Not enough data
+            }
+            (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4)
+        },
+        _ => {
+            if buffer.len() < 10 {
+                return Ok(None);  // Not enough data
+            }
+            let length_bytes = [
+                buffer[2], buffer[3], buffer[4], buffer[5],
+                buffer[6], buffer[7], buffer[8], buffer[9]
+            ];
+            (u64::from_be_bytes(length_bytes), 10)
+        },
+    };
+    // usize can be 32 bits, so reject lengths we can't address
+    let frame_length = usize::try_from(payload_length).ok()
+        .and_then(|length| length.checked_add(header_size))
+        .ok_or(DecodeError::PayloadTooLarge { opcode, length: payload_length })?;
+
+    if buffer.len() < frame_length {
+        return Ok(None);  // Not enough data
+    }
+
+    buffer.drain(0..header_size);
+    let payload: Vec<u8> = buffer.drain(0..frame_length - header_size).collect();
+
+    match opcode {
+        0x01 => {  // Text frame
+            // one bad sequence shouldn't cost us the whole frame
+            let payload_str = String::from_utf8(payload).unwrap_or_else(|err| {
+                warn!("invalid utf-8 in text frame at byte {}, replacing", err.utf8_error().valid_up_to());
+                String::from_utf8_lossy(err.as_bytes()).into_owned()
+            });
+            Ok(Some(Frame::Text(payload_str)))
+        }
+        0x08 => {  // Close frame, the status code is optional
+            match payload.len() {
+                0 => Ok(Some(Frame::Close(None))),
+                1 => Err(DecodeError::InvalidClosePayload(1)),
+                _ => Ok(Some(Frame::Close(Some(u16::from_be_bytes([payload[0], payload[1]]))))),
+            }
+        }
+        0x02 => Ok(Some(Frame::Binary(payload))),
+        _ => Ok(Some(Frame::Other { opcode, payload })),
+    }
+}
+
+// client frames must be masked, with a fresh random mask each
+pub fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    encode_frame_with_mask(opcode, payload, rand::random())
+}
+
+// the payload is copied once and masked in place 8 bytes at a time
+pub fn encode_frame_with_mask(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let payload_length = payload.len();
+    let mut frame = Vec::with_capacity(MAX_CLIENT_HEADER_SIZE + payload_length);
+
+    frame.push(0x80 | opcode); // Final fragment
+
+    // Determine payload length format and write it to the frame
+    match payload_length {
+        len if len <= 125 => frame.push(0x80 | len as u8),
+        len if len <= 65_535 => {
+            frame.push(0x80 | 126); // Mask set and indicator for 2-byte extended length
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127); // Mask set and indicator for 8-byte extended length
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    frame.extend_from_slice(&mask);
+
+    let payload_start = frame.len();
+    frame.extend_from_slice(payload);
+    let wide_mask = u64::from_ne_bytes([mask[0], mask[1], mask[2], mask[3], mask[0], mask[1], mask[2], mask[3]]);
+    let mut chunks = frame[payload_start..].chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        let masked = u64::from_ne_bytes(chunk.try_into().expect("chunk is 8 bytes")) ^ wide_mask;
+        chunk.copy_from_slice(&masked.to_ne_bytes());
+    }
+    // chunks are a multiple of 4 long, so the remainder starts at mask[0] again
+    for (i, byte) in chunks.into_remainder().iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    frame
+}
+
+// what a server sends, unmasked; for the mock server and fault injection
+pub fn encode_server_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MAX_CLIENT_HEADER_SIZE + payload.len());
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len <= 125 => frame.push(len as u8),
+        len if len <= 65_535 => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// (opcode, unmasked payload) of the first whole frame a client sent, what the mock server reads
+pub fn decode_client_frame(buffer: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    if buffer.len() < 2 {
+        return None;
+    }
+    let opcode = buffer[0] & 0x0F;
+    let masked = buffer[1] & 0x80 != 0;
+    let (length, mut offset) = match buffer[1] & 0x7F {
+        126 => (u16::from_be_bytes(buffer.get(2..4)?.try_into().ok()?) as usize, 4),
+        127 => (u64::from_be_bytes(buffer.get(2..10)?.try_into().ok()?) as usize, 10),
+        length => (length as usize, 2),
+    };
+    let mask: Option<[u8; 4]> = match masked {
+        true => {
+            let mask = buffer.get(offset..offset + 4)?.try_into().ok()?;
+            offset += 4;
+            Some(mask)
+        }
+        false => None,
+    };
+    let mut payload = buffer.get(offset..offset.checked_add(length)?)?.to_vec();
+    if let Some(mask) = mask {
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[index % 4];
+        }
+    }
+    buffer.drain(..offset + length);
+    Some((opcode, payload))
+}
+
+pub fn encode_text_frame(data: &str) -> Vec<u8> {
+    encode_frame(0x01, data.as_bytes())
+}
+
+pub fn encode_close_frame(status_code: u16) -> Vec<u8> {
+    encode_frame(0x08, &status_code.to_be_bytes())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endpoint {
+    #[default]
+    Chart,
+    // the embeddable widgets' server, quotes only but lighter
+    WidgetData,
+    // a plain ws:// server speaking the chart protocol, e.g. testing::MockServer
+    Plain(SocketAddr)
+}
+
+impl Endpoint {
+    pub fn host(&self) -> &'static str {
+        match *self {
+            Endpoint::Chart => "data.tradingview.com",
+            Endpoint::WidgetData => "widgetdata.tradingview.com",
+            Endpoint::Plain(_) => "localhost",
+        }
+    }
+
+    fn address(&self) -> Result<SocketAddr, WebSocketError> {
+        let host = match *self {
+            Endpoint::Chart => "data.tradingview.com:443",
+            Endpoint::WidgetData => "widgetdata.tradingview.com:443",
+            Endpoint::Plain(addr) => return Ok(addr),
+        };
+        host.to_socket_addrs()
+            .and_then(|mut addrs| addrs.next().ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no addresses found")))
+            .map_err(|source| WebSocketError::AddressParseError { host, source })
+    }
+
+    fn path(&self) -> &'static str {
+        match *self {
+            Endpoint::Chart | Endpoint::Plain(_) => "/socket.io/websocket?&type=chart",
+            Endpoint::WidgetData => "/socket.io/websocket?from=embed-widget&type=quotes",
+        }
+    }
+
+    fn origin(&self) -> &'static str {
+        match *self {
+            Endpoint::Chart | Endpoint::Plain(_) => "https://www.tradingview.com",
+            Endpoint::WidgetData => "https://s.tradingview.com",
+        }
+    }
+}
+
 pub struct WebSocket {
     tls_stream: Box<dyn ReadWrite + Send + Sync + 'static>,
-    incoming_tx: Sender<String>,
-    outgoing_rx: Receiver<Vec<String>>
+    early_data: Vec<u8>,
+    incoming_tx: Sender<Incoming>,
+    outgoing_rx: Receiver<Outgoing>,
+    shutdown: Arc<AtomicBool>,
+    max_rx_buffer: usize,
+    frame_handler: Arc<dyn FrameHandler>
 }
 
 impl WebSocket {
-    pub fn new(incoming_tx: Sender<String>, outgoing_rx: Receiver<Vec<String>>) -> Result<WebSocket, Box<dyn Error + Send>> {
-        let addr = "data.tradingview.com:443".to_socket_addrs().map_err(|_| WebSocketError::AddressParseError)?.next().unwrap();
-        let stream = TcpStream::connect(addr).map_err(|_| WebSocketError::ConnectError)?;
-
-        // Establish a TLS connection
-        let connector = TlsConnector::new().map_err(|_| WebSocketError::TlsCreationError)?;
-        let mut tls_stream = connector.connect("data.tradingview.com", stream).map_err(|_| WebSocketError::TlsConnectError)?;
-        
+    pub fn new(endpoint: Endpoint, incoming_tx: Sender<Incoming>, outgoing_rx: Receiver<Outgoing>, shutdown: Arc<AtomicBool>, max_rx_buffer: usize) -> Result<WebSocket, Box<dyn Error + Send>> {
+        let stream = TcpStream::connect(endpoint.address()?).map_err(WebSocketError::ConnectError)?;
+        // the timeout is set once the handshakes are done, through a handle that outlives the tls wrapper
+        let socket = stream.try_clone().map_err(WebSocketError::ConnectError)?;
+
+        // Establish a TLS connection, unless it's a local plain one
+        let mut tls_stream: Box<dyn ReadWrite + Send + Sync + 'static> = match endpoint {
+            Endpoint::Plain(_) => Box::new(stream),
+            _ => {
+                let connector = TlsConnector::new().map_err(WebSocketError::TlsCreationError)?;
+                Box::new(connector.connect(endpoint.host(), stream).map_err(WebSocketError::TlsConnectError)?)
+            }
+        };
+
         // Perform the WebSocket handshake with the server manually.
-        let request = "\
-            GET /socket.io/websocket?&type=chart HTTP/1.1\r\n\
-            Host: data.tradingview.com\r\n\
+        let request = format!("\
+            GET {} HTTP/1.1\r\n\
+            Host: {}\r\n\
             Connection: Upgrade\r\n\
             Upgrade: websocket\r\n\
             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
             Sec-WebSocket-Version: 13\r\n\
-            Origin: https://www.tradingview.com\r\n\
-            \r\n";
-        tls_stream.write_all(request.as_bytes()).map_err(|_| WebSocketError::WriteError)?;
+            Origin: {}\r\n\
+            \r\n", endpoint.path(), endpoint.host(), endpoint.origin());
+        tls_stream.write_all(request.as_bytes()).map_err(WebSocketError::WriteError)?;
 
         // Read the server's response to ensure it's a 101 Switching Protocols response.
         let mut buffer = [0u8; 65536];
-        tls_stream.read(&mut buffer).map_err(|_| WebSocketError::ReadError)?;
-        let response = std::str::from_utf8(&buffer).map_err(|_| WebSocketError::StringConversionError)?;
-        assert!(response.contains("101 Switching Protocols"));
+        let read_bytes = tls_stream.read(&mut buffer).map_err(WebSocketError::ReadError)?;
+        let response = String::from_utf8_lossy(&buffer[..read_bytes]);
+        if !response.contains("101 Switching Protocols") {
+            let status_line = response.lines().next().unwrap_or_default().to_string();
+            return Err(WebSocketError::HandshakeError(status_line).into());
+        }
+        socket.set_read_timeout(Some(READ_TIMEOUT)).map_err(WebSocketError::ConnectError)?;
+        // the first frames may have come in the same read as the response
+        let handshake_end = buffer[..read_bytes].windows(4).position(|window| window == b"\r\n\r\n").map_or(read_bytes, |end| end + 4);
 
         Ok(WebSocket { 
-            tls_stream: Box::new(tls_stream),
+            tls_stream,
+            early_data: buffer[handshake_end..read_bytes].to_vec(),
             incoming_tx,
-            outgoing_rx
+            outgoing_rx,
+            shutdown,
+            max_rx_buffer,
+            frame_handler: Arc::new(DefaultFrameHandler)
         })
     }
     
-    fn decode_frame(&self, buffer: &mut VecDeque<u8>) -> Result<Option<String>, Box<dyn Error>> {
-        if buffer.len() < 2 {
-            return Ok(None);  // Not enough data
-        }
-
-        let fin_and_opcode = buffer[0];
-        let opcode = fin_and_opcode & 0x0F;
-
-        match opcode {
-            0x01 => {  // Text frame
-                let mask_and_length_byte = buffer[1];
-                let (payload_length, header_size) = match mask_and_length_byte & 0x7F {
-                    0..=125 => (mask_and_length_byte as usize, 2), // Direct length encoding
-                    126 => {
-                        if buffer.len() < 4 {
-                            return Ok(None);  // Not enough data
-                        }
-                        (u16::from_be_bytes([buffer[2], buffer[3]]) as usize, 4)
-                    },
-                    127 => {
-                        if buffer.len() < 10 {
-                            return Ok(None);  // Not enough data
-                        }
-                        // Note: Since usize can be 32-bits on some platforms (like 32-bit systems), 
-                        // this can potentially be a problem if the length is greater than usize::MAX.
-                        // You might want to handle this scenario, e.g., by rejecting too-large messages.
-                        let length_bytes = [
-                            buffer[2], buffer[3], buffer[4], buffer[5], 
-                            buffer[6], buffer[7], buffer[8], buffer[9]
-                        ];
-                        (u64::from_be_bytes(length_bytes) as usize, 10)
-                    },
-                    _ => return Err("Invalid payload length format".into())
-                };
-
-                if buffer.len() < (header_size + payload_length) {
-                    return Ok(None);  // Not enough data
-                }
+    // every frame goes through the fault injector, the ones that came with the handshake too
+    pub fn with_faults(self, config: FaultConfig) -> WebSocket {
+        let mut transport = FaultyTransport::new(self.tls_stream, config);
+        transport.feed(&self.early_data);
+        WebSocket { tls_stream: Box::new(transport), early_data: vec![], ..self }
+    }
 
-                // Drain the header bytes
-                for _ in 0..header_size {
-                    buffer.pop_front();
-                }
+    pub fn with_frame_handler(self, frame_handler: Arc<dyn FrameHandler>) -> WebSocket {
+        WebSocket { frame_handler, ..self }
+    }
 
-                // Drain and collect the payload bytes
-                let payload_bytes: Vec<u8> = buffer.drain(0..payload_length).collect();
-                let payload_str = std::str::from_utf8(&payload_bytes)?;
+    pub fn close(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        let frame = encode_close_frame(1000);
+        self.tls_stream.write_all(&frame).map_err(WebSocketError::WriteError)?;
+        Ok(())
+    }
 
-                Ok(Some(payload_str.to_string()))
-            }
-            0x88 => {  // Close frame
-                // Handle the close frame
-                // For example, if you want to print the status code:
-                let status_code = u16::from_be_bytes([buffer[2], buffer[3]]);
-                println!("Received close frame with status code: {}", status_code);
-                buffer.drain(0..4);  // Drain the entire frame, including status code
-                Ok(None)  // Or you can choose to return an error or another appropriate result
-            }
-            // Add handling for other frame types if needed...
-            _ => {
-                println!("{:02x?}", buffer);
-                Err("Unsupported frame type".into())
-            }
+    fn write_messages(&mut self, outgoing_messages: Vec<String>) -> Result<(), Box<dyn Error + Send>> {
+        for outgoing_message in outgoing_messages {
+            trace!("outgoing_message: {}", outgoing_message);
+            let encoded_frame = encode_text_frame(&outgoing_message);
+            self.tls_stream.write_all(&encoded_frame).map_err(WebSocketError::WriteError)?;
         }
+        Ok(())
     }
 
-    fn encode_text_frame(&self, data: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut frame = vec![];
-
-        let payload_length = data.len();
-
-        frame.push(0x81); // Final fragment, text frame
+    // command messages come in between frame replies, so whatever is queued goes out right away
+    fn drain_outgoing(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        while let Ok(outgoing) = self.outgoing_rx.try_recv() {
+            self.write_messages(outgoing.into_messages())?;
+        }
+        Ok(())
+    }
 
-        // Determine payload length format and write it to the frame
-        match payload_length {
-            len if len <= 125 => {
-                frame.push(0x80 | len as u8);
-            }
-            len if len <= 65_535 => {
-                frame.push(0x80 | 126); // Mask set and indicator for 2-byte extended length
-                frame.extend(&[(len >> 8) as u8, len as u8]); // 2-byte big-endian length
-            }
-            len => {
-                frame.push(0x80 | 127); // Mask set and indicator for 8-byte extended length
-                frame.extend(&[
-                    ((len >> 56) & 0xFF) as u8,
-                    ((len >> 48) & 0xFF) as u8,
-                    ((len >> 40) & 0xFF) as u8,
-                    ((len >> 32) & 0xFF) as u8,
-                    ((len >> 24) & 0xFF) as u8,
-                    ((len >> 16) & 0xFF) as u8,
-                    ((len >> 8) & 0xFF) as u8,
-                    (len & 0xFF) as u8,
-                ]); // 8-byte big-endian length
+    // writes what the api sends until the reply to what it was just handed; false when the api
+    // hung up first
+    fn await_reply(&mut self) -> Result<bool, Box<dyn Error + Send>> {
+        while let Ok(outgoing) = self.outgoing_rx.recv() {
+            match outgoing {
+                Outgoing::Reply(outgoing_messages) => {
+                    self.write_messages(outgoing_messages)?;
+                    return Ok(true);
+                }
+                Outgoing::Unprompted(outgoing_messages) => self.write_messages(outgoing_messages)?,
             }
         }
+        Ok(false)
+    }
 
-        // Generate a random mask
-        let mask = [
-            rand::random::<u8>(),
-            rand::random::<u8>(),
-            rand::random::<u8>(),
-            rand::random::<u8>(),
-        ];
-        frame.extend_from_slice(&mask);
-
-        // Mask the data
-        for (i, byte) in data.bytes().enumerate() {
-            frame.push(byte ^ mask[i % 4]);
+    // lets the api send its teardown messages before the close frame goes out
+    fn shutdown(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        info!("shutting down websocket");
+        // the api may already be gone if it was the one that stopped
+        if self.incoming_tx.send(Incoming::Shutdown).is_ok() && self.await_reply()? {
+            self.drain_outgoing()?;
         }
-
-        Ok(frame)
+        self.close()
     }
 
     pub fn handle_stream(&mut self) -> Result<(), Box<dyn Error + Send>> {
-        let mut rx_buffer = VecDeque::new();
+        let mut rx_buffer = VecDeque::from(std::mem::take(&mut self.early_data));
+        let mut received = Instant::now();
 
         let mut temp_buffer = [0u8; 65536];
         loop {
-            let read_bytes = self.tls_stream.read(&mut temp_buffer).map_err(|_| WebSocketError::ReadError)?;
-
-            if read_bytes == 0 {
-                break;  // The stream has closed or there's an error.
+            if self.shutdown.load(Ordering::SeqCst) {
+                return self.shutdown();
             }
+            self.drain_outgoing()?;
 
-            rx_buffer.extend(&temp_buffer[0..read_bytes]);
-
+            // what's buffered is handled before reading, it may have come with the handshake
             loop {
-                match self.decode_frame(&mut rx_buffer) {
-                    Ok(Some(incoming_message)) => {
-                        self.incoming_tx.send(incoming_message).map_err(|_| WebSocketError::ChannelSendError)?;
-                        let outgoing_messages = self.outgoing_rx.recv().map_err(|_| WebSocketError::ChannelReceiveError)?;
-                        for outgoing_message in outgoing_messages {
-                            println!("outgoing_message: {}", outgoing_message);
-                            let encoded_frame = self.encode_text_frame(&outgoing_message).map_err(|_| WebSocketError::FrameEncodeError)?;
-                            self.tls_stream.write_all(&encoded_frame).map_err(|_| WebSocketError::WriteError)?;
+                match decode_frame(&mut rx_buffer) {
+                    Ok(Some(Frame::Close(status_code))) => {
+                        info!("received close frame with status code: {:?}", status_code);
+                        self.close()?;
+                        return Ok(());
+                    },
+                    Ok(Some(frame)) => {
+                        let incoming_message = match frame {
+                            Frame::Text(incoming_message) => incoming_message,
+                            frame => {
+                                let opcode = frame.opcode();
+                                match self.frame_handler.handle(frame) {
+                                    FrameAction::Text(incoming_message) => incoming_message,
+                                    FrameAction::Reply { opcode, payload } => {
+                                        self.tls_stream.write_all(&encode_frame(opcode, &payload)).map_err(WebSocketError::WriteError)?;
+                                        continue;
+                                    }
+                                    FrameAction::Ignore => continue,
+                                    FrameAction::Unsupported => return Err(WebSocketError::FrameDecodeError(DecodeError::UnsupportedOpcode(opcode)).into()),
+                                }
+                            }
+                        };
+                        trace!("incoming_message: {}", incoming_message);
+                        let sent = self.incoming_tx.send(Incoming::Frame(incoming_message, received));
+                        if sent.is_err() || !self.await_reply()? {
+                            // the api side hung up, so we're done
+                            self.close()?;
+                            return Ok(());
                         }
                     },
                     Ok(None) => break,  // Not enough data yet
-                    Err(_) => return Err(WebSocketError::FrameDecodeError.into()),
+                    Err(err) => return Err(WebSocketError::FrameDecodeError(err).into()),
                 }
             }
-        }
 
-        Ok(())
+            let read_bytes = match self.tls_stream.read(&mut temp_buffer) {
+                Ok(read_bytes) => read_bytes,
+                Err(err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => continue,
+                Err(err) => return Err(WebSocketError::ReadError(err).into()),
+            };
+            received = Instant::now();
+
+            // a clean close always arrives as a close frame first
+            if read_bytes == 0 {
+                return Err(WebSocketError::UnexpectedEof { buffered: rx_buffer.len() }.into());
+            }
+
+            // a frame that never completes would otherwise grow the buffer forever
+            if rx_buffer.len() + read_bytes > self.max_rx_buffer {
+                return Err(WebSocketError::BufferOverflow { size: rx_buffer.len() + read_bytes, limit: self.max_rx_buffer }.into());
+            }
+            rx_buffer.extend(&temp_buffer[0..read_bytes]);
+        }
     }
-}
\ No newline at end of file
+}
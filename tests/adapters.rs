@@ -0,0 +1,61 @@
+#![cfg(feature = "barter")]
+
+use barter_data::event::DataKind;
+use barter_data::streams::consumer::MarketStreamEvent;
+use barter_instrument::exchange::ExchangeId;
+use barter_instrument::Side;
+use chrono::{TimeZone, Utc};
+use futures_core::Stream;
+use tradingview_websocket::adapters::{self, BarterMarketStream};
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::trade::{Trade, TradeSide};
+
+fn bar(time: i64) -> Bar {
+    Bar { time, open: 1.0, high: 3.0, low: 0.5, close: 2.0, volume: 10.0 }
+}
+
+#[test]
+fn closed_bars_become_candles_closing_at_the_end_of_their_interval() {
+    let received = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+    let event = Event::BarClosed { symbol: "BINANCE:BTCUSDT".to_string(), interval: "1".to_string(), bar: bar(1_700_000_000) };
+    let events = adapters::market_events(&event, received);
+    let barter = events[0].to_barter().expect("candle");
+    assert_eq!(barter.exchange, ExchangeId::Other);
+    assert_eq!(barter.instrument, "BINANCE:BTCUSDT");
+    assert_eq!(barter.time_received, received);
+    match barter.kind {
+        DataKind::Candle(candle) => {
+            assert_eq!(candle.close_time, Utc.timestamp_opt(1_700_000_060, 0).unwrap());
+            assert_eq!((candle.open, candle.high, candle.low, candle.close, candle.volume), (1.0, 3.0, 0.5, 2.0, 10.0));
+        }
+        kind => panic!("expected a candle, got {:?}", kind),
+    }
+}
+
+#[test]
+fn forming_bars_and_unsided_trades_are_skipped() {
+    let received = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+    let forming = Event::Bars { symbol: "BINANCE:BTCUSDT".to_string(), interval: "1".to_string(), bars: vec![bar(1_700_000_000)], correlation_id: None };
+    assert!(adapters::market_events(&forming, received)[0].to_barter().is_none());
+    let unsided = Event::Trade { symbol: "BINANCE:BTCUSDT".to_string(), trade: Trade { time: 1_700_000_000, price: 2.0, size: 0.5, side: TradeSide::Unknown }, local_time_ms: None };
+    assert!(adapters::market_events(&unsided, received)[0].to_barter().is_none());
+}
+
+#[test]
+fn sided_trades_become_public_trades() {
+    let received = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+    let event = Event::Trade { symbol: "BINANCE:BTCUSDT".to_string(), trade: Trade { time: 1_700_000_000, price: 2.0, size: 0.5, side: TradeSide::Sell }, local_time_ms: None };
+    match adapters::market_events(&event, received)[0].to_barter().expect("trade").kind {
+        DataKind::Trade(trade) => {
+            assert_eq!((trade.price, trade.amount, trade.side), (2.0, 0.5, Side::Sell));
+        }
+        kind => panic!("expected a trade, got {:?}", kind),
+    }
+}
+
+#[test]
+fn barter_stream_yields_market_stream_events() {
+    fn market_stream<S: Stream<Item = MarketStreamEvent<String, DataKind>>>() {}
+    market_stream::<BarterMarketStream>();
+}
@@ -0,0 +1,16 @@
+use serde_json::json;
+use tradingview_websocket::event::ServerErrorKind;
+use tradingview_websocket::tradingview_api::is_auth_error;
+
+#[test]
+fn rejected_tokens_are_auth_errors() {
+    assert!(is_auth_error(ServerErrorKind::Critical, &json!({"m": "critical_error", "p": ["chart_session_id", "invalid_auth_token"]})));
+    assert!(is_auth_error(ServerErrorKind::Protocol, &json!({"m": "protocol_error", "p": ["auth_token_expired: please sign in again"]})));
+}
+
+#[test]
+fn other_errors_mentioning_tokens_are_not() {
+    assert!(!is_auth_error(ServerErrorKind::Critical, &json!({"m": "critical_error", "p": ["chart_session_id", "unexpected_error", "symbol BINANCE:TOKENUSDT"]})));
+    assert!(!is_auth_error(ServerErrorKind::Protocol, &json!({"m": "protocol_error", "p": ["wrong data"]})));
+    assert!(!is_auth_error(ServerErrorKind::Study, &json!({"m": "study_error", "p": ["chart_session_id", "study_id", "st1", "study_not_auth:Script@tv-scripting-101!"]})));
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::TempPath;
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::bar_store::BarStore;
+
+fn bar(time: i64) -> Bar {
+    Bar { time, open: 1.0, high: 1.0, low: 1.0, close: 1.0, volume: 1.0 }
+}
+
+fn minutes(from: i64, to: i64) -> Vec<Bar> {
+    (from..=to).map(|minute| bar(minute * 60)).collect()
+}
+
+// bars every minute from 0 to 600, from a finished download that started at 0; bound as
+// (_path, store) so the store is dropped, and the database closed, before the path goes
+fn stored(name: &str) -> (TempPath, BarStore) {
+    let path = TempPath::new(name);
+    let store = BarStore::open(&path).expect("store");
+    store.insert_download("NASDAQ:AAPL", "1", 0, 600, &minutes(0, 10), true).expect("inserted");
+    (path, store)
+}
+
+fn times(bars: &[Bar]) -> Vec<i64> {
+    bars.iter().map(|bar| bar.time).collect()
+}
+
+#[test]
+fn a_range_ending_before_the_newest_bar_comes_from_the_store() {
+    let (_path, store) = stored("store-inside");
+    let (bars, fetch_from) = store.top_up("NASDAQ:AAPL", "1", 60, 300).expect("top up");
+    assert_eq!(times(&bars), vec![60, 120, 180, 240, 300]);
+    assert_eq!(fetch_from, None);
+}
+
+#[test]
+fn a_range_past_the_newest_bar_fetches_from_it() {
+    let (_path, store) = stored("store-past");
+    let (bars, fetch_from) = store.top_up("NASDAQ:AAPL", "1", 300, 900).expect("top up");
+    assert_eq!(times(&bars), vec![300, 360, 420, 480, 540]);
+    // the newest stored bar may have been forming
+    assert_eq!(fetch_from, Some(600));
+}
+
+#[test]
+fn a_range_older_than_the_complete_one_is_fetched_whole() {
+    let (_path, store) = stored("store-older");
+    let (bars, fetch_from) = store.top_up("NASDAQ:AAPL", "1", -600, 300).expect("top up");
+    assert_eq!(times(&bars), Vec::<i64>::new());
+    assert_eq!(fetch_from, Some(-600));
+}
+
+#[test]
+fn a_download_that_reaches_the_stored_bars_extends_them() {
+    let (_path, store) = stored("store-joined");
+    store.insert_download("NASDAQ:AAPL", "1", -600, 60, &minutes(-10, 1), true).expect("inserted");
+    assert_eq!(store.complete_from("NASDAQ:AAPL", "1").expect("mark"), Some(-600));
+    store.insert_download("NASDAQ:AAPL", "1", 600, 1200, &minutes(10, 20), true).expect("inserted");
+    assert_eq!(store.complete_from("NASDAQ:AAPL", "1").expect("mark"), Some(-600));
+}
+
+#[test]
+fn a_download_that_leaves_a_gap_starts_the_mark_over() {
+    let (_path, store) = stored("store-gap");
+    // nothing between 600 and 1020 was ever fetched
+    store.insert_download("NASDAQ:AAPL", "1", 1020, 1200, &minutes(17, 20), true).expect("inserted");
+    assert_eq!(store.complete_from("NASDAQ:AAPL", "1").expect("mark"), Some(1020));
+    let (bars, fetch_from) = store.top_up("NASDAQ:AAPL", "1", 0, 1200).expect("top up");
+    assert_eq!(times(&bars), Vec::<i64>::new());
+    assert_eq!(fetch_from, Some(0));
+}
+
+#[test]
+fn a_download_older_than_the_stored_bars_leaves_the_mark() {
+    let (_path, store) = stored("store-before");
+    store.insert_download("NASDAQ:AAPL", "1", -1200, -600, &minutes(-20, -10), true).expect("inserted");
+    assert_eq!(store.complete_from("NASDAQ:AAPL", "1").expect("mark"), Some(0));
+}
+
+#[test]
+fn a_cut_short_download_past_the_stored_bars_drops_the_mark() {
+    let (_path, store) = stored("store-cut-short");
+    // only the newest page came back before the stop
+    store.insert_download("NASDAQ:AAPL", "1", 0, 1200, &minutes(15, 20), false).expect("inserted");
+    assert_eq!(store.complete_from("NASDAQ:AAPL", "1").expect("mark"), None);
+    let (_, fetch_from) = store.top_up("NASDAQ:AAPL", "1", 0, 1200).expect("top up");
+    assert_eq!(fetch_from, Some(0));
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use tradingview_websocket::book::{BookError, BookSide, BookStatus, DepthUpdate, Level, OrderBook};
+
+fn level(price: f64, size: f64) -> Level {
+    Level { price, size }
+}
+
+fn bid(price: f64, size: f64) -> DepthUpdate {
+    DepthUpdate { side: BookSide::Bid, price, size }
+}
+
+fn ask(price: f64, size: f64) -> DepthUpdate {
+    DepthUpdate { side: BookSide::Ask, price, size }
+}
+
+// 99/100 bids against 101/102 asks, at sequence 10
+fn book() -> OrderBook {
+    let mut book = OrderBook::new("BINANCE:BTCUSDT", Duration::from_secs(60));
+    book.apply_snapshot(&[level(99.0, 2.0), level(100.0, 1.0)], &[level(102.0, 2.0), level(101.0, 1.0), level(103.0, 0.0)], Some(10)).expect("snapshot");
+    book
+}
+
+#[test]
+fn a_snapshot_orders_each_side_best_first() {
+    let book = book();
+    assert_eq!(book.best_bid(), Some(level(100.0, 1.0)));
+    assert_eq!(book.best_ask(), Some(level(101.0, 1.0)));
+    assert_eq!(book.spread(), Some(1.0));
+    assert_eq!(book.mid(), Some(100.5));
+    let depth = book.depth(5);
+    assert_eq!(depth.bids, vec![level(100.0, 1.0), level(99.0, 2.0)]);
+    // the empty level isn't kept
+    assert_eq!(depth.asks, vec![level(101.0, 1.0), level(102.0, 2.0)]);
+    assert_eq!(depth.sequence, Some(10));
+    assert_eq!(book.status(), BookStatus::Ok);
+}
+
+#[test]
+fn updates_change_add_and_remove_levels() {
+    let mut book = book();
+    book.apply(&[bid(100.0, 0.0), bid(99.5, 3.0), ask(101.0, 4.0)], Some(11)).expect("applied");
+    assert_eq!(book.depth(1).bids, vec![level(99.5, 3.0)]);
+    assert_eq!(book.best_ask(), Some(level(101.0, 4.0)));
+    assert_eq!(book.sequence(), Some(11));
+}
+
+#[test]
+fn a_gap_waits_for_the_next_snapshot() {
+    let mut book = book();
+    // an update the snapshot already had
+    book.apply(&[bid(100.0, 9.0)], Some(10)).expect("ignored");
+    assert_eq!(book.best_bid(), Some(level(100.0, 1.0)));
+    assert_eq!(book.apply(&[bid(100.0, 9.0)], Some(12)), Err(BookError::Gap { last: 10, sequence: 12 }));
+    assert_eq!(book.status(), BookStatus::NeedsSnapshot);
+    assert_eq!(book.apply(&[bid(100.0, 9.0)], Some(13)), Err(BookError::NeedsSnapshot));
+    book.apply_snapshot(&[level(100.0, 9.0)], &[level(101.0, 1.0)], Some(13)).expect("snapshot");
+    book.apply(&[ask(101.0, 2.0)], Some(14)).expect("applied");
+    assert_eq!(book.status(), BookStatus::Ok);
+}
+
+#[test]
+fn an_invalid_level_leaves_the_book_alone() {
+    let mut book = book();
+    assert_eq!(book.apply(&[bid(100.5, 1.0), ask(f64::NAN, 1.0)], Some(11)).map_err(|err| err.to_string()), Err("invalid level NaN x 1".to_string()));
+    assert_eq!(book.best_bid(), Some(level(100.0, 1.0)));
+    assert_eq!(book.sequence(), Some(10));
+}
+
+#[test]
+fn a_bid_at_the_ask_is_locked_not_crossed() {
+    let mut book = book();
+    book.apply(&[bid(101.0, 1.0)], Some(11)).expect("applied");
+    assert!(book.is_locked());
+    assert!(!book.is_crossed());
+    assert_eq!(book.status(), BookStatus::Locked);
+    book.apply(&[bid(101.5, 1.0)], Some(12)).expect("applied");
+    assert!(!book.is_locked());
+    assert!(book.is_crossed());
+    assert_eq!(book.status(), BookStatus::Crossed);
+}
+
+#[test]
+fn empty_and_stale_books() {
+    let mut book = OrderBook::new("BINANCE:BTCUSDT", Duration::ZERO);
+    // never updated
+    assert_eq!(book.status(), BookStatus::Stale);
+    let mut fresh = OrderBook::new("BINANCE:BTCUSDT", Duration::from_secs(60));
+    fresh.apply_snapshot(&[level(100.0, 1.0)], &[], None).expect("snapshot");
+    assert_eq!(fresh.status(), BookStatus::Empty);
+    assert_eq!(fresh.spread(), None);
+    book.apply_snapshot(&[level(100.0, 1.0)], &[level(101.0, 1.0)], None).expect("snapshot");
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(book.status(), BookStatus::Stale);
+}
@@ -0,0 +1,29 @@
+use tradingview_websocket::{bar::Bar, candles::CandleCache};
+
+fn bar(time: i64, close: f64) -> Bar {
+    Bar { time, open: close, high: close + 1.0, low: close - 1.0, close, volume: 10.0 }
+}
+
+#[test]
+fn a_roll_shifts_every_cached_bar_and_returns_the_revisions() {
+    let mut candles = CandleCache::new(100);
+    candles.merge("CME_MINI:ES1!", vec![bar(60, 100.0), bar(120, 101.0)]);
+    // the new contract's bar at 120 overlaps the old one
+    let (rolled_at, offset) = candles.roll_offset("CME_MINI:ES1!", &[bar(120, 111.0), bar(180, 112.0)]).expect("overlap");
+    assert_eq!((rolled_at, offset), (120, 10.0));
+    let revisions = candles.shift("CME_MINI:ES1!", offset);
+    assert_eq!(revisions, vec![(bar(60, 100.0), bar(60, 110.0)), (bar(120, 101.0), bar(120, 111.0))]);
+    assert_eq!(candles.window("CME_MINI:ES1!", 120, 2), vec![bar(60, 110.0), bar(120, 111.0)]);
+}
+
+#[test]
+fn rolls_only_touch_their_own_symbol() {
+    let mut candles = CandleCache::new(100);
+    candles.merge("CME_MINI:ES1!", vec![bar(60, 100.0)]);
+    candles.merge("CME_MINI:NQ1!", vec![bar(60, 200.0)]);
+    assert_eq!(candles.roll_offset("CME_MINI:ES1!", &[bar(180, 111.0)]), None);
+    assert_eq!(candles.roll_offset("NYMEX:CL1!", &[bar(60, 70.0)]), None);
+    assert_eq!(candles.shift("NYMEX:CL1!", 5.0), vec![]);
+    candles.shift("CME_MINI:ES1!", 5.0);
+    assert_eq!(candles.window("CME_MINI:NQ1!", 60, 1), vec![bar(60, 200.0)]);
+}
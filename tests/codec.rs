@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use proptest::prelude::*;
+use tradingview_websocket::tradingview_api::{frame_message, split_messages};
+use tradingview_websocket::websocket::{decode_client_frame, decode_frame, encode_frame_with_mask, encode_server_frame, DefaultFrameHandler, Frame, FrameAction, FrameHandler};
+
+// lengths around the 7 bit, 16 bit and 64 bit length encodings
+fn payload() -> impl Strategy<Value = String> {
+    prop_oneof![
+        ".{0,130}",
+        proptest::collection::vec(any::<char>(), 60_000..70_000).prop_map(String::from_iter),
+    ]
+}
+
+// what the server could send, minus the ~m~N~m~ the splitter would take for a header
+fn message() -> impl Strategy<Value = String> {
+    "[^~]{0,200}"
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn server_text_frames_round_trip(text in payload()) {
+        let mut buffer = VecDeque::from(encode_server_frame(0x01, text.as_bytes()));
+        match decode_frame(&mut buffer) {
+            Ok(Some(Frame::Text(decoded))) => prop_assert_eq!(decoded, text),
+            _ => prop_assert!(false, "not decoded as a text frame"),
+        }
+        prop_assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn frames_decode_the_same_however_they_arrive(texts in proptest::collection::vec(".{0,300}", 1..5), cuts in proptest::collection::vec(any::<prop::sample::Index>(), 0..8)) {
+        let bytes: Vec<u8> = texts.iter().flat_map(|text| encode_server_frame(0x01, text.as_bytes())).collect();
+        let mut cuts: Vec<usize> = cuts.iter().map(|cut| cut.index(bytes.len() + 1)).collect();
+        cuts.push(bytes.len());
+        cuts.sort_unstable();
+        let mut buffer = VecDeque::new();
+        let mut decoded = vec![];
+        let mut start = 0;
+        for cut in cuts {
+            buffer.extend(&bytes[start..cut]);
+            start = cut;
+            while let Some(frame) = decode_frame(&mut buffer).expect("valid frames") {
+                match frame {
+                    Frame::Text(text) => decoded.push(text),
+                    _ => prop_assert!(false, "not a text frame"),
+                }
+            }
+        }
+        prop_assert_eq!(decoded, texts);
+    }
+
+    #[test]
+    fn binary_frames_round_trip(bytes in proptest::collection::vec(any::<u8>(), 0..70_000)) {
+        let mut buffer = VecDeque::from(encode_server_frame(0x02, &bytes));
+        prop_assert!(matches!(decode_frame(&mut buffer), Ok(Some(Frame::Binary(decoded))) if decoded == bytes));
+        prop_assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn close_frames_round_trip(code in any::<u16>()) {
+        let mut buffer = VecDeque::from(encode_server_frame(0x08, &code.to_be_bytes()));
+        prop_assert!(matches!(decode_frame(&mut buffer), Ok(Some(Frame::Close(Some(decoded)))) if decoded == code));
+    }
+
+    // control frame payloads are at most 125 bytes
+    #[test]
+    fn pings_are_answered_with_their_payload(bytes in proptest::collection::vec(any::<u8>(), 0..126)) {
+        let mut buffer = VecDeque::from(encode_server_frame(0x09, &bytes));
+        let ping = decode_frame(&mut buffer).expect("valid frame").expect("whole frame");
+        let answered = matches!(DefaultFrameHandler.handle(ping), FrameAction::Reply { opcode: 0x0A, payload } if payload == bytes);
+        prop_assert!(answered, "no pong");
+    }
+
+    #[test]
+    fn masked_client_frames_round_trip(opcode in 0u8..16, bytes in proptest::collection::vec(any::<u8>(), 0..70_000), mask in any::<[u8; 4]>()) {
+        let mut buffer = encode_frame_with_mask(opcode, &bytes, mask);
+        prop_assert_eq!(decode_client_frame(&mut buffer), Some((opcode, bytes)));
+        prop_assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn masked_frames_are_rejected_from_the_server(bytes in proptest::collection::vec(any::<u8>(), 0..200), mask in any::<[u8; 4]>()) {
+        let mut buffer = VecDeque::from(encode_frame_with_mask(0x01, &bytes, mask));
+        prop_assert!(decode_frame(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn decoding_garbage_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let mut buffer = VecDeque::from(bytes);
+        while let Ok(Some(_)) = decode_frame(&mut buffer) {}
+    }
+
+    // blank messages are left out
+    #[test]
+    fn framed_messages_split_back(messages in proptest::collection::vec(message(), 1..10)) {
+        let frame: String = messages.iter().map(|message| frame_message(message)).collect();
+        let split: Vec<&str> = split_messages(&frame).collect();
+        prop_assert_eq!(split, messages.iter().map(String::as_str).filter(|message| !message.trim().is_empty()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn garbage_between_messages_is_skipped(messages in proptest::collection::vec("[a-z0-9{}:,\"]{1,50}", 1..10), garbage in "[a-z ]{1,20}") {
+        let frame: String = messages.iter().map(|message| garbage.clone() + &frame_message(message)).collect();
+        let split: Vec<&str> = split_messages(&frame).collect();
+        prop_assert_eq!(split, messages.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn truncated_messages_are_dropped(messages in proptest::collection::vec("[a-z0-9{}:,\"]{2,50}", 2..10), cut in any::<prop::sample::Index>()) {
+        let cut = cut.index(messages.len());
+        let frame: String = messages.iter().enumerate().map(|(index, message)| match index == cut {
+            true => frame_message(message)[..frame_message(message).len() - 1].to_string(),
+            false => frame_message(message),
+        }).collect();
+        let split: Vec<&str> = split_messages(&frame).collect();
+        let kept: Vec<&str> = messages.iter().enumerate().filter(|(index, _)| *index != cut).map(|(_, message)| message.as_str()).collect();
+        prop_assert_eq!(split, kept);
+    }
+
+    #[test]
+    fn splitting_garbage_never_panics(frame in ".{0,512}") {
+        let _ = split_messages(&frame).count();
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::sync::{mpsc, Arc};
+use std::time::Instant;
+
+use serde_json::json;
+use tradingview_websocket::connect_script::ConnectScript;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::series::SeriesOptions;
+use tradingview_websocket::state;
+use tradingview_websocket::tradingview_api::{frame_message, ApiOptions, Mode, TradingViewApi};
+use tradingview_websocket::websocket::Incoming;
+
+const SYMBOL: &str = "CME_MINI:ES1!";
+
+fn options(stitch_rolls: bool) -> ApiOptions {
+    let script = ConnectScript::parse(&json!({"chart": {"symbol": SYMBOL}}).to_string()).expect("script");
+    ApiOptions {
+        connect_script: Some(script),
+        series: SeriesOptions { stitch_rolls, ..SeriesOptions::default() },
+        emit_corrections: true,
+        ..ApiOptions::default()
+    }
+}
+
+fn symbol_resolved(contract: &str) -> String {
+    frame_message(&json!({"m": "symbol_resolved", "p": ["chart_session_id", "symbol_id", {"pro_name": SYMBOL, "root": "ES", "current_contract": contract}]}).to_string())
+}
+
+fn timescale_update(closes: &[(i64, f64)]) -> String {
+    let entries: Vec<serde_json::Value> = closes.iter().map(|&(time, close)| json!({"i": 0, "v": [time, close, close, close, close, 1.0]})).collect();
+    frame_message(&json!({"m": "timescale_update", "p": ["chart_session_id", {"series_id": {"s": entries}}]}).to_string())
+}
+
+// one connection made with options, from the server's hello to shutdown; the options are
+// cloned the way every reconnect clones them
+fn connection(options: &ApiOptions, frames: Vec<String>) -> Vec<Arc<Event>> {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, _outgoing_rx) = mpsc::channel();
+    let (events_tx, events_rx) = mpsc::sync_channel(1000);
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Stream, options.clone()).expect("api");
+    let connected = fs::read_to_string("tests/fixtures/messages/connected.txt").expect("connected fixture");
+    for frame in std::iter::once(connected).chain(frames) {
+        incoming_tx.send(Incoming::Frame(frame, Instant::now())).expect("send frame");
+    }
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+    events_rx.try_iter().collect()
+}
+
+fn rolls(events: &[Arc<Event>]) -> Vec<(String, String)> {
+    events.iter().filter_map(|event| match **event {
+        Event::ContractRolled { ref previous, ref contract, .. } => Some((previous.clone(), contract.clone())),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn a_roll_between_connections_is_reported() {
+    let options = options(false);
+    let first = connection(&options, vec![symbol_resolved("ESZ2024")]);
+    assert_eq!(rolls(&first), vec![]);
+    let second = connection(&options, vec![symbol_resolved("ESH2025")]);
+    assert_eq!(rolls(&second), vec![("ESZ2024".to_string(), "ESH2025".to_string())]);
+    // and the same contract again is no roll
+    let third = connection(&options, vec![symbol_resolved("ESH2025")]);
+    assert_eq!(rolls(&third), vec![]);
+}
+
+#[test]
+fn a_roll_while_stopped_is_reported_after_a_restore() {
+    let saved = options(false);
+    connection(&saved, vec![symbol_resolved("ESZ2024")]);
+    let exported = state::export_state(&Mode::Stream, &saved);
+    assert_eq!(exported.contracts.get(SYMBOL).map(String::as_str), Some("ESZ2024"));
+    let restored = state::ClientState::from_json(&exported.to_json().expect("json")).expect("state");
+    let (_, options) = state::restore_state(restored, options(false));
+    let events = connection(&options, vec![symbol_resolved("ESH2025")]);
+    assert_eq!(rolls(&events), vec![("ESZ2024".to_string(), "ESH2025".to_string())]);
+}
+
+#[test]
+fn bars_of_the_connection_before_the_roll_are_stitched() {
+    let options = options(true);
+    connection(&options, vec![symbol_resolved("ESZ2024"), timescale_update(&[(60, 100.0), (120, 101.0)])]);
+    // the history sent again has the old contract up to the bar the roll happened in
+    let events = connection(&options, vec![symbol_resolved("ESH2025"), timescale_update(&[(60, 100.0), (120, 111.0), (180, 112.0)])]);
+    let mut corrected = vec![];
+    let mut bars = vec![];
+    for event in &events {
+        match **event {
+            Event::BarCorrected { ref previous, ref bar, .. } => corrected.push((bar.time, previous.close, bar.close)),
+            Event::Bars { bars: ref batch, .. } => bars.extend(batch.iter().map(|bar| (bar.time, bar.close))),
+            _ => {}
+        }
+    }
+    assert_eq!(corrected, vec![(60, 100.0, 110.0), (120, 101.0, 111.0)]);
+    // the old contract's bar in the history stays where the stitch put it
+    assert_eq!(bars, vec![(180, 112.0)]);
+}
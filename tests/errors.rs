@@ -0,0 +1,43 @@
+use std::fs;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use serde_json::json;
+use tradingview_websocket::tradingview_api::{frame_message, ApiOptions, ErrorPolicy, Mode, TradingViewApi};
+use tradingview_websocket::websocket::{Incoming, Outgoing};
+
+// the sessions the client deletes when the server reports a critical error for `session`,
+// followed by the teardown at shutdown
+fn deleted_after(session: Option<&str>) -> Vec<String> {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, outgoing_rx) = mpsc::channel();
+    let (events_tx, _events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions { error_policy: ErrorPolicy::DropSession, ..ApiOptions::default() };
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Stream, options).expect("api");
+    let connected = fs::read_to_string("tests/fixtures/messages/connected.txt").expect("connected fixture");
+    incoming_tx.send(Incoming::Frame(connected, Instant::now())).expect("send frame");
+    if let Some(session) = session {
+        let error = frame_message(&json!({"m": "critical_error", "p": [session, "unexpected_error", "internal error"]}).to_string());
+        incoming_tx.send(Incoming::Frame(error, Instant::now())).expect("send frame");
+    }
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+    outgoing_rx.iter().flat_map(Outgoing::into_messages).filter(|message| message.contains("delete_session")).collect()
+}
+
+#[test]
+fn a_critical_error_deletes_the_session_it_names() {
+    let teardown = deleted_after(None);
+    for (session, method) in [("chart_session_id", "chart_delete_session"), ("quote_session_id", "quote_delete_session")] {
+        let deleted = deleted_after(Some(session));
+        assert_eq!(deleted[1..], teardown[..], "{:?}", deleted);
+        assert!(deleted[0].contains(method), "{}", deleted[0]);
+    }
+}
+
+#[test]
+fn a_session_the_client_didnt_open_is_left_alone() {
+    // a quote-looking id isn't enough to make it the quote session
+    assert_eq!(deleted_after(Some("quotes_for_someone_else")), deleted_after(None));
+}
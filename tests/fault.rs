@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::io::{Cursor, Read};
+
+use tradingview_websocket::fault::{FaultConfig, FaultyTransport};
+use tradingview_websocket::websocket::{decode_frame, encode_server_frame, Frame};
+
+fn frames(texts: &[&str]) -> Vec<u8> {
+    texts.iter().flat_map(|text| encode_server_frame(0x01, text.as_bytes())).collect()
+}
+
+// the texts the client decodes from everything the transport hands over, and how many reads it took
+fn received(mut transport: impl Read) -> (Vec<String>, usize) {
+    let mut buffer = VecDeque::new();
+    let mut chunk = [0u8; 1024];
+    let mut reads = 0;
+    loop {
+        let read = transport.read(&mut chunk).expect("read");
+        if read == 0 {
+            break;
+        }
+        reads += 1;
+        buffer.extend(&chunk[..read]);
+    }
+    let mut texts = vec![];
+    while let Ok(Some(frame)) = decode_frame(&mut buffer) {
+        if let Frame::Text(text) = frame {
+            texts.push(text);
+        }
+    }
+    (texts, reads)
+}
+
+fn faults(spec: &str) -> FaultConfig {
+    spec.parse().expect("fault config")
+}
+
+#[test]
+fn no_faults_pass_frames_through() {
+    let transport = FaultyTransport::new(Cursor::new(frames(&["a", "b"])), faults("seed=1"));
+    assert_eq!(received(transport).0, vec!["a", "b"]);
+}
+
+#[test]
+fn dropped_and_duplicated_frames() {
+    let transport = FaultyTransport::new(Cursor::new(frames(&["a", "b"])), faults("drop=1,seed=1"));
+    assert!(received(transport).0.is_empty());
+    let transport = FaultyTransport::new(Cursor::new(frames(&["a", "b"])), faults("duplicate=1,seed=1"));
+    assert_eq!(received(transport).0, vec!["a", "a", "b", "b"]);
+}
+
+#[test]
+fn split_frames_reassemble() {
+    let transport = FaultyTransport::new(Cursor::new(frames(&["first", "second"])), faults("split=1,seed=1"));
+    assert_eq!(received(transport), (vec!["first".to_string(), "second".to_string()], 4));
+}
+
+#[test]
+fn truncated_frames_keep_half_their_payload() {
+    let transport = FaultyTransport::new(Cursor::new(frames(&["~m~4~m~abcd"])), faults("truncate=1,seed=1"));
+    assert_eq!(received(transport).0, vec!["~m~4~"]);
+}
+
+#[test]
+fn disconnect_ends_the_stream_mid_frame() {
+    let transport = FaultyTransport::new(Cursor::new(frames(&["a long enough frame", "never read"])), faults("disconnect=1,seed=1"));
+    let (texts, reads) = received(transport);
+    assert!(texts.is_empty());
+    assert_eq!(reads, 1);
+}
+
+#[test]
+fn fed_bytes_get_the_same_faults() {
+    let mut transport = FaultyTransport::new(Cursor::new(frames(&["later"])), faults("duplicate=1,seed=1"));
+    transport.feed(&frames(&["early"]));
+    assert_eq!(received(transport).0, vec!["early", "early", "later", "later"]);
+}
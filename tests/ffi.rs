@@ -0,0 +1,62 @@
+#![cfg(feature = "ffi")]
+
+use std::ffi::CString;
+use std::fs;
+use std::ptr;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use tradingview_websocket::event::Event;
+use tradingview_websocket::ffi::{self, TV_INVALID_ARGUMENT};
+use tradingview_websocket::quote::QuoteField;
+use tradingview_websocket::study::{PlotInfo, StudyPoint};
+
+#[test]
+fn checked_in_header_matches_the_generated_one() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/tradingview_websocket.h"));
+    let checked_in = fs::read_to_string("include/tradingview_websocket.h").expect("header");
+    assert!(checked_in == generated, "include/tradingview_websocket.h is out of date, rewrite it with UPDATE_HEADER=1 cargo build --features ffi");
+}
+
+#[test]
+fn unknown_quote_fields_create_no_client() {
+    let fields = CString::new("lp,not_a_field").unwrap();
+    assert!(unsafe { ffi::tv_client_create(fields.as_ptr()) }.is_null());
+    let empty = CString::new(" , ").unwrap();
+    assert!(unsafe { ffi::tv_client_create(empty.as_ptr()) }.is_null());
+}
+
+#[test]
+fn null_clients_are_invalid_arguments() {
+    let symbol = CString::new("NASDAQ:AAPL").unwrap();
+    unsafe {
+        assert_eq!(ffi::tv_client_subscribe(ptr::null_mut(), symbol.as_ptr()), i64::from(TV_INVALID_ARGUMENT));
+        assert_eq!(ffi::tv_client_unsubscribe(ptr::null_mut(), symbol.as_ptr()), TV_INVALID_ARGUMENT);
+        assert!(ffi::tv_client_poll(ptr::null_mut(), 0).is_null());
+        // both ignore null
+        ffi::tv_string_free(ptr::null_mut());
+        ffi::tv_client_destroy(ptr::null_mut());
+    }
+}
+
+#[test]
+fn quote_values_are_keyed_by_field() {
+    let event = Event::Quote { symbol: "NASDAQ:AAPL".to_string(), values: vec![(QuoteField::LastPrice, json!(189.5)), (QuoteField::Volume, json!(1000))], correlation_id: None, local_time_ms: None };
+    let json = ffi::event_json(&event).expect("json");
+    assert_eq!(json, json!({ "type": "quote", "symbol": "NASDAQ:AAPL", "values": { "lp": 189.5, "volume": 1000 } }));
+}
+
+#[test]
+fn study_values_are_keyed_by_plot() {
+    let plot = |name: &str| PlotInfo { id: format!("plot_{}", name), name: name.to_string(), kind: "line".to_string(), color: None };
+    let event = Event::StudyValues {
+        symbol: "NASDAQ:AAPL".to_string(),
+        study: "st1".to_string(),
+        parent: None,
+        plots: Arc::from(vec![plot("RSI"), plot("Signal")]),
+        points: vec![StudyPoint { time: 60, values: vec![55.5, f64::NAN] }]
+    };
+    let json = ffi::event_json(&event).expect("json");
+    assert_eq!(json["points"], json!([{ "time": 60, "values": { "RSI": 55.5, "Signal": Value::Null } }]));
+    assert_eq!(json["plots"][0]["name"], "RSI");
+}
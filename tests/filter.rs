@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::filter::{EventFilter, FilterChain};
+use tradingview_websocket::quote::QuoteField;
+
+fn chain(spec: &str) -> FilterChain {
+    FilterChain::new(vec![spec.parse::<EventFilter>().expect("filter")])
+}
+
+fn quote(field: QuoteField, value: f64) -> Event {
+    Event::Quote { symbol: "BINANCE:BTCUSDT".to_string(), values: vec![(field, Value::from(value))], correlation_id: None, local_time_ms: None }
+}
+
+fn bars(time: i64) -> Event {
+    let bar = Bar { time, open: 1.0, high: 1.0, low: 1.0, close: 1.0, volume: 1.0 };
+    Event::Bars { symbol: "BINANCE:BTCUSDT".to_string(), interval: "1".to_string(), bars: vec![bar], correlation_id: None }
+}
+
+#[test]
+fn every_leaves_bars_alone() {
+    let mut filters = chain("every=1s");
+    let now = Instant::now();
+    assert!(filters.apply(bars(60), now).is_some());
+    assert!(filters.apply(bars(120), now).is_some());
+}
+
+#[test]
+fn held_quote_values_go_out_when_the_interval_is_over() {
+    let mut filters = chain("every=1s");
+    let now = Instant::now();
+    assert!(filters.apply(quote(QuoteField::LastPrice, 1.0), now).is_some());
+    assert!(filters.apply(quote(QuoteField::LastPrice, 2.0), now + Duration::from_millis(100)).is_none());
+    assert!(filters.apply(quote(QuoteField::Volume, 5.0), now + Duration::from_millis(200)).is_none());
+    assert_eq!(filters.next_due(), Some(now + Duration::from_secs(1)));
+    assert!(filters.due(now + Duration::from_millis(999)).is_empty());
+    let due = filters.due(now + Duration::from_secs(1));
+    assert_eq!(due.len(), 1);
+    match due[0] {
+        Event::Quote { ref values, .. } => assert_eq!(*values, vec![(QuoteField::LastPrice, Value::from(2.0)), (QuoteField::Volume, Value::from(5.0))]),
+        ref event => panic!("unexpected {} event", event.type_name()),
+    }
+    assert_eq!(filters.next_due(), None);
+    // the flush started the next interval
+    assert!(filters.apply(quote(QuoteField::LastPrice, 3.0), now + Duration::from_millis(1500)).is_none());
+}
+
+#[test]
+fn held_quotes_keep_their_correlation_id_and_newest_local_time() {
+    let mut filters = chain("every=1s");
+    let now = Instant::now();
+    let tagged = |value: f64| Event::Quote { symbol: "BINANCE:BTCUSDT".to_string(), values: vec![(QuoteField::LastPrice, Value::from(value))], correlation_id: Some(4), local_time_ms: Some(1_700_000_000_250 + value as i64) };
+    assert!(filters.apply(tagged(1.0), now).is_some());
+    assert!(filters.apply(tagged(2.0), now + Duration::from_millis(100)).is_none());
+    match filters.due(now + Duration::from_secs(1))[..] {
+        [Event::Quote { correlation_id, local_time_ms, .. }] => assert_eq!((correlation_id, local_time_ms), (Some(4), Some(1_700_000_000_252))),
+        _ => panic!("expected one held quote"),
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Instant;
+
+use tradingview_websocket::event::Event;
+use tradingview_websocket::invariants::InvariantMode;
+use tradingview_websocket::json;
+use tradingview_websocket::parse_pool::ParsePool;
+use tradingview_websocket::quote::QuoteField;
+use tradingview_websocket::study::StudySpec;
+use tradingview_websocket::tradingview_api::{parse_frame, parse_message, split_messages, ApiOptions, MessageType, Mode, TradingViewApi};
+use tradingview_websocket::websocket::Incoming;
+
+// sanitized frames as the server sends them, one message type or situation per file, each with a
+// .golden file holding how its messages are classified and the events a stream gets from them.
+// After an intended change, UPDATE_GOLDEN=1 cargo test --test golden rewrites the golden files
+const FIXTURES: &str = "tests/fixtures/messages";
+
+// every variant, so a new one needs a fixture before this passes
+const ALL_VARIANTS: [&str; 16] = [
+    "ConnectedToServer", "Ping", "ProtocolError", "QuoteCompleted", "SeriesLoading", "SymbolResolved",
+    "TimescaleUpdate", "SeriesCompleted", "StudyCompleted", "StudyError", "CriticalError", "StudyLoading",
+    "SeriesUpdate", "StudyUpdate", "SymbolError", "QuoteData"
+];
+
+fn variant(message: &MessageType) -> &'static str {
+    match *message {
+        MessageType::ConnectedToServer(_) => "ConnectedToServer",
+        MessageType::Ping(_) => "Ping",
+        MessageType::ProtocolError(_) => "ProtocolError",
+        MessageType::QuoteCompleted(_) => "QuoteCompleted",
+        MessageType::SeriesLoading(_) => "SeriesLoading",
+        MessageType::SymbolResolved(_) => "SymbolResolved",
+        MessageType::TimescaleUpdate(_) => "TimescaleUpdate",
+        MessageType::SeriesCompleted(_) => "SeriesCompleted",
+        MessageType::StudyCompleted(_) => "StudyCompleted",
+        MessageType::StudyError(_) => "StudyError",
+        MessageType::CriticalError(_) => "CriticalError",
+        MessageType::StudyLoading(_) => "StudyLoading",
+        MessageType::SeriesUpdate(_) => "SeriesUpdate",
+        MessageType::StudyUpdate(_) => "StudyUpdate",
+        MessageType::SymbolError(_) => "SymbolError",
+        MessageType::QuoteData => "QuoteData",
+    }
+}
+
+fn describe(event: &Event) -> String {
+    match *event {
+        Event::Bars { ref symbol, ref interval, ref bars, .. } => format!("Bars {} {} {:?}", symbol, interval, bars),
+        Event::HistoryCompleted { ref symbol } => format!("HistoryCompleted {}", symbol),
+        Event::GapFilled { ref symbol, ref interval, ref bars } => format!("GapFilled {} {} {:?}", symbol, interval, bars),
+        Event::BarCorrected { ref symbol, ref interval, ref previous, ref bar } => format!("BarCorrected {} {} {:?} -> {:?}", symbol, interval, previous, bar),
+        Event::ServerError { kind, ref payload } => format!("ServerError {:?} {}", kind, payload),
+        Event::SubscriptionFailed { ref symbol, ref reason, .. } => format!("SubscriptionFailed {} {}", symbol, reason),
+        Event::DataQualityWarning { ref symbol, time, ref issue } => format!("DataQualityWarning {} {} {:?}", symbol, time, issue),
+        Event::FeedStale { silent_for } => format!("FeedStale {:?}", silent_for),
+        Event::Quote { ref symbol, ref values, .. } => format!("Quote {} {:?}", symbol, values),
+        Event::QuoteFieldChanged { ref symbol, field, ref old, ref new } => format!("QuoteFieldChanged {} {:?} {:?} -> {}", symbol, field, old, new),
+        Event::QuoteCompleted { ref symbol } => format!("QuoteCompleted {}", symbol),
+        Event::ContractResolved { ref symbol, ref root, ref contract } => format!("ContractResolved {} {:?} {}", symbol, root, contract),
+        Event::ContractRolled { ref symbol, ref previous, ref contract } => format!("ContractRolled {} {} -> {}", symbol, previous, contract),
+        Event::StudyValues { ref symbol, ref study, ref parent, ref points, .. } => format!("StudyValues {} {} {:?} {:?}", symbol, study, parent, points),
+        Event::CorporateEvents { ref symbol, ref events } => format!("CorporateEvents {} {:?}", symbol, events),
+        Event::TradingHours { ref symbol, ref hours } => format!("TradingHours {} {:?}", symbol, hours.description),
+        Event::Trade { ref symbol, ref trade, .. } => format!("Trade {} {:?}", symbol, trade),
+        Event::BarClosed { ref symbol, ref interval, ref bar } => format!("BarClosed {} {} {:?}", symbol, interval, bar),
+        Event::SessionStats { ref symbol, .. } => format!("SessionStats {}", symbol),
+        Event::NewsItem { ref symbol, ref item } => format!("NewsItem {} {:?}", symbol, item),
+        Event::EconomicEvent { ref event } => format!("EconomicEvent {:?}", event),
+        // elapsed depends on the machine
+        Event::CommandResult { ref command, ref session, ref target, ref outcome, .. } => format!("CommandResult {} {} {} {:?}", command, session, target, outcome),
+        Event::ClockSkew { skew_ms, delay_ms, samples } => format!("ClockSkew {} {} {}", skew_ms, delay_ms, samples),
+        Event::Connected { ref server } => format!("Connected {} {} {:?}", server.release, server.protocol, server.javastudies),
+        // rates depend on the machine
+        Event::Stats { ref sessions, ref symbols, .. } => format!("Stats {} sessions {} symbols", sessions.len(), symbols.len()),
+        Event::ProtocolViolation { ref violation } => format!("ProtocolViolation {}", violation),
+    }
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(FIXTURES).expect("fixture directory")
+        .map(|entry| entry.expect("fixture entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// what a stream reports for the frame, once connected
+fn stream_events(frame: &str) -> Vec<String> {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, _outgoing_rx) = mpsc::channel();
+    let (events_tx, events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions {
+        quote_fields: vec![QuoteField::LastPrice, QuoteField::Change, QuoteField::ChangePercent, QuoteField::Volume],
+        studies: vec![StudySpec::pine("sma", "STD;SMA", None)],
+        // drift from what the fixtures asked for shows up in their golden files
+        invariants: InvariantMode::Report,
+        ..ApiOptions::default()
+    };
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Stream, options).expect("api");
+    let connected = fs::read_to_string(Path::new(FIXTURES).join("connected.txt")).expect("connected fixture");
+    for frame in [connected.as_str(), frame] {
+        incoming_tx.send(Incoming::Frame(frame.to_string(), Instant::now())).expect("send frame");
+    }
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    // errors are reported as events too, what matters is what came out
+    let _ = api.handler();
+    drop(api);
+    events_rx.iter().map(|event| describe(&event)).collect()
+}
+
+fn golden(frame: &str) -> String {
+    let mut lines = vec![];
+    for message in split_messages(frame) {
+        let classified = match parse_message(message) {
+            Ok(message_type) => variant(&message_type).to_string(),
+            Err(err) => format!("error {}", err),
+        };
+        lines.push(format!("message {}", classified));
+    }
+    lines.extend(stream_events(frame).into_iter().map(|event| format!("event {}", event)));
+    lines.join("\n") + "\n"
+}
+
+#[test]
+fn fixtures_match_their_golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = vec![];
+    for path in fixtures() {
+        let frame = fs::read_to_string(&path).expect("fixture");
+        let actual = golden(&frame);
+        let golden_path = path.with_extension("golden");
+        if update {
+            fs::write(&golden_path, &actual).expect("write golden file");
+            continue;
+        }
+        let expected = fs::read_to_string(&golden_path).unwrap_or_default();
+        if actual != expected {
+            mismatches.push(format!("{}:\n--- expected\n{}--- actual\n{}", path.display(), expected, actual));
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn every_message_type_has_a_fixture() {
+    let mut seen = BTreeSet::new();
+    for path in fixtures() {
+        let frame = fs::read_to_string(&path).expect("fixture");
+        seen.extend(split_messages(&frame).filter_map(|message| parse_message(message).ok()).map(|message_type| variant(&message_type)));
+    }
+    let missing: Vec<&str> = ALL_VARIANTS.iter().copied().filter(|variant| !seen.contains(variant)).collect();
+    assert!(missing.is_empty(), "no fixture for {:?}", missing);
+}
+
+// the classification of a message can't hang on how the server spaces its json
+#[test]
+fn classification_ignores_whitespace() {
+    let mut mismatches = vec![];
+    for path in fixtures() {
+        let frame = fs::read_to_string(&path).expect("fixture");
+        for message in split_messages(&frame) {
+            let Ok(value) = serde_json::from_str::<serde_json::Value>(message) else {
+                continue;
+            };
+            let expected = parse_message(message).ok().map(|message_type| variant(&message_type));
+            for spelling in [serde_json::to_string(&value).expect("compact"), serde_json::to_string_pretty(&value).expect("pretty")] {
+                let actual = parse_message(&spelling).ok().map(|message_type| variant(&message_type));
+                if actual != expected {
+                    mismatches.push(format!("{}: {:?} as {:?}, {:?} as {:?}", path.display(), message, expected, spelling, actual));
+                }
+            }
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+// a frame cut up over the pool's workers comes back whole and in order
+#[test]
+fn parse_pool_keeps_frame_order() {
+    let pool = ParsePool::new(3);
+    let session = pool.session();
+    for path in fixtures() {
+        let frame = fs::read_to_string(&path).expect("fixture");
+        let expected: Vec<_> = parse_frame(frame.clone()).messages.into_iter()
+            .map(|message| (message.span, message.message_type.ok().map(|message_type| variant(&message_type))))
+            .collect();
+        let parsed = session.parse(frame.clone()).expect("parse");
+        assert_eq!(parsed.frame, frame);
+        let actual: Vec<_> = parsed.messages.into_iter()
+            .map(|message| (message.span, message.message_type.ok().map(|message_type| variant(&message_type))))
+            .collect();
+        assert_eq!(actual, expected, "{}", path.display());
+    }
+}
+
+// whichever backend json::parse uses, it builds the same tree serde_json does
+#[test]
+fn json_backend_matches_serde_json() {
+    for path in fixtures() {
+        let frame = fs::read_to_string(&path).expect("fixture");
+        for message in split_messages(&frame) {
+            let expected = serde_json::from_str::<serde_json::Value>(message).ok();
+            assert_eq!(json::parse(message).ok(), expected, "{}: {}", path.display(), message);
+            // long enough for simd-json to take it
+            let padded = format!("{}{}", message, " ".repeat(4096));
+            assert_eq!(json::parse(&padded).ok(), expected, "{}: {} padded", path.display(), message);
+        }
+    }
+}
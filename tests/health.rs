@@ -0,0 +1,36 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tradingview_websocket::health::Health;
+use tradingview_websocket::stats::Stats;
+
+#[test]
+fn no_connection_is_unhealthy() {
+    let health = Arc::new(Health::default());
+    assert_eq!(health.report(&Stats::default(), Duration::from_secs(60)).status, "unhealthy");
+}
+
+#[test]
+fn quiet_sessions_stay_healthy_while_pings_arrive() {
+    let health = Arc::new(Health::default());
+    let connection = health.connect();
+    connection.record_message(Some("chart_session_id"));
+    thread::sleep(Duration::from_millis(50));
+    // a ping names no session
+    connection.record_message(None);
+    let report = health.report(&Stats::default(), Duration::from_millis(40));
+    assert_eq!(report.status, "ok");
+    assert!(report.sessions[0].last_message_age >= 0.05);
+}
+
+#[test]
+fn a_connection_that_hears_nothing_is_unhealthy() {
+    let health = Arc::new(Health::default());
+    let connection = health.connect();
+    connection.record_message(None);
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(health.report(&Stats::default(), Duration::from_millis(40)).status, "unhealthy");
+    drop(connection);
+    assert_eq!(health.report(&Stats::default(), Duration::from_secs(60)).connections, 0);
+}
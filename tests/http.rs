@@ -0,0 +1,28 @@
+use tradingview_websocket::http::{decode_chunked, with_query};
+
+#[test]
+fn chunks_are_joined() {
+    let body = b"4\r\nWiki\r\n5;name=value\r\npedia\r\n0\r\n\r\n";
+    assert_eq!(decode_chunked(body).expect("decoded"), b"Wikipedia");
+}
+
+#[test]
+fn truncated_bodies_are_errors_not_panics() {
+    let body = b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+    for end in 0..body.len() - 5 {
+        assert!(decode_chunked(&body[..end]).is_err(), "{:?}", String::from_utf8_lossy(&body[..end]));
+    }
+    // the CRLF after a chunk is missing
+    assert!(decode_chunked(b"4\r\nWiki").is_err());
+    assert!(decode_chunked(b"4\r\nWiki\r").is_err());
+    assert!(decode_chunked(b"4\r\nWikiXX0\r\n\r\n").is_err());
+    assert!(decode_chunked(b"ffffffffffffffff\r\nWiki\r\n").is_err());
+}
+
+#[test]
+fn query_values_are_percent_encoded() {
+    let url = with_query("https://symbol-search.tradingview.com/symbol_search/v3/", &[("text", "S&P 500"), ("exchange", "")]);
+    assert_eq!(url, "https://symbol-search.tradingview.com/symbol_search/v3/?text=S%26P+500&exchange=");
+    let url = with_query("https://economic-calendar.tradingview.com/events", &[("from", "2026-10-16T00:00:00.000Z"), ("countries", "US,EU")]);
+    assert_eq!(url, "https://economic-calendar.tradingview.com/events?from=2026-10-16T00%3A00%3A00.000Z&countries=US%2CEU");
+}
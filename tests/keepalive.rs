@@ -0,0 +1,61 @@
+use std::fs;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use tradingview_websocket::keepalive::{Keepalive, PingReply};
+use tradingview_websocket::stats::Stats;
+use tradingview_websocket::tradingview_api::{frame_message, ApiOptions, Mode, TradingViewApi};
+use tradingview_websocket::websocket::{Incoming, Outgoing};
+
+#[test]
+fn coalesce_echoes_every_held_id_in_one_frame() {
+    let stats = Stats::default();
+    let mut keepalive = Keepalive::new(PingReply::Coalesce(Duration::from_millis(500)));
+    let now = Instant::now();
+    for id in 1..=3 {
+        assert_eq!(keepalive.ping(id, now, now + Duration::from_millis(id as u64 * 100), &stats), None);
+    }
+    assert_eq!(keepalive.next_due(), Some(now + Duration::from_millis(600)));
+    assert!(keepalive.due(now + Duration::from_millis(599), &stats).is_empty());
+    assert_eq!(keepalive.due(now + Duration::from_millis(600), &stats), vec!["~m~4~m~~h~1~m~4~m~~h~2~m~4~m~~h~3".to_string()]);
+    assert_eq!(keepalive.pending(), 0);
+    assert_eq!(stats.snapshot().coalesced_pings, 2);
+}
+
+#[test]
+fn jitter_answers_in_order() {
+    let stats = Stats::default();
+    let mut keepalive = Keepalive::new(PingReply::Jitter(Duration::from_millis(200)));
+    let now = Instant::now();
+    for id in 1..=20 {
+        assert_eq!(keepalive.ping(id, now, now, &stats), None);
+    }
+    let replies = keepalive.due(now + Duration::from_millis(200), &stats);
+    let expected: Vec<String> = (1..=20).map(|id| frame_message(&format!("~h~{}", id))).collect();
+    assert_eq!(replies, expected);
+}
+
+#[test]
+fn held_ping_replies_dont_take_a_frame_reply() {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, outgoing_rx) = mpsc::channel();
+    let (events_tx, _events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions { ping_reply: PingReply::Coalesce(Duration::from_millis(1)), ..ApiOptions::default() };
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Quotes(vec![]), options).expect("api");
+    let ping = fs::read_to_string("tests/fixtures/messages/ping.txt").expect("ping fixture");
+    incoming_tx.send(Incoming::Frame(ping.clone(), Instant::now())).expect("send frame");
+    let api = std::thread::spawn(move || {
+        let _ = api.handler();
+    });
+    // the held reply comes on its own once the window is over, the frame's reply is empty
+    let outgoing: Vec<Outgoing> = outgoing_rx.iter().take(2).collect();
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    api.join().expect("api thread");
+    match outgoing[..] {
+        [Outgoing::Reply(ref reply), Outgoing::Unprompted(ref held)] => {
+            assert!(reply.is_empty(), "{:?}", reply);
+            assert_eq!(*held, vec![ping]);
+        }
+        _ => panic!("expected an empty reply and then the held ping reply"),
+    }
+}
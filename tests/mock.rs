@@ -0,0 +1,62 @@
+#![cfg(feature = "testing")]
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+use tradingview_websocket::client;
+use tradingview_websocket::quote::QuoteField;
+use tradingview_websocket::testing::{MockScript, MockServer};
+use tradingview_websocket::tradingview_api::ApiOptions;
+
+// enough of an executor to await one future on the test thread
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+fn quote_script() -> MockScript {
+    MockScript::parse(r#"
+        hello
+        < quote_add_symbols
+        ping 1
+        > {"m":"qsd","p":["quote_session_id",{"n":"CRYPTO:BTCUSD","s":"ok","v":{"lp":37001.5}}]}
+    "#).expect("script")
+}
+
+#[test]
+fn get_quote_runs_against_a_mock_server() {
+    let server = MockServer::start(quote_script()).expect("mock server");
+    let options = ApiOptions { endpoint: server.endpoint(), ..ApiOptions::default() };
+    let quote = client::get_quote("CRYPTO:BTCUSD", &[QuoteField::LastPrice], options).expect("quote");
+    assert_eq!(quote.value.get(QuoteField::LastPrice), Some(&37001.5.into()));
+    let received = server.stop().expect("every step played");
+    assert!(received.iter().any(|message| message.contains("quote_add_symbols") && message.contains("CRYPTO:BTCUSD")), "{:?}", received);
+    assert!(received.iter().any(|message| message.contains("quote_delete_session")), "{:?}", received);
+}
+
+#[test]
+fn get_quote_async_completes_with_the_quote() {
+    let server = MockServer::start(quote_script()).expect("mock server");
+    let options = ApiOptions { endpoint: server.endpoint(), ..ApiOptions::default() };
+    let quote = block_on(client::get_quote_async("CRYPTO:BTCUSD", &[QuoteField::LastPrice], options)).expect("quote");
+    assert_eq!(quote.value.get(QuoteField::LastPrice), Some(&37001.5.into()));
+    server.stop().expect("every step played");
+}
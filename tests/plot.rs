@@ -0,0 +1,67 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TempPath;
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::plot;
+use tradingview_websocket::study::{PlotInfo, StudyPoint};
+
+fn bar(time: i64) -> Bar {
+    Bar { time, open: 100.0, high: 101.0, low: 99.0, close: 100.5, volume: 1.0 }
+}
+
+fn study_values(symbol: &str, points: Vec<StudyPoint>) -> String {
+    let plot = |name: &str, kind: &str| PlotInfo { id: format!("plot_{}", name), name: name.to_string(), kind: kind.to_string(), color: None };
+    let event = Event::StudyValues {
+        symbol: symbol.to_string(),
+        study: "rsi".to_string(),
+        parent: None,
+        plots: Arc::from(vec![plot("RSI", "line"), plot("Color", "colorer")]),
+        points
+    };
+    serde_json::to_string(&event).expect("serialized")
+}
+
+fn write_events(name: &str, lines: &[String]) -> TempPath {
+    let path = TempPath::new(name);
+    std::fs::write(&path, lines.join("\n") + "\n").expect("events written");
+    path
+}
+
+#[test]
+fn study_values_line_up_with_the_bars() {
+    let path = write_events("events.jsonl", &[
+        study_values("CRYPTO:BTCUSD", vec![StudyPoint { time: 60, values: vec![40.0, 1.0] }, StudyPoint { time: 120, values: vec![f64::NAN, 1.0] }]),
+        // a later update of the same point wins
+        study_values("CRYPTO:BTCUSD", vec![StudyPoint { time: 180, values: vec![55.0, 2.0] }, StudyPoint { time: 60, values: vec![45.0, 2.0] }]),
+        study_values("CRYPTO:ETHUSD", vec![StudyPoint { time: 0, values: vec![70.0, 1.0] }]),
+    ]);
+    let bars = vec![bar(0), bar(60), bar(120), bar(180)];
+    let series = plot::read_study_series(path.to_str().expect("utf-8 path"), "CRYPTO:BTCUSD", &bars).expect("read");
+    // the colorer isn't a value to draw
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0].name, "rsi RSI");
+    assert_eq!(series[0].values, vec![None, Some(45.0), None, Some(55.0)]);
+}
+
+#[test]
+fn studies_are_drawn_below_the_candles() {
+    let path = write_events("chart-events.jsonl", &[
+        study_values("CRYPTO:BTCUSD", (0..20).map(|index| StudyPoint { time: index * 60, values: vec![index as f64, 0.0] }).collect()),
+    ]);
+    let bars: Vec<Bar> = (0..20).map(|index| bar(index * 60)).collect();
+    let studies = plot::read_study_series(path.to_str().expect("utf-8 path"), "CRYPTO:BTCUSD", &bars).expect("read");
+    let out = TempPath::new("chart.svg");
+    plot::render_chart(out.to_str().expect("utf-8 path"), "CRYPTO:BTCUSD", &bars, &[], &studies, (800, 600)).expect("rendered");
+    let svg = std::fs::read_to_string(&out).expect("chart written");
+    // the study's line next to the candles
+    assert!(svg.contains("<polyline"), "{}", svg);
+}
+
+#[test]
+fn unreadable_events_are_an_error() {
+    let path = write_events("broken.jsonl", &["{not json".to_string()]);
+    assert!(plot::read_study_series(path.to_str().expect("utf-8 path"), "CRYPTO:BTCUSD", &[bar(0)]).is_err());
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::sync::{mpsc, Arc};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use tradingview_websocket::clock::ClockSkew;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::quote::{AccessLevel, QuoteField};
+use tradingview_websocket::tradingview_api::{frame_message, ApiCommand, ApiOptions, Mode, TradingViewApi};
+use tradingview_websocket::websocket::{Incoming, Outgoing};
+
+// the quote_set_fields message a quotes connection sends once connected, with the default
+// anonymous token
+fn set_fields_message(fields: &[QuoteField]) -> String {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, outgoing_rx) = mpsc::channel();
+    let (events_tx, _events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions {
+        quote_fields: fields.to_vec(),
+        quote_session_fields: fields.to_vec(),
+        ..ApiOptions::default()
+    };
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Quotes(vec!["NASDAQ:AAPL".to_string()]), options).expect("api");
+    let connected = fs::read_to_string("tests/fixtures/messages/connected.txt").expect("connected fixture");
+    incoming_tx.send(Incoming::Frame(connected, Instant::now())).expect("send frame");
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+    outgoing_rx.iter().flat_map(Outgoing::into_messages).find(|message| message.contains("quote_set_fields")).expect("quote_set_fields sent")
+}
+
+#[test]
+fn fundamentals_need_an_authenticated_session() {
+    assert_eq!(QuoteField::MarketCap.required_access(), AccessLevel::Authenticated);
+    assert_eq!(QuoteField::LastPrice.required_access(), AccessLevel::Anonymous);
+    assert!(!AccessLevel::Anonymous.allows(QuoteField::EarningsPerShare));
+    assert!(AccessLevel::Authenticated.allows(QuoteField::EarningsPerShare));
+    assert_eq!(AccessLevel::of_token("unauthorized_user_token"), AccessLevel::Anonymous);
+}
+
+#[test]
+fn anonymous_session_refuses_gated_fields() {
+    let message = set_fields_message(&[QuoteField::LastPrice, QuoteField::MarketCap]);
+    assert!(message.contains(r#""lp""#), "{}", message);
+    assert!(!message.contains("market_cap_basic"), "{}", message);
+}
+
+#[test]
+fn anonymous_session_leaves_gated_fields_out_of_all() {
+    let message = set_fields_message(&[]);
+    assert!(message.contains(r#""volume""#), "{}", message);
+    assert!(!message.contains("price_earnings_ttm"), "{}", message);
+}
+
+#[test]
+fn normalized_quotes_carry_lp_time_on_the_local_clock() {
+    // a clock 5s behind the server's, which beats the fixture's old connect timestamp
+    let clock = Arc::new(ClockSkew::default());
+    let now = SystemTime::now();
+    let now_ms = now.duration_since(UNIX_EPOCH).expect("after the epoch").as_millis() as i64;
+    clock.record_connect(now_ms + 5000, now);
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, _outgoing_rx) = mpsc::channel();
+    let (events_tx, events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions { clock, normalize_times: true, quote_fields: vec![QuoteField::LastPrice], ..ApiOptions::default() };
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Quotes(vec!["CRYPTO:BTCUSD".to_string()]), options).expect("api");
+    for fixture in ["connected", "qsd"] {
+        let frame = fs::read_to_string(format!("tests/fixtures/messages/{}.txt", fixture)).expect("fixture");
+        incoming_tx.send(Incoming::Frame(frame, Instant::now())).expect("send frame");
+    }
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+    let local_times: Vec<Option<i64>> = events_rx.try_iter().filter_map(|event| match *event {
+        Event::Quote { local_time_ms, .. } => Some(local_time_ms),
+        _ => None,
+    }).collect();
+    assert_eq!(local_times, vec![Some(1_700_000_060_000 - 5000)]);
+}
+
+#[test]
+fn commands_between_frames_dont_take_a_frame_reply() {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, outgoing_rx) = mpsc::channel();
+    let (events_tx, _events_rx) = mpsc::sync_channel(1000);
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Quotes(vec!["NASDAQ:AAPL".to_string()]), ApiOptions::default()).expect("api");
+    let connected = fs::read_to_string("tests/fixtures/messages/connected.txt").expect("connected fixture");
+    incoming_tx.send(Incoming::Frame(connected, Instant::now())).expect("send frame");
+    incoming_tx.send(Incoming::Command(ApiCommand::HibernateQuotes, None)).expect("send command");
+    let ping = fs::read_to_string("tests/fixtures/messages/ping.txt").expect("ping fixture");
+    incoming_tx.send(Incoming::Frame(ping.clone(), Instant::now())).expect("send frame");
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+    let outgoing: Vec<Outgoing> = outgoing_rx.iter().collect();
+    let replies: Vec<&Vec<String>> = outgoing.iter().filter_map(|outgoing| match *outgoing {
+        Outgoing::Reply(ref messages) => Some(messages),
+        Outgoing::Unprompted(_) => None,
+    }).collect();
+    // one for each frame and one for the shutdown
+    assert_eq!(replies.len(), 3);
+    assert_eq!(*replies[1], vec![ping]);
+    match outgoing[1] {
+        Outgoing::Unprompted(ref messages) => assert!(messages.iter().any(|message| message.contains("quote_hibernate_all")), "{:?}", messages),
+        Outgoing::Reply(ref messages) => panic!("the command's messages came as a reply: {:?}", messages),
+    }
+}
+
+#[test]
+fn quote_errors_without_a_symbol_are_skipped() {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, _outgoing_rx) = mpsc::channel();
+    let (events_tx, events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions::default();
+    let stats = options.stats.clone();
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Quotes(vec!["NASDAQ:AAPL".to_string()]), options).expect("api");
+    let connected = fs::read_to_string("tests/fixtures/messages/connected.txt").expect("connected fixture");
+    let frames = [
+        connected,
+        frame_message(r#"{"m":"qsd","p":["quote_session_id",{"s":"error","errmsg":"invalid symbol"}]}"#),
+        frame_message(r#"{"m":"qsd","p":["quote_session_id",{"n":"","s":"error","errmsg":"invalid symbol"}]}"#),
+        frame_message(r#"{"m":"qsd","p":["quote_session_id",{"n":"NASDAQ:APPL","s":"error","errmsg":"invalid symbol"}]}"#),
+    ];
+    for frame in frames {
+        incoming_tx.send(Incoming::Frame(frame, Instant::now())).expect("send frame");
+    }
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+    let failed: Vec<String> = events_rx.try_iter().filter_map(|event| match *event {
+        Event::SubscriptionFailed { ref symbol, .. } => Some(symbol.clone()),
+        _ => None,
+    }).collect();
+    assert_eq!(failed, vec!["NASDAQ:APPL".to_string()]);
+    assert!(stats.snapshot().errors.contains(&("parse", 2)), "{:?}", stats.snapshot().errors);
+}
+
+#[test]
+fn watched_fields_dont_have_to_be_reported() {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, _outgoing_rx) = mpsc::channel();
+    let (events_tx, events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions { quote_fields: vec![QuoteField::LastPrice], quote_changes: vec![QuoteField::ChangePercent], ..ApiOptions::default() };
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Quotes(vec!["CRYPTO:BTCUSD".to_string()]), options).expect("api");
+    let connected = fs::read_to_string("tests/fixtures/messages/connected.txt").expect("connected fixture");
+    let frames = [
+        connected,
+        frame_message(r#"{"m":"qsd","p":["quote_session_id",{"n":"CRYPTO:BTCUSD","s":"ok","v":{"lp":37001.5,"chp":0.33}}]}"#),
+        frame_message(r#"{"m":"qsd","p":["quote_session_id",{"n":"CRYPTO:BTCUSD","s":"ok","v":{"lp":37002.5}}]}"#),
+        // a watched field that didn't move sends nothing
+        frame_message(r#"{"m":"qsd","p":["quote_session_id",{"n":"CRYPTO:BTCUSD","s":"ok","v":{"chp":0.33}}]}"#),
+        frame_message(r#"{"m":"qsd","p":["quote_session_id",{"n":"CRYPTO:BTCUSD","s":"ok","v":{"chp":0.35}}]}"#),
+    ];
+    for frame in frames {
+        incoming_tx.send(Incoming::Frame(frame, Instant::now())).expect("send frame");
+    }
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+    let mut quotes = vec![];
+    let mut changes = vec![];
+    for event in events_rx.try_iter() {
+        match *event {
+            Event::Quote { ref values, .. } => quotes.push(values.clone()),
+            Event::QuoteFieldChanged { field, ref old, ref new, .. } => changes.push((field, old.clone(), new.clone())),
+            _ => {}
+        }
+    }
+    assert_eq!(quotes, vec![vec![(QuoteField::LastPrice, 37001.5.into())], vec![(QuoteField::LastPrice, 37002.5.into())]]);
+    assert_eq!(changes, vec![(QuoteField::ChangePercent, None, 0.33.into()), (QuoteField::ChangePercent, Some(0.33.into()), 0.35.into())]);
+}
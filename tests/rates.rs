@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+use tradingview_websocket::event::Event;
+use tradingview_websocket::rates::{QueueDepths, RateMeter, SessionRate, SymbolRate};
+
+fn quote(symbol: &str) -> Event {
+    Event::Quote { symbol: symbol.to_string(), values: vec![], correlation_id: None, local_time_ms: None }
+}
+
+fn bars(symbol: &str) -> Event {
+    Event::Bars { symbol: symbol.to_string(), interval: "1".to_string(), bars: vec![], correlation_id: None }
+}
+
+// traffic on two sessions and three symbols, two of which tie
+fn busy_meter(start: Instant) -> RateMeter {
+    let mut meter = RateMeter::new(start);
+    for _ in 0..4 {
+        meter.record_frame(250);
+    }
+    for _ in 0..6 {
+        meter.record_message(Some("quote_session_id"));
+    }
+    for _ in 0..2 {
+        meter.record_message(Some("chart_session_id"));
+    }
+    // a ping names no session
+    meter.record_message(None);
+    for event in [quote("NASDAQ:MSFT"), bars("CRYPTO:BTCUSD"), quote("CRYPTO:BTCUSD"), quote("NASDAQ:AAPL"), quote("CRYPTO:BTCUSD"), quote("NASDAQ:AAPL"), quote("NASDAQ:MSFT")] {
+        meter.record_event(&event);
+    }
+    // not market data
+    meter.record_event(&Event::FeedStale { silent_for: Duration::from_secs(1) });
+    meter.record_dropped_event();
+    meter
+}
+
+#[test]
+fn rates_are_per_second_of_the_interval() {
+    let start = Instant::now();
+    let mut meter = busy_meter(start);
+    let queues = QueueDepths { held_quotes: 3, pending_commands: 1, pending_pings: 0, dropped_events: 0 };
+    match meter.report(start + Duration::from_secs(2), queues) {
+        Event::Stats { interval, frames_per_sec, messages_per_sec, bytes_per_sec, queues, .. } => {
+            assert_eq!(interval, Duration::from_secs(2));
+            assert_eq!(frames_per_sec, 2.0);
+            assert_eq!(messages_per_sec, 4.5);
+            assert_eq!(bytes_per_sec, 500.0);
+            // the meter's own count of dropped events
+            assert_eq!(queues, QueueDepths { held_quotes: 3, pending_commands: 1, pending_pings: 0, dropped_events: 1 });
+        }
+        _ => panic!("not a Stats event"),
+    }
+}
+
+#[test]
+fn busiest_sessions_and_symbols_come_first() {
+    let start = Instant::now();
+    let mut meter = busy_meter(start);
+    match meter.report(start + Duration::from_secs(2), QueueDepths::default()) {
+        Event::Stats { sessions, symbols, .. } => {
+            assert_eq!(sessions, vec![
+                SessionRate { session: "quote_session_id".to_string(), messages: 6, per_sec: 3.0 },
+                SessionRate { session: "chart_session_id".to_string(), messages: 2, per_sec: 1.0 },
+            ]);
+            // ties go by name
+            assert_eq!(symbols, vec![
+                SymbolRate { symbol: "CRYPTO:BTCUSD".to_string(), updates: 3, per_sec: 1.5 },
+                SymbolRate { symbol: "NASDAQ:AAPL".to_string(), updates: 2, per_sec: 1.0 },
+                SymbolRate { symbol: "NASDAQ:MSFT".to_string(), updates: 2, per_sec: 1.0 },
+            ]);
+        }
+        _ => panic!("not a Stats event"),
+    }
+}
+
+#[test]
+fn a_report_starts_the_next_interval() {
+    let start = Instant::now();
+    let mut meter = busy_meter(start);
+    meter.report(start + Duration::from_secs(2), QueueDepths::default());
+    meter.record_frame(100);
+    meter.record_message(Some("chart_session_id"));
+    match meter.report(start + Duration::from_secs(6), QueueDepths::default()) {
+        Event::Stats { interval, frames_per_sec, messages_per_sec, bytes_per_sec, sessions, symbols, queues } => {
+            assert_eq!(interval, Duration::from_secs(4));
+            assert_eq!(frames_per_sec, 0.25);
+            assert_eq!(messages_per_sec, 0.25);
+            assert_eq!(bytes_per_sec, 25.0);
+            assert_eq!(sessions, vec![SessionRate { session: "chart_session_id".to_string(), messages: 1, per_sec: 0.25 }]);
+            assert_eq!(symbols, vec![]);
+            assert_eq!(queues.dropped_events, 0);
+        }
+        _ => panic!("not a Stats event"),
+    }
+}
+
+#[test]
+fn an_empty_interval_reports_no_rates() {
+    let start = Instant::now();
+    let mut meter = busy_meter(start);
+    match meter.report(start, QueueDepths::default()) {
+        Event::Stats { frames_per_sec, sessions, .. } => {
+            assert_eq!(frames_per_sec, 0.0);
+            assert!(sessions.iter().all(|session| session.per_sec == 0.0), "{:?}", sessions);
+        }
+        _ => panic!("not a Stats event"),
+    }
+}
@@ -0,0 +1,36 @@
+use tradingview_websocket::{bar::Bar, resample::Resampler};
+
+fn bar(time: i64, close: f64) -> Bar {
+    Bar { time, open: close, high: close, low: close, close, volume: 1.0 }
+}
+
+fn five_minutes() -> Resampler {
+    Resampler::new("1", &["5".to_string()]).expect("resampler")
+}
+
+#[test]
+fn a_bucket_the_history_starts_inside_is_dropped() {
+    let mut resampler = five_minutes();
+    // history from 00:03, so the 00:00 bucket is missing its first three minutes
+    let bars: Vec<Bar> = (3..10).map(|minute| bar(minute * 60, minute as f64)).collect();
+    let closed = resampler.update(&bars);
+    assert_eq!(closed, vec![]);
+    let closed = resampler.update(&[bar(600, 10.0)]);
+    assert_eq!(closed, vec![("5".to_string(), Bar { time: 300, open: 5.0, high: 9.0, low: 5.0, close: 9.0, volume: 5.0 })]);
+}
+
+#[test]
+fn a_bucket_the_history_starts_at_is_complete() {
+    let mut resampler = five_minutes();
+    let bars: Vec<Bar> = (0..6).map(|minute| bar(minute * 60, minute as f64)).collect();
+    let closed = resampler.update(&bars);
+    assert_eq!(closed, vec![("5".to_string(), Bar { time: 0, open: 0.0, high: 4.0, low: 0.0, close: 4.0, volume: 5.0 })]);
+    assert_eq!(resampler.open_bars(), vec![("5".to_string(), bar(300, 5.0))]);
+}
+
+#[test]
+fn a_partial_bucket_isnt_shown_while_it_fills() {
+    let mut resampler = five_minutes();
+    resampler.update(&[bar(120, 2.0)]);
+    assert_eq!(resampler.open_bars(), vec![]);
+}
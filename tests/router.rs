@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::router::SinkRouter;
+use tradingview_websocket::sink::Sink;
+
+// the rows a route was given, in order
+#[derive(Clone, Default)]
+struct Rows(Arc<Mutex<Vec<(String, i64, f64)>>>);
+
+impl Sink for Rows {
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> Result<(), Box<dyn Error + Send>> {
+        self.0.lock().expect("rows").push((symbol.to_string(), bar.time, bar.close));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error + Send>> {
+        Ok(())
+    }
+}
+
+fn bar(time: i64, close: f64) -> Bar {
+    Bar { time, open: close, high: close, low: close, close, volume: 1.0 }
+}
+
+fn bars(bars: Vec<Bar>) -> Event {
+    Event::Bars { symbol: "CRYPTO:BTCUSD".to_string(), interval: "1".to_string(), bars, correlation_id: None }
+}
+
+fn router() -> (SinkRouter, Rows) {
+    let rows = Rows::default();
+    let mut router = SinkRouter::default();
+    router.add_route("BTCUSD", Box::new(rows.clone()));
+    (router, rows)
+}
+
+fn written(rows: &Rows) -> Vec<(i64, f64)> {
+    rows.0.lock().expect("rows").iter().map(|(_, time, close)| (*time, *close)).collect()
+}
+
+#[test]
+fn the_forming_bar_is_written_once_after_it_closes() {
+    let (mut router, rows) = router();
+    router.handle_event(&bars(vec![bar(0, 1.0), bar(60, 2.0), bar(120, 3.0)])).expect("routed");
+    assert_eq!(written(&rows), vec![(0, 1.0), (60, 2.0)]);
+    for close in [3.5, 4.0, 4.5] {
+        router.handle_event(&bars(vec![bar(120, close)])).expect("routed");
+    }
+    assert_eq!(written(&rows), vec![(0, 1.0), (60, 2.0)]);
+    router.handle_event(&bars(vec![bar(180, 5.0)])).expect("routed");
+    assert_eq!(written(&rows), vec![(0, 1.0), (60, 2.0), (120, 4.5)]);
+}
+
+#[test]
+fn revisions_of_closed_bars_are_written_again() {
+    let (mut router, rows) = router();
+    router.handle_event(&bars(vec![bar(0, 1.0), bar(60, 2.0)])).expect("routed");
+    let corrected = Event::BarCorrected { symbol: "CRYPTO:BTCUSD".to_string(), interval: "1".to_string(), previous: bar(0, 1.0), bar: bar(0, 1.5) };
+    router.handle_event(&corrected).expect("routed");
+    // a correction of the forming bar is only another update of it
+    let corrected = Event::BarCorrected { symbol: "CRYPTO:BTCUSD".to_string(), interval: "1".to_string(), previous: bar(60, 2.0), bar: bar(60, 2.5) };
+    router.handle_event(&corrected).expect("routed");
+    assert_eq!(written(&rows), vec![(0, 1.0), (0, 1.5)]);
+}
+
+#[test]
+fn intervals_form_separately() {
+    let (mut router, rows) = router();
+    router.handle_event(&bars(vec![bar(0, 1.0)])).expect("routed");
+    let quarter = Event::Bars { symbol: "CRYPTO:BTCUSD".to_string(), interval: "15".to_string(), bars: vec![bar(0, 10.0), bar(900, 11.0)], correlation_id: None };
+    router.handle_event(&quarter).expect("routed");
+    assert_eq!(written(&rows), vec![(0, 10.0)]);
+    router.write_forming().expect("written");
+    let mut rows = written(&rows);
+    rows.sort_by(|a, b| a.partial_cmp(b).expect("no NaN"));
+    assert_eq!(rows, vec![(0, 1.0), (0, 10.0), (900, 11.0)]);
+}
+
+#[test]
+fn gap_bars_and_resampled_bars_go_straight_through() {
+    let (mut router, rows) = router();
+    router.handle_event(&bars(vec![bar(600, 6.0)])).expect("routed");
+    router.handle_event(&Event::GapFilled { symbol: "CRYPTO:BTCUSD".to_string(), interval: "1".to_string(), bars: vec![bar(60, 1.0)] }).expect("routed");
+    router.handle_event(&Event::BarClosed { symbol: "CRYPTO:BTCUSD".to_string(), interval: "5".to_string(), bar: bar(0, 2.0) }).expect("routed");
+    assert_eq!(written(&rows), vec![(60, 1.0), (0, 2.0)]);
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use tradingview_websocket::ack::CommandOutcome;
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::calendar::{EconomicEvent, Importance};
+use tradingview_websocket::corporate::CorporateEvent;
+use tradingview_websocket::event::{Event, ServerErrorKind, EVENT_TYPES};
+use tradingview_websocket::invariants::Violation;
+use tradingview_websocket::market::TradingHours;
+use tradingview_websocket::news::NewsItem;
+use tradingview_websocket::quality::DataQualityIssue;
+use tradingview_websocket::quote::QuoteField;
+use tradingview_websocket::rates::{QueueDepths, SessionRate, SymbolRate};
+use tradingview_websocket::schema;
+use tradingview_websocket::server_info::ServerInfo;
+use tradingview_websocket::session::RangeStats;
+use tradingview_websocket::study::{PlotInfo, StudyPoint};
+use tradingview_websocket::trade::{Trade, TradeSide};
+
+// the schema events are stored and replayed against; a change to it shows up in review, and
+// UPDATE_SCHEMA=1 cargo test --test schema rewrites it
+const SCHEMA: &str = "schema/event.schema.json";
+
+fn bar(time: i64) -> Bar {
+    Bar { time, open: 1.0, high: 2.0, low: 0.5, close: 1.5, volume: 100.0 }
+}
+
+fn range() -> RangeStats {
+    RangeStats { from: 1700000000, open: 1.0, high: 2.0, low: 0.5, close: 1.5, volume: 100.0, vwap: Some(1.25), bars: 3 }
+}
+
+// one of every variant
+fn events() -> Vec<Event> {
+    let symbol = "NASDAQ:AAPL".to_string();
+    let mut hours = TradingHours::parse("America/New_York", "0930-1600:23456", "20241225,20250101").expect("trading hours");
+    hours.description = Some("Regular Trading Hours".to_string());
+    vec![
+        Event::Bars { symbol: symbol.clone(), interval: "1".to_string(), bars: vec![bar(1700000000), bar(1700000060)], correlation_id: None },
+        Event::HistoryCompleted { symbol: symbol.clone() },
+        Event::GapFilled { symbol: symbol.clone(), interval: "1".to_string(), bars: vec![bar(1700000120)] },
+        Event::BarCorrected { symbol: symbol.clone(), interval: "1".to_string(), previous: bar(1700000060), bar: bar(1700000060) },
+        Event::ServerError { kind: ServerErrorKind::Critical, payload: Arc::new(json!({"m": "critical_error", "p": ["chart_session_id"]})) },
+        Event::SubscriptionFailed { symbol: symbol.clone(), reason: "invalid symbol".to_string(), correlation_id: Some(7) },
+        Event::DataQualityWarning { symbol: symbol.clone(), time: 1700000000, issue: DataQualityIssue::MissingBars { after: 1700000000, count: 2 } },
+        Event::DataQualityWarning { symbol: symbol.clone(), time: 1700000000, issue: DataQualityIssue::ZeroPrice },
+        Event::FeedStale { silent_for: Duration::from_millis(61500) },
+        Event::Quote { symbol: symbol.clone(), values: vec![(QuoteField::LastPrice, json!(189.5)), (QuoteField::Description, json!("Apple Inc."))], correlation_id: Some(3), local_time_ms: Some(1700000000250) },
+        Event::QuoteFieldChanged { symbol: symbol.clone(), field: QuoteField::LastPrice, old: None, new: json!(189.5) },
+        Event::QuoteCompleted { symbol: symbol.clone() },
+        Event::ContractResolved { symbol: "CME_MINI:ES1!".to_string(), root: Some("ES".to_string()), contract: "ESZ2024".to_string() },
+        Event::ContractRolled { symbol: "CME_MINI:ES1!".to_string(), previous: "ESZ2024".to_string(), contract: "ESH2025".to_string() },
+        Event::StudyValues {
+            symbol: symbol.clone(),
+            study: "rsi".to_string(),
+            parent: None,
+            plots: Arc::from(vec![PlotInfo { id: "plot_0".to_string(), name: "RSI".to_string(), kind: "line".to_string(), color: Some("#7E57C2".to_string()) }]),
+            points: vec![StudyPoint { time: 1700000000, values: vec![f64::NAN] }, StudyPoint { time: 1700000060, values: vec![55.5] }]
+        },
+        Event::CorporateEvents { symbol: symbol.clone(), events: vec![
+            CorporateEvent::Earnings { time: 1700000000, actual: None, estimate: Some(1.5) },
+            CorporateEvent::Dividend { time: 1700000000, amount: 0.24 },
+            CorporateEvent::Split { time: 1700000000, numerator: 4.0, denominator: 1.0 }
+        ] },
+        Event::TradingHours { symbol: symbol.clone(), hours },
+        Event::Trade { symbol: symbol.clone(), trade: Trade { time: 1700000000, price: 189.5, size: 10.0, side: TradeSide::Buy }, local_time_ms: None },
+        Event::BarClosed { symbol: symbol.clone(), interval: "5".to_string(), bar: bar(1700000000) },
+        Event::SessionStats { symbol: symbol.clone(), session: range(), anchored: vec![range()] },
+        Event::NewsItem { symbol: symbol.clone(), item: NewsItem {
+            id: "story".to_string(),
+            title: "Apple earnings".to_string(),
+            provider: "reuters".to_string(),
+            published: 1700000000,
+            symbols: vec![symbol.clone()],
+            link: None
+        } },
+        Event::EconomicEvent { event: EconomicEvent {
+            id: "cpi".to_string(),
+            title: "Inflation Rate YoY".to_string(),
+            country: "US".to_string(),
+            currency: Some("USD".to_string()),
+            importance: Importance::High,
+            time: 1700000000,
+            actual: None,
+            forecast: Some(3.4),
+            previous: Some(3.5),
+            unit: Some("%".to_string())
+        } },
+        Event::CommandResult {
+            command: "create_study".to_string(),
+            session: "chart_session_id".to_string(),
+            target: "rsi".to_string(),
+            outcome: CommandOutcome::Failed { reason: "study_not_auth".to_string() },
+            elapsed: Duration::from_millis(250),
+            correlation_id: None
+        },
+        Event::ClockSkew { skew_ms: -420, delay_ms: 35, samples: 128 },
+        Event::Connected { server: ServerInfo {
+            session_id: "<0.1234.567>_sfo-charts-1-webchart-1@sfo-compute-1_x".to_string(),
+            release: "registry.xtools.tv/tvbs_release/webchart:release_206-21".to_string(),
+            protocol: "json".to_string(),
+            auth_scheme_vsn: Some(2),
+            javastudies: vec!["3.66".to_string()],
+            studies_metadata_hash: None,
+            via: Some("203.0.113.10:443".to_string()),
+            timestamp_ms: Some(1700000000123)
+        } },
+        Event::Stats {
+            interval: Duration::from_secs(10),
+            frames_per_sec: 4.2,
+            messages_per_sec: 12.5,
+            bytes_per_sec: 2048.0,
+            sessions: vec![SessionRate { session: "qs_1".to_string(), messages: 100, per_sec: 10.0 }],
+            symbols: vec![SymbolRate { symbol: symbol.clone(), updates: 80, per_sec: 8.0 }],
+            queues: QueueDepths { held_quotes: 1, pending_commands: 0, pending_pings: 0, dropped_events: 3 }
+        },
+        Event::ProtocolViolation { violation: Violation::BarTimeRegressed {
+            message: "du".to_string(),
+            series: "series_id".to_string(),
+            previous: 1700000060,
+            time: 1700000000
+        } },
+    ]
+}
+
+#[test]
+fn schema_matches_the_committed_file() {
+    let actual = serde_json::to_string_pretty(&schema::event_schema()).expect("schema") + "\n";
+    if std::env::var_os("UPDATE_SCHEMA").is_some() {
+        fs::write(SCHEMA, &actual).expect("write schema");
+        return;
+    }
+    let expected = fs::read_to_string(SCHEMA).unwrap_or_default();
+    assert!(actual == expected, "{} is out of date, a changed field may need a new SCHEMA_VERSION", SCHEMA);
+}
+
+#[test]
+fn events_survive_a_round_trip() {
+    for event in events() {
+        let serialized = serde_json::to_value(&event).expect("serialize");
+        let restored: Event = serde_json::from_value(serialized.clone()).unwrap_or_else(|err| panic!("{}: {}", serialized, err));
+        assert_eq!(serde_json::to_value(&restored).expect("serialize again"), serialized);
+    }
+}
+
+#[test]
+fn type_names_match_the_json() {
+    let events = events();
+    for event in events.iter() {
+        assert_eq!(serde_json::to_value(event).expect("serialize")["type"], event.type_name());
+    }
+    let mut seen: Vec<&str> = events.iter().map(Event::type_name).collect();
+    seen.dedup();
+    assert_eq!(seen, EVENT_TYPES);
+}
+
+#[test]
+fn field_names_are_stable() {
+    let serialized: Vec<Value> = events().iter().map(|event| serde_json::to_value(event).expect("serialize")).collect();
+    assert_eq!(serialized[0]["bars"][0], json!({"time": 1700000000, "open": 1.0, "high": 2.0, "low": 0.5, "close": 1.5, "volume": 100.0}));
+    assert_eq!(serialized[5]["correlation_id"], 7);
+    assert_eq!(serialized[6]["issue"], json!({"kind": "missing_bars", "after": 1700000000, "count": 2}));
+    assert_eq!(serialized[8], json!({"type": "feed_stale", "silent_for_ms": 61500}));
+    assert_eq!(serialized[9]["values"], json!([["lp", 189.5], ["description", "Apple Inc."]]));
+    assert_eq!(serialized[14]["points"][0]["values"], json!([null]));
+    assert_eq!(serialized[16]["hours"], json!({
+        "timezone": "America/New_York",
+        "session": "0930-1600:23456",
+        "holidays": "20241225,20250101",
+        "description": "Regular Trading Hours"
+    }));
+    assert_eq!(serialized[17]["trade"]["side"], "buy");
+    assert_eq!(serialized[22], json!({
+        "type": "command_result",
+        "command": "create_study",
+        "session": "chart_session_id",
+        "target": "rsi",
+        "outcome": {"status": "failed", "reason": "study_not_auth"},
+        "elapsed_ms": 250
+    }));
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use serde_json::json;
+use tradingview_websocket::event::Event;
+use tradingview_websocket::quality::DataQualityIssue;
+use tradingview_websocket::series::SeriesOptions;
+use tradingview_websocket::tradingview_api::{frame_message, ApiOptions, Mode, TradingViewApi};
+use tradingview_websocket::websocket::{Incoming, Outgoing};
+
+fn entry(time: i64, high: f64, low: f64) -> serde_json::Value {
+    json!({"i": 0, "v": [time, 100.0, high, low, 100.0, 1.0]})
+}
+
+fn timescale_update(entries: Vec<serde_json::Value>) -> String {
+    frame_message(&json!({"m": "timescale_update", "p": ["chart_session_id", {"series_id_15": {"s": entries}}]}).to_string())
+}
+
+fn series_completed() -> String {
+    frame_message(&json!({"m": "series_completed", "p": ["chart_session_id", "series_id_15", "streaming", "s2"]}).to_string())
+}
+
+#[test]
+fn extra_intervals_are_validated_backfilled_and_checkpointed() {
+    let (incoming_tx, incoming_rx) = mpsc::channel();
+    let (outgoing_tx, outgoing_rx) = mpsc::channel();
+    let (events_tx, events_rx) = mpsc::sync_channel(1000);
+    let options = ApiOptions {
+        series: SeriesOptions { extra_intervals: vec!["15".to_string()], ..SeriesOptions::default() },
+        validate_bars: true,
+        ..ApiOptions::default()
+    };
+    // the 15 minute series last saw 1700000000 before the reconnect
+    options.checkpoints.record("CRYPTO:BTCUSD@15", 1_700_000_000);
+    let checkpoints = options.checkpoints.clone();
+    let mut api = TradingViewApi::new(incoming_rx, outgoing_tx, events_tx, Mode::Stream, options).expect("api");
+    let connected = fs::read_to_string("tests/fixtures/messages/connected.txt").expect("connected fixture");
+    let frames = [
+        connected,
+        timescale_update(vec![entry(1_700_002_700, 101.0, 99.0), entry(1_700_003_600, 99.0, 101.0)]),
+        series_completed(),
+        // the page that request_more_data brings back, down to the checkpoint
+        timescale_update(vec![entry(1_700_000_000, 101.0, 99.0), entry(1_700_000_900, 101.0, 99.0), entry(1_700_001_800, 101.0, 99.0)]),
+        series_completed(),
+    ];
+    for frame in frames {
+        incoming_tx.send(Incoming::Frame(frame, Instant::now())).expect("send frame");
+    }
+    incoming_tx.send(Incoming::Shutdown).expect("send shutdown");
+    let _ = api.handler();
+    drop(api);
+
+    let mut warnings = vec![];
+    let mut live = vec![];
+    let mut gap = vec![];
+    for event in events_rx.try_iter() {
+        match *event {
+            Event::DataQualityWarning { time, ref issue, .. } => warnings.push((time, issue.clone())),
+            Event::Bars { ref interval, ref bars, .. } if interval == "15" => live.extend(bars.iter().map(|bar| bar.time)),
+            Event::GapFilled { ref interval, ref bars, .. } if interval == "15" => gap.extend(bars.iter().map(|bar| bar.time)),
+            _ => {}
+        }
+    }
+    assert_eq!(warnings, vec![(1_700_003_600, DataQualityIssue::HighBelowLow)]);
+    assert_eq!(live, vec![1_700_002_700, 1_700_003_600]);
+    assert_eq!(gap, vec![1_700_000_900, 1_700_001_800]);
+    assert_eq!(checkpoints.last_bar_time("CRYPTO:BTCUSD@15"), Some(1_700_003_600));
+    // the chart interval's checkpoint is left alone
+    assert_eq!(checkpoints.last_bar_time("CRYPTO:BTCUSD"), None);
+    let more: Vec<String> = outgoing_rx.iter().flat_map(Outgoing::into_messages).filter(|message| message.contains("request_more_data")).collect();
+    assert_eq!(more.len(), 1, "{:?}", more);
+    assert!(more[0].contains("series_id_15"), "{}", more[0]);
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::TempPath;
+use tradingview_websocket::bar::Bar;
+use tradingview_websocket::sink;
+use tradingview_websocket::timestamp::TimestampFormatter;
+
+fn bars() -> Vec<Bar> {
+    vec![
+        Bar { time: 1700000000, open: 37000.0, high: 37010.0, low: 36990.0, close: 37005.0, volume: 12.5 },
+        Bar { time: 1700000060, open: 37005.0, high: 37020.5, low: 37001.0, close: 37019.0, volume: 0.25 }
+    ]
+}
+
+// writes the bars through open_sink and reads them back with read_bars
+fn round_trip(name: &str) -> Vec<(String, Bar)> {
+    let path = TempPath::new(name);
+    let path = path.to_str().expect("utf-8 path");
+    {
+        let mut sink = sink::open_sink(path, TimestampFormatter::default()).expect("open sink");
+        sink.write_bars("NASDAQ:AAPL", &bars()).expect("write bars");
+        sink.flush().expect("flush");
+    }
+    sink::read_bars(path).expect("read bars")
+}
+
+fn expected() -> Vec<(String, Bar)> {
+    bars().into_iter().map(|bar| ("NASDAQ:AAPL".to_string(), bar)).collect()
+}
+
+#[test]
+fn csv_round_trips() {
+    assert_eq!(round_trip("bars.csv"), expected());
+}
+
+#[test]
+fn jsonl_round_trips() {
+    assert_eq!(round_trip("bars.jsonl"), expected());
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_round_trips() {
+    assert_eq!(round_trip("bars.parquet"), expected());
+}
+
+#[cfg(not(feature = "parquet"))]
+#[test]
+fn parquet_needs_the_feature() {
+    let path = TempPath::new("bars.parquet");
+    assert!(sink::open_sink(path.to_str().expect("utf-8 path"), TimestampFormatter::default()).is_err());
+}
+
+// creating the producer doesn't reach out to the broker yet
+#[cfg(feature = "kafka")]
+#[test]
+fn kafka_urls_open_a_producer() {
+    assert!(sink::open_sink("kafka://127.0.0.1:9092/crypto.btc", TimestampFormatter::default()).is_ok());
+    assert!(sink::open_sink("kafka://127.0.0.1:9092/", TimestampFormatter::default()).is_err());
+    assert!(sink::open_sink("kafka://crypto.btc", TimestampFormatter::default()).is_err());
+}
+
+#[cfg(not(feature = "kafka"))]
+#[test]
+fn kafka_needs_the_feature() {
+    assert!(sink::open_sink("kafka://127.0.0.1:9092/crypto.btc", TimestampFormatter::default()).is_err());
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use serde_json::json;
+use tradingview_websocket::connect_script::ConnectScript;
+use tradingview_websocket::quote::QuoteField;
+use tradingview_websocket::series::SeriesOptions;
+use tradingview_websocket::state::{self, ClientState};
+use tradingview_websocket::study::StudySpec;
+use tradingview_websocket::tradingview_api::{ApiOptions, Mode};
+
+fn study(id: &str, length: u32) -> StudySpec {
+    StudySpec { id: id.to_string(), script: "Script@tv-scripting-101!".to_string(), inputs: json!({"pineId": "STD;RSI", "in_0": length}), parent: None, plots: Arc::from(vec![]) }
+}
+
+// what a stream run with these options leaves behind
+fn saved(options: ApiOptions) -> ClientState {
+    options.checkpoints.record("CRYPTO:BTCUSD", 1_700_000_000);
+    state::export_state(&Mode::Stream, &options)
+}
+
+fn lengths(options: &ApiOptions) -> Vec<(String, serde_json::Value)> {
+    options.studies.iter().map(|study| (study.id.clone(), study.inputs["in_0"].clone())).collect()
+}
+
+#[test]
+fn the_command_line_adds_to_the_saved_subscriptions() {
+    let state = saved(ApiOptions {
+        studies: vec![study("rsi", 14), study("slow", 50)],
+        quote_fields: vec![QuoteField::LastPrice],
+        ..ApiOptions::default()
+    });
+    let given = ApiOptions {
+        studies: vec![study("rsi", 7), study("fast", 5)],
+        quote_fields: vec![QuoteField::Volume, QuoteField::LastPrice],
+        ..ApiOptions::default()
+    };
+    let (mode, options, warnings) = state::merge_state(state, given);
+    assert!(matches!(mode, Mode::Stream));
+    // a study given again under its id takes the saved one's place
+    assert_eq!(lengths(&options), vec![("rsi".to_string(), json!(7)), ("slow".to_string(), json!(50)), ("fast".to_string(), json!(5))]);
+    assert_eq!(options.quote_fields, vec![QuoteField::LastPrice, QuoteField::Volume]);
+    assert_eq!(options.checkpoints.last_bar_time("CRYPTO:BTCUSD"), Some(1_700_000_000));
+    assert_eq!(warnings, Vec::<String>::new());
+}
+
+#[test]
+fn saved_settings_the_command_line_replaces_are_warned_about() {
+    let series = |currency: &str| SeriesOptions { currency: Some(currency.to_string()), ..SeriesOptions::default() };
+    let script = |symbol: &str, quote_symbols: &str| ConnectScript::parse(&json!({"quote_symbols": [quote_symbols], "chart": {"symbol": symbol}}).to_string()).expect("script");
+    let state = saved(ApiOptions { series: series("EUR"), connect_script: Some(script("CRYPTO:BTCUSD", "NASDAQ:AAPL")), ..ApiOptions::default() });
+    let given = ApiOptions { series: series("JPY"), connect_script: Some(script("CRYPTO:ETHUSD", "NASDAQ:MSFT")), ..ApiOptions::default() };
+    let (_, options, warnings) = state::merge_state(state, given);
+    assert_eq!(options.series.currency.as_deref(), Some("JPY"));
+    let script = options.connect_script.expect("script");
+    assert_eq!(script.quote_symbols, vec!["NASDAQ:AAPL".to_string(), "NASDAQ:MSFT".to_string()]);
+    assert_eq!(script.chart.expect("chart").symbol, "CRYPTO:ETHUSD");
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+}
+
+#[test]
+fn unset_options_keep_the_saved_ones() {
+    let series = SeriesOptions { extra_intervals: vec!["15".to_string()], ..SeriesOptions::default() };
+    let state = saved(ApiOptions { series: series.clone(), quote_session_fields: vec![QuoteField::LastPrice], ..ApiOptions::default() });
+    let (_, options, warnings) = state::merge_state(state, ApiOptions::default());
+    assert_eq!(options.series, series);
+    assert_eq!(options.quote_session_fields, vec![QuoteField::LastPrice]);
+    assert_eq!(warnings, Vec::<String>::new());
+}